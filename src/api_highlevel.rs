@@ -5,13 +5,16 @@
 
 use crate::api_types;
 use crate::common::*;
-use futures::future::FutureResult;
+use crate::lnurl::{self, MIN_SENDABLE_MSAT};
+use futures::future::{self, FutureResult};
 use futures::{
     future::{loop_fn, Loop},
     Future,
 };
+use std::time::Duration;
+use url::Url;
 
-pub struct ApiHigh<D: Db + 'static, L: LightningNode, G: Log> {
+pub struct ApiHigh<D: Db + 'static, L: LightningNode + 'static, G: Log> {
     pub api_low: ApiLow<D, L>,
     pub log: G,
 }
@@ -21,10 +24,90 @@ impl<D: Db, L: LightningNode, G: Log> ApiHigh<D, L, G> {
         &'a self,
         request: api_types::GenerateInvoiceRequest,
     ) -> impl Future<Item = api_types::GenerateInvoiceResponse, Error = ErrLogged> + Send + 'a {
-        let api_types::GenerateInvoiceRequest { lesser, satoshis } = request;
+        let api_types::GenerateInvoiceRequest {
+            lesser,
+            satoshis,
+            description,
+            description_hash,
+            expiry_seconds,
+        } = request;
+        let spec = match to_invoice_spec(satoshis, description, description_hash, expiry_seconds) {
+            Ok(spec) => spec,
+            Err(conflict) => return future::Either::A(FutureResult::from(Ok(conflict))),
+        };
+        future::Either::B(
+            FutureResult::from(spec)
+                .and_then(move |spec| self.api_low.generate_invoice(lesser, spec))
+                .map(Into::into) // convert Invoice to GenerateInvoiceOk
+                .then(move |res| to_user_result(res, &self.log))
+                .map(Into::into), // convert Result<_, _> to ResultSerDe<_, _>
+        )
+    }
+
+    pub fn generate_hold_invoice<'a>(
+        &'a self,
+        request: api_types::GenerateHoldInvoiceRequest,
+    ) -> impl Future<Item = api_types::GenerateInvoiceResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::GenerateHoldInvoiceRequest {
+            middle,
+            payment_hash,
+            satoshis,
+            description,
+            description_hash,
+            expiry_seconds,
+        } = request;
+        let spec = match to_invoice_spec(satoshis, description, description_hash, expiry_seconds) {
+            Ok(spec) => spec,
+            Err(conflict) => return future::Either::A(FutureResult::from(Ok(conflict))),
+        };
+        future::Either::B(
+            FutureResult::from(spec)
+                .and_then(move |spec| {
+                    self.api_low
+                        .generate_hold_invoice(middle, spec, payment_hash)
+                })
+                .map(Into::into) // convert Invoice to GenerateInvoiceOk
+                .then(move |res| to_user_result(res, &self.log))
+                .map(Into::into), // convert Result<_, _> to ResultSerDe<_, _>
+        )
+    }
+
+    pub fn settle_hold_invoice<'a>(
+        &'a self,
+        request: api_types::SettleHoldInvoiceRequest,
+    ) -> impl Future<Item = api_types::ResolveHoldInvoiceResponse, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::SettleHoldInvoiceRequest { middle, preimage } = request;
         self.api_low
-            .generate_invoice(lesser, satoshis)
-            .map(Into::into) // convert Invoice to GenerateInvoiceOk
+            .settle_hold_invoice(middle, preimage)
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    pub fn cancel_hold_invoice<'a>(
+        &'a self,
+        request: api_types::CancelHoldInvoiceRequest,
+    ) -> impl Future<Item = api_types::ResolveHoldInvoiceResponse, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::CancelHoldInvoiceRequest {
+            middle,
+            payment_hash,
+        } = request;
+        self.api_low
+            .cancel_hold_invoice(middle, payment_hash)
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    pub fn cancel_invoice<'a>(
+        &'a self,
+        payment_hash: PaymentHash,
+        request: api_types::CancelInvoiceRequest,
+    ) -> impl Future<Item = api_types::ResolveHoldInvoiceResponse, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::CancelInvoiceRequest { middle } = request;
+        self.api_low
+            .cancel_invoice(middle, payment_hash)
             .then(move |res| to_user_result(res, &self.log))
             .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
     }
@@ -46,6 +129,387 @@ impl<D: Db, L: LightningNode, G: Log> ApiHigh<D, L, G> {
             .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
     }
 
+    /// Pay a Lightning Address or LNURL-pay code from master's account.
+    pub fn pay_lnurl<'a>(
+        &'a self,
+        request: api_types::PayLnurlRequest,
+    ) -> impl Future<Item = api_types::PayLnurlResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::PayLnurlRequest {
+            master,
+            target,
+            amount_satoshis,
+            fee_satoshis,
+        } = request;
+        let link = match lnurl::pay_link(&target) {
+            Ok(link) => link,
+            Err(InvalidPayLink) => {
+                return future::Either::A(FutureResult::from(Ok(Err(
+                    api_types::PayLnurlErr::InvalidTarget(()),
+                )
+                .into())))
+            }
+        };
+        future::Either::B(
+            self.api_low
+                .pay_lnurl(master, link, amount_satoshis, fee_satoshis)
+                .map(Into::into) // convert PaidInvoiceOutgoing to PayInvoiceOk
+                .then(move |res| to_user_result(res, &self.log))
+                .map(Into::into), // convert Result<_, _> to ResultSerDe<_, _>
+        )
+    }
+
+    pub fn keysend<'a>(
+        &'a self,
+        request: api_types::KeysendRequest,
+    ) -> impl Future<Item = api_types::KeysendResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::KeysendRequest {
+            master,
+            pubkey,
+            amount_satoshis,
+            fee_satoshis,
+        } = request;
+        self.api_low
+            .keysend(master, pubkey.0, amount_satoshis, fee_satoshis)
+            .map(Into::into) // convert KeysendOutgoing to KeysendOk
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    pub fn quote<'a>(
+        &'a self,
+        request: api_types::QuoteRequest,
+    ) -> impl Future<Item = api_types::QuoteResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::QuoteRequest {
+            invoice,
+            amount_satoshis,
+        } = request;
+        self.api_low
+            .estimate_fee(invoice.0, amount_satoshis)
+            .map(Into::into) // convert FeeQuote to QuoteOk
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    pub fn withdraw_onchain<'a>(
+        &'a self,
+        request: api_types::WithdrawOnchainRequest,
+    ) -> impl Future<Item = api_types::WithdrawOnchainResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::WithdrawOnchainRequest {
+            master,
+            address,
+            amount_satoshis,
+            target_blocks,
+        } = request;
+        self.api_low
+            .withdraw_onchain(master, address, amount_satoshis, target_blocks)
+            .map(Into::into) // convert OnchainOutgoing to WithdrawOnchainOk
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    pub fn node_info<'a>(
+        &'a self,
+    ) -> impl Future<Item = api_types::NodeInfoResponse, Error = ErrLogged> + Send + 'a {
+        self.api_low
+            .node_info()
+            .map(Into::into) // convert NodeInfo to NodeInfoResponse
+            .map_err(move |err| err.log(&self.log))
+    }
+
+    pub fn new_address<'a>(
+        &'a self,
+        request: api_types::NewAddressRequest,
+    ) -> impl Future<Item = api_types::NewAddressResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::NewAddressRequest { lesser } = request;
+        self.api_low
+            .new_address(lesser)
+            .map(|address| api_types::NewAddressResponse { address })
+            .map_err(move |err| err.log(&self.log))
+    }
+
+    pub fn create_offer<'a>(
+        &'a self,
+        request: api_types::CreateOfferRequest,
+    ) -> impl Future<Item = api_types::CreateOfferResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::CreateOfferRequest {
+            lesser,
+            amount_satoshis,
+            description,
+        } = request;
+        let spec = OfferSpec {
+            amount: amount_satoshis,
+            description,
+        };
+        self.api_low
+            .create_offer(lesser, spec)
+            .map(|offer| api_types::CreateOfferOk { offer })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    /// Register a name for middle's account, answering with its Lightning Address on the server
+    /// reachable at public_url.
+    pub fn register_name<'a>(
+        &'a self,
+        request: api_types::RegisterNameRequest,
+        public_url: Url,
+    ) -> impl Future<Item = api_types::RegisterNameResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::RegisterNameRequest { middle, name } = request;
+        let name: Name = match name.parse() {
+            Ok(name) => name,
+            Err(InvalidName) => {
+                return future::Either::A(FutureResult::from(Ok(Err(
+                    api_types::RegisterNameErr::InvalidName(()),
+                )
+                .into())))
+            }
+        };
+        let address = lnurl::lightning_address(&name, &public_url);
+        future::Either::B(
+            self.api_low
+                .register_name(middle, name)
+                .map(|()| api_types::RegisterNameOk { address })
+                .then(move |res| to_user_result(res, &self.log))
+                .map(Into::into), // convert Result<_, _> to ResultSerDe<_, _>
+        )
+    }
+
+    /// The LNURL pay request for name. Wallets may send as much as the node can receive.
+    pub fn lnurl_pay_request<'a>(
+        &'a self,
+        name: String,
+        public_url: Url,
+    ) -> impl Future<Item = api_types::LnurlResponse<api_types::LnurlPayRequest>, Error = ErrLogged>
+           + Send
+           + 'a {
+        FutureResult::from(
+            name.parse()
+                .map_err(|InvalidName| LnurlPayError::UnknownName),
+        )
+        .and_then(move |name: Name| {
+            self.api_low
+                .name_owner(&name)
+                .map_err(|GetNameOwnerError::NameDoesNotExist| LnurlPayError::UnknownName)
+                .map(move |_owner| name)
+        })
+        .and_then(move |name| {
+            self.api_low
+                .node_info()
+                .map_err(LnurlPayError::NodeInfo)
+                .map(move |info| {
+                    let address = lnurl::lightning_address(&name, &public_url);
+                    api_types::LnurlPayRequest {
+                        tag: api_types::LnurlTag::PayRequest,
+                        callback: UrlSerDe(lnurl::pay_callback(&name, &public_url)),
+                        min_sendable: MIN_SENDABLE_MSAT,
+                        max_sendable: info.capacity.inbound.0.saturating_mul(1000),
+                        metadata: lnurl::pay_metadata(&address),
+                    }
+                })
+        })
+        .then(move |res| to_user_result(res, &self.log))
+        .map(Into::into) // convert Result<_, _> to LnurlResponse<_>
+    }
+
+    /// An invoice for amount_msat to name's account, committing to the metadata of name's pay
+    /// request.
+    pub fn lnurl_pay_callback<'a>(
+        &'a self,
+        name: String,
+        query: api_types::LnurlPayCallbackQuery,
+        public_url: Url,
+    ) -> impl Future<Item = api_types::LnurlResponse<api_types::LnurlPayInvoice>, Error = ErrLogged>
+           + Send
+           + 'a {
+        let api_types::LnurlPayCallbackQuery {
+            amount: amount_msat,
+        } = query;
+        let amount = if amount_msat < MIN_SENDABLE_MSAT || amount_msat % 1000 != 0 {
+            Err(LnurlPayError::Amount)
+        } else {
+            Ok(Satoshis(amount_msat / 1000))
+        };
+        let name = name
+            .parse()
+            .map_err(|InvalidName| LnurlPayError::UnknownName);
+        FutureResult::from(amount.and_then(|amount| name.map(|name: Name| (amount, name))))
+            .and_then(move |(amount, name)| {
+                self.api_low
+                    .name_owner(&name)
+                    .map_err(|GetNameOwnerError::NameDoesNotExist| LnurlPayError::UnknownName)
+                    .and_then(move |owner| {
+                        let address = lnurl::lightning_address(&name, &public_url);
+                        let spec = InvoiceSpec::create_hashed(
+                            Some(amount),
+                            lnurl::pay_metadata(&address),
+                            crate::invoice::DEFAULT_EXPIRY,
+                        )
+                        .expect("hashed descriptions are never too long");
+                        self.api_low
+                            .generate_invoice(owner, spec)
+                            .map_err(LnurlPayError::Invoice)
+                    })
+            })
+            .map(|invoice| api_types::LnurlPayInvoice {
+                pr: InvoiceSerDe(invoice),
+                routes: Vec::new(),
+            })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to LnurlResponse<_>
+    }
+
+    /// Reserve funds from master's account for a voucher, answering with its LNURL-withdraw link
+    /// on the server reachable at public_url.
+    pub fn create_voucher<'a>(
+        &'a self,
+        request: api_types::CreateVoucherRequest,
+        public_url: Url,
+    ) -> impl Future<Item = api_types::CreateVoucherResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::CreateVoucherRequest {
+            master,
+            amount_satoshis,
+            fee_satoshis,
+            uses,
+            expiry_seconds,
+        } = request;
+        let expiry = expiry_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(lnurl::DEFAULT_VOUCHER_EXPIRY);
+        self.api_low
+            .create_voucher(
+                master,
+                amount_satoshis,
+                fee_satoshis,
+                uses.unwrap_or(1),
+                expiry,
+            )
+            .map(move |k1| api_types::CreateVoucherOk {
+                k1,
+                lnurl: lnurl::encode(&lnurl::withdraw_link(k1, &public_url)),
+            })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    /// The LNURL withdraw request for the voucher identified by k1.
+    pub fn lnurl_withdraw_request<'a>(
+        &'a self,
+        k1: U256,
+        public_url: Url,
+    ) -> impl Future<
+        Item = api_types::LnurlResponse<api_types::LnurlWithdrawRequest>,
+        Error = ErrLogged,
+    > + Send
+           + 'a {
+        self.api_low
+            .get_voucher(k1)
+            .map(move |voucher| api_types::LnurlWithdrawRequest {
+                tag: api_types::LnurlTag::WithdrawRequest,
+                callback: UrlSerDe(lnurl::withdraw_callback(&public_url)),
+                k1,
+                default_description: "Voucher".to_owned(),
+                min_withdrawable: MIN_SENDABLE_MSAT,
+                max_withdrawable: voucher.amount.0.saturating_mul(1000),
+            })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to LnurlResponse<_>
+    }
+
+    /// Pay the invoice a wallet submitted from the voucher identified by k1.
+    pub fn lnurl_withdraw_callback<'a>(
+        &'a self,
+        query: api_types::LnurlWithdrawCallbackQuery,
+    ) -> impl Future<Item = api_types::LnurlResponse<api_types::LnurlOk>, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::LnurlWithdrawCallbackQuery { k1, pr } = query;
+        self.api_low
+            .withdraw_voucher(k1, pr.0)
+            .map(|_outgoing| api_types::LnurlOk {
+                status: api_types::LnurlStatus::Ok,
+            })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to LnurlResponse<_>
+    }
+
+    /// Challenge a wallet to log in, answering with the challenge's LNURL-auth link on the server
+    /// reachable at public_url and the token of the session it starts.
+    pub fn create_auth_challenge<'a>(
+        &'a self,
+        request: api_types::AuthChallengeRequest,
+        public_url: Url,
+    ) -> impl Future<Item = api_types::AuthChallengeResponse, Error = ErrLogged> + Send + 'a {
+        let api_types::AuthChallengeRequest { link } = request;
+        self.api_low
+            .create_auth_challenge(link)
+            .map(move |(k1, challenge)| api_types::AuthChallengeResponse {
+                k1,
+                token: challenge.token,
+                lnurl: lnurl::encode(&lnurl::auth_link(k1, &challenge, &public_url)),
+            })
+            .map_err(move |err| err.log(&self.log))
+    }
+
+    /// A wallet's answer to a login challenge.
+    pub fn lnurl_auth<'a>(
+        &'a self,
+        query: api_types::LnurlAuthQuery,
+    ) -> impl Future<Item = api_types::LnurlResponse<api_types::LnurlOk>, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::LnurlAuthQuery { k1, sig, key } = query;
+        self.api_low
+            .answer_auth_challenge(k1, &sig.0, key.0)
+            .map(|()| api_types::LnurlOk {
+                status: api_types::LnurlStatus::Ok,
+            })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to LnurlResponse<_>
+    }
+
+    /// The balance of the account a session was started for.
+    pub fn check_balance_session<'a>(
+        &'a self,
+        token: U256,
+    ) -> impl Future<Item = api_types::SessionCheckBalanceResponse, Error = ErrLogged> + Send + 'a
+    {
+        self.api_low
+            .session(token)
+            .map_err(SessionOr::Session)
+            .and_then(move |capability| {
+                self.api_low
+                    .check_balance(capability.middle())
+                    .map_err(SessionOr::Other)
+            })
+            .map(|balance_satoshis| api_types::CheckBalanceOk { balance_satoshis })
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
+    /// Pay an invoice from the account of a session started with a Master.
+    pub fn pay_invoice_session<'a>(
+        &'a self,
+        token: U256,
+        request: api_types::SessionPayInvoiceRequest,
+    ) -> impl Future<Item = api_types::SessionPayInvoiceResponse, Error = ErrLogged> + Send + 'a
+    {
+        let api_types::SessionPayInvoiceRequest {
+            invoice,
+            amount_satoshis,
+            fee_satoshis,
+        } = request;
+        self.api_low
+            .session(token)
+            .and_then(|capability| capability.master().ok_or(SessionError::NotMaster))
+            .map_err(SessionOr::Session)
+            .and_then(move |master| {
+                self.api_low
+                    .pay_invoice(master, invoice.0, amount_satoshis, fee_satoshis)
+                    .map_err(SessionOr::Other)
+            })
+            .map(Into::into) // convert PaidInvoice to PayInvoiceOk
+            .then(move |res| to_user_result(res, &self.log))
+            .map(Into::into) // convert Result<_, _> to ResultSerDe<_, _>
+    }
+
     pub fn check_balance<'a>(
         &'a self,
         middle: Middle,
@@ -76,9 +540,11 @@ impl<D: Db, L: LightningNode, G: Log> ApiHigh<D, L, G> {
         loop_fn((), move |()| {
             self.api_low
                 .check_invoice_status(payment_hash)
-                .map(|status| match status {
-                    InvoiceStatus::Unpaid(_) => Loop::Continue(()),
-                    InvoiceStatus::Paid(paid_invoice) => Loop::Break(paid_invoice),
+                .map_err(AwaitInvoiceError::Status)
+                .and_then(|status| match status {
+                    InvoiceStatus::Unpaid(_) | InvoiceStatus::Accepted(_) => Ok(Loop::Continue(())),
+                    InvoiceStatus::Paid(paid_invoice) => Ok(Loop::Break(paid_invoice)),
+                    InvoiceStatus::Cancelled(_) => Err(AwaitInvoiceError::Cancelled),
                 })
         })
         .map(Into::into) // convert PaidInvoice to AwaitInvoiceOk
@@ -87,6 +553,52 @@ impl<D: Db, L: LightningNode, G: Log> ApiHigh<D, L, G> {
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum AwaitInvoiceError {
+    Status(CheckInvoiceStatusError),
+    /// The invoice will never be paid.
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub enum LnurlPayError {
+    /// The name is not registered, or is not a valid name.
+    UnknownName,
+    /// Not a whole number of satoshis, or less than MIN_SENDABLE_MSAT.
+    Amount,
+    NodeInfo(NodeInfoError),
+    Invoice(GenerateInvoiceError),
+}
+
+/// Errors of requests authorized by a session token.
+#[derive(Debug, Clone)]
+pub enum SessionOr<E> {
+    Session(SessionError),
+    Other(E),
+}
+
+/// Parameters common to invoice generating requests. A request with both a description and a
+/// description hash is answered with ConflictingDescription.
+fn to_invoice_spec(
+    satoshis: Option<Satoshis>,
+    description: Option<String>,
+    description_hash: Option<U256>,
+    expiry_seconds: Option<u64>,
+) -> Result<Result<InvoiceSpec, GenerateInvoiceError>, api_types::GenerateInvoiceResponse> {
+    let description = match (description, description_hash) {
+        (Some(_), Some(_)) => {
+            return Err(Err(api_types::GenerateInvoiceErr::ConflictingDescription(())).into());
+        }
+        (Some(memo), None) => Description::Direct(memo),
+        (None, Some(hash)) => Description::Hash(hash),
+        (None, None) => Description::Direct("".to_owned()),
+    };
+    let expiry = expiry_seconds
+        .map(Duration::from_secs)
+        .unwrap_or(crate::invoice::DEFAULT_EXPIRY);
+    Ok(InvoiceSpec::create(satoshis, description, expiry).map_err(GenerateInvoiceError::Invalid))
+}
+
 /// Extract and log server error from result if result is a server error.
 ///
 /// if result is a server error, log the server error to log and return Err(ErrLogged)
@@ -1,43 +1,246 @@
 use crate::common::*;
-use futures::future::FutureResult;
-use futures::stream::Stream;
+use crate::invoice::MAX_DESCRIPTION_BYTES;
+use crate::lnurl::{self, verify_auth, AUTH_CHALLENGE_EXPIRY, MAX_VOUCHER_EXPIRY, SESSION_EXPIRY};
+use crate::onchain::DEFAULT_CONFIRMATIONS;
+use crate::subscription::{supervise, Backoff, HealthMonitor};
+use futures::future::{Either, FutureResult};
 use futures::Future;
-use std::sync::Arc;
+use secp256k1::Signature;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, SystemTime};
+use url::Url;
 
-pub struct ApiLow<D: Db + 'static, L: LightningNode> {
+/// How often expired vouchers and sessions are looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct ApiLow<D: Db + 'static, L: LightningNode + 'static> {
     database: Arc<D>,
-    lighting_node: L,
+    lighting_node: Arc<L>,
+    paid_invoice_subscription: HealthMonitor,
+    received_keysend_subscription: HealthMonitor,
+    accepted_invoice_subscription: HealthMonitor,
+    incoming_transaction_subscription: HealthMonitor,
+    offer_invoice_subscription: HealthMonitor,
+    /// On-chain deposits are credited once they have this many confirmations.
+    required_confirmations: Arc<AtomicU32>,
+    /// Limits on splitting outgoing payments.
+    multi_path: Mutex<MultiPath>,
+    /// Only invoices for this network are paid.
+    network: Mutex<Network>,
+    /// Whether LNURL services on this machine or its private networks may be paid.
+    allow_private_lnurl: AtomicBool,
+    /// Background work, such as refunding expired vouchers, logs here.
+    log: Arc<dyn Log>,
 }
 
 impl<D: Db, L: LightningNode> ApiLow<D, L> {
     /// link lightning node to db
-    pub fn create(database: D, lighting_node: L) -> ApiLow<D, L> {
+    pub fn create<G: Log + 'static>(database: D, lighting_node: L, log: G) -> ApiLow<D, L> {
+        Self::create_with_backoff(database, lighting_node, log, Backoff::default())
+    }
+
+    /// link lightning node to db, reconnecting to the node's incoming payment streams according
+    /// to backoff
+    pub fn create_with_backoff<G: Log + 'static>(
+        database: D,
+        lighting_node: L,
+        log: G,
+        backoff: Backoff,
+    ) -> ApiLow<D, L> {
         let database = Arc::new(database);
+        let lighting_node = Arc::new(lighting_node);
+        let log = Arc::new(log);
 
         // spawn a new thread to take paid invoices from lightning node post them to database
         let db2 = database.clone();
-        let stream = lighting_node
-            .paid_invoices()
-            .map(move |paid_invoice| db2.receive_paid_invoice(paid_invoice));
-        thread::spawn(|| stream.for_each(|_| FutureResult::from(Ok(()))).wait());
+        let paid_invoice_subscription = supervise(
+            "paid_invoices",
+            Arc::downgrade(&lighting_node),
+            |node: &L| node.paid_invoices(),
+            move |paid_invoice| -> DynFut<(), ReceivePaidInvoiceErr> {
+                // The node may report an invoice again after the subscription is re-established.
+                Box::new(
+                    db2.receive_paid_invoice(paid_invoice)
+                        .or_else(|err| match err {
+                            ReceivePaidInvoiceErr::Duplicate(_) => Ok(()),
+                            other => Err(other),
+                        }),
+                )
+            },
+            log.clone(),
+            backoff,
+        );
+
+        // and another to credit incoming keysends
+        let db3 = database.clone();
+        let received_keysend_subscription = supervise(
+            "received_keysends",
+            Arc::downgrade(&lighting_node),
+            |node: &L| node.received_keysends(),
+            move |keysend| -> DynFut<(), ReceiveKeysendErr> {
+                // As with paid invoices, the node may report a keysend again.
+                Box::new(db3.receive_keysend(keysend).or_else(|err| match err {
+                    ReceiveKeysendErr::Duplicate(_) => Ok(()),
+                    other => Err(other),
+                }))
+            },
+            log.clone(),
+            backoff,
+        );
+
+        // and a third to mark hold invoices accepted once their payment arrives
+        let db4 = database.clone();
+        let accepted_invoice_subscription = supervise(
+            "accepted_invoices",
+            Arc::downgrade(&lighting_node),
+            |node: &L| node.accepted_invoices(),
+            move |payment_hash| -> DynFut<(), ReceiveAcceptedInvoiceErr> {
+                // The node reports an invoice accepted again if the subscription is
+                // re-established before the invoice is settled or cancelled.
+                Box::new(
+                    db4.receive_accepted_invoice(payment_hash)
+                        .or_else(|err| match err {
+                            ReceiveAcceptedInvoiceErr::NotUnpaid(_, InvoiceStatus::Accepted(_)) => {
+                                Ok(())
+                            }
+                            other => Err(other),
+                        }),
+                )
+            },
+            log.clone(),
+            backoff,
+        );
+
+        // and a fourth to credit on-chain deposits once they are buried deep enough
+        let db5 = database.clone();
+        let required_confirmations = Arc::new(AtomicU32::new(DEFAULT_CONFIRMATIONS));
+        let required = required_confirmations.clone();
+        let incoming_transaction_subscription = supervise(
+            "incoming_transactions",
+            Arc::downgrade(&lighting_node),
+            |node: &L| node.incoming_transactions(),
+            move |transaction| -> DynFut<(), ReceiveOnchainDepositErr> {
+                if transaction.confirmations < required.load(Ordering::Relaxed) {
+                    return Box::new(FutureResult::from(Ok(())));
+                }
+                // Outputs are reported again with every block. Outputs to the node's own
+                // change addresses belong to no account. Neither needs attention.
+                Box::new(
+                    db5.receive_onchain_deposit(transaction)
+                        .or_else(|err| match err {
+                            ReceiveOnchainDepositErr::Duplicate(_)
+                            | ReceiveOnchainDepositErr::NoMatch(_) => Ok(()),
+                            other => Err(other),
+                        }),
+                )
+            },
+            log.clone(),
+            backoff,
+        );
 
-        // TODO log errors from stream and from processing stream
+        // and a fifth to bind invoices issued for offers to the offer's account
+        let db6 = database.clone();
+        let offer_invoice_subscription = supervise(
+            "offer_invoices",
+            Arc::downgrade(&lighting_node),
+            |node: &L| node.offer_invoices(),
+            move |offer_invoice| db6.receive_offer_invoice(offer_invoice),
+            log.clone(),
+            backoff,
+        );
+
+        // and a last thread to refund expired vouchers and end expired sessions, starting with
+        // any which expired while lapi was down
+        let db7 = Arc::downgrade(&database);
+        let sweep_log: Arc<dyn Log> = log.clone();
+        thread::spawn(move || {
+            while let Some(database) = db7.upgrade() {
+                sweep_expired(&*database, &sweep_log);
+                drop(database);
+                thread::sleep(SWEEP_INTERVAL);
+            }
+        });
 
         ApiLow {
             database,
             lighting_node,
+            paid_invoice_subscription,
+            received_keysend_subscription,
+            accepted_invoice_subscription,
+            incoming_transaction_subscription,
+            offer_invoice_subscription,
+            required_confirmations,
+            multi_path: Mutex::new(MultiPath::default()),
+            network: Mutex::new(Network::default()),
+            allow_private_lnurl: AtomicBool::new(false),
+            log,
         }
     }
 
+    /// The network the node is on. Defaults to Network::Mainnet.
+    pub fn set_network(&self, network: Network) {
+        *self.network.lock().unwrap() = network;
+    }
+
+    /// Split outgoing payments over at most multi_path.max_parts routes, giving up on finding
+    /// routes after multi_path.timeout. Defaults to MultiPath::default().
+    pub fn set_multi_path(&self, multi_path: MultiPath) {
+        *self.multi_path.lock().unwrap() = multi_path;
+    }
+
+    /// Allow paying LNURL services on this machine or its private networks. Their urls come from
+    /// users, so by default they are refused rather than letting users probe those networks.
+    pub fn set_allow_private_lnurl(&self, allow: bool) {
+        self.allow_private_lnurl.store(allow, Ordering::Relaxed);
+    }
+
+    /// Credit on-chain deposits once they have this many confirmations. Defaults to
+    /// DEFAULT_CONFIRMATIONS. With 0, deposits are credited while still in the mempool.
+    pub fn set_required_confirmations(&self, confirmations: u32) {
+        self.required_confirmations
+            .store(confirmations, Ordering::Relaxed);
+    }
+
+    /// Health of the subscription which credits paid invoices to accounts. While unhealthy,
+    /// deposits are not being credited.
+    pub fn paid_invoice_subscription(&self) -> HealthMonitor {
+        self.paid_invoice_subscription.clone()
+    }
+
+    /// Health of the subscription which credits incoming keysends to accounts.
+    pub fn received_keysend_subscription(&self) -> HealthMonitor {
+        self.received_keysend_subscription.clone()
+    }
+
+    /// Health of the subscription which marks hold invoices as accepted.
+    pub fn accepted_invoice_subscription(&self) -> HealthMonitor {
+        self.accepted_invoice_subscription.clone()
+    }
+
+    /// Health of the subscription which credits on-chain deposits to accounts.
+    pub fn incoming_transaction_subscription(&self) -> HealthMonitor {
+        self.incoming_transaction_subscription.clone()
+    }
+
+    /// Health of the subscription which binds invoices issued for offers to accounts. While
+    /// unhealthy, payments to offers are not being credited.
+    pub fn offer_invoice_subscription(&self) -> HealthMonitor {
+        self.offer_invoice_subscription.clone()
+    }
+
     pub fn generate_invoice<'a>(
         &'a self,
         lesser: Lesser,
-        satoshis: Satoshis,
+        spec: InvoiceSpec,
     ) -> impl Future<Item = Invoice, Error = GenerateInvoiceError> + 'a {
-        self.lighting_node
-            .create_invoice(satoshis)
-            .map_err(GenerateInvoiceError::Create)
+        self.check_receivable(&spec)
+            .and_then(move |()| {
+                self.lighting_node
+                    .create_invoice(spec)
+                    .map_err(GenerateInvoiceError::Create)
+            })
             .and_then(move |invoice| {
                 // If the database is unable to store the invoice, we don't return it.
                 self.database
@@ -47,6 +250,141 @@ impl<D: Db, L: LightningNode> ApiLow<D, L> {
             })
     }
 
+    /// Refuse to invoice for more than the node can currently receive. Amountless invoices are
+    /// not checked, the payer chooses how much to send.
+    fn check_receivable(
+        &self,
+        spec: &InvoiceSpec,
+    ) -> impl Future<Item = (), Error = GenerateInvoiceError> {
+        let satoshis = match spec.satoshis() {
+            Some(satoshis) => satoshis,
+            None => return Either::A(FutureResult::from(Ok(()))),
+        };
+        Either::B(
+            self.lighting_node
+                .node_info()
+                .map_err(GenerateInvoiceError::NodeInfo)
+                .and_then(move |info| {
+                    if satoshis > info.capacity.inbound {
+                        Err(GenerateInvoiceError::ToLarge)
+                    } else {
+                        Ok(())
+                    }
+                }),
+        )
+    }
+
+    /// Generate a hold invoice for payment_hash. Only middle may settle or cancel it. When
+    /// settled, the payment is credited to middle's account.
+    pub fn generate_hold_invoice<'a>(
+        &'a self,
+        middle: Middle,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> impl Future<Item = Invoice, Error = GenerateInvoiceError> + 'a {
+        self.check_receivable(&spec)
+            .and_then(move |()| {
+                self.lighting_node
+                    .create_hold_invoice(spec, payment_hash)
+                    .map_err(GenerateInvoiceError::Create)
+            })
+            .and_then(move |invoice| {
+                self.database
+                    .store_unpaid_invoice(middle.into(), &invoice)
+                    .map_err(GenerateInvoiceError::Store)
+                    .map(|()| invoice)
+            })
+    }
+
+    /// Claim the payment held for a hold invoice owned by middle. The invoice is credited once
+    /// the node reports it paid.
+    pub fn settle_hold_invoice<'a>(
+        &'a self,
+        middle: Middle,
+        preimage: Preimage,
+    ) -> impl Future<Item = (), Error = ResolveHoldInvoiceError> + 'a {
+        self.owned_invoice_status(middle, preimage.hash())
+            .and_then(|status| match status {
+                InvoiceStatus::Accepted(_) => Ok(()),
+                other => Err(ResolveHoldInvoiceError::from(other)),
+            })
+            .and_then(move |()| {
+                self.lighting_node
+                    .settle_hold_invoice(preimage)
+                    .map_err(ResolveHoldInvoiceError::Node)
+            })
+    }
+
+    /// Cancel a hold invoice owned by middle, returning any held payment to the payer.
+    pub fn cancel_hold_invoice<'a>(
+        &'a self,
+        middle: Middle,
+        payment_hash: PaymentHash,
+    ) -> impl Future<Item = (), Error = ResolveHoldInvoiceError> + 'a {
+        self.owned_invoice_status(middle, payment_hash)
+            .and_then(|status| match status {
+                InvoiceStatus::Unpaid(_) | InvoiceStatus::Accepted(_) => Ok(()),
+                other => Err(ResolveHoldInvoiceError::from(other)),
+            })
+            .and_then(move |()| {
+                self.lighting_node
+                    .cancel_hold_invoice(payment_hash)
+                    .map_err(ResolveHoldInvoiceError::Node)
+            })
+            .and_then(move |()| {
+                self.database
+                    .cancel_invoice(payment_hash)
+                    .map_err(ResolveHoldInvoiceError::Cancel)
+            })
+    }
+
+    /// Cancel an unpaid invoice owned by middle, so it can no longer be paid. Hold invoices
+    /// may also be cancelled once their payment is held, as with cancel_hold_invoice.
+    pub fn cancel_invoice<'a>(
+        &'a self,
+        middle: Middle,
+        payment_hash: PaymentHash,
+    ) -> impl Future<Item = (), Error = ResolveHoldInvoiceError> + 'a {
+        self.owned_invoice_status(middle, payment_hash)
+            .and_then(|status| match status {
+                InvoiceStatus::Unpaid(_) | InvoiceStatus::Accepted(_) => Ok(()),
+                other => Err(ResolveHoldInvoiceError::from(other)),
+            })
+            .and_then(move |()| {
+                self.lighting_node
+                    .cancel_invoice(payment_hash)
+                    .map_err(ResolveHoldInvoiceError::Node)
+            })
+            .and_then(move |()| {
+                self.database
+                    .cancel_invoice(payment_hash)
+                    .map_err(ResolveHoldInvoiceError::Cancel)
+            })
+    }
+
+    /// Status of the invoice with payment_hash, provided it belongs to middle.
+    fn owned_invoice_status<'a>(
+        &'a self,
+        middle: Middle,
+        payment_hash: PaymentHash,
+    ) -> impl Future<Item = InvoiceStatus, Error = ResolveHoldInvoiceError> + 'a {
+        self.database
+            .get_invoice_owner(payment_hash)
+            .map_err(ResolveHoldInvoiceError::from)
+            .and_then(move |owner| {
+                if owner == Lesser::from(middle) {
+                    Ok(())
+                } else {
+                    Err(ResolveHoldInvoiceError::NotOwner)
+                }
+            })
+            .and_then(move |()| {
+                self.database
+                    .check_invoice_status(payment_hash)
+                    .map_err(ResolveHoldInvoiceError::from)
+            })
+    }
+
     pub fn pay_invoice<'a>(
         &'a self,
         master: Master,
@@ -54,6 +392,412 @@ impl<D: Db, L: LightningNode> ApiLow<D, L> {
         amount: Satoshis,
         fee: Fee<Satoshis>,
     ) -> impl Future<Item = PaidInvoiceOutgoing, Error = PayInvoiceError> + 'a {
+        if !self.network.lock().unwrap().accepts(&invoice) {
+            return Either::A(FutureResult::from(Err(PayInvoiceError::WrongNetwork)));
+        }
+        // invoices which ask for an amount are paid exactly that, rounded up to a whole satoshi
+        if let Some(pico) = invoice.amount_pico_btc() {
+            let requested = Satoshis::from_pico_btc(pico)
+                .unwrap_or_else(|NotDivisible { whole, change: _ }| whole + Satoshis(1));
+            if amount != requested {
+                return Either::A(FutureResult::from(Err(PayInvoiceError::AmountMismatch)));
+            }
+        }
+        let multi_path = *self.multi_path.lock().unwrap();
+        Either::B(self.spend(master, amount, fee, move || {
+            self.lighting_node
+                .pay_invoice(invoice, amount, fee, multi_path)
+        }))
+    }
+
+    /// Pay amount to the LNURL-pay service whose pay request is at link, e.g. a Lightning
+    /// Address. The invoice the service issues must be for exactly amount and commit to the pay
+    /// request's metadata, it is then paid like any other. Services on private addresses are
+    /// refused, see set_allow_private_lnurl.
+    pub fn pay_lnurl<'a>(
+        &'a self,
+        master: Master,
+        link: Url,
+        amount: Satoshis,
+        fee: Fee<Satoshis>,
+    ) -> impl Future<Item = PaidInvoiceOutgoing, Error = PayLnurlError> + 'a {
+        let (amount_msat, amount_pico_btc) = match amount
+            .0
+            .checked_mul(1000)
+            .and_then(|msat| Some((msat, msat.checked_mul(10)?)))
+        {
+            Some(amounts) => amounts,
+            None => return Either::A(FutureResult::from(Err(PayLnurlError::AmountOutOfRange))),
+        };
+        let allow_private = self.allow_private_lnurl.load(Ordering::Relaxed);
+        Either::B(
+            lnurl::fetch_pay_request(link, allow_private)
+                .map_err(PayLnurlError::Resolve)
+                .and_then(move |request| {
+                    if amount_msat < request.min_sendable || amount_msat > request.max_sendable {
+                        Err(PayLnurlError::AmountOutOfRange)
+                    } else {
+                        Ok(request)
+                    }
+                })
+                .and_then(move |request| {
+                    lnurl::fetch_invoice(&request.callback.0, amount_msat, allow_private)
+                        .map_err(PayLnurlError::Resolve)
+                        .and_then(move |invoice| {
+                            let hash = lnurl::metadata_hash(&request.metadata);
+                            if get_description(&invoice) != Description::Hash(hash)
+                                || invoice.amount_pico_btc() != Some(amount_pico_btc)
+                            {
+                                Err(PayLnurlError::InvoiceMismatch)
+                            } else {
+                                Ok(invoice)
+                            }
+                        })
+                })
+                .and_then(move |invoice| {
+                    self.pay_invoice(master, invoice, amount, fee)
+                        .map_err(PayLnurlError::Pay)
+                }),
+        )
+    }
+
+    /// Pay amount directly to the node identified by pubkey.
+    pub fn keysend<'a>(
+        &'a self,
+        master: Master,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        fee: Fee<Satoshis>,
+    ) -> impl Future<Item = KeysendOutgoing, Error = PayInvoiceError> + 'a {
+        self.spend(master, amount, fee, move || {
+            self.lighting_node.keysend(pubkey, amount, fee)
+        })
+    }
+
+    /// Generate an on-chain address for lesser. Deposits to it are credited to lesser's account
+    /// once confirmed.
+    pub fn new_address<'a>(
+        &'a self,
+        lesser: Lesser,
+    ) -> impl Future<Item = Address, Error = GenerateAddressError> + 'a {
+        self.lighting_node
+            .new_address()
+            .map_err(GenerateAddressError::Node)
+            .and_then(move |address| {
+                // An address is only handed out once deposits to it can be credited.
+                self.database
+                    .store_address(lesser, &address)
+                    .map_err(GenerateAddressError::Store)
+                    .map(|()| address)
+            })
+    }
+
+    /// Create a BOLT12 offer for lesser. Payment for every invoice issued for it is credited to
+    /// lesser's account.
+    pub fn create_offer<'a>(
+        &'a self,
+        lesser: Lesser,
+        spec: OfferSpec,
+    ) -> impl Future<Item = Offer, Error = GenerateOfferError> + 'a {
+        // invoices for the offer carry its description
+        if spec.description.len() > MAX_DESCRIPTION_BYTES {
+            return Either::A(FutureResult::from(Err(
+                GenerateOfferError::DescriptionTooLong,
+            )));
+        }
+        Either::B(
+            self.lighting_node
+                .create_offer(spec)
+                .map_err(GenerateOfferError::Node)
+                .and_then(move |offer| {
+                    // An offer is only handed out once its invoices can be credited.
+                    self.database
+                        .store_offer(lesser, offer.id)
+                        .map_err(GenerateOfferError::Store)
+                        .map(|()| offer)
+                }),
+        )
+    }
+
+    /// Register name to middle's account. Payments to the name's Lightning Address are credited
+    /// to it.
+    pub fn register_name<'a>(
+        &'a self,
+        middle: Middle,
+        name: Name,
+    ) -> impl Future<Item = (), Error = StoreNameError> + 'a {
+        self.database.store_name(middle.into(), &name)
+    }
+
+    /// The account payments to name are credited to.
+    pub fn name_owner<'a>(
+        &'a self,
+        name: &Name,
+    ) -> impl Future<Item = Lesser, Error = GetNameOwnerError> + 'a {
+        self.database.get_name_owner(name)
+    }
+
+    /// Reserve uses × (amount + fee) from master's account for a voucher. Each use may withdraw
+    /// up to amount, paying up to fee in routing fees. Once the voucher is used up or expires,
+    /// whatever is left of the reservation is refunded; expired vouchers are refunded within
+    /// SWEEP_INTERVAL. Returns k1, which identifies the voucher.
+    pub fn create_voucher<'a>(
+        &'a self,
+        master: Master,
+        amount: Satoshis,
+        fee: Fee<Satoshis>,
+        uses: u32,
+        expiry: Duration,
+    ) -> impl Future<Item = U256, Error = CreateVoucherError> + 'a {
+        let voucher = Some(expiry)
+            .filter(|expiry| *expiry <= MAX_VOUCHER_EXPIRY)
+            .and_then(|expiry| SystemTime::now().checked_add(expiry))
+            .ok_or(CreateVoucherError::ExpiryOutOfRange)
+            .and_then(|expires_at| {
+                if uses == 0 {
+                    return Err(CreateVoucherError::NoUses);
+                }
+                let voucher = Voucher {
+                    account: Master::random(),
+                    owner: master.into(),
+                    amount,
+                    fee,
+                    uses,
+                    expires_at,
+                };
+                // An overflowing reservation could never be covered.
+                let reservation = voucher
+                    .reservation()
+                    .ok_or(CreateVoucherError::InsufficientBalance)?;
+                Ok((voucher, reservation))
+            });
+        FutureResult::from(voucher)
+            .and_then(move |(voucher, reservation)| {
+                self.database
+                    .withdraw(master, reservation)
+                    .map_err(|WithdrawalError::InsufficeintBalance| {
+                        CreateVoucherError::InsufficientBalance
+                    })
+                    .and_then(move |()| {
+                        self.database
+                            .deposit(voucher.account.into(), reservation)
+                            .map_err(CreateVoucherError::Reserve)
+                            .map(|()| voucher)
+                    })
+            })
+            .and_then(move |voucher| {
+                let k1 = U256::random();
+                self.database
+                    .store_voucher(k1, &voucher)
+                    .map_err(CreateVoucherError::Store)
+                    .map(move |()| k1)
+            })
+    }
+
+    /// The voucher identified by k1, as long as it can still be withdrawn from.
+    pub fn get_voucher<'a>(
+        &'a self,
+        k1: U256,
+    ) -> impl Future<Item = Voucher, Error = WithdrawVoucherError> + 'a {
+        self.database
+            .get_voucher(k1)
+            .map_err(|GetVoucherError::VoucherDoesNotExist| WithdrawVoucherError::NoSuchVoucher)
+            .and_then(|voucher| {
+                if voucher.is_expired() {
+                    Err(WithdrawVoucherError::Expired)
+                } else if voucher.uses == 0 {
+                    Err(WithdrawVoucherError::UsedUp)
+                } else {
+                    Ok(voucher)
+                }
+            })
+    }
+
+    /// Pay invoice from the voucher identified by k1, exactly as pay_invoice would from an
+    /// account. A use is counted once the payment succeeds, so failed payments may be retried.
+    /// Concurrent withdrawals may overrun the count, but never the reservation.
+    pub fn withdraw_voucher<'a>(
+        &'a self,
+        k1: U256,
+        invoice: Invoice,
+    ) -> impl Future<Item = PaidInvoiceOutgoing, Error = WithdrawVoucherError> + 'a {
+        // round amount requested up to the nearest whole satoshi
+        let amount = invoice.amount_pico_btc().map(|pico| {
+            Satoshis::from_pico_btc(pico)
+                .unwrap_or_else(|NotDivisible { whole, change: _ }| whole + Satoshis(1))
+        });
+        self.get_voucher(k1)
+            .and_then(move |voucher| match amount {
+                Some(amount) if amount <= voucher.amount => Ok((voucher, amount)),
+                _ => Err(WithdrawVoucherError::Amount),
+            })
+            .and_then(move |(voucher, amount)| {
+                self.pay_invoice(voucher.account, invoice, amount, voucher.fee)
+                    .map_err(WithdrawVoucherError::Pay)
+            })
+            .and_then(move |outgoing| {
+                self.database.use_voucher(k1).then(move |uses| match uses {
+                    Ok(0) => Either::A(
+                        refund_voucher(&*self.database, k1)
+                            .map_err(WithdrawVoucherError::Refund)
+                            .map(|_refunded| outgoing),
+                    ),
+                    // Either uses are left, or the voucher expired and was refunded during
+                    // the payment.
+                    Ok(_) | Err(GetVoucherError::VoucherDoesNotExist) => {
+                        Either::B(FutureResult::from(Ok(outgoing)))
+                    }
+                })
+            })
+    }
+
+    /// Challenge a wallet to log in (LUD-04). With link, the wallet's key is linked to that
+    /// capability, otherwise the key must have been linked before. Returns k1, for the wallet
+    /// to sign, and the token of the session started once it has.
+    pub fn create_auth_challenge<'a>(
+        &'a self,
+        link: Option<Capability>,
+    ) -> impl Future<Item = (U256, AuthChallenge), Error = StoreAuthChallengeError> + 'a {
+        let k1 = U256::random();
+        let challenge = AuthChallenge {
+            token: U256::random(),
+            link,
+            expires_at: SystemTime::now() + AUTH_CHALLENGE_EXPIRY,
+        };
+        self.database
+            .store_auth_challenge(k1, &challenge)
+            .map(move |()| (k1, challenge))
+    }
+
+    /// Start the session of the challenge identified by k1, once sig proves the wallet holds
+    /// linking_key. Each challenge may be answered once.
+    pub fn answer_auth_challenge<'a>(
+        &'a self,
+        k1: U256,
+        sig: &Signature,
+        linking_key: PublicKey,
+    ) -> impl Future<Item = (), Error = AnswerAuthError> + 'a {
+        if !verify_auth(k1, sig, &linking_key) {
+            return Either::A(FutureResult::from(Err(AnswerAuthError::BadSignature)));
+        }
+        Either::B(
+            self.database
+                .take_auth_challenge(k1)
+                .map_err(|TakeAuthChallengeError::ChallengeDoesNotExist| {
+                    AnswerAuthError::NoSuchChallenge
+                })
+                .and_then(|challenge| {
+                    if challenge.expires_at <= SystemTime::now() {
+                        Err(AnswerAuthError::Expired)
+                    } else {
+                        Ok(challenge)
+                    }
+                })
+                .and_then(move |challenge| {
+                    let capability: DynFut<Capability, AnswerAuthError> = match challenge.link {
+                        Some(capability) => Box::new(
+                            self.database
+                                .store_linking_key(&linking_key, capability)
+                                .map_err(|StoreLinkingKeyError::EntryAlreadyExists(_)| {
+                                    AnswerAuthError::AlreadyLinked
+                                })
+                                .map(move |()| capability),
+                        ),
+                        None => {
+                            Box::new(self.database.get_linked_capability(&linking_key).map_err(
+                                |GetLinkedCapabilityError::KeyNotLinked| {
+                                    AnswerAuthError::UnknownKey
+                                },
+                            ))
+                        }
+                    };
+                    capability.map(move |capability| (challenge.token, capability))
+                })
+                .and_then(move |(token, capability)| {
+                    let session = Session {
+                        capability,
+                        expires_at: SystemTime::now() + SESSION_EXPIRY,
+                    };
+                    self.database
+                        .store_session(token, &session)
+                        .map_err(AnswerAuthError::Store)
+                }),
+        )
+    }
+
+    /// The capability a session was started with.
+    pub fn session<'a>(
+        &'a self,
+        token: U256,
+    ) -> impl Future<Item = Capability, Error = SessionError> + 'a {
+        self.database
+            .get_session(token)
+            .map_err(|GetSessionError::SessionDoesNotExist| SessionError::NoSession)
+            .and_then(|session| {
+                if session.expires_at <= SystemTime::now() {
+                    Err(SessionError::Expired)
+                } else {
+                    Ok(session.capability)
+                }
+            })
+    }
+
+    /// Send amount on-chain to address from master's account. The miner fee is estimated for
+    /// confirmation within target_blocks blocks and withdrawn along with amount; whatever the
+    /// node does not spend of it is refunded. Addresses recognisably for another network are
+    /// refused before anything is withdrawn.
+    pub fn withdraw_onchain<'a>(
+        &'a self,
+        master: Master,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> impl Future<Item = OnchainOutgoing, Error = WithdrawOnchainError> + 'a {
+        if !self.network.lock().unwrap().accepts_address(&address) {
+            return Either::A(FutureResult::from(Err(WithdrawOnchainError::WrongNetwork)));
+        }
+        Either::B(
+            self.lighting_node
+                .estimate_onchain_fee(address.clone(), amount, target_blocks)
+                .map_err(WithdrawOnchainError::Estimate)
+                .and_then(move |fee| {
+                    self.spend(master, amount, fee, move || {
+                        self.lighting_node
+                            .send_onchain(address, amount, target_blocks, fee)
+                    })
+                    .map_err(WithdrawOnchainError::Spend)
+                }),
+        )
+    }
+
+    /// Identity and capacity of the lightning node.
+    pub fn node_info(&self) -> impl Future<Item = NodeInfo, Error = NodeInfoError> {
+        self.lighting_node.node_info()
+    }
+
+    /// Estimate the fee for paying amount to invoice. Nothing is paid or withdrawn.
+    pub fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> impl Future<Item = FeeQuote, Error = EstimateFeeError> {
+        self.lighting_node.estimate_fee(invoice, amount)
+    }
+
+    /// Withdraw amount + fee from master's account, then attempt the payment. If the payment is
+    /// aborted, or the node can't make it, the withdrawal is refunded. If the payment succeeds,
+    /// unused fees are refunded.
+    fn spend<'a, T, P>(
+        &'a self,
+        master: Master,
+        amount: Satoshis,
+        fee: Fee<Satoshis>,
+        pay: P,
+    ) -> impl Future<Item = T, Error = PayInvoiceError> + 'a
+    where
+        T: Outgoing + Send + 'static,
+        P: FnOnce() -> DynFut<T, PayError> + Send + 'a,
+    {
         // This function is a tangled bundle of future combinators, sorely in need of async await syntax.
 
         // A malicious client may attempt to send large values for amount and fee, inducing an add overflow.
@@ -70,40 +814,30 @@ impl<D: Db, L: LightningNode> ApiLow<D, L> {
                     .map(move |_| total_withdrawal)
             })
             .and_then(move |total_withdrawal| {
-                self.lighting_node
-                    .pay_invoice(invoice, amount, fee)
-                    .or_else(
-                        move |payerr| -> Box<
-                            Future<Item = PaidInvoiceOutgoing, Error = PayInvoiceError> + Send,
-                        > {
-                            match payerr {
-                                // payment failed, refund entire transaction
-                                PayError::PaymentAborted => Box::new(
-                                    self.database
-                                        .deposit(master.into(), total_withdrawal)
-                                        .map_err(PayInvoiceError::Refund)
-                                        .and_then(|()| {
-                                            Err(PayInvoiceError::Pay(PayError::PaymentAborted))
-                                        }),
-                                ),
-                                other => {
-                                    Box::new(FutureResult::from(Err(PayInvoiceError::Pay(other))))
-                                }
-                            }
-                        },
-                    )
+                pay().or_else(
+                    move |payerr| -> Box<Future<Item = T, Error = PayInvoiceError> + Send> {
+                        if payerr.nothing_sent() {
+                            // payment failed, refund entire transaction
+                            Box::new(
+                                self.database
+                                    .deposit(master.into(), total_withdrawal)
+                                    .map_err(PayInvoiceError::Refund)
+                                    .and_then(|()| Err(PayInvoiceError::Pay(payerr))),
+                            )
+                        } else {
+                            Box::new(FutureResult::from(Err(PayInvoiceError::Pay(payerr))))
+                        }
+                    },
+                )
             })
-            .and_then(move |paid_invoice_outgoing| {
+            .and_then(move |outgoing| {
                 /// payment succeeded, refund any unused fees
-                debug_assert_eq!(fee, paid_invoice_outgoing.fees_offered);
-                debug_assert!(
-                    paid_invoice_outgoing.fees_offered >= paid_invoice_outgoing.fees_paid
-                );
-                let change: Fee<Satoshis> =
-                    paid_invoice_outgoing.fees_offered - paid_invoice_outgoing.fees_paid;
+                debug_assert_eq!(fee, outgoing.fees_offered());
+                debug_assert!(outgoing.fees_offered() >= outgoing.fees_paid());
+                let change: Fee<Satoshis> = outgoing.fees_offered() - outgoing.fees_paid();
                 self.database
                     .deposit(master.into(), change.0)
-                    .map(|()| paid_invoice_outgoing)
+                    .map(|()| outgoing)
                     .map_err(PayInvoiceError::RefundFee)
             })
     }
@@ -123,15 +857,231 @@ impl<D: Db, L: LightningNode> ApiLow<D, L> {
     }
 }
 
+/// A successful outgoing payment.
+trait Outgoing {
+    fn fees_offered(&self) -> Fee<Satoshis>;
+    fn fees_paid(&self) -> Fee<Satoshis>;
+}
+
+/// Refund every voucher that has expired, and forget expired sessions and auth challenges.
+fn sweep_expired<D: Db>(database: &D, log: &Arc<dyn Log>) {
+    let now = SystemTime::now();
+    let expired = match database
+        .remove_expired_sessions(now)
+        .and_then(|()| database.expired_vouchers(now))
+        .wait()
+    {
+        Ok(expired) => expired,
+        Err(never) => match never {},
+    };
+    for k1 in expired {
+        if let Err(err) = refund_voucher(database, k1).wait() {
+            err.log(log);
+        }
+    }
+}
+
+/// Remove the voucher identified by k1 and return whatever is left of its reservation to its
+/// owner. A voucher that was already removed has nothing left to refund.
+fn refund_voucher<D: Db>(
+    database: &D,
+    k1: U256,
+) -> impl Future<Item = Satoshis, Error = RefundVoucherError> + '_ {
+    database
+        .remove_voucher(k1)
+        .then(move |removed| match removed {
+            Err(GetVoucherError::VoucherDoesNotExist) => {
+                Either::A(FutureResult::from(Ok(Satoshis(0))))
+            }
+            Ok(voucher) => Either::B(
+                database
+                    .check_balance(voucher.account.into())
+                    .or_else(|CheckBalanceError::NoBalance| Ok(Satoshis(0)))
+                    .and_then(move |left| {
+                        database
+                            .withdraw(voucher.account, left)
+                            .map_err(RefundVoucherError::Withdraw)
+                            .map(move |()| (voucher, left))
+                    })
+                    .and_then(move |(voucher, left)| {
+                        database
+                            .deposit(voucher.owner, left)
+                            .map_err(RefundVoucherError::Deposit)
+                            .map(move |()| left)
+                    }),
+            ),
+        })
+}
+
+impl Outgoing for PaidInvoiceOutgoing {
+    fn fees_offered(&self) -> Fee<Satoshis> {
+        self.fees_offered
+    }
+
+    fn fees_paid(&self) -> Fee<Satoshis> {
+        self.fees_paid
+    }
+}
+
+impl Outgoing for KeysendOutgoing {
+    fn fees_offered(&self) -> Fee<Satoshis> {
+        self.fees_offered
+    }
+
+    fn fees_paid(&self) -> Fee<Satoshis> {
+        self.fees_paid
+    }
+}
+
+impl Outgoing for OnchainOutgoing {
+    fn fees_offered(&self) -> Fee<Satoshis> {
+        self.fees_offered
+    }
+
+    fn fees_paid(&self) -> Fee<Satoshis> {
+        self.fees_paid
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum GenerateInvoiceError {
+    /// The requested invoice parameters were rejected before contacting the lightning node.
+    Invalid(InvoiceSpecInvalid),
+    /// The amount is more than the node's channels can currently receive.
+    ToLarge,
+    /// Capacity could not be checked.
+    NodeInfo(NodeInfoError),
     Create(CreateInvoiceError),
     Store(StoreInvoiceError),
 }
 
+#[derive(Debug, Clone)]
+pub enum GenerateAddressError {
+    Node(NewAddressError),
+    Store(StoreAddressError),
+}
+
+#[derive(Debug, Clone)]
+pub enum GenerateOfferError {
+    /// Invoices issued for the offer could not carry its description.
+    DescriptionTooLong,
+    Node(CreateOfferError),
+    Store(StoreOfferError),
+}
+
+#[derive(Debug, Clone)]
+pub enum CreateVoucherError {
+    /// The account can't cover every use, or the reservation overflows.
+    InsufficientBalance,
+    NoUses,
+    ExpiryOutOfRange,
+    /// Funds were withdrawn from the account, but could not be moved to the voucher's.
+    Reserve(DepositError),
+    Store(StoreVoucherError),
+}
+
+#[derive(Debug)]
+pub enum PayLnurlError {
+    /// The pay request or the invoice could not be fetched.
+    Resolve(ResolveError),
+    /// The service does not accept amount.
+    AmountOutOfRange,
+    /// The service's invoice is for another amount, or does not commit to its metadata.
+    InvoiceMismatch,
+    Pay(PayInvoiceError),
+}
+
+#[derive(Debug, Clone)]
+pub enum WithdrawVoucherError {
+    NoSuchVoucher,
+    Expired,
+    UsedUp,
+    /// The invoice has no amount, or asks for more than a use may withdraw.
+    Amount,
+    Pay(PayInvoiceError),
+    /// The payment succeeded, but the rest of the used up voucher could not be refunded.
+    Refund(RefundVoucherError),
+}
+
+#[derive(Debug, Clone)]
+pub enum RefundVoucherError {
+    /// The voucher's funds were spent while being refunded.
+    Withdraw(WithdrawalError),
+    Deposit(DepositError),
+}
+
+#[derive(Debug, Clone)]
+pub enum AnswerAuthError {
+    /// The signature is not of k1 by the linking key.
+    BadSignature,
+    /// No challenge has this k1, or it was already answered.
+    NoSuchChallenge,
+    Expired,
+    /// The key was linked before, and can't be linked again.
+    AlreadyLinked,
+    /// The key is not linked to any account.
+    UnknownKey,
+    Store(StoreSessionError),
+}
+
+#[derive(Debug, Clone)]
+pub enum SessionError {
+    NoSession,
+    Expired,
+    /// The session can only view the balance.
+    NotMaster,
+}
+
+#[derive(Debug, Clone)]
+pub enum ResolveHoldInvoiceError {
+    NoSuchInvoice,
+    /// The invoice belongs to another account.
+    NotOwner,
+    /// Payment has not arrived yet, there is nothing to settle.
+    NotAccepted,
+    AlreadyPaid,
+    AlreadyCancelled,
+    Node(HoldInvoiceError),
+    /// The node cancelled the invoice, but the database could not record it.
+    Cancel(CancelInvoiceError),
+}
+
+impl From<CheckInvoiceStatusError> for ResolveHoldInvoiceError {
+    fn from(other: CheckInvoiceStatusError) -> Self {
+        match other {
+            CheckInvoiceStatusError::InvoiceDoesNotExist => ResolveHoldInvoiceError::NoSuchInvoice,
+        }
+    }
+}
+
+impl From<InvoiceStatus> for ResolveHoldInvoiceError {
+    /// The error for an invoice which is not in the status an operation expects.
+    fn from(other: InvoiceStatus) -> Self {
+        match other {
+            InvoiceStatus::Unpaid(_) => ResolveHoldInvoiceError::NotAccepted,
+            InvoiceStatus::Accepted(_) => ResolveHoldInvoiceError::NotAccepted,
+            InvoiceStatus::Paid(_) => ResolveHoldInvoiceError::AlreadyPaid,
+            InvoiceStatus::Cancelled(_) => ResolveHoldInvoiceError::AlreadyCancelled,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WithdrawOnchainError {
+    /// The address is for a network other than the node's. Nothing was withdrawn.
+    WrongNetwork,
+    /// Nothing was withdrawn.
+    Estimate(EstimateOnchainFeeError),
+    Spend(PayInvoiceError),
+}
+
 #[derive(Debug, Clone)]
 pub enum PayInvoiceError {
     InsufficientBalance,
+    /// The invoice is for a network other than the node's. Nothing was withdrawn.
+    WrongNetwork,
+    /// The invoice asks for an amount other than the one given. Nothing was withdrawn.
+    AmountMismatch,
     Pay(PayError),
     /// Payment failed, but balance was not refuned due to numerical overflow.
     Refund(DepositError),
@@ -142,6 +1092,7 @@ pub enum PayInvoiceError {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::fake_log::MemLog;
     use crate::test_util::*;
 
     fn assert_valid_paid(paid: PaidInvoice, original: Invoice, amount_paid: Satoshis) {
@@ -152,19 +1103,19 @@ mod test {
     fn assert_paid(is: InvoiceStatus) -> PaidInvoice {
         match is {
             InvoiceStatus::Paid(iv) => iv,
-            InvoiceStatus::Unpaid(_) => panic!(),
+            other => panic!("{:?}", other),
         }
     }
 
     fn assert_unpaid(is: InvoiceStatus) -> Invoice {
         match is {
-            InvoiceStatus::Paid(_) => panic!(),
             InvoiceStatus::Unpaid(iv) => iv,
+            other => panic!("{:?}", other),
         }
     }
 
     fn generate_invoice<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
-        api.generate_invoice(Master::random().into(), Satoshis(1))
+        api.generate_invoice(Master::random().into(), Satoshis(1).into())
             .wait()
             .unwrap();
     }
@@ -172,7 +1123,7 @@ mod test {
     fn pay_invoice<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
         let master = Master::random();
         let invoice = api
-            .generate_invoice(master.into(), Satoshis(1))
+            .generate_invoice(master.into(), Satoshis(1).into())
             .wait()
             .unwrap();
         api.pay_invoice(ACCOUNT_A, invoice, Satoshis(1), Fee(Satoshis(10)))
@@ -190,12 +1141,12 @@ mod test {
         );
 
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(2))
+            .generate_invoice(acct_b.into(), Satoshis(2).into())
             .wait()
             .unwrap();
 
         // pay invoice of n satoshis to lesser
-        api.pay_invoice(ACCOUNT_A, invoice, Satoshis(3), DEFAULT_FEE)
+        api.pay_invoice(ACCOUNT_A, invoice, Satoshis(2), DEFAULT_FEE)
             .wait()
             .unwrap();
 
@@ -204,7 +1155,7 @@ mod test {
             api.check_balance(acct_b.into())
                 .wait()
                 .expect("balance was not updated"),
-            Satoshis(3)
+            Satoshis(2)
         );
     }
 
@@ -213,10 +1164,14 @@ mod test {
         let ApiLow {
             lighting_node,
             database,
+            ..
         } = api;
 
         // create invoice for n satoshis, this invoice is not yet associated with an account
-        let invoice = lighting_node.create_invoice(Satoshis(2)).wait().unwrap();
+        let invoice = lighting_node
+            .create_invoice(Satoshis(2).into())
+            .wait()
+            .unwrap();
 
         // assert invoice status NonExistent
         assert_eq!(
@@ -229,7 +1184,12 @@ mod test {
 
         // pay invoice
         lighting_node
-            .pay_invoice(invoice.clone(), Satoshis(3), DEFAULT_FEE)
+            .pay_invoice(
+                invoice.clone(),
+                Satoshis(3),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
             .wait()
             .unwrap();
 
@@ -259,7 +1219,7 @@ mod test {
 
         // create invoice for n satoshis
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(2))
+            .generate_invoice(acct_b.into(), Satoshis(2).into())
             .wait()
             .unwrap();
 
@@ -271,7 +1231,7 @@ mod test {
         );
 
         // pay invoice
-        api.pay_invoice(ACCOUNT_A, invoice.clone(), Satoshis(3), DEFAULT_FEE)
+        api.pay_invoice(ACCOUNT_A, invoice.clone(), Satoshis(2), DEFAULT_FEE)
             .wait()
             .unwrap();
 
@@ -283,13 +1243,13 @@ mod test {
                     .unwrap(),
             ),
             invoice,
-            Satoshis(3),
+            Satoshis(2),
         );
 
         // assert balance for lesser is n
         assert_eq!(
             api.check_balance(acct_b.into()).wait().unwrap(),
-            Satoshis(3)
+            Satoshis(2)
         );
     }
 
@@ -311,12 +1271,12 @@ mod test {
         // create untracked {A,B}invoice for n satoshis
         let ai = api
             .lighting_node
-            .create_invoice(Satoshis(1))
+            .create_invoice(Satoshis(1).into())
             .wait()
             .unwrap();
         let bi = api
             .lighting_node
-            .create_invoice(Satoshis(1))
+            .create_invoice(Satoshis(1).into())
             .wait()
             .unwrap();
 
@@ -390,7 +1350,7 @@ mod test {
     fn pay_two<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
         let acct_b = Master::random();
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(1))
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
             .wait()
             .unwrap();
         api.pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
@@ -401,7 +1361,7 @@ mod test {
             Satoshis(1)
         );
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(2))
+            .generate_invoice(acct_b.into(), Satoshis(2).into())
             .wait()
             .unwrap();
         api.pay_invoice(ACCOUNT_A, invoice, Satoshis(2), DEFAULT_FEE)
@@ -417,7 +1377,7 @@ mod test {
     fn pay_twice<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
         let acct_b = Master::random();
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(1))
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
             .wait()
             .unwrap();
         api.pay_invoice(ACCOUNT_A, invoice.clone(), Satoshis(1), DEFAULT_FEE)
@@ -427,12 +1387,12 @@ mod test {
             api.check_balance(acct_b.into()).wait().unwrap(),
             Satoshis(1)
         );
-        api.pay_invoice(ACCOUNT_A, invoice, Satoshis(2), DEFAULT_FEE)
+        api.pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
             .wait()
             .unwrap();
         assert_eq!(
             api.check_balance(acct_b.into()).wait().unwrap(),
-            Satoshis(3)
+            Satoshis(2)
         );
     }
 
@@ -445,13 +1405,14 @@ mod test {
 
         let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
         let invoice = api
-            .generate_invoice(acct_b.into(), Satoshis(1))
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
             .wait()
             .unwrap();
         let PaidInvoiceOutgoing {
             paid_invoice,
             fees_offered,
             fees_paid,
+            ..
         } = api
             .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
             .wait()
@@ -477,13 +1438,14 @@ mod test {
 
         let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
         let invoice = api
-            .generate_invoice(ACCOUNT_A.into(), Satoshis(1))
+            .generate_invoice(ACCOUNT_A.into(), Satoshis(1).into())
             .wait()
             .unwrap();
         let PaidInvoiceOutgoing {
             paid_invoice,
             fees_offered,
             fees_paid,
+            ..
         } = api
             .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
             .wait()
@@ -498,13 +1460,14 @@ mod test {
     fn unused_fees_are_refunded<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
         let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
         let invoice = api
-            .generate_invoice(Master::random().into(), Satoshis(1))
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
             .wait()
             .unwrap();
         let PaidInvoiceOutgoing {
             paid_invoice,
             fees_offered,
             fees_paid,
+            ..
         } = api
             .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
             .wait()
@@ -517,6 +1480,989 @@ mod test {
         assert_eq!(initial_a_balance, final_a_balance + fees_paid.0);
     }
 
+    /// Generate an invoice without an amount, the payer's choice of amount is credited.
+    fn amountless_invoice<D: Db, L: LightningNode>(api: ApiLow<D, L>) {
+        let acct_b = Master::random();
+        let spec = InvoiceSpec::create(
+            None,
+            Description::Direct("tip jar".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .unwrap();
+        let invoice = api.generate_invoice(acct_b.into(), spec).wait().unwrap();
+        assert_eq!(invoice.amount_pico_btc(), None);
+        api.pay_invoice(ACCOUNT_A, invoice.clone(), Satoshis(7), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_valid_paid(
+            assert_paid(
+                api.check_invoice_status(get_payment_hash(&invoice))
+                    .wait()
+                    .unwrap(),
+            ),
+            invoice,
+            Satoshis(7),
+        );
+        assert_eq!(
+            api.check_balance(acct_b.into()).wait().unwrap(),
+            Satoshis(7)
+        );
+    }
+
+    fn fake_api() -> ApiLow<FakeDb, FakeLightningNode> {
+        ApiLow::create(
+            db_with_account_a_balance(),
+            FakeLightningNode::new(),
+            MemLog::new(),
+        )
+    }
+
+    #[test]
+    fn keysend_spends_from_account() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let KeysendOutgoing {
+            preimage: _,
+            amount,
+            fees_offered,
+            fees_paid,
+        } = api
+            .keysend(ACCOUNT_A, pubkey_b(), Satoshis(5), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_eq!(amount, Satoshis(5));
+        assert_eq!(fees_offered, DEFAULT_FEE);
+        assert!(fees_paid <= fees_offered);
+        let final_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        assert_eq!(
+            initial_a_balance,
+            final_a_balance + Satoshis(5) + fees_paid.0
+        );
+    }
+
+    #[test]
+    fn keysend_insufficient_balance() {
+        let api = fake_api();
+        match api
+            .keysend(Master::random(), pubkey_b(), Satoshis(5), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::InsufficientBalance) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn incoming_keysend_credited() {
+        let log = Arc::new(MemLog::new());
+        let api = ApiLow::create(FakeDb::new(), FakeLightningNode::new(), log.clone());
+        let acct_b = Master::random();
+        for _ in 0..500 {
+            if api.received_keysend_subscription().get().is_healthy() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        // a keysend without a lesser record can't be credited
+        let anonymous = api.lighting_node.simulate_keysend(Satoshis(3), None);
+        api.lighting_node
+            .simulate_keysend(Satoshis(4), Some(acct_b.into()));
+
+        for _ in 0..500 {
+            if api.check_balance(acct_b.into()).wait().is_ok() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(
+            api.check_balance(acct_b.into()).wait().unwrap(),
+            Satoshis(4)
+        );
+        match log.errors().as_slice() {
+            [LogErr::ReceiveKeysend(ReceiveKeysendErr::NoRecipient(keysend))] => {
+                assert_eq!(keysend, &anonymous)
+            }
+            other => panic!("unexpected errors logged {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invoice_above_inbound_capacity_refused() {
+        let api = fake_api();
+        api.lighting_node.set_capacity(Capacity {
+            inbound: Satoshis(100),
+            outbound: Satoshis(0),
+        });
+        api.generate_invoice(Master::random().into(), Satoshis(100).into())
+            .wait()
+            .unwrap();
+        match api
+            .generate_invoice(Master::random().into(), Satoshis(101).into())
+            .wait()
+        {
+            Err(GenerateInvoiceError::ToLarge) => {}
+            other => panic!("{:?}", other),
+        }
+        // the payer of an amountless invoice must respect capacity themselves
+        let amountless = InvoiceSpec::create(
+            None,
+            Description::Direct("".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .unwrap();
+        api.generate_invoice(Master::random().into(), amountless)
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn onchain_deposit_credited_once_confirmed() {
+        let log = Arc::new(MemLog::new());
+        let api = ApiLow::create(FakeDb::new(), FakeLightningNode::new(), log.clone());
+        api.set_required_confirmations(2);
+        wait_until(|| api.incoming_transaction_subscription().get().is_healthy());
+        let acct_b = Master::random();
+        let address = api.new_address(acct_b.into()).wait().unwrap();
+        api.lighting_node.simulate_deposit(address, Satoshis(7));
+        // change paid back to the node's wallet belongs to no account
+        api.lighting_node
+            .simulate_deposit(Address("change".to_owned()), Satoshis(3));
+
+        api.lighting_node.mine_blocks(1);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            api.check_balance(acct_b.into()).wait(),
+            Err(CheckBalanceError::NoBalance)
+        );
+
+        // further confirmations don't credit the deposit again
+        api.lighting_node.mine_blocks(3);
+        wait_until(|| api.check_balance(acct_b.into()).wait().is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(
+            api.check_balance(acct_b.into()).wait().unwrap(),
+            Satoshis(7)
+        );
+        match log.errors().as_slice() {
+            [] => {}
+            other => panic!("unexpected errors logged {:?}", other),
+        }
+    }
+
+    #[test]
+    fn onchain_withdrawal_refunds_fee_change() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let OnchainOutgoing {
+            amount,
+            fees_offered,
+            fees_paid,
+            ..
+        } = api
+            .withdraw_onchain(ACCOUNT_A, Address("fake1b".to_owned()), Satoshis(100), 144)
+            .wait()
+            .unwrap();
+        assert_eq!(amount, Satoshis(100));
+        assert!(fees_paid < fees_offered);
+        let final_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        assert_eq!(
+            initial_a_balance,
+            final_a_balance + Satoshis(100) + fees_paid.0
+        );
+    }
+
+    #[test]
+    fn onchain_withdrawal_rejected() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        // a fast confirmation costs more than the account holds
+        match api
+            .withdraw_onchain(ACCOUNT_A, Address("fake1b".to_owned()), Satoshis(100), 1)
+            .wait()
+        {
+            Err(WithdrawOnchainError::Spend(PayInvoiceError::InsufficientBalance)) => {}
+            other => panic!("{:?}", other),
+        }
+        match api
+            .withdraw_onchain(ACCOUNT_A, Address(String::new()), Satoshis(100), 144)
+            .wait()
+        {
+            Err(WithdrawOnchainError::Estimate(EstimateOnchainFeeError::InvalidAddress)) => {}
+            other => panic!("{:?}", other),
+        }
+        let testnet = Address("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_owned());
+        match api
+            .withdraw_onchain(ACCOUNT_A, testnet, Satoshis(100), 144)
+            .wait()
+        {
+            Err(WithdrawOnchainError::WrongNetwork) => {}
+            other => panic!("{:?}", other),
+        }
+        api.lighting_node.queue_outcome(PayOutcome::Aborted);
+        match api
+            .withdraw_onchain(ACCOUNT_A, Address("fake1b".to_owned()), Satoshis(100), 144)
+            .wait()
+        {
+            Err(WithdrawOnchainError::Spend(PayInvoiceError::Pay(PayError::PaymentAborted))) => {}
+            other => panic!("{:?}", other),
+        }
+        let final_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        assert_eq!(initial_a_balance, final_a_balance);
+    }
+
+    #[test]
+    fn aborted_payment_refunded() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node.queue_outcome(PayOutcome::Aborted);
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::PaymentAborted)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance
+        );
+    }
+
+    #[test]
+    fn unsupported_payment_refunded() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node.queue_outcome(PayOutcome::Unsupported);
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::Unsupported(_))) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance
+        );
+    }
+
+    #[test]
+    fn foreign_invoice_aborted() {
+        let api = fake_api();
+        let invoice = FakeLightningNode::new()
+            .create_invoice(Satoshis(1).into())
+            .wait()
+            .unwrap();
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::PaymentAborted)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_payment_failure_not_refunded() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node
+            .queue_outcome(PayOutcome::Unknown("htlc in flight".to_owned()));
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::Unknown(_))) => {}
+            other => panic!("{:?}", other),
+        }
+        // the payment may yet succeed, so the funds stay withdrawn
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(1) - DEFAULT_FEE.0
+        );
+    }
+
+    #[test]
+    fn invalid_preimage_reported() {
+        let api = fake_api();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node.queue_outcome(PayOutcome::InvalidPreimage);
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::InvalidResponse(
+                PaidInvoiceInvalid::PreimageMismatch,
+            ))) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_fee_change_refunded() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node
+            .queue_outcome(PayOutcome::FeesPaid(Fee(Satoshis(3))));
+        api.lighting_node
+            .set_latency(std::time::Duration::from_millis(20));
+        let outgoing = api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(3)));
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(4)
+        );
+    }
+
+    #[test]
+    fn delayed_settlement_credited_later() {
+        let api = fake_api();
+        let acct_b = Master::random();
+        let invoice = api
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+        api.lighting_node
+            .queue_outcome(PayOutcome::DelayedSettlement(
+                std::time::Duration::from_millis(100),
+            ));
+        api.pay_invoice(ACCOUNT_A, invoice.clone(), Satoshis(1), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_unpaid(
+            api.check_invoice_status(get_payment_hash(&invoice))
+                .wait()
+                .unwrap(),
+        );
+        wait_until(|| api.check_balance(acct_b.into()).wait().is_ok());
+        assert_paid(
+            api.check_invoice_status(get_payment_hash(&invoice))
+                .wait()
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn dropped_subscription_recovers() {
+        let log = Arc::new(MemLog::new());
+        let api = ApiLow::create_with_backoff(
+            FakeDb::new(),
+            FakeLightningNode::new(),
+            log.clone(),
+            Backoff {
+                initial: std::time::Duration::from_millis(1),
+                max: std::time::Duration::from_millis(1),
+            },
+        );
+        let acct_b = Master::random();
+        wait_until(|| api.paid_invoice_subscription().get().is_healthy());
+        let invoice = api
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+
+        // settled while the subscription is down, credited once it is back
+        api.lighting_node.drop_paid_invoices();
+        api.lighting_node
+            .pay_invoice(invoice, Satoshis(1), DEFAULT_FEE, MultiPath::default())
+            .wait()
+            .unwrap();
+        wait_until(|| api.check_balance(acct_b.into()).wait().is_ok());
+        match log.errors().as_slice() {
+            [LogErr::SubscribePaidInvoices(_)] => {}
+            other => panic!("unexpected errors logged {:?}", other),
+        }
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        panic!("timed out");
+    }
+
+    /// Generate a hold invoice owned by owner, then pay it from another thread, returning the
+    /// payer's result once the payment resolves.
+    fn pay_hold_invoice(
+        api: &ApiLow<FakeDb, FakeLightningNode>,
+        owner: Master,
+        preimage: Preimage,
+    ) -> std::thread::JoinHandle<Result<PaidInvoiceOutgoing, PayError>> {
+        wait_until(|| api.accepted_invoice_subscription().get().is_healthy());
+        let invoice = api
+            .generate_hold_invoice(owner.into(), Satoshis(5).into(), preimage.hash())
+            .wait()
+            .unwrap();
+        let node = api.lighting_node.clone();
+        let payer = std::thread::spawn(move || {
+            node.pay_invoice(invoice, Satoshis(5), DEFAULT_FEE, MultiPath::default())
+                .wait()
+        });
+        wait_until(|| match api.check_invoice_status(preimage.hash()).wait() {
+            Ok(InvoiceStatus::Accepted(_)) => true,
+            _ => false,
+        });
+        payer
+    }
+
+    #[test]
+    fn hold_invoice_settle() {
+        let api = fake_api();
+        let owner = Master::random();
+        let preimage = Preimage(U256::random());
+        let payer = pay_hold_invoice(&api, owner, preimage);
+
+        // only the owner may settle
+        match api
+            .settle_hold_invoice(Master::random().into(), preimage)
+            .wait()
+        {
+            Err(ResolveHoldInvoiceError::NotOwner) => {}
+            other => panic!("{:?}", other),
+        }
+
+        api.settle_hold_invoice(owner.into(), preimage)
+            .wait()
+            .unwrap();
+        assert_eq!(
+            payer.join().unwrap().unwrap().paid_invoice.preimage(),
+            &preimage
+        );
+        wait_until(|| api.check_balance(owner.into()).wait().is_ok());
+        assert_eq!(api.check_balance(owner.into()).wait().unwrap(), Satoshis(5));
+        assert_paid(api.check_invoice_status(preimage.hash()).wait().unwrap());
+        match api.settle_hold_invoice(owner.into(), preimage).wait() {
+            Err(ResolveHoldInvoiceError::AlreadyPaid) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn hold_invoice_cancel() {
+        let api = fake_api();
+        let owner = Master::random();
+        let preimage = Preimage(U256::random());
+        let payer = pay_hold_invoice(&api, owner, preimage);
+
+        api.cancel_hold_invoice(owner.into(), preimage.hash())
+            .wait()
+            .unwrap();
+        match payer.join().unwrap() {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        match api.check_invoice_status(preimage.hash()).wait().unwrap() {
+            InvoiceStatus::Cancelled(_) => {}
+            other => panic!("{:?}", other),
+        }
+        match api.settle_hold_invoice(owner.into(), preimage).wait() {
+            Err(ResolveHoldInvoiceError::AlreadyCancelled) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            api.check_balance(owner.into()).wait().unwrap_err(),
+            CheckBalanceError::NoBalance
+        );
+    }
+
+    #[test]
+    fn invoice_amount_mismatch_refused() {
+        let api = fake_api();
+        let balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let invoice = api
+            .generate_invoice(Master::random().into(), Satoshis(5).into())
+            .wait()
+            .unwrap();
+        for amount in &[Satoshis(4), Satoshis(6)] {
+            match api
+                .pay_invoice(ACCOUNT_A, invoice.clone(), *amount, DEFAULT_FEE)
+                .wait()
+            {
+                Err(PayInvoiceError::AmountMismatch) => {}
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(api.check_balance(ACCOUNT_A.into()).wait().unwrap(), balance);
+    }
+
+    #[test]
+    fn wrong_network_invoice_refused() {
+        let api = ApiLow::create(
+            db_with_account_a_balance(),
+            FakeLightningNode::on(Network::Testnet),
+            MemLog::new(),
+        );
+        api.set_network(Network::Testnet);
+        let balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let mainnet = FakeLightningNode::new()
+            .create_invoice(Satoshis(5).into())
+            .wait()
+            .unwrap();
+        assert!(!Network::Regtest.accepts(&mainnet));
+        match api
+            .pay_invoice(ACCOUNT_A, mainnet, Satoshis(5), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::WrongNetwork) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(api.check_balance(ACCOUNT_A.into()).wait().unwrap(), balance);
+
+        let testnet = api
+            .generate_invoice(Master::random().into(), Satoshis(5).into())
+            .wait()
+            .unwrap();
+        assert!(to_bolt11(&testnet).starts_with("lntb"));
+        assert!(!Network::Regtest.accepts(&testnet));
+        api.pay_invoice(ACCOUNT_A, testnet, Satoshis(5), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn offer_payments_credited() {
+        let api = fake_api();
+        wait_until(|| api.offer_invoice_subscription().get().is_healthy());
+        let owner = Master::random();
+        let spec = OfferSpec {
+            amount: None,
+            description: "donations".to_owned(),
+        };
+        let offer = api.create_offer(owner.into(), spec).wait().unwrap();
+        // every payer gets a fresh invoice for the same offer
+        for amount in &[Satoshis(3), Satoshis(4)] {
+            let invoice = api
+                .lighting_node
+                .simulate_invoice_request(&offer, Some(*amount))
+                .unwrap();
+            let payment_hash = get_payment_hash(&invoice);
+            wait_until(|| api.check_invoice_status(payment_hash).wait().is_ok());
+            api.pay_invoice(ACCOUNT_A, invoice, *amount, DEFAULT_FEE)
+                .wait()
+                .unwrap();
+        }
+        wait_until(|| api.check_balance(owner.into()).wait() == Ok(Satoshis(7)));
+
+        let too_long = OfferSpec {
+            amount: None,
+            description: "x".repeat(MAX_DESCRIPTION_BYTES + 1),
+        };
+        match api.create_offer(owner.into(), too_long).wait() {
+            Err(GenerateOfferError::DescriptionTooLong) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn names_registered_once() {
+        let api = fake_api();
+        let (owner, squatter) = (Master::random(), Master::random());
+        let name: Name = "alice".parse().unwrap();
+        match api.name_owner(&name).wait() {
+            Err(GetNameOwnerError::NameDoesNotExist) => {}
+            other => panic!("{:?}", other),
+        }
+        api.register_name(owner.into(), name.clone())
+            .wait()
+            .unwrap();
+        assert_eq!(api.name_owner(&name).wait(), Ok(owner.into()));
+        // first come, first served
+        assert_eq!(
+            api.register_name(squatter.into(), name.clone()).wait(),
+            Err(StoreNameError::EntryAlreadyExists(
+                squatter.into(),
+                name.clone()
+            ))
+        );
+        assert_eq!(api.name_owner(&name).wait(), Ok(owner.into()));
+    }
+
+    #[test]
+    fn auth_sessions() {
+        use secp256k1::{key::SecretKey, Message, Secp256k1};
+
+        let api = fake_api();
+        let secp = Secp256k1::new();
+        let wallet_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let linking_key = PublicKey::from_secret_key(&secp, &wallet_key);
+        let sign = |k1: U256| secp.sign(&Message::from_slice(&k1.0).unwrap(), &wallet_key);
+
+        let (k1, challenge) = api
+            .create_auth_challenge(Some(Capability::Middle(ACCOUNT_A.into())))
+            .wait()
+            .unwrap();
+        match api
+            .answer_auth_challenge(k1, &sign(U256::random()), linking_key)
+            .wait()
+        {
+            Err(AnswerAuthError::BadSignature) => {}
+            other => panic!("{:?}", other),
+        }
+        api.answer_auth_challenge(k1, &sign(k1), linking_key)
+            .wait()
+            .unwrap();
+        assert_eq!(
+            api.session(challenge.token).wait().unwrap(),
+            Capability::Middle(ACCOUNT_A.into())
+        );
+        match api.answer_auth_challenge(k1, &sign(k1), linking_key).wait() {
+            Err(AnswerAuthError::NoSuchChallenge) => {}
+            other => panic!("{:?}", other),
+        }
+
+        // a linked key can't be linked to another account
+        let (k1, _) = api
+            .create_auth_challenge(Some(Capability::Master(Master::random())))
+            .wait()
+            .unwrap();
+        match api.answer_auth_challenge(k1, &sign(k1), linking_key).wait() {
+            Err(AnswerAuthError::AlreadyLinked) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let expired = AuthChallenge {
+            token: U256::random(),
+            link: None,
+            expires_at: SystemTime::now() - Duration::from_secs(1),
+        };
+        let k1 = U256::random();
+        api.database
+            .store_auth_challenge(k1, &expired)
+            .wait()
+            .unwrap();
+        match api.answer_auth_challenge(k1, &sign(k1), linking_key).wait() {
+            Err(AnswerAuthError::Expired) => {}
+            other => panic!("{:?}", other),
+        }
+        match api.session(expired.token).wait() {
+            Err(SessionError::NoSession) => {}
+            other => panic!("{:?}", other),
+        }
+
+        // expired sessions are forgotten by the sweeper
+        let token = U256::random();
+        let session = Session {
+            capability: Capability::Middle(ACCOUNT_A.into()),
+            expires_at: SystemTime::now() - Duration::from_secs(1),
+        };
+        api.database.store_session(token, &session).wait().unwrap();
+        match api.session(token).wait() {
+            Err(SessionError::Expired) => {}
+            other => panic!("{:?}", other),
+        }
+        sweep_expired(&*api.database, &api.log);
+        match api.session(token).wait() {
+            Err(SessionError::NoSession) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn pays_lnurl() {
+        use crate::http::HttpError;
+
+        let api = fake_api();
+        wait_until(|| api.paid_invoice_subscription().get().is_healthy());
+        let recipient = Master::random();
+        // the stand-in is another service with a Lightning Address for alice, and api's node
+        // happens to issue its invoices
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap();
+        let metadata = lnurl::pay_metadata(&format!("alice@{}", host));
+        let invoice = |amount: u64, description: Description| {
+            let spec = InvoiceSpec::create(
+                Some(Satoshis(amount)),
+                description,
+                crate::invoice::DEFAULT_EXPIRY,
+            )
+            .unwrap();
+            to_bolt11(&api.generate_invoice(recipient.into(), spec).wait().unwrap())
+        };
+        let committed = invoice(21, Description::Hash(lnurl::metadata_hash(&metadata)));
+        let uncommitted = invoice(20, Description::Direct("".to_owned()));
+        let pay_request = serde_json::json!({
+            "tag": "payRequest",
+            "callback": format!("http://{}/callback", host),
+            "minSendable": 1000,
+            "maxSendable": 30_000,
+            "metadata": metadata,
+        });
+        stand_in_server(listener, move |target| {
+            let body = match target {
+                "/.well-known/lnurlp/alice" => pay_request.clone(),
+                "/callback?amount=21000" => serde_json::json!({ "pr": committed, "routes": [] }),
+                "/callback?amount=20000" => serde_json::json!({ "pr": uncommitted }),
+                _ => serde_json::json!({ "status": "ERROR", "reason": "unknown name" }),
+            };
+            body.to_string()
+        });
+        let pay = |name: &str, amount: u64| {
+            let link = lnurl::pay_link(&format!("{}@{}", name, host)).unwrap();
+            api.pay_lnurl(ACCOUNT_A, link, Satoshis(amount), DEFAULT_FEE)
+                .wait()
+        };
+
+        // the stand-in is on this machine
+        match pay("alice", 21) {
+            Err(PayLnurlError::Resolve(ResolveError::Http(HttpError::PrivateAddress))) => {}
+            other => panic!("{:?}", other),
+        }
+        api.set_allow_private_lnurl(true);
+        match pay("bob", 21) {
+            Err(PayLnurlError::Resolve(ResolveError::Refused(reason))) => {
+                assert_eq!(reason, "unknown name")
+            }
+            other => panic!("{:?}", other),
+        }
+        match pay("alice", 31) {
+            Err(PayLnurlError::AmountOutOfRange) => {}
+            other => panic!("{:?}", other),
+        }
+        match pay("alice", u64::max_value() / 1000) {
+            Err(PayLnurlError::AmountOutOfRange) => {}
+            other => panic!("{:?}", other),
+        }
+        match pay("alice", 20) {
+            Err(PayLnurlError::InvoiceMismatch) => {}
+            other => panic!("{:?}", other),
+        }
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let outgoing = pay("alice", 21).unwrap();
+        assert_eq!(outgoing.paid_invoice.amount_paid(), &Satoshis(21));
+        wait_until(|| api.check_balance(recipient.into()).wait() == Ok(Satoshis(21)));
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(21) - outgoing.fees_paid.0
+        );
+    }
+
+    #[test]
+    fn voucher_used_up() {
+        let api = fake_api();
+        wait_until(|| api.paid_invoice_subscription().get().is_healthy());
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let k1 = api
+            .create_voucher(
+                ACCOUNT_A,
+                Satoshis(5),
+                Fee(Satoshis(2)),
+                2,
+                Duration::from_secs(60),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(14)
+        );
+
+        let recipient = Master::random();
+        let withdraw = |amount: Satoshis| {
+            let invoice = api
+                .generate_invoice(recipient.into(), amount.into())
+                .wait()
+                .unwrap();
+            api.withdraw_voucher(k1, invoice).wait()
+        };
+        match withdraw(Satoshis(6)) {
+            Err(WithdrawVoucherError::Amount) => {}
+            other => panic!("{:?}", other),
+        }
+        withdraw(Satoshis(5)).unwrap();
+        assert_eq!(api.get_voucher(k1).wait().unwrap().uses, 1);
+        withdraw(Satoshis(4)).unwrap();
+        match withdraw(Satoshis(1)) {
+            Err(WithdrawVoucherError::NoSuchVoucher) => {}
+            other => panic!("{:?}", other),
+        }
+        wait_until(|| api.check_balance(recipient.into()).wait() == Ok(Satoshis(9)));
+
+        // the fake node charges half the fee offered, the rest is refunded along with the
+        // unspent satoshi
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(11)
+        );
+    }
+
+    #[test]
+    fn voucher_refunded_on_expiry() {
+        let api = fake_api();
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        match api
+            .create_voucher(
+                ACCOUNT_A,
+                Satoshis(5),
+                Fee(Satoshis(2)),
+                0,
+                Duration::from_secs(60),
+            )
+            .wait()
+        {
+            Err(CreateVoucherError::NoUses) => {}
+            other => panic!("{:?}", other),
+        }
+        match api
+            .create_voucher(
+                Master::random(),
+                Satoshis(5),
+                Fee(Satoshis(2)),
+                1,
+                Duration::from_secs(60),
+            )
+            .wait()
+        {
+            Err(CreateVoucherError::InsufficientBalance) => {}
+            other => panic!("{:?}", other),
+        }
+        match api
+            .create_voucher(
+                ACCOUNT_A,
+                Satoshis(5),
+                Fee(Satoshis(2)),
+                1,
+                MAX_VOUCHER_EXPIRY + Duration::from_secs(1),
+            )
+            .wait()
+        {
+            Err(CreateVoucherError::ExpiryOutOfRange) => {}
+            other => panic!("{:?}", other),
+        }
+
+        let k1 = api
+            .create_voucher(
+                ACCOUNT_A,
+                Satoshis(5),
+                Fee(Satoshis(2)),
+                3,
+                Duration::from_millis(50),
+            )
+            .wait()
+            .unwrap();
+        assert!(api.get_voucher(k1).wait().is_ok());
+        sweep_expired(&*api.database, &api.log);
+        assert!(api.get_voucher(k1).wait().is_ok());
+        thread::sleep(Duration::from_millis(50));
+        sweep_expired(&*api.database, &api.log);
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait(),
+            Ok(initial_a_balance)
+        );
+        match api.get_voucher(k1).wait() {
+            Err(WithdrawVoucherError::NoSuchVoucher) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancel_unpaid_invoice() {
+        let api = fake_api();
+        let owner = Master::random();
+        let invoice = api
+            .generate_invoice(owner.into(), Satoshis(5).into())
+            .wait()
+            .unwrap();
+        let payment_hash = get_payment_hash(&invoice);
+
+        // only the owner may cancel
+        match api
+            .cancel_invoice(Master::random().into(), payment_hash)
+            .wait()
+        {
+            Err(ResolveHoldInvoiceError::NotOwner) => {}
+            other => panic!("{:?}", other),
+        }
+
+        api.cancel_invoice(owner.into(), payment_hash)
+            .wait()
+            .unwrap();
+        match api.check_invoice_status(payment_hash).wait().unwrap() {
+            InvoiceStatus::Cancelled(_) => {}
+            other => panic!("{:?}", other),
+        }
+        match api
+            .pay_invoice(ACCOUNT_A, invoice, Satoshis(5), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::PaymentAborted)) => {}
+            other => panic!("{:?}", other),
+        }
+        match api.cancel_invoice(owner.into(), payment_hash).wait() {
+            Err(ResolveHoldInvoiceError::AlreadyCancelled) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancelled_invoice_payment_not_credited() {
+        let api = fake_api();
+        let owner = Master::random();
+        let invoice = api
+            .generate_invoice(owner.into(), Satoshis(5).into())
+            .wait()
+            .unwrap();
+        let payment_hash = get_payment_hash(&invoice);
+        let preimage = api.lighting_node.get_preimage(payment_hash).unwrap();
+        api.cancel_invoice(owner.into(), payment_hash)
+            .wait()
+            .unwrap();
+
+        // the payment arrives anyway
+        let paid = PaidInvoice::create(invoice, preimage, Satoshis(5)).unwrap();
+        match api.database.receive_paid_invoice(paid).wait() {
+            Err(ReceivePaidInvoiceErr::Cancelled(_)) => {}
+            other => panic!("{:?}", other),
+        }
+        match api.check_invoice_status(payment_hash).wait().unwrap() {
+            InvoiceStatus::Cancelled(_) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            api.check_balance(owner.into()).wait().unwrap_err(),
+            CheckBalanceError::NoBalance
+        );
+    }
+
+    #[test]
+    fn hold_invoice_settle_before_payment() {
+        let api = fake_api();
+        let owner = Master::random();
+        let preimage = Preimage(U256::random());
+        api.generate_hold_invoice(owner.into(), Satoshis(5).into(), preimage.hash())
+            .wait()
+            .unwrap();
+        match api.settle_hold_invoice(owner.into(), preimage).wait() {
+            Err(ResolveHoldInvoiceError::NotAccepted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
     /// Create a new test for each constructable combination of db/node implementations
     macro_rules! test_all_impls {
         ($test:ident) => {
@@ -528,6 +2474,7 @@ mod test {
                     $test(ApiLow::create(
                         crate::fake_db::db_with_account_a_balance(),
                         FakeLightningNode::new(),
+                        MemLog::new(),
                     ));
                 }
 
@@ -536,6 +2483,7 @@ mod test {
                     $test(ApiLow::create(
                         crate::fake_db::db_with_account_a_balance(),
                         init_default_lightning_client().unwrap(),
+                        MemLog::new(),
                     ));
                 }
             }
@@ -553,4 +2501,5 @@ mod test {
     test_all_impls!(pay_invoice_to_local);
     test_all_impls!(pay_invoice_to_local_to_self);
     test_all_impls!(unused_fees_are_refunded);
+    test_all_impls!(amountless_invoice);
 }
@@ -5,9 +5,15 @@ use serde::{Deserialize, Serialize};
 // /invoice
 // {
 //   "lesser": "<hex u256>",
-//   "satoshis": <integer>
+//   "satoshis": <integer>,                  (optional, omit to let the payer choose)
+//   "description": "<string>",              (optional)
+//   "description_hash": "<hex u256>",       (optional, exclusive with description)
+//   "expiry_seconds": <uint>                (optional, default 3600)
 // }
-// -> { "error": { "to_large": null } }
+// -> { "error": { "to_large": null }
+//             | { "description_too_long": null }
+//             | { "conflicting_description": null }
+//             | { "expiry_out_of_range": null } }
 //  | { "ok": {
 //      "invoice": "<bech32 invoice>",
 //      "extras": {
@@ -18,7 +24,14 @@ use serde::{Deserialize, Serialize};
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct GenerateInvoiceRequest {
     pub lesser: Lesser,
-    pub satoshis: Satoshis,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub satoshis: Option<Satoshis>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_hash: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_seconds: Option<u64>,
 }
 
 pub type GenerateInvoiceResponse = ResultSerDe<GenerateInvoiceOk, GenerateInvoiceErr>;
@@ -27,6 +40,10 @@ pub type GenerateInvoiceResponse = ResultSerDe<GenerateInvoiceOk, GenerateInvoic
 #[serde(rename_all = "snake_case")]
 pub enum GenerateInvoiceErr {
     ToLarge(()),
+    DescriptionTooLong(()),
+    /// Both description and description_hash were provided.
+    ConflictingDescription(()),
+    ExpiryOutOfRange(()),
 }
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
@@ -51,7 +68,8 @@ pub struct GenerateInvoiceExtras {
 //   "fee_satoshis": <uint>
 // }
 // -> { "error": { "insufficient_balance": null }
-//             | { "aborted": null } }
+//             | { "aborted": null }
+//             | { "wrong_network": null } }
 //  | { "ok": { "fees_paid_satoshis": <uint> } }
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 pub struct PayInvoiceRequest {
@@ -68,6 +86,10 @@ pub type PayInvoiceResponse = ResultSerDe<PayInvoiceOk, PayInvoiceErr>;
 pub enum PayInvoiceErr {
     InsufficientBalance(()),
     Aborted(()),
+    /// The invoice is for another bitcoin network than this server's.
+    WrongNetwork(()),
+    /// The invoice asks for an amount other than amount_satoshis.
+    AmountMismatch(()),
 }
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
@@ -76,6 +98,312 @@ pub struct PayInvoiceOk {
     pub fees_paid_satoshis: Fee<Satoshis>,
 }
 
+// POST
+// /pay
+// Authorization: Bearer <session token: hex u256>
+// {
+//   "invoice": "<bech32 invoice>",
+//   "amount_satoshis": <uint>,
+//   "fee_satoshis": <uint>
+// }
+// -> { "error": { "unauthorized": null }
+//             | { "session_expired": null }
+//             | { "insufficient_balance": null }
+//             | { "aborted": null }
+//             | { "wrong_network": null } }
+//  | { "ok": { "fees_paid_satoshis": <uint> } }
+//
+// Pays from the account of a session started with a Master.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct SessionPayInvoiceRequest {
+    pub invoice: InvoiceSerDe,
+    pub amount_satoshis: Satoshis,
+    pub fee_satoshis: Fee<Satoshis>,
+}
+
+pub type SessionPayInvoiceResponse = ResultSerDe<PayInvoiceOk, SessionErr<PayInvoiceErr>>;
+
+// POST
+// /pay/lnurl
+// {
+//   "master": "<hex u256>",
+//   "target": "<name@domain | bech32 lnurl>",
+//   "amount_satoshis": <uint>,
+//   "fee_satoshis": <uint>
+// }
+// -> { "error": { "invalid_target": null }
+//             | { "unreachable": null }
+//             | { "refused": "<reason given by the service>" }
+//             | { "amount_out_of_range": null }
+//             | { "invoice_mismatch": null }
+//             | { "insufficient_balance": null }
+//             | { "aborted": null }
+//             | { "wrong_network": null } }
+//  | { "ok": { "preimage": "<hex u256>", "fees_paid_satoshis": <uint> } }
+//
+// Pays a Lightning Address or LNURL-pay code, fetching the invoice from the service behind it.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct PayLnurlRequest {
+    pub master: Master,
+    pub target: String,
+    pub amount_satoshis: Satoshis,
+    pub fee_satoshis: Fee<Satoshis>,
+}
+
+pub type PayLnurlResponse = ResultSerDe<PayInvoiceOk, PayLnurlErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PayLnurlErr {
+    /// Neither a Lightning Address nor an lnurl1 code of an https url.
+    InvalidTarget(()),
+    /// The service could not be reached, or its answer was not understood.
+    Unreachable(()),
+    /// The service answered with an error.
+    Refused(String),
+    /// The service does not accept the amount.
+    AmountOutOfRange(()),
+    /// The service's invoice is for another amount, or does not commit to its metadata.
+    InvoiceMismatch(()),
+    InsufficientBalance(()),
+    Aborted(()),
+    WrongNetwork(()),
+}
+
+// POST
+// /keysend
+// {
+//   "master": "<hex u256>",
+//   "pubkey": "<hex compressed secp256k1 public key>",
+//   "amount_satoshis": <uint>,
+//   "fee_satoshis": <uint>
+// }
+// -> { "error": { "insufficient_balance": null }
+//             | { "aborted": null } }
+//  | { "ok": { "preimage": "<hex u256>", "fees_paid_satoshis": <uint> } }
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct KeysendRequest {
+    pub master: Master,
+    pub pubkey: PubKeySerDe,
+    pub amount_satoshis: Satoshis,
+    pub fee_satoshis: Fee<Satoshis>,
+}
+
+pub type KeysendResponse = ResultSerDe<KeysendOk, PayInvoiceErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct KeysendOk {
+    pub preimage: Preimage,
+    pub fees_paid_satoshis: Fee<Satoshis>,
+}
+
+// POST
+// /hold_invoice
+// {
+//   "middle": "<hex u256>",
+//   "payment_hash": "<hex u256>",
+//   "satoshis": <integer>,                  (optional, omit to let the payer choose)
+//   "description": "<string>",              (optional)
+//   "description_hash": "<hex u256>",       (optional, exclusive with description)
+//   "expiry_seconds": <uint>                (optional, default 3600)
+// }
+// -> same as POST /invoice
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct GenerateHoldInvoiceRequest {
+    pub middle: Middle,
+    pub payment_hash: PaymentHash,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub satoshis: Option<Satoshis>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description_hash: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_seconds: Option<u64>,
+}
+
+// POST
+// /hold_invoice/settle
+// {
+//   "middle": "<hex u256>",
+//   "preimage": "<hex u256>"
+// }
+// -> { "error": { "non_existent": null }
+//             | { "unauthorized": null }
+//             | { "not_accepted": null }
+//             | { "already_paid": null }
+//             | { "already_cancelled": null } }
+//  | { "ok": null }
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct SettleHoldInvoiceRequest {
+    pub middle: Middle,
+    pub preimage: Preimage,
+}
+
+// POST
+// /hold_invoice/cancel
+// {
+//   "middle": "<hex u256>",
+//   "payment_hash": "<hex u256>"
+// }
+// -> same as POST /hold_invoice/settle
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CancelHoldInvoiceRequest {
+    pub middle: Middle,
+    pub payment_hash: PaymentHash,
+}
+
+// DELETE
+// /invoice/<hex payment hash>
+// {
+//   "middle": "<hex u256>"
+// }
+// -> same as POST /hold_invoice/settle
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CancelInvoiceRequest {
+    pub middle: Middle,
+}
+
+pub type ResolveHoldInvoiceResponse = ResultSerDe<(), ResolveHoldInvoiceErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveHoldInvoiceErr {
+    NonExistent(()),
+    /// The invoice was generated by a different account.
+    Unauthorized(()),
+    /// Payment has not arrived.
+    NotAccepted(()),
+    AlreadyPaid(()),
+    AlreadyCancelled(()),
+}
+
+// POST
+// /quote
+// {
+//   "invoice": "<bech32 invoice>",
+//   "amount_satoshis": <uint>
+// }
+// -> { "error": { "no_route": null } }
+//  | { "ok": { "expected_fee_satoshis": <uint>, "worst_case_fee_satoshis": <uint> } }
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct QuoteRequest {
+    pub invoice: InvoiceSerDe,
+    pub amount_satoshis: Satoshis,
+}
+
+pub type QuoteResponse = ResultSerDe<QuoteOk, QuoteErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteErr {
+    NoRoute(()),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct QuoteOk {
+    pub expected_fee_satoshis: Fee<Satoshis>,
+    /// Offering this as fee_satoshis when paying should not abort the payment for lack of fee.
+    pub worst_case_fee_satoshis: Fee<Satoshis>,
+}
+
+// POST
+// /address
+// {
+//   "lesser": "<hex u256>"
+// }
+// -> { "address": "<on-chain address>" }
+//
+// Deposits to the address are credited to lesser's account once confirmed.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct NewAddressRequest {
+    pub lesser: Lesser,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct NewAddressResponse {
+    pub address: Address,
+}
+
+// POST
+// /offer
+// {
+//   "lesser": "<hex u256>",
+//   "amount_satoshis": <uint>,            (optional, the payer chooses if absent)
+//   "description": "<string>"
+// }
+// -> { "error": { "description_too_long": null }
+//             | { "unsupported": null } }
+//  | { "ok": { "offer": "<bech32 offer>" } }
+//
+// The offer can be published once and paid any number of times. Every payment to it is
+// credited to lesser's account. Neither the lnd nor the Core Lightning backend can answer the
+// invoice requests offers lead to, so with either of them every request is unsupported.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CreateOfferRequest {
+    pub lesser: Lesser,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount_satoshis: Option<Satoshis>,
+    pub description: String,
+}
+
+pub type CreateOfferResponse = ResultSerDe<CreateOfferOk, CreateOfferErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CreateOfferErr {
+    DescriptionTooLong(()),
+    /// The lightning node can't answer invoice requests.
+    Unsupported(()),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CreateOfferOk {
+    pub offer: Offer,
+}
+
+// POST
+// /withdraw/onchain
+// {
+//   "master": "<hex u256>",
+//   "address": "<on-chain address>",
+//   "amount_satoshis": <uint>,
+//   "target_blocks": <uint>
+// }
+// -> { "error": { "insufficient_balance": null }
+//             | { "invalid_address": null }
+//             | { "aborted": null }
+//             | { "wrong_network": null } }
+//  | { "ok": { "txid": "<hex u256>", "fees_paid_satoshis": <uint> } }
+//
+// The miner fee is estimated for confirmation within target_blocks blocks. The balance must
+// cover amount_satoshis plus that estimate; any of the estimate left unspent is refunded.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct WithdrawOnchainRequest {
+    pub master: Master,
+    pub address: Address,
+    pub amount_satoshis: Satoshis,
+    pub target_blocks: u32,
+}
+
+pub type WithdrawOnchainResponse = ResultSerDe<WithdrawOnchainOk, WithdrawOnchainErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawOnchainErr {
+    InsufficientBalance(()),
+    InvalidAddress(()),
+    Aborted(()),
+    /// The address is for another bitcoin network than this server's.
+    WrongNetwork(()),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct WithdrawOnchainOk {
+    pub txid: U256,
+    pub fees_paid_satoshis: Fee<Satoshis>,
+}
+
 // GET
 // /balance/<middle: hex u256>
 // -> { "error": { "no_balance": null } }
@@ -93,10 +421,76 @@ pub struct CheckBalanceOk {
     pub balance_satoshis: Satoshis,
 }
 
+// GET
+// /balance
+// Authorization: Bearer <session token: hex u256>
+// -> { "error": { "unauthorized": null } | { "session_expired": null } | { "no_balance": null } }
+//  | { "ok": { "balance_satoshis": <uint> } }
+pub type SessionCheckBalanceResponse = ResultSerDe<CheckBalanceOk, SessionErr<CheckBalanceErr>>;
+
+// POST
+// /auth
+// {
+//   "link": { "master": "<hex u256>" } | { "middle": "<hex u256>" }   (optional)
+// }
+// -> { "k1": "<hex u256>", "token": "<hex u256>", "lnurl": "<bech32 lnurl>" }
+//
+// A login challenge for wallets (LUD-04). Once a wallet answers it, token authorizes requests
+// as the account the wallet's key is linked to. With link, the key is linked to that Master or
+// Middle first. Keep token secret, unlike the lnurl.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct AuthChallengeRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<Capability>,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct AuthChallengeResponse {
+    pub k1: U256,
+    pub token: U256,
+    pub lnurl: String,
+}
+
+/// Errors of requests authorized by a session token, on top of the errors of the request
+/// itself.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SessionErr<E> {
+    Session(SessionRefused),
+    Other(E),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRefused {
+    /// No session has the token, or the session may not make this request.
+    Unauthorized(()),
+    SessionExpired(()),
+}
+
+// GET
+// /node
+// -> {
+//   "alias": "<string>",
+//   "pubkey": "<hex compressed secp256k1 public key>",
+//   "inbound_satoshis": <uint>,
+//   "outbound_satoshis": <uint>
+// }
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct NodeInfoResponse {
+    pub alias: String,
+    pub pubkey: PubKeySerDe,
+    /// The most an invoice can currently be generated for.
+    pub inbound_satoshis: Satoshis,
+    pub outbound_satoshis: Satoshis,
+}
+
 // GET
 // /invoice/<payment hash: hex u256>
 // -> { "error": { "expired": null } | { "non_existent": null } }
 //  | { "ok": { "waiting": null }
+//          | { "accepted": null }
+//          | { "cancelled": null }
 //          | { "paid": {
 //                "preimage": "<hex u256>",
 //                "amount_paid_satoshis": <uint>
@@ -109,12 +503,17 @@ pub type CheckInvoiceResponse = ResultSerDe<CheckInvoiceOk, CheckInvoiceErr>;
 pub enum CheckInvoiceErr {
     Expired(()),
     NonExistent(()),
+    /// Only returned when awaiting an invoice.
+    Cancelled(()),
 }
 
 #[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum CheckInvoiceOk {
     Waiting(()),
+    /// Payment for a hold invoice is being held, pending settlement.
+    Accepted(()),
+    Cancelled(()),
     Paid {
         preimage: Preimage,
         amount_paid_satoshis: Satoshis,
@@ -124,7 +523,7 @@ pub enum CheckInvoiceOk {
 // GET
 // Upgrade: websocket
 // /invoice/<payment hash: hex u256>
-// -> { "error": { "expired": null } | { "non_existent": null } }
+// -> { "error": { "expired": null } | { "non_existent": null } | { "cancelled": null } }
 //  | { "ok": {
 //      "preimage": "<hex u256>",
 //      "amount_paid_satoshis": <uint>
@@ -137,6 +536,232 @@ pub struct AwaitInvoiceOk {
     pub amount_paid_satoshis: Satoshis,
 }
 
+// POST
+// /name
+// {
+//   "middle": "<hex u256>",
+//   "name": "<string>"
+// }
+// -> { "error": { "invalid_name": null } | { "taken": null } }
+//  | { "ok": { "address": "<name>@<host>" } }
+//
+// Names are 1 to 64 lower case letters, digits and -_. Payments to the Lightning Address are
+// credited to middle's account.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct RegisterNameRequest {
+    pub middle: Middle,
+    pub name: String,
+}
+
+pub type RegisterNameResponse = ResultSerDe<RegisterNameOk, RegisterNameErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterNameErr {
+    InvalidName(()),
+    /// The name is registered to some account already.
+    Taken(()),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct RegisterNameOk {
+    pub address: String,
+}
+
+// POST
+// /voucher
+// {
+//   "master": "<hex u256>",
+//   "amount_satoshis": <uint>,
+//   "fee_satoshis": <uint>,
+//   "uses": <uint>,                         (optional, default 1)
+//   "expiry_seconds": <uint>                (optional, default one week, at most 90 days)
+// }
+// -> { "error": { "insufficient_balance": null }
+//             | { "no_uses": null }
+//             | { "expiry_out_of_range": null } }
+//  | { "ok": { "k1": "<hex u256>", "lnurl": "<bech32 lnurl>" } }
+//
+// uses × (amount_satoshis + fee_satoshis) is reserved from master's account. Whoever holds the
+// LNURL-withdraw link may withdraw up to amount_satoshis per use, paying up to fee_satoshis in
+// routing fees. What is left once the voucher is used up or expires is refunded.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CreateVoucherRequest {
+    pub master: Master,
+    pub amount_satoshis: Satoshis,
+    pub fee_satoshis: Fee<Satoshis>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uses: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_seconds: Option<u64>,
+}
+
+pub type CreateVoucherResponse = ResultSerDe<CreateVoucherOk, CreateVoucherErr>;
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CreateVoucherErr {
+    InsufficientBalance(()),
+    NoUses(()),
+    ExpiryOutOfRange(()),
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct CreateVoucherOk {
+    pub k1: U256,
+    pub lnurl: String,
+}
+
+// The LNURL endpoints below are called by wallets, so they follow the LNURL specs rather than
+// the conventions of the rest of this api. Any of them may respond with
+// { "status": "ERROR", "reason": "<string>" }
+
+// GET
+// /.well-known/lnurlp/<name>
+// -> {
+//   "tag": "payRequest",
+//   "callback": "<url>",
+//   "minSendable": <uint millisatoshis>,
+//   "maxSendable": <uint millisatoshis>,
+//   "metadata": "<json array of [mime type, content] pairs>"
+// }
+//
+// The pay request for the Lightning Address name@<host> (LUD-06, LUD-16).
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlPayRequest {
+    pub tag: LnurlTag,
+    pub callback: UrlSerDe,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub metadata: String,
+}
+
+// GET
+// /lnurlp/<name>/callback?amount=<uint millisatoshis>
+// -> { "pr": "<bech32 invoice>", "routes": [] }
+//
+// The invoice's description hash is the sha256 of the pay request's metadata.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlPayCallbackQuery {
+    pub amount: u64,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlPayInvoice {
+    pub pr: InvoiceSerDe,
+    /// Deprecated by LUD-06, always empty.
+    #[serde(default)]
+    pub routes: Vec<serde_json::Value>,
+}
+
+// GET
+// /lnurlw/<k1: hex u256>
+// -> {
+//   "tag": "withdrawRequest",
+//   "callback": "<url>",
+//   "k1": "<hex u256>",
+//   "defaultDescription": "<string>",
+//   "minWithdrawable": <uint millisatoshis>,
+//   "maxWithdrawable": <uint millisatoshis>
+// }
+//
+// The LNURL-withdraw request of a voucher (LUD-03).
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlWithdrawRequest {
+    pub tag: LnurlTag,
+    pub callback: UrlSerDe,
+    pub k1: U256,
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+}
+
+// GET
+// /lnurlw/callback?k1=<hex u256>&pr=<bech32 invoice>
+// -> { "status": "OK" }
+//
+// The invoice is paid before responding.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlWithdrawCallbackQuery {
+    pub k1: U256,
+    pub pr: InvoiceSerDe,
+}
+
+// GET
+// /lnurla?tag=login&k1=<hex u256>&action=<string>&sig=<hex DER signature>&key=<hex public key>
+// -> { "status": "OK" }
+//
+// A wallet answers a login challenge, signing k1 with its linking key.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlAuthQuery {
+    pub k1: U256,
+    pub sig: SignatureSerDe,
+    pub key: PubKeySerDe,
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlOk {
+    pub status: LnurlStatus,
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum LnurlTag {
+    PayRequest,
+    WithdrawRequest,
+}
+
+/// Successful LNURL responses carry no reason, errors carry one. Errors are tried first since
+/// some successful responses consist of a status only.
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LnurlResponse<T> {
+    Error(LnurlError),
+    Ok(T),
+}
+
+impl<T> From<Result<T, LnurlError>> for LnurlResponse<T> {
+    fn from(other: Result<T, LnurlError>) -> Self {
+        match other {
+            Ok(t) => LnurlResponse::Ok(t),
+            Err(err) => LnurlResponse::Error(err),
+        }
+    }
+}
+
+impl<T> Into<Result<T, LnurlError>> for LnurlResponse<T> {
+    fn into(self) -> Result<T, LnurlError> {
+        match self {
+            LnurlResponse::Ok(t) => Ok(t),
+            LnurlResponse::Error(err) => Err(err),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Serialize, Deserialize, Debug)]
+pub struct LnurlError {
+    pub status: LnurlStatus,
+    pub reason: String,
+}
+
+impl LnurlError {
+    pub fn new(reason: &str) -> Self {
+        LnurlError {
+            status: LnurlStatus::Error,
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LnurlStatus {
+    Ok,
+    Error,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -175,9 +800,44 @@ mod test {
             }),
             GenerateInvoiceRequest {
                 lesser: Lesser(TYPED_U256_A),
-                satoshis: Satoshis(20),
+                satoshis: Some(Satoshis(20)),
+                description: None,
+                description_hash: None,
+                expiry_seconds: None,
+            },
+        );
+        ser_de_equiv(
+            json!({
+                "lesser": VALID_U256_A,
+                "satoshis": 20,
+                "description_hash": VALID_U256_A,
+                "expiry_seconds": 600
+            }),
+            GenerateInvoiceRequest {
+                lesser: Lesser(TYPED_U256_A),
+                satoshis: Some(Satoshis(20)),
+                description: None,
+                description_hash: Some(TYPED_U256_A),
+                expiry_seconds: Some(600),
+            },
+        );
+        ser_de_equiv(
+            json!({
+                "lesser": VALID_U256_A,
+                "description": "tip jar"
+            }),
+            GenerateInvoiceRequest {
+                lesser: Lesser(TYPED_U256_A),
+                satoshis: None,
+                description: Some("tip jar".to_owned()),
+                description_hash: None,
+                expiry_seconds: None,
             },
         );
+        ser_de_equiv::<GenerateInvoiceResponse>(
+            json!({ "error": { "conflicting_description": null } }),
+            Err(GenerateInvoiceErr::ConflictingDescription(())).into(),
+        );
         ser_de_equiv::<GenerateInvoiceResponse>(
             json!({ "error": { "to_large": null } }),
             Err(GenerateInvoiceErr::ToLarge(())).into(),
@@ -237,6 +897,14 @@ mod test {
             json!({ "error": { "aborted": null } }),
             Err(PayInvoiceErr::Aborted(())).into(),
         );
+        ser_de_equiv::<PayInvoiceResponse>(
+            json!({ "error": { "wrong_network": null } }),
+            Err(PayInvoiceErr::WrongNetwork(())).into(),
+        );
+        ser_de_equiv::<PayInvoiceResponse>(
+            json!({ "error": { "amount_mismatch": null } }),
+            Err(PayInvoiceErr::AmountMismatch(())).into(),
+        );
         ser_de_equiv::<PayInvoiceResponse>(
             json!({ "ok": {
                 "fees_paid_satoshis": 10,
@@ -253,6 +921,237 @@ mod test {
         from_value::<PayInvoiceResponse>(json!({ "err": "fees_paid_satoshis" })).unwrap_err();
     }
 
+    #[test]
+    fn post_keysend() {
+        const PUBKEY: &str = "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad";
+        ser_de_equiv(
+            json!({
+                "master": VALID_U256_A,
+                "pubkey": PUBKEY,
+                "amount_satoshis": 30,
+                "fee_satoshis": 3
+            }),
+            KeysendRequest {
+                master: Master(TYPED_U256_A),
+                pubkey: PubKeySerDe(PublicKey::from_slice(&hex::decode(PUBKEY).unwrap()).unwrap()),
+                amount_satoshis: Satoshis(30),
+                fee_satoshis: Fee(Satoshis(3)),
+            },
+        );
+        from_value::<KeysendRequest>(json!({
+            "master": VALID_U256_A,
+            "pubkey": VALID_U256_A,
+            "amount_satoshis": 30,
+            "fee_satoshis": 3
+        }))
+        .unwrap_err();
+        ser_de_equiv::<KeysendResponse>(
+            json!({ "ok": {
+                "fees_paid_satoshis": 1,
+                "preimage": PREIMAGE_A_RAW,
+            } }),
+            Ok(KeysendOk {
+                fees_paid_satoshis: Fee(Satoshis(1)),
+                preimage: PREIMAGE_A,
+            })
+            .into(),
+        );
+    }
+
+    #[test]
+    fn post_quote() {
+        ser_de_equiv(
+            json!({
+                "invoice": VALID_INVOICE_A,
+                "amount_satoshis": 30
+            }),
+            QuoteRequest {
+                invoice: InvoiceSerDe(VALID_INVOICE_A.parse().unwrap()),
+                amount_satoshis: Satoshis(30),
+            },
+        );
+        ser_de_equiv::<QuoteResponse>(
+            json!({ "error": { "no_route": null } }),
+            Err(QuoteErr::NoRoute(())).into(),
+        );
+        ser_de_equiv::<QuoteResponse>(
+            json!({ "ok": {
+                "expected_fee_satoshis": 1,
+                "worst_case_fee_satoshis": 3,
+            } }),
+            Ok(QuoteOk {
+                expected_fee_satoshis: Fee(Satoshis(1)),
+                worst_case_fee_satoshis: Fee(Satoshis(3)),
+            })
+            .into(),
+        );
+    }
+
+    #[test]
+    fn get_node() {
+        const PUBKEY: &str = "03e7156ae33b0a208d0744199163177e909e80176e55d97a2f221ede0f934dd9ad";
+        ser_de_equiv(
+            json!({
+                "alias": "lapi",
+                "pubkey": PUBKEY,
+                "inbound_satoshis": 1000,
+                "outbound_satoshis": 20,
+            }),
+            NodeInfoResponse {
+                alias: "lapi".to_owned(),
+                pubkey: PubKeySerDe(PublicKey::from_slice(&hex::decode(PUBKEY).unwrap()).unwrap()),
+                inbound_satoshis: Satoshis(1000),
+                outbound_satoshis: Satoshis(20),
+            },
+        );
+    }
+
+    #[test]
+    fn post_address() {
+        ser_de_equiv(
+            json!({ "lesser": VALID_U256_A }),
+            NewAddressRequest {
+                lesser: Lesser(TYPED_U256_A),
+            },
+        );
+        ser_de_equiv(
+            json!({ "address": "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq" }),
+            NewAddressResponse {
+                address: Address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_owned()),
+            },
+        );
+    }
+
+    #[test]
+    fn post_offer() {
+        ser_de_equiv(
+            json!({ "lesser": VALID_U256_A, "description": "tips" }),
+            CreateOfferRequest {
+                lesser: Lesser(TYPED_U256_A),
+                amount_satoshis: None,
+                description: "tips".to_owned(),
+            },
+        );
+        ser_de_equiv(
+            json!({ "lesser": VALID_U256_A, "amount_satoshis": 21, "description": "tea" }),
+            CreateOfferRequest {
+                lesser: Lesser(TYPED_U256_A),
+                amount_satoshis: Some(Satoshis(21)),
+                description: "tea".to_owned(),
+            },
+        );
+        let offer = Offer {
+            id: TYPED_U256_A,
+            description: "tips".to_owned(),
+            amount: None,
+            issuer: pubkey_b(),
+            network: Network::Mainnet,
+        };
+        ser_de_equiv::<CreateOfferResponse>(
+            json!({ "ok": { "offer": offer.to_string() } }),
+            Ok(CreateOfferOk { offer }).into(),
+        );
+        ser_de_equiv::<CreateOfferResponse>(
+            json!({ "error": { "description_too_long": null } }),
+            Err(CreateOfferErr::DescriptionTooLong(())).into(),
+        );
+        ser_de_equiv::<CreateOfferResponse>(
+            json!({ "error": { "unsupported": null } }),
+            Err(CreateOfferErr::Unsupported(())).into(),
+        );
+    }
+
+    #[test]
+    fn post_withdraw_onchain() {
+        ser_de_equiv(
+            json!({
+                "master": VALID_U256_A,
+                "address": "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                "amount_satoshis": 30000,
+                "target_blocks": 6
+            }),
+            WithdrawOnchainRequest {
+                master: Master(TYPED_U256_A),
+                address: Address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_owned()),
+                amount_satoshis: Satoshis(30000),
+                target_blocks: 6,
+            },
+        );
+        ser_de_equiv::<WithdrawOnchainResponse>(
+            json!({ "error": { "invalid_address": null } }),
+            Err(WithdrawOnchainErr::InvalidAddress(())).into(),
+        );
+        ser_de_equiv::<WithdrawOnchainResponse>(
+            json!({ "error": { "wrong_network": null } }),
+            Err(WithdrawOnchainErr::WrongNetwork(())).into(),
+        );
+        ser_de_equiv::<WithdrawOnchainResponse>(
+            json!({ "ok": {
+                "txid": VALID_U256_A,
+                "fees_paid_satoshis": 705,
+            } }),
+            Ok(WithdrawOnchainOk {
+                txid: TYPED_U256_A,
+                fees_paid_satoshis: Fee(Satoshis(705)),
+            })
+            .into(),
+        );
+    }
+
+    #[test]
+    fn post_hold_invoice() {
+        ser_de_equiv(
+            json!({
+                "middle": VALID_U256_A,
+                "payment_hash": VALID_U256_A,
+                "satoshis": 20
+            }),
+            GenerateHoldInvoiceRequest {
+                middle: Middle(TYPED_U256_A),
+                payment_hash: TYPED_U256_A,
+                satoshis: Some(Satoshis(20)),
+                description: None,
+                description_hash: None,
+                expiry_seconds: None,
+            },
+        );
+        ser_de_equiv(
+            json!({
+                "middle": VALID_U256_A,
+                "preimage": PREIMAGE_A_RAW
+            }),
+            SettleHoldInvoiceRequest {
+                middle: Middle(TYPED_U256_A),
+                preimage: PREIMAGE_A,
+            },
+        );
+        ser_de_equiv(
+            json!({
+                "middle": VALID_U256_A,
+                "payment_hash": VALID_U256_A
+            }),
+            CancelHoldInvoiceRequest {
+                middle: Middle(TYPED_U256_A),
+                payment_hash: TYPED_U256_A,
+            },
+        );
+        ser_de_equiv(
+            json!({ "middle": VALID_U256_A }),
+            CancelInvoiceRequest {
+                middle: Middle(TYPED_U256_A),
+            },
+        );
+        ser_de_equiv::<ResolveHoldInvoiceResponse>(json!({ "ok": null }), Ok(()).into());
+        ser_de_equiv::<ResolveHoldInvoiceResponse>(
+            json!({ "error": { "unauthorized": null } }),
+            Err(ResolveHoldInvoiceErr::Unauthorized(())).into(),
+        );
+        ser_de_equiv::<ResolveHoldInvoiceResponse>(
+            json!({ "error": { "not_accepted": null } }),
+            Err(ResolveHoldInvoiceErr::NotAccepted(())).into(),
+        );
+    }
+
     #[test]
     fn get_balance() {
         ser_de_equiv::<CheckBalanceResponse>(
@@ -282,6 +1181,10 @@ mod test {
             json!({ "ok": { "waiting": null } }),
             Ok(CheckInvoiceOk::Waiting(())).into(),
         );
+        ser_de_equiv::<CheckInvoiceResponse>(
+            json!({ "ok": { "accepted": null } }),
+            Ok(CheckInvoiceOk::Accepted(())).into(),
+        );
         ser_de_equiv::<CheckInvoiceResponse>(
             json!({ "ok": {
                 "paid": {
@@ -319,4 +1222,199 @@ mod test {
             .into(),
         );
     }
+
+    #[test]
+    fn post_name() {
+        ser_de_equiv(
+            json!({ "middle": VALID_U256_A, "name": "alice" }),
+            RegisterNameRequest {
+                middle: Middle(TYPED_U256_A),
+                name: "alice".to_owned(),
+            },
+        );
+        ser_de_equiv::<RegisterNameResponse>(
+            json!({ "ok": { "address": "alice@pay.example.com" } }),
+            Ok(RegisterNameOk {
+                address: "alice@pay.example.com".to_owned(),
+            })
+            .into(),
+        );
+        ser_de_equiv::<RegisterNameResponse>(
+            json!({ "error": { "taken": null } }),
+            Err(RegisterNameErr::Taken(())).into(),
+        );
+    }
+
+    #[test]
+    fn post_voucher() {
+        ser_de_equiv(
+            json!({
+                "master": VALID_U256_A,
+                "amount_satoshis": 1000,
+                "fee_satoshis": 10,
+                "uses": 5
+            }),
+            CreateVoucherRequest {
+                master: Master(TYPED_U256_A),
+                amount_satoshis: Satoshis(1000),
+                fee_satoshis: Fee(Satoshis(10)),
+                uses: Some(5),
+                expiry_seconds: None,
+            },
+        );
+        ser_de_equiv::<CreateVoucherResponse>(
+            json!({ "ok": { "k1": VALID_U256_A, "lnurl": "lnurl1dp68gurn8ghj7" } }),
+            Ok(CreateVoucherOk {
+                k1: TYPED_U256_A,
+                lnurl: "lnurl1dp68gurn8ghj7".to_owned(),
+            })
+            .into(),
+        );
+        ser_de_equiv::<CreateVoucherResponse>(
+            json!({ "error": { "no_uses": null } }),
+            Err(CreateVoucherErr::NoUses(())).into(),
+        );
+    }
+
+    #[test]
+    fn lnurl_withdraw() {
+        ser_de_equiv::<LnurlResponse<LnurlWithdrawRequest>>(
+            json!({
+                "tag": "withdrawRequest",
+                "callback": "https://pay.example.com/lnurlw/callback",
+                "k1": VALID_U256_A,
+                "defaultDescription": "voucher",
+                "minWithdrawable": 1000,
+                "maxWithdrawable": 2000000
+            }),
+            LnurlResponse::Ok(LnurlWithdrawRequest {
+                tag: LnurlTag::WithdrawRequest,
+                callback: "https://pay.example.com/lnurlw/callback".parse().unwrap(),
+                k1: TYPED_U256_A,
+                default_description: "voucher".to_owned(),
+                min_withdrawable: 1000,
+                max_withdrawable: 2000000,
+            }),
+        );
+        ser_de_equiv::<LnurlResponse<LnurlOk>>(
+            json!({ "status": "OK" }),
+            LnurlResponse::Ok(LnurlOk {
+                status: LnurlStatus::Ok,
+            }),
+        );
+        ser_de_equiv::<LnurlResponse<LnurlOk>>(
+            json!({ "status": "ERROR", "reason": "voucher expired" }),
+            LnurlResponse::Error(LnurlError::new("voucher expired")),
+        );
+    }
+
+    #[test]
+    fn post_auth() {
+        ser_de_equiv(json!({}), AuthChallengeRequest { link: None });
+        ser_de_equiv(
+            json!({ "link": { "middle": VALID_U256_A } }),
+            AuthChallengeRequest {
+                link: Some(Capability::Middle(Middle(TYPED_U256_A))),
+            },
+        );
+        ser_de_equiv(
+            json!({ "k1": VALID_U256_A, "token": VALID_U256_A, "lnurl": "lnurl1dp68gurn8ghj7" }),
+            AuthChallengeResponse {
+                k1: TYPED_U256_A,
+                token: TYPED_U256_A,
+                lnurl: "lnurl1dp68gurn8ghj7".to_owned(),
+            },
+        );
+    }
+
+    #[test]
+    fn session_responses() {
+        ser_de_equiv::<SessionCheckBalanceResponse>(
+            json!({ "error": { "unauthorized": null } }),
+            Err(SessionErr::Session(SessionRefused::Unauthorized(()))).into(),
+        );
+        ser_de_equiv::<SessionCheckBalanceResponse>(
+            json!({ "error": { "no_balance": null } }),
+            Err(SessionErr::Other(CheckBalanceErr::NoBalance(()))).into(),
+        );
+        ser_de_equiv::<SessionPayInvoiceResponse>(
+            json!({ "error": { "session_expired": null } }),
+            Err(SessionErr::Session(SessionRefused::SessionExpired(()))).into(),
+        );
+        ser_de_equiv(
+            json!({
+                "invoice": VALID_INVOICE_A,
+                "amount_satoshis": 10,
+                "fee_satoshis": 1
+            }),
+            SessionPayInvoiceRequest {
+                invoice: InvoiceSerDe(VALID_INVOICE_A.parse().unwrap()),
+                amount_satoshis: Satoshis(10),
+                fee_satoshis: Fee(Satoshis(1)),
+            },
+        );
+    }
+
+    #[test]
+    fn post_pay_lnurl() {
+        ser_de_equiv(
+            json!({
+                "master": VALID_U256_A,
+                "target": "alice@pay.example.com",
+                "amount_satoshis": 21,
+                "fee_satoshis": 1
+            }),
+            PayLnurlRequest {
+                master: Master(TYPED_U256_A),
+                target: "alice@pay.example.com".to_owned(),
+                amount_satoshis: Satoshis(21),
+                fee_satoshis: Fee(Satoshis(1)),
+            },
+        );
+        ser_de_equiv::<PayLnurlResponse>(
+            json!({ "error": { "refused": "unknown name" } }),
+            Err(PayLnurlErr::Refused("unknown name".to_owned())).into(),
+        );
+        ser_de_equiv::<PayLnurlResponse>(
+            json!({ "error": { "invoice_mismatch": null } }),
+            Err(PayLnurlErr::InvoiceMismatch(())).into(),
+        );
+    }
+
+    #[test]
+    fn lnurl_pay() {
+        ser_de_equiv::<LnurlResponse<LnurlPayRequest>>(
+            json!({
+                "tag": "payRequest",
+                "callback": "https://pay.example.com/lnurlp/alice/callback",
+                "minSendable": 1000,
+                "maxSendable": 5000000,
+                "metadata": "[[\"text/plain\",\"tea\"]]"
+            }),
+            LnurlResponse::Ok(LnurlPayRequest {
+                tag: LnurlTag::PayRequest,
+                callback: "https://pay.example.com/lnurlp/alice/callback"
+                    .parse()
+                    .unwrap(),
+                min_sendable: 1000,
+                max_sendable: 5000000,
+                metadata: r#"[["text/plain","tea"]]"#.to_owned(),
+            }),
+        );
+        ser_de_equiv::<LnurlResponse<LnurlPayRequest>>(
+            json!({ "status": "ERROR", "reason": "unknown name" }),
+            LnurlResponse::Error(LnurlError::new("unknown name")),
+        );
+        ser_de_equiv(
+            json!({ "amount": 21000 }),
+            LnurlPayCallbackQuery { amount: 21000 },
+        );
+        ser_de_equiv::<LnurlResponse<LnurlPayInvoice>>(
+            json!({ "pr": VALID_INVOICE_A, "routes": [] }),
+            LnurlResponse::Ok(LnurlPayInvoice {
+                pr: InvoiceSerDe(VALID_INVOICE_A.parse().unwrap()),
+                routes: vec![],
+            }),
+        );
+    }
 }
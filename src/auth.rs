@@ -22,6 +22,31 @@ pub struct Middle(pub U256);
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Debug)]
 pub struct Lesser(pub U256);
 
+/// Either secret level, as held by a login session.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Master(Master),
+    Middle(Middle),
+}
+
+impl Capability {
+    /// Every capability can view the balance.
+    pub fn middle(&self) -> Middle {
+        match self {
+            Capability::Master(master) => (*master).into(),
+            Capability::Middle(middle) => *middle,
+        }
+    }
+
+    pub fn master(&self) -> Option<Master> {
+        match self {
+            Capability::Master(master) => Some(*master),
+            Capability::Middle(_) => None,
+        }
+    }
+}
+
 /// Anyone can check invoice status if they have the 256 bit payment-hash for the invoice.
 
 impl Master {
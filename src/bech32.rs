@@ -0,0 +1,225 @@
+//! The bech32 character set and conversion between bytes and 5 bit groups. BOLT12 strings use
+//! the character set without bech32's checksum, LNURLs are checksummed bech32 (BIP173) without
+//! its length limit.
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const SEPARATOR: char = '1';
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bech32Error {
+    /// No '1' separates the human readable part from the data.
+    NoSeparator,
+    /// A character outside the bech32 character set.
+    InvalidChar(char),
+    /// Upper and lower case characters are mixed.
+    MixedCase,
+    /// The data does not end on a byte boundary, or the padding is not zero.
+    InvalidPadding,
+    /// The checksum is missing or does not match.
+    InvalidChecksum,
+}
+
+/// hrp followed by the separator, data and a checksum.
+pub fn encode(hrp: &str, data: &[u8]) -> String {
+    let groups = to_base32(data);
+    let checksum = checksum(hrp, &groups);
+    let mut encoded = String::with_capacity(hrp.len() + 1 + groups.len() + checksum.len());
+    encoded.push_str(hrp);
+    encoded.push(SEPARATOR);
+    encoded.extend(
+        groups
+            .iter()
+            .chain(checksum.iter())
+            .map(|group| CHARSET[*group as usize] as char),
+    );
+    encoded
+}
+
+/// hrp followed by the separator and data, without a checksum.
+pub fn encode_unchecked(hrp: &str, data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(hrp.len() + 1 + (data.len() * 8 + 4) / 5);
+    encoded.push_str(hrp);
+    encoded.push(SEPARATOR);
+    encoded.extend(
+        to_base32(data)
+            .into_iter()
+            .map(|group| CHARSET[group as usize] as char),
+    );
+    encoded
+}
+
+/// Split a checksummed string into its lower cased human readable part and data.
+pub fn decode(encoded: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    let (hrp, groups) = split(encoded)?;
+    if groups.len() < 6 || polymod(expand_hrp(&hrp).chain(groups.iter().cloned())) != 1 {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+    let data = from_base32(&groups[..groups.len() - 6])?;
+    Ok((hrp, data))
+}
+
+/// Split a string without a checksum into its lower cased human readable part and data.
+pub fn decode_unchecked(encoded: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    let (hrp, groups) = split(encoded)?;
+    Ok((hrp, from_base32(&groups)?))
+}
+
+/// The lower cased human readable part and 5 bit groups of encoded.
+fn split(encoded: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    let has_lower = encoded.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = encoded.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(Bech32Error::MixedCase);
+    }
+    let encoded = encoded.to_ascii_lowercase();
+    let separator = encoded.rfind(SEPARATOR).ok_or(Bech32Error::NoSeparator)?;
+    let (hrp, data) = (&encoded[..separator], &encoded[separator + 1..]);
+    let groups = data
+        .chars()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|&x| x as char == c)
+                .map(|group| group as u8)
+                .ok_or(Bech32Error::InvalidChar(c))
+        })
+        .collect::<Result<Vec<u8>, Bech32Error>>()?;
+    Ok((hrp.to_owned(), groups))
+}
+
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    values.fold(1, |chk, value| {
+        let top = chk >> 25;
+        let chk = (chk & 0x01ff_ffff) << 5 ^ u32::from(value);
+        (0..5)
+            .filter(|i| top >> i & 1 == 1)
+            .fold(chk, |chk, i| chk ^ GENERATOR[i])
+    })
+}
+
+fn expand_hrp(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 0x1f))
+}
+
+/// The six 5 bit groups that make the polymod of hrp and groups one.
+fn checksum(hrp: &str, groups: &[u8]) -> [u8; 6] {
+    let values = expand_hrp(hrp)
+        .chain(groups.iter().cloned())
+        .chain([0; 6].iter().cloned());
+    let polymod = polymod(values) ^ 1;
+    let mut checksum = [0; 6];
+    for (i, group) in checksum.iter_mut().enumerate() {
+        *group = (polymod >> (5 * (5 - i)) & 0x1f) as u8;
+    }
+    checksum
+}
+
+/// Regroup bytes into 5 bit groups, padding the last group with zeros.
+pub fn to_base32(data: &[u8]) -> Vec<u8> {
+    let mut groups = Vec::with_capacity((data.len() * 8 + 4) / 5);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for byte in data {
+        acc = acc << 8 | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            groups.push((acc >> bits & 0x1f) as u8);
+        }
+    }
+    if bits > 0 {
+        groups.push((acc << (5 - bits) & 0x1f) as u8);
+    }
+    groups
+}
+
+/// Regroup 5 bit groups into bytes. Fewer than 5 bits of zero padding may be left over.
+pub fn from_base32(groups: &[u8]) -> Result<Vec<u8>, Bech32Error> {
+    let mut data = Vec::with_capacity(groups.len() * 5 / 8);
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for group in groups {
+        acc = (acc << 5 | u32::from(*group)) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((acc >> bits) as u8);
+        }
+    }
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for len in 0..12 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 5) as u8).collect();
+            let encoded = encode_unchecked("lno", &data);
+            assert_eq!(decode_unchecked(&encoded), Ok(("lno".to_owned(), data)));
+            assert_eq!(
+                decode_unchecked(&encoded.to_ascii_uppercase()).map(|(_, data)| data.len()),
+                Ok(len)
+            );
+        }
+        assert_eq!(encode_unchecked("a", &[0xff]), "a1lu");
+        assert_eq!(decode_unchecked("a1lu"), Ok(("a".to_owned(), vec![0xff])));
+    }
+
+    #[test]
+    fn checksummed() {
+        // test vectors from BIP173
+        assert_eq!(encode("a", &[]), "a12uel5l");
+        assert_eq!(
+            encode(
+                "abcdef",
+                &from_base32(&(0..32).collect::<Vec<u8>>()).unwrap()
+            ),
+            "abcdef1qpzry9x8gf2tvdw0s3jn54khce6mua7lmqqqxw"
+        );
+        // from LUD-01
+        assert_eq!(
+            encode(
+                "lnurl",
+                b"https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df"
+            )
+            .to_ascii_uppercase(),
+            "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS"
+        );
+        assert_eq!(decode("A12UEL5L"), Ok(("a".to_owned(), vec![])));
+        for len in 0..12 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 5) as u8).collect();
+            assert_eq!(
+                decode(&encode("lnurl", &data)),
+                Ok(("lnurl".to_owned(), data))
+            );
+        }
+        assert_eq!(decode("a12uel5m"), Err(Bech32Error::InvalidChecksum));
+        assert_eq!(decode("a1qqq"), Err(Bech32Error::InvalidChecksum));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(decode_unchecked("qqqq"), Err(Bech32Error::NoSeparator));
+        assert_eq!(decode_unchecked("a1qb"), Err(Bech32Error::InvalidChar('b')));
+        assert_eq!(decode_unchecked("a1Qq"), Err(Bech32Error::MixedCase));
+        // 0xff followed by a non-zero padding bit
+        assert_eq!(decode_unchecked("a1l7"), Err(Bech32Error::InvalidPadding));
+        // a whole spare group
+        assert_eq!(decode_unchecked("a1luq"), Err(Bech32Error::InvalidPadding));
+    }
+}
@@ -0,0 +1,1607 @@
+//! Core Lightning backend. Talks JSON-RPC over the node's `lightning-rpc` unix socket.
+//!
+//! Each call opens its own connection and runs on its own thread, so long-polling calls like
+//! waitanyinvoice don't hold up other requests.
+
+use crate::common::*;
+use futures::{
+    future::{self, FutureResult},
+    stream,
+    sync::oneshot,
+    Future, Stream,
+};
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    io::Write,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    thread,
+};
+
+const BACKEND_NAME: &str = "cln";
+const MSAT_PER_SATOSHI: u64 = 1000;
+/// The riskfactor `pay` uses by default, so quotes follow the routes payments will take.
+const ROUTE_RISK_FACTOR: u64 = 10;
+/// How long each waitblockheight call waits for a new block before asking again.
+const BLOCK_WAIT_SECONDS: u64 = 600;
+/// Error code returned by waitblockheight when no block arrived in time.
+const WAIT_TIMEOUT_CODE: i64 = 2000;
+
+/// Error code returned by delinvoice when the invoice is no longer in the expected status.
+const DELINVOICE_STATUS_MISMATCH_CODE: i64 = 906;
+
+/// Error codes returned by `pay` after which the payment is known to have stopped.
+const PAY_ABORTED_CODES: [i64; 6] = [
+    203, // destination permanently failed the payment
+    204, // the last route tried failed, and no other was found
+    205, // unable to find a route
+    206, // route too expensive for maxfee
+    207, // invoice expired
+    210, // retry_for ran out, every attempt has been resolved
+];
+
+/// Error codes returned by `withdraw` when no transaction was broadcast.
+const WITHDRAW_ABORTED_CODES: [i64; 4] = [
+    -32602, // invalid parameters, such as an address for another network
+    300,    // amount above the maximum
+    301,    // insufficient funds
+    302,    // output would be dust
+];
+/// Weight of a withdrawal spending one segwit output, paying the address and returning
+/// change. Wallets with only small outputs spend more.
+const WITHDRAW_WEIGHT: u64 = 610;
+
+pub struct ClnClient {
+    socket: PathBuf,
+    /// File holding the pay_index of the last paid invoice handed to a subscriber. Paid
+    /// invoices after this index are replayed on the next subscription.
+    pay_index: Arc<PathBuf>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ClnClient {
+    pub fn new(socket: impl Into<PathBuf>, pay_index: impl Into<PathBuf>) -> ClnClient {
+        ClnClient {
+            socket: socket.into(),
+            pay_index: Arc::new(pay_index.into()),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The feerate in satoshis per kiloweight to confirm within target_blocks, and the fee a
+    /// withdrawal pays at it. Uses the estimate for the longest target within target_blocks,
+    /// or the shortest target if cln has none that short.
+    fn withdraw_fee(
+        &self,
+        target_blocks: u32,
+    ) -> DynFut<(u64, Fee<Satoshis>), EstimateOnchainFeeError> {
+        Box::new(
+            self.call("feerates", json!({ "style": "perkw" }))
+                .map_err(|err| EstimateOnchainFeeError::Unknown(format!("{:?}", err)))
+                .and_then(move |FeeRatesResponse { perkw }| {
+                    let estimates = perkw.estimates;
+                    let estimate = estimates
+                        .iter()
+                        .filter(|estimate| estimate.blockcount <= target_blocks)
+                        .max_by_key(|estimate| estimate.blockcount)
+                        .or_else(|| estimates.iter().min_by_key(|estimate| estimate.blockcount))
+                        .ok_or_else(|| {
+                            EstimateOnchainFeeError::Unknown(
+                                "cln has no feerate estimates yet".to_owned(),
+                            )
+                        })?;
+                    let fee = (estimate.feerate * WITHDRAW_WEIGHT + 999) / 1000;
+                    Ok((estimate.feerate, Fee(Satoshis(fee))))
+                }),
+        )
+    }
+
+    fn call<T: DeserializeOwned + Send + 'static>(
+        &self,
+        method: &'static str,
+        params: Value,
+    ) -> DynFut<T, RpcError> {
+        call(
+            self.socket.clone(),
+            self.next_id.fetch_add(1, Ordering::Relaxed),
+            method,
+            params,
+        )
+    }
+}
+
+impl LightningNode for ClnClient {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        // cln computes the description hash itself, so it needs the full description.
+        let (description, deschashonly) = match (spec.description(), spec.hashed_description()) {
+            (Description::Direct(memo), _) => (memo.clone(), false),
+            (Description::Hash(_), Some(description)) => (description.to_owned(), true),
+            (Description::Hash(_), None) => {
+                return Box::new(FutureResult::from(Err(CreateInvoiceError::Unsupported(
+                    "core lightning can't create an invoice from a description hash alone",
+                ))));
+            }
+        };
+        let msatoshi = match spec.satoshis() {
+            Some(satoshis) => match to_msat(satoshis) {
+                Some(msat) => json!(msat),
+                None => {
+                    return Box::new(FutureResult::from(Err(CreateInvoiceError::Unknown(
+                        format!("invoice amount {} overflowed max value for cln", satoshis.0),
+                    ))));
+                }
+            },
+            None => json!("any"),
+        };
+        let mut params = json!({
+            "msatoshi": msatoshi,
+            // labels must be unique per node
+            "label": format!("lapi-{}", U256::random()),
+            "description": description,
+            "expiry": spec.expiry().as_secs(),
+        });
+        if deschashonly {
+            // the invoice carries only the hash
+            params["deschashonly"] = json!(true);
+        }
+        Box::new(
+            self.call("invoice", params)
+                .map_err(|err| CreateInvoiceError::Network {
+                    backend_name: BACKEND_NAME.to_owned(),
+                    err: format!("{:?}", err),
+                })
+                .and_then(|InvoiceResponse { bolt11 }| {
+                    parse_bolt11(&bolt11).map_err(CreateInvoiceError::InvalidInvoice)
+                }),
+        )
+    }
+
+    /// cln splits payments as it sees fit; `pay` takes no limit on the number of parts, so
+    /// multi_path.max_parts is not enforced.
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        let (msat, max_fee_msat) = match (to_msat(amount), to_msat(max_fee.0)) {
+            (Some(msat), Some(max_fee_msat)) => (msat, max_fee_msat),
+            _ => {
+                return Box::new(FutureResult::from(Err(PayError::Unknown(format!(
+                    "payment amount {} or max_fee {} overflowed max value for cln",
+                    amount.0,
+                    (max_fee.0).0
+                )))));
+            }
+        };
+        let socket = self.socket.clone();
+        let next_id = self.next_id.clone();
+        let mut params = json!({
+            "bolt11": to_bolt11(&invoice),
+            "maxfee": max_fee_msat,
+            "retry_for": multi_path.timeout.as_secs(),
+        });
+        // cln rejects an amount for invoices which specify their own
+        if invoice.amount_pico_btc().is_none() {
+            params["msatoshi"] = json!(msat);
+        }
+        Box::new(
+            self.call("pay", params)
+                .map_err(|err| match err {
+                    RpcError::Rpc { code, .. } if PAY_ABORTED_CODES.contains(&code) => {
+                        PayError::PaymentAborted
+                    }
+                    other => PayError::Unknown(format!("{:?}", other)),
+                })
+                .and_then(move |response: PayResponse| {
+                    let PayResponse {
+                        payment_preimage,
+                        amount_msat,
+                        amount_sent_msat,
+                    } = response;
+                    let fee_msat =
+                        amount_sent_msat
+                            .0
+                            .checked_sub(amount_msat.0)
+                            .ok_or_else(|| {
+                                PayError::Unknown(format!(
+                                    "cln sent {} msat, less than the amount {} msat",
+                                    amount_sent_msat.0, amount_msat.0
+                                ))
+                            })?;
+                    // Round partial satoshis of fee up, so the fee is never under-reported.
+                    // maxfee was given in whole satoshis, so this can't exceed max_fee.
+                    let fees_paid = Fee(Satoshis(
+                        (fee_msat + MSAT_PER_SATOSHI - 1) / MSAT_PER_SATOSHI,
+                    ));
+                    // the amount cln delivered, rounded up to a whole satoshi like the invoice's
+                    let amount_paid =
+                        Satoshis((amount_msat.0 + MSAT_PER_SATOSHI - 1) / MSAT_PER_SATOSHI);
+                    let paid_invoice = PaidInvoice::create(invoice, payment_preimage, amount_paid)?;
+                    Ok(PaidInvoiceOutgoing {
+                        paid_invoice,
+                        fees_offered: max_fee,
+                        fees_paid,
+                        parts: 1,
+                    })
+                })
+                .and_then(move |outgoing| {
+                    let payment_hash = get_payment_hash(outgoing.paid_invoice.invoice());
+                    call(
+                        socket,
+                        next_id.fetch_add(1, Ordering::Relaxed),
+                        "listsendpays",
+                        json!({ "payment_hash": payment_hash }),
+                    )
+                    .then(
+                        move |sendpays: Result<ListSendPaysResponse, RpcError>| {
+                            // Each part of the payment is a completed sendpay. The payment succeeded
+                            // either way, so failing to count them is not reported as a failure.
+                            let parts = sendpays
+                                .map(|ListSendPaysResponse { payments }| {
+                                    payments
+                                        .iter()
+                                        .filter(|sendpay| sendpay.status == "complete")
+                                        .count() as u32
+                                })
+                                .unwrap_or(1);
+                            Ok(PaidInvoiceOutgoing {
+                                parts: parts.max(1),
+                                ..outgoing
+                            })
+                        },
+                    )
+                }),
+        )
+    }
+
+    fn paid_invoices(
+        &self,
+    ) -> crate::lighting_node::DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        let socket = self.socket.clone();
+        let next_id = self.next_id.clone();
+        let index_file = self.pay_index.clone();
+        let lastpay_index = match read_pay_index(&index_file) {
+            Ok(index) => index,
+            Err(err) => {
+                return Box::new(stream::once(Err(SubscribePaidInvoicesError::Unknown(
+                    format!("reading pay index {:?}: {:?}", index_file, err),
+                ))));
+            }
+        };
+        // The state is the pay_index of the last invoice yielded. The subscriber has finished
+        // with that invoice by the time the next one is requested, so that is when the index
+        // is persisted.
+        let stream = stream::unfold((lastpay_index, false), move |(lastpay_index, yielded)| {
+            let persisted = if yielded {
+                write_pay_index(&index_file, lastpay_index)
+            } else {
+                Ok(())
+            };
+            let socket = socket.clone();
+            let id = next_id.fetch_add(1, Ordering::Relaxed);
+            let fut = FutureResult::from(persisted)
+                .map_err(|err| {
+                    SubscribePaidInvoicesError::Unknown(format!("persisting pay index: {:?}", err))
+                })
+                .and_then(move |()| {
+                    call(
+                        socket,
+                        id,
+                        "waitanyinvoice",
+                        json!({ "lastpay_index": lastpay_index }),
+                    )
+                    .map_err(|err| SubscribePaidInvoicesError::Unknown(format!("{:?}", err)))
+                })
+                .and_then(|response: WaitAnyInvoiceResponse| {
+                    let pay_index = response.pay_index;
+                    // cln adds an invoice without a bolt11 for each keysend it receives, they
+                    // are passed over rather than failing the subscription on every retry
+                    if response.bolt11.is_none() {
+                        return Ok((None, (pay_index, true)));
+                    }
+                    to_paid_invoice(response)
+                        .map(|paid| (Some(paid), (pay_index, true)))
+                        .map_err(|err| SubscribePaidInvoicesError::Unknown(format!("{:?}", err)))
+                });
+            Some(fut)
+        });
+        Box::new(stream.filter_map(|paid| paid))
+    }
+
+    /// cln generates the preimage and sends it in the record keysend payees expect.
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        let (msat, max_fee_msat) = match (to_msat(amount), to_msat(max_fee.0)) {
+            (Some(msat), Some(max_fee_msat)) => (msat, max_fee_msat),
+            _ => {
+                return Box::new(FutureResult::from(Err(PayError::Unknown(format!(
+                    "keysend amount {} or max_fee {} overflowed max value for cln",
+                    amount.0,
+                    (max_fee.0).0
+                )))));
+            }
+        };
+        let params = json!({
+            "destination": hex::encode(&pubkey.serialize()[..]),
+            "msatoshi": msat,
+            "maxfee": max_fee_msat,
+            "retry_for": MultiPath::default().timeout.as_secs(),
+        });
+        Box::new(
+            self.call("keysend", params)
+                .map_err(|err| match err {
+                    RpcError::Rpc { code, .. } if PAY_ABORTED_CODES.contains(&code) => {
+                        PayError::PaymentAborted
+                    }
+                    other => PayError::Unknown(format!("{:?}", other)),
+                })
+                .and_then(move |response: PayResponse| {
+                    let PayResponse {
+                        payment_preimage,
+                        amount_msat,
+                        amount_sent_msat,
+                    } = response;
+                    let fee_msat =
+                        amount_sent_msat
+                            .0
+                            .checked_sub(amount_msat.0)
+                            .ok_or_else(|| {
+                                PayError::Unknown(format!(
+                                    "cln sent {} msat, less than the amount {} msat",
+                                    amount_sent_msat.0, amount_msat.0
+                                ))
+                            })?;
+                    Ok(KeysendOutgoing {
+                        preimage: payment_preimage,
+                        amount,
+                        fees_offered: max_fee,
+                        // rounded up, as for pay_invoice
+                        fees_paid: Fee(Satoshis(
+                            (fee_msat + MSAT_PER_SATOSHI - 1) / MSAT_PER_SATOSHI,
+                        )),
+                    })
+                }),
+        )
+    }
+
+    /// cln only shows the custom records of incoming keysends to plugins, so the Lesser to
+    /// credit can't be known. The subscription fails rather than silently never reporting.
+    fn received_keysends(
+        &self,
+    ) -> crate::lighting_node::DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        Box::new(stream::once(Err(SubscribePaidInvoicesError::Unknown(
+            "crediting keysends is not supported by core lightning without a plugin".to_owned(),
+        ))))
+    }
+
+    fn create_hold_invoice(
+        &self,
+        _spec: InvoiceSpec,
+        _payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        // Holding payments requires a plugin on the cln side.
+        Box::new(FutureResult::from(Err(CreateInvoiceError::Unsupported(
+            "hold invoices are not supported by core lightning without a plugin",
+        ))))
+    }
+
+    fn settle_hold_invoice(&self, _preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        Box::new(FutureResult::from(Err(HoldInvoiceError::Unsupported(
+            "hold invoices are not supported by core lightning without a plugin",
+        ))))
+    }
+
+    fn cancel_hold_invoice(&self, _payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        Box::new(FutureResult::from(Err(HoldInvoiceError::Unsupported(
+            "hold invoices are not supported by core lightning without a plugin",
+        ))))
+    }
+
+    fn accepted_invoices(
+        &self,
+    ) -> crate::lighting_node::DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        Box::new(future::empty().into_stream())
+    }
+
+    /// getroute proposes a single route, so the expected fee is also the worst case.
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        let msat = match to_msat(amount) {
+            Some(msat) => msat,
+            None => {
+                return Box::new(FutureResult::from(Err(EstimateFeeError::Unknown(format!(
+                    "quote amount {} overflowed max value for cln",
+                    amount.0
+                )))));
+            }
+        };
+        let params = json!({
+            "id": hex::encode(&invoice.recover_payee_pub_key().0.serialize()[..]),
+            "msatoshi": msat,
+            "riskfactor": ROUTE_RISK_FACTOR,
+        });
+        Box::new(
+            self.call("getroute", params)
+                .map_err(|err| match err {
+                    RpcError::Rpc { code: 205, .. } => EstimateFeeError::NoRoute,
+                    other => EstimateFeeError::Unknown(format!("{:?}", other)),
+                })
+                .and_then(move |GetRouteResponse { route }| {
+                    // the first hop carries the amount plus every fee along the route
+                    let sent = route.first().ok_or(EstimateFeeError::NoRoute)?.amount_msat;
+                    let fee_msat = sent.0.checked_sub(msat).ok_or_else(|| {
+                        EstimateFeeError::Unknown(format!(
+                            "cln route sends {} msat, less than the amount {} msat",
+                            sent.0, msat
+                        ))
+                    })?;
+                    let fee = Fee(Satoshis(
+                        (fee_msat + MSAT_PER_SATOSHI - 1) / MSAT_PER_SATOSHI,
+                    ));
+                    Ok(FeeQuote {
+                        expected: fee,
+                        worst_case: fee,
+                    })
+                }),
+        )
+    }
+
+    /// Capacity is summed over channels in normal operation with a connected peer. Channel
+    /// reserves are not subtracted.
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        let info = self.call("getinfo", json!({}));
+        let funds = self.call("listfunds", json!({}));
+        Box::new(
+            info.join(funds)
+                .map_err(|err| NodeInfoError::Unknown(format!("{:?}", err)))
+                .and_then(|(info, funds): (GetInfoResponse, ListFundsResponse)| {
+                    let pubkey = hex::decode(&info.id)
+                        .ok()
+                        .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+                        .ok_or_else(|| {
+                            NodeInfoError::Unknown(format!(
+                                "cln reported invalid node id {:?}",
+                                info.id
+                            ))
+                        })?;
+                    let mut inbound_msat: u64 = 0;
+                    let mut outbound_msat: u64 = 0;
+                    for channel in funds.channels {
+                        if !channel.connected || channel.state != "CHANNELD_NORMAL" {
+                            continue;
+                        }
+                        let remote = channel
+                            .amount_msat
+                            .0
+                            .checked_sub(channel.our_amount_msat.0)
+                            .ok_or_else(|| {
+                                NodeInfoError::Unknown(format!(
+                                    "cln reported a channel balance above its capacity {:?}",
+                                    channel
+                                ))
+                            })?;
+                        inbound_msat += remote;
+                        outbound_msat += channel.our_amount_msat.0;
+                    }
+                    // partial satoshis can't be spent or received
+                    Ok(NodeInfo {
+                        alias: info.alias,
+                        pubkey,
+                        capacity: Capacity {
+                            inbound: Satoshis(inbound_msat / MSAT_PER_SATOSHI),
+                            outbound: Satoshis(outbound_msat / MSAT_PER_SATOSHI),
+                        },
+                    })
+                }),
+        )
+    }
+
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        Box::new(
+            self.call("newaddr", json!({ "addresstype": "bech32" }))
+                .map(|NewAddrResponse { bech32 }| Address(bech32))
+                .map_err(|err| NewAddressError::Unknown(format!("{:?}", err))),
+        )
+    }
+
+    /// cln has no subscription for wallet outputs. The wallet is listed once on subscription,
+    /// then again after each block, so outputs still in the mempool are first reported when
+    /// the next block arrives.
+    fn incoming_transactions(
+        &self,
+    ) -> crate::lighting_node::DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        let socket = self.socket.clone();
+        let next_id = self.next_id.clone();
+        // The state is the height last listed at, along with the confirmations last reported
+        // for each output.
+        let stream = stream::unfold(
+            (None, BTreeMap::new()),
+            move |(height, mut reported): (Option<u64>, BTreeMap<OutPoint, u32>)| {
+                let wait: DynFut<BlockHeight, RpcError> = match height {
+                    None => call(
+                        socket.clone(),
+                        next_id.fetch_add(1, Ordering::Relaxed),
+                        "getinfo",
+                        json!({}),
+                    ),
+                    Some(height) => Box::new(
+                        call(
+                            socket.clone(),
+                            next_id.fetch_add(1, Ordering::Relaxed),
+                            "waitblockheight",
+                            json!({ "blockheight": height + 1, "timeout": BLOCK_WAIT_SECONDS }),
+                        )
+                        .or_else(move |err| match err {
+                            RpcError::Rpc {
+                                code: WAIT_TIMEOUT_CODE,
+                                ..
+                            } => Ok(BlockHeight {
+                                blockheight: height,
+                            }),
+                            other => Err(other),
+                        }),
+                    ),
+                };
+                let socket = socket.clone();
+                let id = next_id.fetch_add(1, Ordering::Relaxed);
+                let fut = wait
+                    .and_then(move |BlockHeight { blockheight }| {
+                        call(socket, id, "listfunds", json!({}))
+                            .map(move |funds: ListOutputsResponse| (blockheight, funds))
+                    })
+                    .map_err(|err| SubscribePaidInvoicesError::Unknown(format!("{:?}", err)))
+                    .and_then(move |(blockheight, funds)| {
+                        let mut changed = Vec::new();
+                        for output in funds.outputs {
+                            let transaction = to_incoming_transaction(output, blockheight)?;
+                            if let Some(transaction) = transaction {
+                                let previous = reported
+                                    .insert(transaction.outpoint, transaction.confirmations);
+                                if previous != Some(transaction.confirmations) {
+                                    changed.push(transaction);
+                                }
+                            }
+                        }
+                        let changed = stream::iter_ok::<_, SubscribePaidInvoicesError>(changed);
+                        Ok((changed, (Some(blockheight), reported)))
+                    });
+                Some(fut)
+            },
+        );
+        Box::new(stream.flatten())
+    }
+
+    /// feerates knows nothing of the address, an invalid one is only refused by send_onchain.
+    fn estimate_onchain_fee(
+        &self,
+        _address: Address,
+        _amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        Box::new(self.withdraw_fee(target_blocks).map(|(_, fee)| fee))
+    }
+
+    /// withdraw reports the transaction but not the fee it paid, so the fee is estimated again
+    /// and the transaction sent at the estimate's feerate. The account is charged the estimate.
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        let socket = self.socket.clone();
+        let next_id = self.next_id.clone();
+        let fut = self
+            .withdraw_fee(target_blocks)
+            // Nothing was sent.
+            .map_err(|_| PayError::PaymentAborted)
+            .and_then(move |(feerate, fees_paid)| {
+                if fees_paid > max_fee {
+                    // The fee went up since it was quoted.
+                    return future::Either::A(FutureResult::from(Err(PayError::PaymentAborted)));
+                }
+                let params = json!({
+                    "destination": address.0,
+                    "satoshi": amount.0,
+                    "feerate": format!("{}perkw", feerate),
+                });
+                let sent = call(
+                    socket,
+                    next_id.fetch_add(1, Ordering::Relaxed),
+                    "withdraw",
+                    params,
+                )
+                .map(move |WithdrawResponse { txid }| OnchainOutgoing {
+                    txid,
+                    amount,
+                    fees_offered: max_fee,
+                    fees_paid,
+                })
+                .map_err(|err| match err {
+                    RpcError::Rpc { code, .. } if WITHDRAW_ABORTED_CODES.contains(&code) => {
+                        PayError::PaymentAborted
+                    }
+                    other => PayError::Unknown(format!("{:?}", other)),
+                });
+                future::Either::B(sent)
+            });
+        Box::new(fut)
+    }
+
+    /// cln has no cancelled state; the unpaid invoice is deleted, so payments to it fail.
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        let socket = self.socket.clone();
+        let next_id = self.next_id.clone();
+        Box::new(
+            self.call("listinvoices", json!({ "payment_hash": payment_hash }))
+                .map_err(|err| HoldInvoiceError::Unknown(format!("{:?}", err)))
+                .and_then(|ListInvoicesResponse { invoices }| {
+                    // delinvoice takes the label, not the payment hash
+                    match invoices.into_iter().next() {
+                        Some(ref invoice) if invoice.status != "unpaid" => {
+                            Err(HoldInvoiceError::AlreadyResolved)
+                        }
+                        Some(invoice) => Ok(invoice.label),
+                        None => Err(HoldInvoiceError::UnknownInvoice),
+                    }
+                })
+                .and_then(move |label| {
+                    call(
+                        socket,
+                        next_id.fetch_add(1, Ordering::Relaxed),
+                        "delinvoice",
+                        json!({ "label": label, "status": "unpaid" }),
+                    )
+                    .map(|_: Value| ())
+                    .map_err(|err| match err {
+                        // paid or expired since it was listed
+                        RpcError::Rpc {
+                            code: DELINVOICE_STATUS_MISMATCH_CODE,
+                            ..
+                        } => HoldInvoiceError::AlreadyResolved,
+                        other => HoldInvoiceError::Unknown(format!("{:?}", other)),
+                    })
+                }),
+        )
+    }
+
+    fn create_offer(&self, _spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        // cln's offer command answers invoice requests with bolt12 invoices, which Invoice
+        // can't represent, and reports their payment apart from listinvoices' bolt11 ones.
+        Box::new(FutureResult::from(Err(CreateOfferError::Unsupported(
+            "offers are not yet implemented for core lightning",
+        ))))
+    }
+
+    fn offer_invoices(
+        &self,
+    ) -> crate::lighting_node::DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        Box::new(future::empty().into_stream())
+    }
+}
+
+#[derive(Debug)]
+pub enum RpcError {
+    Io(io::Error),
+    /// The response was not valid json, or did not have the expected shape.
+    Json(serde_json::Error),
+    /// The node returned an error object.
+    Rpc {
+        code: i64,
+        message: String,
+    },
+    /// The node closed the connection without responding.
+    NoResponse,
+}
+
+/// Perform a single json-rpc call on its own connection and thread.
+fn call<T: DeserializeOwned + Send + 'static>(
+    socket: PathBuf,
+    id: u64,
+    method: &'static str,
+    params: Value,
+) -> DynFut<T, RpcError> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        // The receiver may have been dropped, there is nobody to tell.
+        let _ = tx.send(call_blocking(&socket, id, method, params));
+    });
+    Box::new(
+        rx.map_err(|oneshot::Canceled| RpcError::NoResponse)
+            .and_then(FutureResult::from),
+    )
+}
+
+fn call_blocking<T: DeserializeOwned>(
+    socket: &Path,
+    id: u64,
+    method: &str,
+    params: Value,
+) -> Result<T, RpcError> {
+    let mut conn = UnixStream::connect(socket).map_err(RpcError::Io)?;
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    });
+    serde_json::to_writer(&mut conn, &request).map_err(RpcError::Json)?;
+    conn.flush().map_err(RpcError::Io)?;
+    // cln doesn't length prefix responses, read exactly one json value
+    let response: RpcResponse<T> = serde_json::Deserializer::from_reader(&mut conn)
+        .into_iter()
+        .next()
+        .ok_or(RpcError::NoResponse)?
+        .map_err(RpcError::Json)?;
+    match response {
+        RpcResponse {
+            result: Some(result),
+            ..
+        } => Ok(result),
+        RpcResponse {
+            error: Some(RpcErrorObject { code, message }),
+            ..
+        } => Err(RpcError::Rpc { code, message }),
+        _ => Err(RpcError::NoResponse),
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcErrorObject>,
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct InvoiceResponse {
+    bolt11: String,
+}
+
+#[derive(Deserialize)]
+struct ListInvoicesResponse {
+    invoices: Vec<ListedInvoice>,
+}
+
+#[derive(Deserialize)]
+struct ListedInvoice {
+    label: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct PayResponse {
+    payment_preimage: Preimage,
+    amount_msat: Msat,
+    amount_sent_msat: Msat,
+}
+
+#[derive(Deserialize)]
+struct ListSendPaysResponse {
+    payments: Vec<SendPay>,
+}
+
+#[derive(Deserialize)]
+struct SendPay {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct GetRouteResponse {
+    route: Vec<RouteHop>,
+}
+
+#[derive(Deserialize)]
+struct RouteHop {
+    /// Called msatoshi by older versions of cln.
+    #[serde(alias = "msatoshi")]
+    amount_msat: Msat,
+}
+
+#[derive(Deserialize)]
+struct GetInfoResponse {
+    id: String,
+    alias: String,
+}
+
+#[derive(Deserialize)]
+struct ListFundsResponse {
+    channels: Vec<FundedChannel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FundedChannel {
+    connected: bool,
+    state: String,
+    /// Total capacity of the channel.
+    amount_msat: Msat,
+    /// Our side of the channel.
+    our_amount_msat: Msat,
+}
+
+#[derive(Deserialize)]
+struct NewAddrResponse {
+    /// Called address by older versions of cln.
+    #[serde(alias = "address")]
+    bech32: String,
+}
+
+#[derive(Deserialize)]
+struct FeeRatesResponse {
+    perkw: FeeRates,
+}
+
+#[derive(Deserialize)]
+struct FeeRates {
+    /// Absent until bitcoind can estimate fees.
+    #[serde(default)]
+    estimates: Vec<FeeEstimate>,
+}
+
+#[derive(Deserialize)]
+struct FeeEstimate {
+    blockcount: u32,
+    feerate: u64,
+}
+
+#[derive(Deserialize)]
+struct WithdrawResponse {
+    txid: U256,
+}
+
+/// The part of a getinfo or waitblockheight response giving the height of the chain.
+#[derive(Deserialize)]
+struct BlockHeight {
+    blockheight: u64,
+}
+
+#[derive(Deserialize)]
+struct ListOutputsResponse {
+    outputs: Vec<FundedOutput>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FundedOutput {
+    txid: U256,
+    output: u32,
+    amount_msat: Msat,
+    /// Absent for outputs cln can't express as an address.
+    address: Option<String>,
+    /// "unconfirmed", "confirmed" or "spent".
+    status: String,
+    /// Height of the block the output was mined in, once confirmed.
+    blockheight: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct WaitAnyInvoiceResponse {
+    /// Absent for invoices generated by incoming keysends.
+    bolt11: Option<String>,
+    pay_index: u64,
+    amount_received_msat: Msat,
+    payment_preimage: Preimage,
+}
+
+/// An amount in millisatoshis. Older versions of cln report amounts as strings like
+/// "1000msat", newer versions as plain integers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Msat(u64);
+
+impl<'de> Deserialize<'de> for Msat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Number(n) => n
+                .as_u64()
+                .map(Msat)
+                .ok_or_else(|| de::Error::custom(format!("invalid msat amount {}", n))),
+            Value::String(s) => s
+                .trim_end_matches("msat")
+                .parse()
+                .map(Msat)
+                .map_err(|_| de::Error::custom(format!("invalid msat amount {:?}", s))),
+            other => Err(de::Error::custom(format!("invalid msat amount {}", other))),
+        }
+    }
+}
+
+fn to_msat(satoshis: Satoshis) -> Option<u64> {
+    satoshis.0.checked_mul(MSAT_PER_SATOSHI)
+}
+
+fn to_paid_invoice(response: WaitAnyInvoiceResponse) -> Result<PaidInvoice, ToPaidInvoiceError> {
+    let WaitAnyInvoiceResponse {
+        bolt11,
+        amount_received_msat,
+        payment_preimage,
+        ..
+    } = response;
+    let invoice = parse_bolt11(&bolt11.ok_or(ToPaidInvoiceError::NoInvoice)?)
+        .map_err(ToPaidInvoiceError::InvalidPaymentRequest)?;
+    // partial satoshis received are not credited
+    let amount = Satoshis(amount_received_msat.0 / MSAT_PER_SATOSHI);
+    PaidInvoice::create(invoice, payment_preimage, amount)
+        .map_err(ToPaidInvoiceError::PaidInvoiceInvalid)
+}
+
+/// Outputs without an address can't have been paid to an account, they are skipped.
+fn to_incoming_transaction(
+    output: FundedOutput,
+    tip: u64,
+) -> Result<Option<IncomingTransaction>, SubscribePaidInvoicesError> {
+    let address = match &output.address {
+        Some(address) => Address(address.clone()),
+        None => return Ok(None),
+    };
+    let confirmations = match (output.status.as_str(), output.blockheight) {
+        ("unconfirmed", _) => 0,
+        (_, Some(height)) if height <= tip => (tip - height + 1) as u32,
+        _ => {
+            return Err(SubscribePaidInvoicesError::Unknown(format!(
+                "cln reported an output mined above the chain tip {}: {:?}",
+                tip, output
+            )));
+        }
+    };
+    Ok(Some(IncomingTransaction {
+        outpoint: OutPoint {
+            txid: output.txid,
+            vout: output.output,
+        },
+        address,
+        // partial satoshis received are not credited
+        amount: Satoshis(output.amount_msat.0 / MSAT_PER_SATOSHI),
+        confirmations,
+    }))
+}
+
+#[derive(Debug, Clone)]
+pub enum ToPaidInvoiceError {
+    NoInvoice,
+    InvalidPaymentRequest(lightning_invoice::ParseOrSemanticError),
+    PaidInvoiceInvalid(PaidInvoiceInvalid),
+}
+
+/// A missing file means no invoices have been handled yet.
+fn read_pay_index(path: &Path) -> io::Result<u64> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
+
+/// Write then rename so a crash never leaves a partially written index.
+fn write_pay_index(path: &Path, pay_index: u64) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, pay_index.to_string())?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use std::collections::VecDeque;
+    use std::os::unix::net::UnixListener;
+    use std::sync::Mutex;
+
+    /// Stand-in for cln's rpc socket, recording the requests it saw. Each connection is served
+    /// on its own thread by respond, which returns the response for a request. When respond
+    /// returns None the connection is held open without a reply, like a waitanyinvoice with
+    /// nothing to report.
+    struct StandIn {
+        dir: PathBuf,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl StandIn {
+        /// Reply to each request with the next of responses.
+        fn start(responses: Vec<Value>) -> StandIn {
+            let responses: Mutex<VecDeque<Value>> = Mutex::new(responses.into());
+            StandIn::start_with(move |_request| responses.lock().unwrap().pop_front())
+        }
+
+        fn start_with<F>(respond: F) -> StandIn
+        where
+            F: Fn(&Value) -> Option<Value> + Send + Sync + 'static,
+        {
+            let dir = std::env::temp_dir().join(format!("lapi-cln-{}", U256::random()));
+            fs::create_dir(&dir).unwrap();
+            let listener = UnixListener::bind(dir.join("lightning-rpc")).unwrap();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let requests2 = requests.clone();
+            let respond = Arc::new(respond);
+            thread::spawn(move || {
+                for conn in listener.incoming() {
+                    let mut conn = conn.unwrap();
+                    let requests = requests2.clone();
+                    let respond = respond.clone();
+                    thread::spawn(move || {
+                        let request: Value = serde_json::Deserializer::from_reader(&mut conn)
+                            .into_iter()
+                            .next()
+                            .unwrap()
+                            .unwrap();
+                        let id = request["id"].clone();
+                        requests.lock().unwrap().push(request.clone());
+                        match respond(&request) {
+                            Some(mut response) => {
+                                response["jsonrpc"] = json!("2.0");
+                                response["id"] = id;
+                                serde_json::to_writer(&mut conn, &response).unwrap();
+                                conn.write_all(b"\n\n").unwrap();
+                            }
+                            // keep the connection open until the test process exits
+                            None => std::mem::forget(conn),
+                        }
+                    });
+                }
+            });
+            StandIn { dir, requests }
+        }
+
+        /// A stand-in which behaves like a node, by delegating to a FakeLightningNode. It can
+        /// pay its own invoices.
+        fn backed_by_fake() -> StandIn {
+            let node = FakeLightningNode::new();
+            let paid = Mutex::new(node.paid_invoices().wait());
+            let pay_index = std::sync::atomic::AtomicU64::new(0);
+            StandIn::start_with(move |request| {
+                let params = &request["params"];
+                let result = match request["method"].as_str().unwrap() {
+                    "invoice" => {
+                        let satoshis = params["msatoshi"]
+                            .as_u64()
+                            .map(|msat| Satoshis(msat / MSAT_PER_SATOSHI));
+                        let description = params["description"].as_str().unwrap().to_owned();
+                        let expiry =
+                            std::time::Duration::from_secs(params["expiry"].as_u64().unwrap());
+                        let spec = if params["deschashonly"] == json!(true) {
+                            InvoiceSpec::create_hashed(satoshis, description, expiry)
+                        } else {
+                            InvoiceSpec::create(satoshis, Description::Direct(description), expiry)
+                        }
+                        .unwrap();
+                        let invoice = node.create_invoice(spec).wait().unwrap();
+                        json!({ "bolt11": to_bolt11(&invoice) })
+                    }
+                    "pay" => {
+                        let invoice = parse_bolt11(params["bolt11"].as_str().unwrap()).unwrap();
+                        let msat = match params["msatoshi"].as_u64() {
+                            Some(msat) => msat,
+                            None => invoice.amount_pico_btc().unwrap() / 10,
+                        };
+                        let max_fee = Fee(Satoshis(params["maxfee"].as_u64().unwrap() / 1000));
+                        let outgoing = node
+                            .pay_invoice(
+                                invoice,
+                                Satoshis(msat / MSAT_PER_SATOSHI),
+                                max_fee,
+                                MultiPath::default(),
+                            )
+                            .wait()
+                            .unwrap();
+                        json!({
+                            "payment_preimage": outgoing.paid_invoice.preimage(),
+                            "amount_msat": msat,
+                            "amount_sent_msat": msat + to_msat(outgoing.fees_paid.0).unwrap(),
+                            "status": "complete",
+                        })
+                    }
+                    "listsendpays" => json!({ "payments": [{ "status": "complete" }] }),
+                    "waitanyinvoice" => {
+                        let paid_invoice = paid.lock().unwrap().next().unwrap().unwrap();
+                        json!({
+                            "bolt11": to_bolt11(paid_invoice.invoice()),
+                            "status": "paid",
+                            "pay_index": pay_index.fetch_add(1, Ordering::SeqCst) + 1,
+                            "amount_received_msat": to_msat(*paid_invoice.amount_paid()).unwrap(),
+                            "payment_preimage": paid_invoice.preimage(),
+                        })
+                    }
+                    method => panic!("unexpected method {}", method),
+                };
+                Some(json!({ "result": result }))
+            })
+        }
+
+        fn client(&self) -> ClnClient {
+            ClnClient::new(self.dir.join("lightning-rpc"), self.dir.join("pay_index"))
+        }
+
+        fn requests(&self) -> Vec<Value> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl Drop for StandIn {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// An invoice along with its preimage. cln's responses are made to agree with these. The
+    /// invoice has been through bolt11, so its timestamp is whole seconds like those cln sends.
+    fn known_invoice(satoshis: Option<Satoshis>) -> (Invoice, Preimage) {
+        let node = FakeLightningNode::new();
+        let spec = InvoiceSpec::create(
+            satoshis,
+            Description::Direct("".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .unwrap();
+        let invoice = node.create_invoice(spec).wait().unwrap();
+        let preimage = node.get_preimage(get_payment_hash(&invoice)).unwrap();
+        (parse_bolt11(&to_bolt11(&invoice)).unwrap(), preimage)
+    }
+
+    #[test]
+    fn create_invoice() {
+        let (invoice, _) = known_invoice(Some(Satoshis(5)));
+        let standin = StandIn::start(vec![json!({ "result": {
+            "bolt11": to_bolt11(&invoice),
+            "payment_hash": get_payment_hash(&invoice),
+            "expires_at": 0,
+        }})]);
+        let spec = InvoiceSpec::create(
+            Some(Satoshis(5)),
+            Description::Direct("one coffee".to_owned()),
+            std::time::Duration::from_secs(600),
+        )
+        .unwrap();
+        assert_eq!(
+            standin.client().create_invoice(spec).wait().unwrap(),
+            invoice
+        );
+        let requests = standin.requests();
+        assert_eq!(requests[0]["method"], "invoice");
+        let params = &requests[0]["params"];
+        assert_eq!(params["msatoshi"], 5000);
+        assert_eq!(params["description"], "one coffee");
+        assert_eq!(params["expiry"], 600);
+    }
+
+    #[test]
+    fn create_invoice_amountless() {
+        let (invoice, _) = known_invoice(None);
+        let standin = StandIn::start(vec![json!({ "result": { "bolt11": to_bolt11(&invoice) }})]);
+        let spec = InvoiceSpec::create(
+            None,
+            Description::Direct("".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .unwrap();
+        standin.client().create_invoice(spec).wait().unwrap();
+        assert_eq!(standin.requests()[0]["params"]["msatoshi"], "any");
+    }
+
+    #[test]
+    fn create_invoice_hashed() {
+        let standin = StandIn::backed_by_fake();
+        let client = standin.client();
+        let metadata = "[[\"text/identifier\",\"alice@example.com\"]]".to_owned();
+        let expiry = crate::invoice::DEFAULT_EXPIRY;
+        let spec = InvoiceSpec::create_hashed(None, metadata.clone(), expiry).unwrap();
+        let invoice = client.create_invoice(spec.clone()).wait().unwrap();
+        assert_eq!(&get_description(&invoice), spec.description());
+        let params = &standin.requests()[0]["params"];
+        assert_eq!(params["description"], json!(metadata));
+        assert_eq!(params["deschashonly"], json!(true));
+        // without the description itself there's nothing to give cln
+        let spec = InvoiceSpec::create(None, spec.description().clone(), expiry).unwrap();
+        match client.create_invoice(spec).wait() {
+            Err(CreateInvoiceError::Unsupported(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn pay_invoice() {
+        let (invoice, preimage) = known_invoice(Some(Satoshis(5)));
+        let standin = StandIn::start(vec![
+            json!({ "result": {
+                "payment_preimage": preimage,
+                "amount_msat": "5000msat",
+                "amount_sent_msat": 5100,
+                "status": "complete",
+            }}),
+            // one part failed and was retried along another route
+            json!({ "result": { "payments": [
+                { "status": "complete" },
+                { "status": "failed" },
+                { "status": "complete" },
+            ]}}),
+        ]);
+        let outgoing = standin
+            .client()
+            .pay_invoice(
+                invoice.clone(),
+                Satoshis(5),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.paid_invoice.preimage(), &preimage);
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(1)));
+        assert_eq!(outgoing.parts, 2);
+        let requests = standin.requests();
+        let params = &requests[0]["params"];
+        assert_eq!(params["maxfee"], 10_000);
+        assert_eq!(params["msatoshi"], Value::Null);
+        assert_eq!(params["retry_for"], 60);
+        assert_eq!(
+            requests[1]["params"]["payment_hash"],
+            json!(get_payment_hash(&invoice))
+        );
+    }
+
+    #[test]
+    fn estimate_fee() {
+        let (invoice, _) = known_invoice(Some(Satoshis(5)));
+        let standin = StandIn::start(vec![
+            json!({ "result": { "route": [
+                { "id": "02aa", "amount_msat": "5012msat", "delay": 20 },
+                { "id": "03bb", "amount_msat": "5000msat", "delay": 9 },
+            ]}}),
+            json!({ "error": { "code": 205, "message": "Could not find a route" }}),
+        ]);
+        let client = standin.client();
+        let quote = client
+            .estimate_fee(invoice.clone(), Satoshis(5))
+            .wait()
+            .unwrap();
+        assert_eq!(quote.expected, Fee(Satoshis(1)));
+        assert_eq!(quote.worst_case, Fee(Satoshis(1)));
+        let params = &standin.requests()[0]["params"];
+        assert_eq!(params["msatoshi"], 5000);
+        assert_eq!(
+            params["id"],
+            hex::encode(&invoice.recover_payee_pub_key().0.serialize()[..])
+        );
+        match client.estimate_fee(invoice, Satoshis(5)).wait() {
+            Err(EstimateFeeError::NoRoute) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn node_info() {
+        let pubkey = pubkey_b();
+        let id = hex::encode(&pubkey.serialize()[..]);
+        let standin = StandIn::start_with(move |request| match request["method"].as_str() {
+            Some("getinfo") => Some(json!({ "result": { "id": id, "alias": "bob" } })),
+            Some("listfunds") => Some(json!({ "result": { "outputs": [], "channels": [
+                { "connected": true, "state": "CHANNELD_NORMAL",
+                  "amount_msat": "100000msat", "our_amount_msat": "30500msat" },
+                { "connected": false, "state": "CHANNELD_NORMAL",
+                  "amount_msat": 50000, "our_amount_msat": 0 },
+                { "connected": true, "state": "CHANNELD_AWAITING_LOCKIN",
+                  "amount_msat": 50000, "our_amount_msat": 50000 },
+            ]}})),
+            _ => None,
+        });
+        let info = standin.client().node_info().wait().unwrap();
+        assert_eq!(info.alias, "bob");
+        assert_eq!(info.pubkey, pubkey);
+        assert_eq!(
+            info.capacity,
+            Capacity {
+                inbound: Satoshis(69),
+                outbound: Satoshis(30),
+            }
+        );
+    }
+
+    #[test]
+    fn pay_invoice_aborted() {
+        let (invoice, _) = known_invoice(Some(Satoshis(5)));
+        let standin = StandIn::start(vec![
+            json!({ "error": { "code": 205, "message": "Could not find a route" }}),
+            json!({ "error": { "code": 210, "message": "Ran out of time" }}),
+        ]);
+        let client = standin.client();
+        match client
+            .pay_invoice(
+                invoice.clone(),
+                Satoshis(5),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        // pay reports a timeout only once every part has failed
+        match client
+            .pay_invoice(invoice, Satoshis(5), DEFAULT_FEE, MultiPath::default())
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn cancel_invoice() {
+        let (invoice, _) = known_invoice(Some(Satoshis(5)));
+        let payment_hash = get_payment_hash(&invoice);
+        let standin = StandIn::start(vec![
+            json!({ "result": { "invoices": [{ "label": "lapi-1", "status": "unpaid" }] }}),
+            json!({ "result": { "label": "lapi-1", "status": "unpaid" }}),
+            json!({ "result": { "invoices": [{ "label": "lapi-2", "status": "paid" }] }}),
+            json!({ "result": { "invoices": [] }}),
+            json!({ "result": { "invoices": [{ "label": "lapi-3", "status": "unpaid" }] }}),
+            json!({ "error": { "code": 906, "message": "Invoice status is paid not unpaid" }}),
+        ]);
+        let client = standin.client();
+        client.cancel_invoice(payment_hash).wait().unwrap();
+        let requests = standin.requests();
+        assert_eq!(requests[0]["method"], json!("listinvoices"));
+        assert_eq!(requests[0]["params"]["payment_hash"], json!(payment_hash));
+        assert_eq!(requests[1]["method"], json!("delinvoice"));
+        assert_eq!(
+            requests[1]["params"],
+            json!({ "label": "lapi-1", "status": "unpaid" })
+        );
+        match client.cancel_invoice(payment_hash).wait() {
+            Err(HoldInvoiceError::AlreadyResolved) => {}
+            other => panic!("{:?}", other),
+        }
+        match client.cancel_invoice(payment_hash).wait() {
+            Err(HoldInvoiceError::UnknownInvoice) => {}
+            other => panic!("{:?}", other),
+        }
+        // paid between listing and deleting
+        match client.cancel_invoice(payment_hash).wait() {
+            Err(HoldInvoiceError::AlreadyResolved) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn paid_invoices_resume_from_pay_index() {
+        let (a, a_pre) = known_invoice(Some(Satoshis(5)));
+        let (b, b_pre) = known_invoice(None);
+        let paid = |invoice: &Invoice, preimage: Preimage, pay_index: u64, msat: u64| {
+            json!({ "result": {
+                "label": "lapi",
+                "bolt11": to_bolt11(invoice),
+                "payment_hash": get_payment_hash(invoice),
+                "status": "paid",
+                "pay_index": pay_index,
+                "amount_received_msat": msat,
+                "payment_preimage": preimage,
+            }})
+        };
+        let standin = StandIn::start(vec![paid(&a, a_pre, 7, 5000), paid(&b, b_pre, 8, 2500)]);
+
+        let mut paid_invoices = standin.client().paid_invoices().wait();
+        let first = paid_invoices.next().unwrap().unwrap();
+        assert_eq!(first.invoice(), &a);
+        let second = paid_invoices.next().unwrap().unwrap();
+        assert_eq!(second.amount_paid(), &Satoshis(2));
+        drop(paid_invoices);
+
+        // a was handled before b was requested, b was never acknowledged
+        assert_eq!(read_pay_index(&standin.dir.join("pay_index")).unwrap(), 7);
+        let requests = standin.requests();
+        assert_eq!(requests[0]["params"]["lastpay_index"], 0);
+        assert_eq!(requests[1]["params"]["lastpay_index"], 7);
+
+        // a new subscription picks up after the persisted index
+        let mut resumed = standin.client().paid_invoices().wait();
+        thread::spawn(move || resumed.next());
+        for _ in 0..500 {
+            if standin.requests().len() == 3 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(standin.requests()[2]["params"]["lastpay_index"], 7);
+    }
+
+    #[test]
+    fn keysend_invoices_passed_over() {
+        let (a, a_pre) = known_invoice(Some(Satoshis(5)));
+        let standin = StandIn::start(vec![
+            json!({ "result": {
+                "label": "keysend-1",
+                "status": "paid",
+                "pay_index": 3,
+                "amount_received_msat": 1000,
+                "payment_preimage": PREIMAGE_A,
+            }}),
+            json!({ "result": {
+                "label": "lapi",
+                "bolt11": to_bolt11(&a),
+                "status": "paid",
+                "pay_index": 4,
+                "amount_received_msat": 5000,
+                "payment_preimage": a_pre,
+            }}),
+        ]);
+        let mut paid_invoices = standin.client().paid_invoices().wait();
+        assert_eq!(paid_invoices.next().unwrap().unwrap().invoice(), &a);
+        assert_eq!(standin.requests()[1]["params"]["lastpay_index"], 3);
+        assert_eq!(read_pay_index(&standin.dir.join("pay_index")).unwrap(), 3);
+
+        match standin.client().received_keysends().wait().next() {
+            Some(Err(SubscribePaidInvoicesError::Unknown(_))) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    crate::conformance::conformance_tests!({
+        let standin = StandIn::backed_by_fake();
+        let node = Arc::new(standin.client());
+        // the stand-in's files are removed when it is dropped
+        std::mem::forget(standin);
+        (node.clone(), node)
+    });
+
+    #[test]
+    fn incoming_transactions() {
+        let txid = U256::random();
+        let listed = AtomicU64::new(0);
+        let standin = StandIn::start_with(move |request| {
+            let result = match request["method"].as_str().unwrap() {
+                "getinfo" => json!({ "id": "", "alias": "", "blockheight": 100 }),
+                // one block arrives, then none
+                "waitblockheight" if request["params"]["blockheight"] == json!(101) => {
+                    json!({ "blockheight": 101 })
+                }
+                "waitblockheight" => return None,
+                "listfunds" => {
+                    let mut pending = json!({ "txid": txid, "output": 1, "amount_msat": 7000,
+                                              "address": "bc1qpending", "status": "unconfirmed" });
+                    if listed.fetch_add(1, Ordering::SeqCst) > 0 {
+                        pending["status"] = json!("confirmed");
+                        pending["blockheight"] = json!(101);
+                    }
+                    json!({ "channels": [], "outputs": [
+                        { "txid": txid, "output": 0, "amount_msat": "5000500msat",
+                          "address": "bc1qmined", "status": "confirmed", "blockheight": 99 },
+                        pending,
+                        { "txid": txid, "output": 2, "amount_msat": 1000,
+                          "status": "confirmed", "blockheight": 99 },
+                    ]})
+                }
+                method => panic!("unexpected method {}", method),
+            };
+            Some(json!({ "result": result }))
+        });
+        let reported: Vec<(u32, Address, Satoshis, u32)> = standin
+            .client()
+            .incoming_transactions()
+            .wait()
+            .take(4)
+            .map(|transaction| {
+                let transaction = transaction.unwrap();
+                assert_eq!(transaction.outpoint.txid, txid);
+                (
+                    transaction.outpoint.vout,
+                    transaction.address,
+                    transaction.amount,
+                    transaction.confirmations,
+                )
+            })
+            .collect();
+        let mined = Address("bc1qmined".to_owned());
+        let pending = Address("bc1qpending".to_owned());
+        assert_eq!(
+            reported,
+            vec![
+                (0, mined.clone(), Satoshis(5000), 2),
+                (1, pending.clone(), Satoshis(7), 0),
+                (0, mined, Satoshis(5000), 3),
+                (1, pending, Satoshis(7), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_address() {
+        let standin = StandIn::start(vec![
+            json!({ "result": { "bech32": "bc1qnew" } }),
+            json!({ "result": { "address": "bc1qold" } }),
+        ]);
+        let client = standin.client();
+        assert_eq!(
+            client.new_address().wait().unwrap(),
+            Address("bc1qnew".to_owned())
+        );
+        assert_eq!(
+            client.new_address().wait().unwrap(),
+            Address("bc1qold".to_owned())
+        );
+        assert_eq!(standin.requests()[0]["method"], json!("newaddr"));
+    }
+
+    #[test]
+    fn send_onchain() {
+        let feerates = json!({ "result": { "perkw": { "estimates": [
+            { "blockcount": 2, "feerate": 5000 },
+            { "blockcount": 6, "feerate": 2000 },
+            { "blockcount": 12, "feerate": 1000 },
+        ]}}});
+        let txid = U256::random();
+        let standin = StandIn::start(vec![
+            feerates.clone(),
+            feerates.clone(),
+            json!({ "result": { "txid": txid, "tx": "", "psbt": "" } }),
+            feerates.clone(),
+            feerates,
+            json!({ "error": { "code": 301, "message": "Could not afford 500000sat" } }),
+        ]);
+        let client = standin.client();
+        let address = Address("bc1qdest".to_owned());
+        // the longest target within 8 blocks is 6, 2000 per kiloweight
+        let fee = client
+            .estimate_onchain_fee(address.clone(), Satoshis(500_000), 8)
+            .wait()
+            .unwrap();
+        assert_eq!(fee, Fee(Satoshis(1220)));
+        let outgoing = client
+            .send_onchain(address.clone(), Satoshis(500_000), 8, fee)
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.txid, txid);
+        assert_eq!(outgoing.fees_paid, fee);
+        let withdraw = &standin.requests()[2];
+        assert_eq!(withdraw["method"], json!("withdraw"));
+        assert_eq!(
+            withdraw["params"],
+            json!({ "destination": "bc1qdest", "satoshi": 500_000, "feerate": "2000perkw" })
+        );
+        // no target is that short, the shortest is used, and its fee is too high
+        match client
+            .send_onchain(address.clone(), Satoshis(500_000), 1, fee)
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(standin.requests().len(), 4);
+        match client
+            .send_onchain(address, Satoshis(500_000), 6, fee)
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn msat_formats() {
+        let msat = |v: Value| serde_json::from_value::<Msat>(v);
+        assert_eq!(msat(json!(1000)).unwrap(), Msat(1000));
+        assert_eq!(msat(json!("1000msat")).unwrap(), Msat(1000));
+        msat(json!("1000sat")).unwrap_err();
+        msat(json!(-1)).unwrap_err();
+    }
+}
@@ -1,26 +1,52 @@
 pub use crate::{
-    api_highlevel::ApiHigh,
-    api_lowlevel::{ApiLow, GenerateInvoiceError, PayInvoiceError},
-    auth::{Lesser, Master, Middle},
+    api_highlevel::{ApiHigh, AwaitInvoiceError, LnurlPayError, SessionOr},
+    api_lowlevel::{
+        AnswerAuthError, ApiLow, CreateVoucherError, GenerateAddressError, GenerateInvoiceError,
+        GenerateOfferError, PayInvoiceError, PayLnurlError, RefundVoucherError,
+        ResolveHoldInvoiceError, SessionError, WithdrawOnchainError, WithdrawVoucherError,
+    },
+    auth::{Capability, Lesser, Master, Middle},
+    cln_client::ClnClient,
     db::{
-        CheckBalanceError, CheckInvoiceStatusError, Db, DepositError, ReceivePaidInvoiceErr,
-        StoreInvoiceError, WithdrawalError,
+        CancelInvoiceError, CheckBalanceError, CheckInvoiceStatusError, Db, DepositError,
+        GetLinkedCapabilityError, GetNameOwnerError, GetSessionError, GetVoucherError,
+        ReceiveAcceptedInvoiceErr, ReceiveKeysendErr, ReceiveOfferInvoiceErr,
+        ReceiveOnchainDepositErr, ReceivePaidInvoiceErr, StoreAddressError,
+        StoreAuthChallengeError, StoreInvoiceError, StoreLinkingKeyError, StoreNameError,
+        StoreOfferError, StoreSessionError, StoreVoucherError, TakeAuthChallengeError,
+        WithdrawalError,
     },
     fake_db::FakeDb,
-    fake_lighting_node::FakeLightningNode,
+    fake_lighting_node::{FakeLightningNode, InvoiceRequestRefused, PayOutcome},
     fake_log::FakeLog,
     future::DynFut,
     invoice::{
-        get_payment_hash, parse_bolt11, to_bolt11, Invoice, InvoiceStatus, PaidInvoice,
-        PaidInvoiceInvalid, PaidInvoiceOutgoing,
+        get_description, get_payment_hash, get_route_hints, parse_bolt11, to_bolt11, Description,
+        Invoice, InvoiceSpec, InvoiceSpecInvalid, InvoiceStatus, PaidInvoice, PaidInvoiceInvalid,
+        PaidInvoiceOutgoing, RouteHintHop,
+    },
+    keysend::{KeysendOutgoing, PublicKey, ReceivedKeysend},
+    lighting_node::{
+        Capacity, CreateInvoiceError, CreateOfferError, EstimateFeeError, EstimateOnchainFeeError,
+        FeeQuote, HoldInvoiceError, LightningNode, MultiPath, NewAddressError, NodeInfo,
+        NodeInfoError, PayError, SubscribePaidInvoicesError,
+    },
+    lnd_client::{
+        init_default_lightning_client, init_network_lightning_client, CreateError, LndClient,
     },
-    lighting_node::{CreateInvoiceError, LightningNode, PayError, SubscribePaidInvoicesError},
-    lnd_client::{init_default_lightning_client, CreateError},
+    lnurl::{AuthChallenge, InvalidName, InvalidPayLink, Name, ResolveError, Session, Voucher},
     log::{ErrLogged, Log, LogErr, LoggedOr, MaybeServerError, ServerError},
+    multi_node::MultiNode,
+    network::{Network, NETWORKS},
+    offer::{Offer, OfferInvoice, OfferSpec, ParseOfferError},
+    onchain::{Address, IncomingTransaction, OnchainOutgoing, OutPoint},
     payment_hash::PaymentHash,
     preimage::Preimage,
+    recording::{RecordingNode, ReplayNode},
     satoshis::{NotDivisible, Satoshis},
     semantics::Fee,
-    ser_de::{InvoiceSerDe, ResultSerDe, UrlSerDe},
+    ser_de::{InvoiceSerDe, PubKeySerDe, ResultSerDe, SignatureSerDe, UrlSerDe},
+    sim_network::{FeePolicy, SimNetwork, SimNode},
+    stderr_log::StderrLog,
     u256::U256,
 };
@@ -0,0 +1,192 @@
+//! Checks of the LightningNode contract, to be run against every implementation.
+//!
+//! Each check takes the node under test, and where a payment is needed, a payer node able to
+//! pay the invoices it creates. Use conformance_tests! to generate a test per check.
+
+#[cfg(test)]
+pub use checks::*;
+
+#[cfg(test)]
+mod checks {
+    use crate::common::*;
+    use crate::lighting_node::DynStream;
+    use futures::{Future, Stream};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// How long to wait for a settlement to show up on paid_invoices.
+    const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How long to watch for a settlement being reported a second time.
+    const DUPLICATE_WINDOW: Duration = Duration::from_millis(200);
+
+    fn spec(satoshis: Option<Satoshis>) -> InvoiceSpec {
+        InvoiceSpec::create(
+            satoshis,
+            Description::Direct("conformance".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .unwrap()
+    }
+
+    /// Forward items from stream onto a channel, so they can be received with a timeout.
+    fn forward<T: Send + 'static, E: Send + 'static>(
+        stream: DynStream<T, E>,
+    ) -> mpsc::Receiver<Result<T, E>> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for item in stream.wait() {
+                if tx.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    pub fn invoice_matches_amount<L: LightningNode>(node: &L) {
+        let invoice = node
+            .create_invoice(spec(Some(Satoshis(21))))
+            .wait()
+            .unwrap();
+        assert_eq!(
+            invoice.amount_pico_btc(),
+            Satoshis(21).checked_to_pico_btc()
+        );
+        let invoice = node.create_invoice(spec(None)).wait().unwrap();
+        assert_eq!(invoice.amount_pico_btc(), None);
+    }
+
+    pub fn invoices_are_unique<L: LightningNode>(node: &L) {
+        let a = node.create_invoice(spec(Some(Satoshis(1)))).wait().unwrap();
+        let b = node.create_invoice(spec(Some(Satoshis(1)))).wait().unwrap();
+        assert_ne!(get_payment_hash(&a), get_payment_hash(&b));
+    }
+
+    pub fn payment_within_max_fee<L: LightningNode, P: LightningNode>(node: &L, payer: &P) {
+        let invoice = node.create_invoice(spec(Some(Satoshis(3)))).wait().unwrap();
+        let max_fee = Fee(Satoshis(10));
+        let PaidInvoiceOutgoing {
+            paid_invoice,
+            fees_offered,
+            fees_paid,
+            parts,
+        } = payer
+            .pay_invoice(invoice.clone(), Satoshis(3), max_fee, MultiPath::default())
+            .wait()
+            .unwrap();
+        assert_eq!(fees_offered, max_fee);
+        assert!(fees_paid <= max_fee);
+        assert!(parts >= 1 && parts <= MultiPath::default().max_parts);
+        assert_eq!(paid_invoice.invoice(), &invoice);
+        assert_eq!(paid_invoice.amount_paid(), &Satoshis(3));
+    }
+
+    pub fn preimage_matches_hash<L: LightningNode, P: LightningNode>(node: &L, payer: &P) {
+        let invoice = node.create_invoice(spec(Some(Satoshis(1)))).wait().unwrap();
+        let outgoing = payer
+            .pay_invoice(
+                invoice.clone(),
+                Satoshis(1),
+                Fee(Satoshis(10)),
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(
+            outgoing.paid_invoice.preimage().hash(),
+            get_payment_hash(&invoice)
+        );
+    }
+
+    pub fn settlement_reported_once<L: LightningNode, P: LightningNode>(node: &L, payer: &P) {
+        let paid = forward(node.paid_invoices());
+        let invoice = node.create_invoice(spec(Some(Satoshis(2)))).wait().unwrap();
+        let payment_hash = get_payment_hash(&invoice);
+        let outgoing = payer
+            .pay_invoice(
+                invoice,
+                Satoshis(2),
+                Fee(Satoshis(10)),
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+
+        // Other settlements, e.g. from earlier tests against the same node, may be reported
+        // too. Only ours is of interest.
+        let reported = loop {
+            let paid_invoice = paid.recv_timeout(SETTLE_TIMEOUT).unwrap().unwrap();
+            if get_payment_hash(paid_invoice.invoice()) == payment_hash {
+                break paid_invoice;
+            }
+        };
+        assert_eq!(reported.preimage(), outgoing.paid_invoice.preimage());
+        assert_eq!(reported.amount_paid(), &Satoshis(2));
+
+        while let Ok(item) = paid.recv_timeout(DUPLICATE_WINDOW) {
+            let paid_invoice = item.unwrap();
+            assert_ne!(
+                get_payment_hash(paid_invoice.invoice()),
+                payment_hash,
+                "settlement reported twice"
+            );
+        }
+    }
+
+    pub fn resubscribe_to_paid_invoices<L: LightningNode, P: LightningNode>(node: &L, payer: &P) {
+        // subscribing more than once must not break the node
+        drop(node.paid_invoices());
+        settlement_reported_once(node, payer);
+    }
+}
+
+/// Generate a test for each conformance check. $init is evaluated once per test and must
+/// produce (node, payer), where payer is able to pay invoices created by node.
+#[cfg(test)]
+macro_rules! conformance_tests {
+    ($init:expr) => {
+        mod conformance {
+            use super::*;
+
+            #[test]
+            fn invoice_matches_amount() {
+                let (node, _payer) = $init;
+                crate::conformance::invoice_matches_amount(&*node);
+            }
+
+            #[test]
+            fn invoices_are_unique() {
+                let (node, _payer) = $init;
+                crate::conformance::invoices_are_unique(&*node);
+            }
+
+            #[test]
+            fn payment_within_max_fee() {
+                let (node, payer) = $init;
+                crate::conformance::payment_within_max_fee(&*node, &*payer);
+            }
+
+            #[test]
+            fn preimage_matches_hash() {
+                let (node, payer) = $init;
+                crate::conformance::preimage_matches_hash(&*node, &*payer);
+            }
+
+            #[test]
+            fn settlement_reported_once() {
+                let (node, payer) = $init;
+                crate::conformance::settlement_reported_once(&*node, &*payer);
+            }
+
+            #[test]
+            fn resubscribe_to_paid_invoices() {
+                let (node, payer) = $init;
+                crate::conformance::resubscribe_to_paid_invoices(&*node, &*payer);
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) use conformance_tests;
@@ -20,6 +20,31 @@ impl From<Invoice> for api_types::GenerateInvoiceOk {
     }
 }
 
+impl From<Satoshis> for InvoiceSpec {
+    /// An invoice for satoshis with an empty description and the default expiry.
+    fn from(satoshis: Satoshis) -> InvoiceSpec {
+        InvoiceSpec::create(
+            Some(satoshis),
+            Description::Direct("".to_owned()),
+            crate::invoice::DEFAULT_EXPIRY,
+        )
+        .expect("default invoice spec is valid")
+    }
+}
+
+impl From<InvoiceSpecInvalid> for api_types::GenerateInvoiceErr {
+    fn from(other: InvoiceSpecInvalid) -> Self {
+        match other {
+            InvoiceSpecInvalid::DescriptionTooLong => {
+                api_types::GenerateInvoiceErr::DescriptionTooLong(())
+            }
+            InvoiceSpecInvalid::ExpiryOutOfRange => {
+                api_types::GenerateInvoiceErr::ExpiryOutOfRange(())
+            }
+        }
+    }
+}
+
 impl<T> From<T> for LoggedOr<T> {
     fn from(other: T) -> Self {
         LoggedOr::UnLogged(other)
@@ -32,6 +57,7 @@ impl From<PaidInvoiceOutgoing> for api_types::PayInvoiceOk {
             paid_invoice,
             fees_offered: _,
             fees_paid,
+            parts: _,
         } = other;
         api_types::PayInvoiceOk {
             preimage: paid_invoice.preimage().clone(),
@@ -40,6 +66,41 @@ impl From<PaidInvoiceOutgoing> for api_types::PayInvoiceOk {
     }
 }
 
+impl From<KeysendOutgoing> for api_types::KeysendOk {
+    fn from(other: KeysendOutgoing) -> Self {
+        api_types::KeysendOk {
+            preimage: other.preimage,
+            fees_paid_satoshis: other.fees_paid,
+        }
+    }
+}
+
+impl From<OnchainOutgoing> for api_types::WithdrawOnchainOk {
+    fn from(other: OnchainOutgoing) -> Self {
+        api_types::WithdrawOnchainOk {
+            txid: other.txid,
+            fees_paid_satoshis: other.fees_paid,
+        }
+    }
+}
+
+impl From<api_types::PayInvoiceErr> for api_types::WithdrawOnchainErr {
+    fn from(other: api_types::PayInvoiceErr) -> Self {
+        match other {
+            api_types::PayInvoiceErr::InsufficientBalance(()) => {
+                api_types::WithdrawOnchainErr::InsufficientBalance(())
+            }
+            api_types::PayInvoiceErr::Aborted(()) => api_types::WithdrawOnchainErr::Aborted(()),
+            api_types::PayInvoiceErr::WrongNetwork(()) => {
+                api_types::WithdrawOnchainErr::WrongNetwork(())
+            }
+            api_types::PayInvoiceErr::AmountMismatch(()) => {
+                unreachable!("on-chain withdrawals pay no invoice")
+            }
+        }
+    }
+}
+
 impl From<WithdrawalError> for PayInvoiceError {
     fn from(other: WithdrawalError) -> Self {
         match other {
@@ -56,6 +117,8 @@ impl From<InvoiceStatus> for api_types::CheckInvoiceOk {
                 amount_paid_satoshis: paid_invoice.amount_paid().clone(),
             },
             InvoiceStatus::Unpaid(_) => api_types::CheckInvoiceOk::Waiting(()),
+            InvoiceStatus::Accepted(_) => api_types::CheckInvoiceOk::Accepted(()),
+            InvoiceStatus::Cancelled(_) => api_types::CheckInvoiceOk::Cancelled(()),
         }
     }
 }
@@ -82,6 +145,8 @@ impl MaybeServerError for PayInvoiceError {
             PayInvoiceError::InsufficientBalance => {
                 Ok(api_types::PayInvoiceErr::InsufficientBalance(()))
             }
+            PayInvoiceError::WrongNetwork => Ok(api_types::PayInvoiceErr::WrongNetwork(())),
+            PayInvoiceError::AmountMismatch => Ok(api_types::PayInvoiceErr::AmountMismatch(())),
             PayInvoiceError::Pay(payerr) => payerr.try_as_response(),
             PayInvoiceError::RefundFee(deposit_err) => {
                 Err(LogErr::PayInvoiceOverflowOnRefundFee(deposit_err))
@@ -93,6 +158,37 @@ impl MaybeServerError for PayInvoiceError {
     }
 }
 
+impl From<api_types::PayInvoiceErr> for api_types::PayLnurlErr {
+    fn from(other: api_types::PayInvoiceErr) -> Self {
+        match other {
+            api_types::PayInvoiceErr::InsufficientBalance(()) => {
+                api_types::PayLnurlErr::InsufficientBalance(())
+            }
+            api_types::PayInvoiceErr::Aborted(()) => api_types::PayLnurlErr::Aborted(()),
+            api_types::PayInvoiceErr::WrongNetwork(()) => api_types::PayLnurlErr::WrongNetwork(()),
+            api_types::PayInvoiceErr::AmountMismatch(()) => {
+                api_types::PayLnurlErr::InvoiceMismatch(())
+            }
+        }
+    }
+}
+
+impl MaybeServerError for PayLnurlError {
+    type NotServerError = api_types::PayLnurlErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            // the other service is at fault, not this server
+            PayLnurlError::Resolve(ResolveError::Refused(reason)) => {
+                Ok(api_types::PayLnurlErr::Refused(reason))
+            }
+            PayLnurlError::Resolve(_) => Ok(api_types::PayLnurlErr::Unreachable(())),
+            PayLnurlError::AmountOutOfRange => Ok(api_types::PayLnurlErr::AmountOutOfRange(())),
+            PayLnurlError::InvoiceMismatch => Ok(api_types::PayLnurlErr::InvoiceMismatch(())),
+            PayLnurlError::Pay(err) => err.try_as_response().map(Into::into),
+        }
+    }
+}
+
 impl MaybeServerError for PayError {
     type NotServerError = api_types::PayInvoiceErr;
     fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
@@ -107,6 +203,9 @@ impl MaybeServerError for GenerateInvoiceError {
     type NotServerError = api_types::GenerateInvoiceErr;
     fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
         match self {
+            GenerateInvoiceError::Invalid(invalid) => Ok(invalid.into()),
+            GenerateInvoiceError::ToLarge => Ok(api_types::GenerateInvoiceErr::ToLarge(())),
+            GenerateInvoiceError::NodeInfo(err) => Err(err.into_log_err()),
             GenerateInvoiceError::Create(create) => create.try_as_response(),
             GenerateInvoiceError::Store(store) => Err(store.into_log_err()),
         }
@@ -133,6 +232,219 @@ impl MaybeServerError for CheckInvoiceStatusError {
     }
 }
 
+impl MaybeServerError for AwaitInvoiceError {
+    type NotServerError = api_types::CheckInvoiceErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            AwaitInvoiceError::Status(status) => status.try_as_response(),
+            AwaitInvoiceError::Cancelled => Ok(api_types::CheckInvoiceErr::Cancelled(())),
+        }
+    }
+}
+
+impl MaybeServerError for ResolveHoldInvoiceError {
+    type NotServerError = api_types::ResolveHoldInvoiceErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            ResolveHoldInvoiceError::NoSuchInvoice => {
+                Ok(api_types::ResolveHoldInvoiceErr::NonExistent(()))
+            }
+            ResolveHoldInvoiceError::NotOwner => {
+                Ok(api_types::ResolveHoldInvoiceErr::Unauthorized(()))
+            }
+            ResolveHoldInvoiceError::NotAccepted => {
+                Ok(api_types::ResolveHoldInvoiceErr::NotAccepted(()))
+            }
+            ResolveHoldInvoiceError::AlreadyPaid => {
+                Ok(api_types::ResolveHoldInvoiceErr::AlreadyPaid(()))
+            }
+            ResolveHoldInvoiceError::AlreadyCancelled => {
+                Ok(api_types::ResolveHoldInvoiceErr::AlreadyCancelled(()))
+            }
+            ResolveHoldInvoiceError::Node(node) => Err(LogErr::HoldInvoice(node)),
+            ResolveHoldInvoiceError::Cancel(cancel) => Err(LogErr::CancelInvoice(cancel)),
+        }
+    }
+}
+
+impl MaybeServerError for GenerateOfferError {
+    type NotServerError = api_types::CreateOfferErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            GenerateOfferError::DescriptionTooLong => {
+                Ok(api_types::CreateOfferErr::DescriptionTooLong(()))
+            }
+            GenerateOfferError::Node(CreateOfferError::Unsupported(_)) => {
+                Ok(api_types::CreateOfferErr::Unsupported(()))
+            }
+            GenerateOfferError::Node(err) => Err(LogErr::CreateOffer(err)),
+            GenerateOfferError::Store(StoreOfferError::EntryAlreadyExists(lesser, offer_id)) => {
+                Err(LogErr::DbStoreOfferDuplicate(lesser, offer_id))
+            }
+        }
+    }
+}
+
+impl MaybeServerError for StoreNameError {
+    type NotServerError = api_types::RegisterNameErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            StoreNameError::EntryAlreadyExists(_, _) => Ok(api_types::RegisterNameErr::Taken(())),
+        }
+    }
+}
+
+impl MaybeServerError for LnurlPayError {
+    type NotServerError = api_types::LnurlError;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            LnurlPayError::UnknownName => Ok(api_types::LnurlError::new("unknown name")),
+            LnurlPayError::Amount => Ok(api_types::LnurlError::new(
+                "amount must be a whole number of satoshis, at least minSendable",
+            )),
+            LnurlPayError::NodeInfo(err) => Err(err.into_log_err()),
+            LnurlPayError::Invoice(err) => err.try_as_response().map(|refused| match refused {
+                api_types::GenerateInvoiceErr::ToLarge(()) => {
+                    api_types::LnurlError::new("amount is more than maxSendable")
+                }
+                _ => api_types::LnurlError::new("invoice refused"),
+            }),
+        }
+    }
+}
+
+impl MaybeServerError for CreateVoucherError {
+    type NotServerError = api_types::CreateVoucherErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            CreateVoucherError::InsufficientBalance => {
+                Ok(api_types::CreateVoucherErr::InsufficientBalance(()))
+            }
+            CreateVoucherError::NoUses => Ok(api_types::CreateVoucherErr::NoUses(())),
+            CreateVoucherError::ExpiryOutOfRange => {
+                Ok(api_types::CreateVoucherErr::ExpiryOutOfRange(()))
+            }
+            CreateVoucherError::Reserve(err) => Err(LogErr::ReserveVoucher(err)),
+            CreateVoucherError::Store(err) => Err(LogErr::StoreVoucher(err)),
+        }
+    }
+}
+
+impl MaybeServerError for WithdrawVoucherError {
+    type NotServerError = api_types::LnurlError;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            WithdrawVoucherError::NoSuchVoucher => {
+                Ok(api_types::LnurlError::new("unknown voucher"))
+            }
+            WithdrawVoucherError::Expired => Ok(api_types::LnurlError::new("voucher expired")),
+            WithdrawVoucherError::UsedUp => Ok(api_types::LnurlError::new("voucher used up")),
+            WithdrawVoucherError::Amount => Ok(api_types::LnurlError::new(
+                "invoice must ask for between minWithdrawable and maxWithdrawable",
+            )),
+            WithdrawVoucherError::Pay(err) => err.try_as_response().map(|refused| match refused {
+                // only the voucher's own withdrawals spend from its account
+                api_types::PayInvoiceErr::InsufficientBalance(()) => {
+                    api_types::LnurlError::new("voucher used up")
+                }
+                api_types::PayInvoiceErr::Aborted(()) => {
+                    api_types::LnurlError::new("payment failed")
+                }
+                api_types::PayInvoiceErr::WrongNetwork(()) => {
+                    api_types::LnurlError::new("invoice is for another network")
+                }
+                api_types::PayInvoiceErr::AmountMismatch(()) => {
+                    api_types::LnurlError::new("payment amount does not match the invoice")
+                }
+            }),
+            WithdrawVoucherError::Refund(err) => Err(err.into_log_err()),
+        }
+    }
+}
+
+impl MaybeServerError for AnswerAuthError {
+    type NotServerError = api_types::LnurlError;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            AnswerAuthError::BadSignature => Ok(api_types::LnurlError::new("invalid signature")),
+            AnswerAuthError::NoSuchChallenge => Ok(api_types::LnurlError::new("unknown k1")),
+            AnswerAuthError::Expired => Ok(api_types::LnurlError::new("challenge expired")),
+            AnswerAuthError::AlreadyLinked => {
+                Ok(api_types::LnurlError::new("key is already linked"))
+            }
+            AnswerAuthError::UnknownKey => Ok(api_types::LnurlError::new("unknown key")),
+            AnswerAuthError::Store(err) => Err(LogErr::StoreSession(err)),
+        }
+    }
+}
+
+impl From<SessionError> for api_types::SessionRefused {
+    fn from(other: SessionError) -> Self {
+        match other {
+            SessionError::NoSession | SessionError::NotMaster => {
+                api_types::SessionRefused::Unauthorized(())
+            }
+            SessionError::Expired => api_types::SessionRefused::SessionExpired(()),
+        }
+    }
+}
+
+impl<E: MaybeServerError> MaybeServerError for SessionOr<E> {
+    type NotServerError = api_types::SessionErr<E::NotServerError>;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            SessionOr::Session(err) => Ok(api_types::SessionErr::Session(err.into())),
+            SessionOr::Other(err) => err.try_as_response().map(api_types::SessionErr::Other),
+        }
+    }
+}
+
+impl From<FeeQuote> for api_types::QuoteOk {
+    fn from(other: FeeQuote) -> Self {
+        api_types::QuoteOk {
+            expected_fee_satoshis: other.expected,
+            worst_case_fee_satoshis: other.worst_case,
+        }
+    }
+}
+
+impl From<NodeInfo> for api_types::NodeInfoResponse {
+    fn from(other: NodeInfo) -> Self {
+        api_types::NodeInfoResponse {
+            alias: other.alias,
+            pubkey: PubKeySerDe(other.pubkey),
+            inbound_satoshis: other.capacity.inbound,
+            outbound_satoshis: other.capacity.outbound,
+        }
+    }
+}
+
+impl MaybeServerError for EstimateFeeError {
+    type NotServerError = api_types::QuoteErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            EstimateFeeError::NoRoute => Ok(api_types::QuoteErr::NoRoute(())),
+            other => Err(LogErr::EstimateFee(other)),
+        }
+    }
+}
+
+impl MaybeServerError for WithdrawOnchainError {
+    type NotServerError = api_types::WithdrawOnchainErr;
+    fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
+        match self {
+            WithdrawOnchainError::WrongNetwork => {
+                Ok(api_types::WithdrawOnchainErr::WrongNetwork(()))
+            }
+            WithdrawOnchainError::Estimate(EstimateOnchainFeeError::InvalidAddress) => {
+                Ok(api_types::WithdrawOnchainErr::InvalidAddress(()))
+            }
+            WithdrawOnchainError::Estimate(other) => Err(LogErr::EstimateOnchainFee(other)),
+            WithdrawOnchainError::Spend(spend) => spend.try_as_response().map(Into::into),
+        }
+    }
+}
+
 impl MaybeServerError for CreateInvoiceError {
     type NotServerError = crate::api_types::GenerateInvoiceErr;
     fn try_as_response(self) -> Result<Self::NotServerError, LogErr> {
@@ -140,6 +452,71 @@ impl MaybeServerError for CreateInvoiceError {
     }
 }
 
+impl ServerError for SubscribePaidInvoicesError {
+    fn into_log_err(self) -> LogErr {
+        LogErr::SubscribePaidInvoices(self)
+    }
+}
+
+impl ServerError for NodeInfoError {
+    fn into_log_err(self) -> LogErr {
+        LogErr::NodeInfo(self)
+    }
+}
+
+impl ServerError for ReceivePaidInvoiceErr {
+    fn into_log_err(self) -> LogErr {
+        LogErr::ReceivePaidInvoice(self)
+    }
+}
+
+impl ServerError for ReceiveAcceptedInvoiceErr {
+    fn into_log_err(self) -> LogErr {
+        LogErr::ReceiveAcceptedInvoice(self)
+    }
+}
+
+impl ServerError for ReceiveOnchainDepositErr {
+    fn into_log_err(self) -> LogErr {
+        LogErr::ReceiveOnchainDeposit(self)
+    }
+}
+
+impl ServerError for GenerateAddressError {
+    fn into_log_err(self) -> LogErr {
+        match self {
+            GenerateAddressError::Node(err) => LogErr::NewAddress(err),
+            GenerateAddressError::Store(StoreAddressError::EntryAlreadyExists(lesser, address)) => {
+                LogErr::DbStoreAddressDuplicate(lesser, address)
+            }
+        }
+    }
+}
+
+impl ServerError for ReceiveOfferInvoiceErr {
+    fn into_log_err(self) -> LogErr {
+        LogErr::ReceiveOfferInvoice(self)
+    }
+}
+
+impl ServerError for RefundVoucherError {
+    fn into_log_err(self) -> LogErr {
+        LogErr::RefundVoucher(self)
+    }
+}
+
+impl ServerError for StoreAuthChallengeError {
+    fn into_log_err(self) -> LogErr {
+        LogErr::StoreAuthChallenge(self)
+    }
+}
+
+impl ServerError for ReceiveKeysendErr {
+    fn into_log_err(self) -> LogErr {
+        LogErr::ReceiveKeysend(self)
+    }
+}
+
 impl ServerError for StoreInvoiceError {
     fn into_log_err(self) -> LogErr {
         match self {
@@ -1,5 +1,7 @@
 use crate::common::*;
+use std::convert::Infallible;
 use std::pin::Pin;
+use std::time::SystemTime;
 
 pub trait Db: Sync + Send {
     fn store_unpaid_invoice(
@@ -19,8 +21,96 @@ pub trait Db: Sync + Send {
         payment_hash: U256,
     ) -> DynFut<InvoiceStatus, CheckInvoiceStatusError>;
 
+    /// The account which generated the invoice.
+    fn get_invoice_owner(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Lesser, CheckInvoiceStatusError>;
+
+    /// Payment for a hold invoice has arrived and is being held.
+    fn receive_accepted_invoice(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> DynFut<(), ReceiveAcceptedInvoiceErr>;
+
+    /// Mark an invoice that has not been paid as cancelled.
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), CancelInvoiceError>;
+
     /// Invoice has been paid,
     fn receive_paid_invoice(&self, paid_invoice: PaidInvoice) -> DynFut<(), ReceivePaidInvoiceErr>;
+
+    /// Credit an incoming keysend to the Lesser named in its custom record. Each keysend is
+    /// credited at most once.
+    fn receive_keysend(&self, keysend: ReceivedKeysend) -> DynFut<(), ReceiveKeysendErr>;
+
+    /// Deposits to address are to be credited to lesser.
+    fn store_address(&self, lesser: Lesser, address: &Address) -> DynFut<(), StoreAddressError>;
+
+    /// Credit an on-chain deposit to the account its address was generated for. Each output is
+    /// credited at most once.
+    fn receive_onchain_deposit(
+        &self,
+        transaction: IncomingTransaction,
+    ) -> DynFut<(), ReceiveOnchainDepositErr>;
+
+    /// Invoices issued for the offer with offer_id are to be credited to lesser.
+    fn store_offer(&self, lesser: Lesser, offer_id: U256) -> DynFut<(), StoreOfferError>;
+
+    /// Store an invoice issued for an offer as an unpaid invoice of the offer's account.
+    fn receive_offer_invoice(
+        &self,
+        offer_invoice: OfferInvoice,
+    ) -> DynFut<(), ReceiveOfferInvoiceErr>;
+
+    /// Payments to name are to be credited to lesser. Each name belongs to one account.
+    fn store_name(&self, lesser: Lesser, name: &Name) -> DynFut<(), StoreNameError>;
+
+    /// The account name was registered to.
+    fn get_name_owner(&self, name: &Name) -> DynFut<Lesser, GetNameOwnerError>;
+
+    /// Store a voucher, identified by k1.
+    fn store_voucher(&self, k1: U256, voucher: &Voucher) -> DynFut<(), StoreVoucherError>;
+
+    fn get_voucher(&self, k1: U256) -> DynFut<Voucher, GetVoucherError>;
+
+    /// Count one use of the voucher, returning how many uses are left. Uses never drop below
+    /// zero, a voucher stays stored until removed.
+    fn use_voucher(&self, k1: U256) -> DynFut<u32, GetVoucherError>;
+
+    /// Remove the voucher so it can no longer be withdrawn from.
+    fn remove_voucher(&self, k1: U256) -> DynFut<Voucher, GetVoucherError>;
+
+    /// The vouchers which expired by now and have not been removed yet.
+    fn expired_vouchers(&self, now: SystemTime) -> DynFut<Vec<U256>, Infallible>;
+
+    /// Store a login challenge, identified by the k1 wallets sign.
+    fn store_auth_challenge(
+        &self,
+        k1: U256,
+        challenge: &AuthChallenge,
+    ) -> DynFut<(), StoreAuthChallengeError>;
+
+    /// Remove and return a login challenge. Each challenge is answered at most once.
+    fn take_auth_challenge(&self, k1: U256) -> DynFut<AuthChallenge, TakeAuthChallengeError>;
+
+    /// Sessions started with linking_key get capability. Each key is linked to one capability.
+    fn store_linking_key(
+        &self,
+        linking_key: &PublicKey,
+        capability: Capability,
+    ) -> DynFut<(), StoreLinkingKeyError>;
+
+    fn get_linked_capability(
+        &self,
+        linking_key: &PublicKey,
+    ) -> DynFut<Capability, GetLinkedCapabilityError>;
+
+    fn store_session(&self, token: U256, session: &Session) -> DynFut<(), StoreSessionError>;
+
+    fn get_session(&self, token: U256) -> DynFut<Session, GetSessionError>;
+
+    /// Remove the sessions and auth challenges which expired by now.
+    fn remove_expired_sessions(&self, now: SystemTime) -> DynFut<(), Infallible>;
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -35,16 +125,133 @@ pub enum WithdrawalError {
     InsufficeintBalance,
 }
 
-// #[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum ReceivePaidInvoiceErr {
     // invoice was already paid
     Duplicate(PaidInvoice),
     // invoice was untracked, it was not associated with an account
     NoMatch(PaidInvoice),
+    // invoice was cancelled, so the payment is not credited
+    Cancelled(PaidInvoice),
+    // Deposit failed
+    Deposit(DepositError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReceiveAcceptedInvoiceErr {
+    // invoice was untracked, it was not associated with an account
+    NoMatch(PaymentHash),
+    // invoice was not waiting for payment
+    NotUnpaid(PaymentHash, InvoiceStatus),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CancelInvoiceError {
+    /// This invoice was never generated
+    InvoiceDoesNotExist,
+    AlreadyPaid,
+    AlreadyCancelled,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReceiveKeysendErr {
+    // keysend with this payment hash was already credited
+    Duplicate(ReceivedKeysend),
+    // payer did not say which account to credit
+    NoRecipient(ReceivedKeysend),
     // Deposit failed
     Deposit(DepositError),
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreAddressError {
+    /// Address was already handed out.
+    EntryAlreadyExists(Lesser, Address),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReceiveOnchainDepositErr {
+    // output was already credited
+    Duplicate(IncomingTransaction),
+    // address was not generated for an account, e.g. change from the node's own spends
+    NoMatch(IncomingTransaction),
+    // Deposit failed
+    Deposit(DepositError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreOfferError {
+    /// Offer was already bound to an account.
+    EntryAlreadyExists(Lesser, U256),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReceiveOfferInvoiceErr {
+    // offer was not created for an account
+    NoMatch(OfferInvoice),
+    // invoice was already stored
+    Store(StoreInvoiceError),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreNameError {
+    /// Name is already registered, to this or another account.
+    EntryAlreadyExists(Lesser, Name),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GetNameOwnerError {
+    /// This name was never registered
+    NameDoesNotExist,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreVoucherError {
+    /// A voucher with this k1 already exists.
+    EntryAlreadyExists(U256),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GetVoucherError {
+    /// No voucher has this k1, or it was removed.
+    VoucherDoesNotExist,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreAuthChallengeError {
+    /// A challenge with this k1 already exists.
+    EntryAlreadyExists(U256),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TakeAuthChallengeError {
+    /// No challenge has this k1, or it was already answered.
+    ChallengeDoesNotExist,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreLinkingKeyError {
+    /// The key is already linked, to this or another account.
+    EntryAlreadyExists(PublicKey),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GetLinkedCapabilityError {
+    /// The key was never linked to an account.
+    KeyNotLinked,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StoreSessionError {
+    /// A session with this token already exists.
+    EntryAlreadyExists(U256),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GetSessionError {
+    SessionDoesNotExist,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum CheckBalanceError {
     /// The account in question does not exist.
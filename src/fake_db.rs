@@ -1,7 +1,10 @@
 use crate::common::*;
 use futures::{future::FutureResult, Future};
 use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::mem;
 use std::sync::Mutex;
+use std::time::SystemTime;
 
 pub struct FakeDb(Mutex<FakeDbInner>);
 
@@ -10,6 +13,15 @@ impl FakeDb {
         let inner = FakeDbInner {
             balances: BTreeMap::new(),
             history: BTreeMap::new(),
+            keysends: BTreeMap::new(),
+            addresses: BTreeMap::new(),
+            deposits: BTreeMap::new(),
+            offers: BTreeMap::new(),
+            names: BTreeMap::new(),
+            vouchers: BTreeMap::new(),
+            auth_challenges: BTreeMap::new(),
+            linking_keys: BTreeMap::new(),
+            sessions: BTreeMap::new(),
         };
         FakeDb(Mutex::new(inner))
     }
@@ -43,14 +55,145 @@ impl Db for FakeDb {
         Box::new(self.0.lock().unwrap().check_invoice_status(payment_hash))
     }
 
+    fn get_invoice_owner(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Lesser, CheckInvoiceStatusError> {
+        Box::new(self.0.lock().unwrap().get_invoice_owner(payment_hash))
+    }
+
+    fn receive_accepted_invoice(
+        &self,
+        payment_hash: PaymentHash,
+    ) -> DynFut<(), ReceiveAcceptedInvoiceErr> {
+        Box::new(
+            self.0
+                .lock()
+                .unwrap()
+                .receive_accepted_invoice(payment_hash),
+        )
+    }
+
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), CancelInvoiceError> {
+        Box::new(self.0.lock().unwrap().cancel_invoice(payment_hash))
+    }
+
     fn receive_paid_invoice(&self, paid_invoice: PaidInvoice) -> DynFut<(), ReceivePaidInvoiceErr> {
         Box::new(self.0.lock().unwrap().receive_paid_invoice(paid_invoice))
     }
+
+    fn receive_keysend(&self, keysend: ReceivedKeysend) -> DynFut<(), ReceiveKeysendErr> {
+        Box::new(self.0.lock().unwrap().receive_keysend(keysend))
+    }
+
+    fn store_address(&self, lesser: Lesser, address: &Address) -> DynFut<(), StoreAddressError> {
+        Box::new(self.0.lock().unwrap().store_address(lesser, address))
+    }
+
+    fn receive_onchain_deposit(
+        &self,
+        transaction: IncomingTransaction,
+    ) -> DynFut<(), ReceiveOnchainDepositErr> {
+        Box::new(self.0.lock().unwrap().receive_onchain_deposit(transaction))
+    }
+
+    fn store_offer(&self, lesser: Lesser, offer_id: U256) -> DynFut<(), StoreOfferError> {
+        Box::new(self.0.lock().unwrap().store_offer(lesser, offer_id))
+    }
+
+    fn receive_offer_invoice(
+        &self,
+        offer_invoice: OfferInvoice,
+    ) -> DynFut<(), ReceiveOfferInvoiceErr> {
+        Box::new(self.0.lock().unwrap().receive_offer_invoice(offer_invoice))
+    }
+
+    fn store_name(&self, lesser: Lesser, name: &Name) -> DynFut<(), StoreNameError> {
+        Box::new(self.0.lock().unwrap().store_name(lesser, name))
+    }
+
+    fn get_name_owner(&self, name: &Name) -> DynFut<Lesser, GetNameOwnerError> {
+        Box::new(self.0.lock().unwrap().get_name_owner(name))
+    }
+
+    fn store_voucher(&self, k1: U256, voucher: &Voucher) -> DynFut<(), StoreVoucherError> {
+        Box::new(self.0.lock().unwrap().store_voucher(k1, voucher))
+    }
+
+    fn get_voucher(&self, k1: U256) -> DynFut<Voucher, GetVoucherError> {
+        Box::new(self.0.lock().unwrap().get_voucher(k1))
+    }
+
+    fn use_voucher(&self, k1: U256) -> DynFut<u32, GetVoucherError> {
+        Box::new(self.0.lock().unwrap().use_voucher(k1))
+    }
+
+    fn remove_voucher(&self, k1: U256) -> DynFut<Voucher, GetVoucherError> {
+        Box::new(self.0.lock().unwrap().remove_voucher(k1))
+    }
+
+    fn expired_vouchers(&self, now: SystemTime) -> DynFut<Vec<U256>, Infallible> {
+        Box::new(self.0.lock().unwrap().expired_vouchers(now))
+    }
+
+    fn store_auth_challenge(
+        &self,
+        k1: U256,
+        challenge: &AuthChallenge,
+    ) -> DynFut<(), StoreAuthChallengeError> {
+        Box::new(self.0.lock().unwrap().store_auth_challenge(k1, challenge))
+    }
+
+    fn take_auth_challenge(&self, k1: U256) -> DynFut<AuthChallenge, TakeAuthChallengeError> {
+        Box::new(self.0.lock().unwrap().take_auth_challenge(k1))
+    }
+
+    fn store_linking_key(
+        &self,
+        linking_key: &PublicKey,
+        capability: Capability,
+    ) -> DynFut<(), StoreLinkingKeyError> {
+        Box::new(
+            self.0
+                .lock()
+                .unwrap()
+                .store_linking_key(linking_key, capability),
+        )
+    }
+
+    fn get_linked_capability(
+        &self,
+        linking_key: &PublicKey,
+    ) -> DynFut<Capability, GetLinkedCapabilityError> {
+        Box::new(self.0.lock().unwrap().get_linked_capability(linking_key))
+    }
+
+    fn store_session(&self, token: U256, session: &Session) -> DynFut<(), StoreSessionError> {
+        Box::new(self.0.lock().unwrap().store_session(token, session))
+    }
+
+    fn get_session(&self, token: U256) -> DynFut<Session, GetSessionError> {
+        Box::new(self.0.lock().unwrap().get_session(token))
+    }
+
+    fn remove_expired_sessions(&self, now: SystemTime) -> DynFut<(), Infallible> {
+        Box::new(self.0.lock().unwrap().remove_expired_sessions(now))
+    }
 }
 
 struct FakeDbInner {
     balances: BTreeMap<Lesser, Satoshis>,
     history: BTreeMap<PaymentHash, (Lesser, InvoiceStatus)>,
+    keysends: BTreeMap<PaymentHash, ReceivedKeysend>,
+    addresses: BTreeMap<Address, Lesser>,
+    deposits: BTreeMap<OutPoint, IncomingTransaction>,
+    offers: BTreeMap<U256, Lesser>,
+    names: BTreeMap<Name, Lesser>,
+    vouchers: BTreeMap<U256, Voucher>,
+    auth_challenges: BTreeMap<U256, AuthChallenge>,
+    /// Keyed by serialized public key.
+    linking_keys: BTreeMap<[u8; 33], Capability>,
+    sessions: BTreeMap<U256, Session>,
 }
 
 impl FakeDbInner {
@@ -137,6 +280,66 @@ impl FakeDbInner {
             .into()
     }
 
+    pub fn get_invoice_owner(
+        &mut self,
+        payment_hash: PaymentHash,
+    ) -> FutureResult<Lesser, CheckInvoiceStatusError> {
+        self.history
+            .get(&payment_hash)
+            .map(|(entry_lesser, _status)| *entry_lesser)
+            .ok_or(CheckInvoiceStatusError::InvoiceDoesNotExist)
+            .into()
+    }
+
+    pub fn receive_accepted_invoice(
+        &mut self,
+        payment_hash: PaymentHash,
+    ) -> FutureResult<(), ReceiveAcceptedInvoiceErr> {
+        self._receive_accepted_invoice(payment_hash).into()
+    }
+
+    fn _receive_accepted_invoice(
+        &mut self,
+        payment_hash: PaymentHash,
+    ) -> Result<(), ReceiveAcceptedInvoiceErr> {
+        let (_lesser, status) = self
+            .history
+            .get_mut(&payment_hash)
+            .ok_or(ReceiveAcceptedInvoiceErr::NoMatch(payment_hash))?;
+        let invoice = match status {
+            InvoiceStatus::Unpaid(invoice) => invoice.clone(),
+            other => {
+                return Err(ReceiveAcceptedInvoiceErr::NotUnpaid(
+                    payment_hash,
+                    other.clone(),
+                ));
+            }
+        };
+        *status = InvoiceStatus::Accepted(invoice);
+        Ok(())
+    }
+
+    pub fn cancel_invoice(
+        &mut self,
+        payment_hash: PaymentHash,
+    ) -> FutureResult<(), CancelInvoiceError> {
+        self._cancel_invoice(payment_hash).into()
+    }
+
+    fn _cancel_invoice(&mut self, payment_hash: PaymentHash) -> Result<(), CancelInvoiceError> {
+        let (_lesser, status) = self
+            .history
+            .get_mut(&payment_hash)
+            .ok_or(CancelInvoiceError::InvoiceDoesNotExist)?;
+        let invoice = match status {
+            InvoiceStatus::Unpaid(invoice) | InvoiceStatus::Accepted(invoice) => invoice.clone(),
+            InvoiceStatus::Paid(_) => return Err(CancelInvoiceError::AlreadyPaid),
+            InvoiceStatus::Cancelled(_) => return Err(CancelInvoiceError::AlreadyCancelled),
+        };
+        *status = InvoiceStatus::Cancelled(invoice);
+        Ok(())
+    }
+
     fn _receive_paid_invoice(
         &mut self,
         paid_invoice: PaidInvoice,
@@ -149,11 +352,14 @@ impl FakeDbInner {
             .get(&payment_hash)
             .ok_or_else(|| ReceivePaidInvoiceErr::NoMatch(paid_invoice.clone()))?;
 
-        // if it is already paid, Err
+        // if it is already paid or was cancelled, Err
         match invoice_status.1 {
             InvoiceStatus::Paid(_) => {
                 return Err(ReceivePaidInvoiceErr::Duplicate(paid_invoice));
             }
+            InvoiceStatus::Cancelled(_) => {
+                return Err(ReceivePaidInvoiceErr::Cancelled(paid_invoice));
+            }
             _ => {}
         };
 
@@ -180,6 +386,241 @@ impl FakeDbInner {
     ) -> FutureResult<(), ReceivePaidInvoiceErr> {
         self._receive_paid_invoice(paid_invoice).into()
     }
+
+    fn _receive_keysend(&mut self, keysend: ReceivedKeysend) -> Result<(), ReceiveKeysendErr> {
+        let lesser = keysend
+            .lesser
+            .ok_or_else(|| ReceiveKeysendErr::NoRecipient(keysend.clone()))?;
+        let payment_hash = keysend.payment_hash();
+        if self.keysends.contains_key(&payment_hash) {
+            return Err(ReceiveKeysendErr::Duplicate(keysend));
+        }
+        self._deposit(lesser, keysend.amount)
+            .map_err(ReceiveKeysendErr::Deposit)?;
+        self.keysends.insert(payment_hash, keysend);
+        Ok(())
+    }
+
+    pub fn receive_keysend(
+        &mut self,
+        keysend: ReceivedKeysend,
+    ) -> FutureResult<(), ReceiveKeysendErr> {
+        self._receive_keysend(keysend).into()
+    }
+
+    pub fn store_address(
+        &mut self,
+        lesser: Lesser,
+        address: &Address,
+    ) -> FutureResult<(), StoreAddressError> {
+        if self.addresses.contains_key(address) {
+            return Err(StoreAddressError::EntryAlreadyExists(
+                lesser,
+                address.clone(),
+            ))
+            .into();
+        }
+        self.addresses.insert(address.clone(), lesser);
+        Ok(()).into()
+    }
+
+    fn _receive_onchain_deposit(
+        &mut self,
+        transaction: IncomingTransaction,
+    ) -> Result<(), ReceiveOnchainDepositErr> {
+        let lesser = *self
+            .addresses
+            .get(&transaction.address)
+            .ok_or_else(|| ReceiveOnchainDepositErr::NoMatch(transaction.clone()))?;
+        if self.deposits.contains_key(&transaction.outpoint) {
+            return Err(ReceiveOnchainDepositErr::Duplicate(transaction));
+        }
+        self._deposit(lesser, transaction.amount)
+            .map_err(ReceiveOnchainDepositErr::Deposit)?;
+        self.deposits.insert(transaction.outpoint, transaction);
+        Ok(())
+    }
+
+    pub fn receive_onchain_deposit(
+        &mut self,
+        transaction: IncomingTransaction,
+    ) -> FutureResult<(), ReceiveOnchainDepositErr> {
+        self._receive_onchain_deposit(transaction).into()
+    }
+
+    pub fn store_offer(
+        &mut self,
+        lesser: Lesser,
+        offer_id: U256,
+    ) -> FutureResult<(), StoreOfferError> {
+        if self.offers.contains_key(&offer_id) {
+            return Err(StoreOfferError::EntryAlreadyExists(lesser, offer_id)).into();
+        }
+        self.offers.insert(offer_id, lesser);
+        Ok(()).into()
+    }
+
+    fn _receive_offer_invoice(
+        &mut self,
+        offer_invoice: OfferInvoice,
+    ) -> Result<(), ReceiveOfferInvoiceErr> {
+        let lesser = *self
+            .offers
+            .get(&offer_invoice.offer_id)
+            .ok_or_else(|| ReceiveOfferInvoiceErr::NoMatch(offer_invoice.clone()))?;
+        self.store_unpaid_invoice(lesser, &offer_invoice.invoice)
+            .wait()
+            .map_err(ReceiveOfferInvoiceErr::Store)
+    }
+
+    pub fn receive_offer_invoice(
+        &mut self,
+        offer_invoice: OfferInvoice,
+    ) -> FutureResult<(), ReceiveOfferInvoiceErr> {
+        self._receive_offer_invoice(offer_invoice).into()
+    }
+
+    pub fn store_name(&mut self, lesser: Lesser, name: &Name) -> FutureResult<(), StoreNameError> {
+        if self.names.contains_key(name) {
+            return Err(StoreNameError::EntryAlreadyExists(lesser, name.clone())).into();
+        }
+        self.names.insert(name.clone(), lesser);
+        Ok(()).into()
+    }
+
+    pub fn get_name_owner(&mut self, name: &Name) -> FutureResult<Lesser, GetNameOwnerError> {
+        self.names
+            .get(name)
+            .cloned()
+            .ok_or(GetNameOwnerError::NameDoesNotExist)
+            .into()
+    }
+
+    pub fn store_voucher(
+        &mut self,
+        k1: U256,
+        voucher: &Voucher,
+    ) -> FutureResult<(), StoreVoucherError> {
+        if self.vouchers.contains_key(&k1) {
+            return Err(StoreVoucherError::EntryAlreadyExists(k1)).into();
+        }
+        self.vouchers.insert(k1, voucher.clone());
+        Ok(()).into()
+    }
+
+    pub fn get_voucher(&mut self, k1: U256) -> FutureResult<Voucher, GetVoucherError> {
+        self.vouchers
+            .get(&k1)
+            .cloned()
+            .ok_or(GetVoucherError::VoucherDoesNotExist)
+            .into()
+    }
+
+    pub fn use_voucher(&mut self, k1: U256) -> FutureResult<u32, GetVoucherError> {
+        self.vouchers
+            .get_mut(&k1)
+            .map(|voucher| {
+                voucher.uses = voucher.uses.saturating_sub(1);
+                voucher.uses
+            })
+            .ok_or(GetVoucherError::VoucherDoesNotExist)
+            .into()
+    }
+
+    pub fn remove_voucher(&mut self, k1: U256) -> FutureResult<Voucher, GetVoucherError> {
+        self.vouchers
+            .remove(&k1)
+            .ok_or(GetVoucherError::VoucherDoesNotExist)
+            .into()
+    }
+
+    pub fn expired_vouchers(&mut self, now: SystemTime) -> FutureResult<Vec<U256>, Infallible> {
+        let expired = self
+            .vouchers
+            .iter()
+            .filter(|(_, voucher)| voucher.expires_at <= now)
+            .map(|(k1, _)| *k1)
+            .collect();
+        Ok(expired).into()
+    }
+
+    pub fn store_auth_challenge(
+        &mut self,
+        k1: U256,
+        challenge: &AuthChallenge,
+    ) -> FutureResult<(), StoreAuthChallengeError> {
+        if self.auth_challenges.contains_key(&k1) {
+            return Err(StoreAuthChallengeError::EntryAlreadyExists(k1)).into();
+        }
+        self.auth_challenges.insert(k1, challenge.clone());
+        Ok(()).into()
+    }
+
+    pub fn take_auth_challenge(
+        &mut self,
+        k1: U256,
+    ) -> FutureResult<AuthChallenge, TakeAuthChallengeError> {
+        self.auth_challenges
+            .remove(&k1)
+            .ok_or(TakeAuthChallengeError::ChallengeDoesNotExist)
+            .into()
+    }
+
+    pub fn store_linking_key(
+        &mut self,
+        linking_key: &PublicKey,
+        capability: Capability,
+    ) -> FutureResult<(), StoreLinkingKeyError> {
+        let serialized = linking_key.serialize();
+        if self.linking_keys.contains_key(&serialized) {
+            return Err(StoreLinkingKeyError::EntryAlreadyExists(*linking_key)).into();
+        }
+        self.linking_keys.insert(serialized, capability);
+        Ok(()).into()
+    }
+
+    pub fn get_linked_capability(
+        &mut self,
+        linking_key: &PublicKey,
+    ) -> FutureResult<Capability, GetLinkedCapabilityError> {
+        self.linking_keys
+            .get(&linking_key.serialize())
+            .cloned()
+            .ok_or(GetLinkedCapabilityError::KeyNotLinked)
+            .into()
+    }
+
+    pub fn store_session(
+        &mut self,
+        token: U256,
+        session: &Session,
+    ) -> FutureResult<(), StoreSessionError> {
+        if self.sessions.contains_key(&token) {
+            return Err(StoreSessionError::EntryAlreadyExists(token)).into();
+        }
+        self.sessions.insert(token, session.clone());
+        Ok(()).into()
+    }
+
+    pub fn get_session(&mut self, token: U256) -> FutureResult<Session, GetSessionError> {
+        self.sessions
+            .get(&token)
+            .cloned()
+            .ok_or(GetSessionError::SessionDoesNotExist)
+            .into()
+    }
+
+    pub fn remove_expired_sessions(&mut self, now: SystemTime) -> FutureResult<(), Infallible> {
+        self.sessions = mem::replace(&mut self.sessions, BTreeMap::new())
+            .into_iter()
+            .filter(|(_, session)| session.expires_at > now)
+            .collect();
+        self.auth_challenges = mem::replace(&mut self.auth_challenges, BTreeMap::new())
+            .into_iter()
+            .filter(|(_, challenge)| challenge.expires_at > now)
+            .collect();
+        Ok(()).into()
+    }
 }
 
 #[cfg(test)]
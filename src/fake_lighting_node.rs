@@ -1,62 +1,537 @@
 use crate::common::*;
+use crate::invoice::MAX_DESCRIPTION_BYTES;
 use bitcoin_hashes::{sha256, Hash};
 use futures::{
     future::FutureResult,
     sink::Sink,
     stream::Stream,
     sync::mpsc::{channel, Receiver, Sender},
+    sync::oneshot,
     Future,
 };
-use lightning_invoice::{Currency, InvoiceBuilder};
-use secp256k1::{key::SecretKey, Secp256k1};
-use std::collections::BTreeMap;
-use std::sync::Mutex;
+use lightning_invoice::InvoiceBuilder;
+use secp256k1::{key::SecretKey, Message, Secp256k1};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const PAID_CHANNEL_BUF_SIZE: usize = 65536;
+/// Every bitcoin there will ever be, in both directions, unless set otherwise.
+const DEFAULT_CAPACITY: Capacity = Capacity {
+    inbound: Satoshis(21_000_000 * 100_000_000),
+    outbound: Satoshis(21_000_000 * 100_000_000),
+};
+/// Virtual size assumed for every transaction the fake sends, that of a one-input, two-output
+/// segwit transaction.
+const TRANSACTION_VSIZE: u64 = 141;
+/// BOLT12's default invoice expiry.
+const OFFER_INVOICE_EXPIRY: Duration = Duration::from_secs(7200);
 
 pub struct FakeLightningNode {
     preimages: Mutex<BTreeMap<PaymentHash, Preimage>>,
-    paid_ivs: Mutex<Option<Sender<Result<PaidInvoice, SubscribePaidInvoicesError>>>>,
+    paid_ivs: Arc<Feed<PaidInvoice>>,
+    /// Scripted results for upcoming payments, used in order.
+    outcomes: Mutex<VecDeque<PayOutcome>>,
+    /// How long pay_invoice takes to respond.
+    latency: Mutex<Duration>,
+    keysends: Feed<ReceivedKeysend>,
+    holds: Mutex<BTreeMap<PaymentHash, Hold>>,
+    accepted: Feed<PaymentHash>,
+    capacity: Mutex<Capacity>,
+    /// Outputs paying to this node's wallet. Each block mined adds a confirmation to every one.
+    outputs: Mutex<Vec<IncomingTransaction>>,
+    transactions: Feed<IncomingTransaction>,
+    /// Channels to this node which are not announced to the network.
+    private_channels: Mutex<Vec<RouteHintHop>>,
+    /// Whether invoices carry a route hint for each private channel.
+    private_route_hints: Mutex<bool>,
+    offers: Mutex<BTreeMap<U256, Offer>>,
+    offer_invoices: Feed<OfferInvoice>,
+    network: Network,
+}
+
+/// What happens to a payment made with pay_invoice or send_onchain. See
+/// FakeLightningNode::queue_outcome.
+#[derive(Clone, Debug)]
+pub enum PayOutcome {
+    /// Pay the invoice, charging half the fee offered.
+    Succeed,
+    /// Pay the invoice, charging exactly this fee, even if it exceeds the fee offered.
+    FeesPaid(Fee<Satoshis>),
+    /// Fail with PaymentAborted. Nothing is paid.
+    Aborted,
+    /// Fail with PayError::Unknown, the state of the payment is unknown to the payer.
+    Unknown(String),
+    /// Fail with PayError::Unsupported, as a backend that can't make the payment does.
+    Unsupported,
+    /// Report success with a preimage that does not match the invoice. Same as Succeed for
+    /// send_onchain.
+    InvalidPreimage,
+    /// Pay the invoice, but don't report the settlement on paid_invoices until after a delay.
+    /// Same as Succeed for send_onchain.
+    DelayedSettlement(Duration),
+}
+
+/// Why the fake refused an invoice request made with simulate_invoice_request.
+#[derive(Clone, Debug)]
+pub enum InvoiceRequestRefused {
+    /// The fake did not create the offer.
+    UnknownOffer,
+    /// The payer offered no amount where the offer names none, or less than the offer asks.
+    Amount,
+    Create(CreateInvoiceError),
+}
+
+/// Key the fake signs its invoices with.
+fn private_key() -> SecretKey {
+    SecretKey::from_slice(&[
+        0xe1, 0x26, 0xf6, 0x8f, 0x7e, 0xaf, 0xcc, 0x8b, 0x74, 0xf5, 0x4d, 0x26, 0x9f, 0xe2, 0x06,
+        0xbe, 0x71, 0x50, 0x00, 0xf9, 0x4d, 0xac, 0x06, 0x7d, 0x1c, 0x04, 0xa8, 0xca, 0x3b, 0x2d,
+        0xb7, 0x34,
+    ])
+    .unwrap()
+}
+
+enum Hold {
+    /// Waiting for payment.
+    Open,
+    /// Payment has arrived. The payer is kept waiting until the invoice is resolved.
+    Accepted {
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        payer: oneshot::Sender<Result<PaidInvoiceOutgoing, PayError>>,
+    },
+    /// Settled or cancelled.
+    Resolved,
 }
 
 impl LightningNode for FakeLightningNode {
-    fn create_invoice(&self, satoshis: Satoshis) -> DynFut<Invoice, CreateInvoiceError> {
-        Box::new(FutureResult::from(self._create_invoice(satoshis)))
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        Box::new(FutureResult::from(self._create_invoice(spec)))
     }
 
+    /// Payments are never split. A payment slower than multi_path.timeout is aborted once the
+    /// timeout passes.
     fn pay_invoice(
         &self,
         invoice: Invoice,
         amount: Satoshis,
         max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
     ) -> DynFut<PaidInvoiceOutgoing, PayError> {
-        let res = self._pay_invoice(invoice, amount, max_fee);
-        Box::new(FutureResult::from(res))
+        let payment_hash = get_payment_hash(&invoice);
+        if self.holds.lock().unwrap().contains_key(&payment_hash) {
+            return self.pay_hold_invoice(invoice, amount, max_fee);
+        }
+        let outcome = self
+            .outcomes
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(PayOutcome::Succeed);
+        let mut latency = *self.latency.lock().unwrap();
+        let res = if latency > multi_path.timeout {
+            latency = multi_path.timeout;
+            Err(PayError::PaymentAborted)
+        } else {
+            self._pay_invoice(invoice, amount, max_fee, outcome)
+        };
+        if latency == Duration::from_secs(0) {
+            return Box::new(FutureResult::from(res));
+        }
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            thread::sleep(latency);
+            let _ = tx.send(res);
+        });
+        Box::new(
+            rx.map_err(|oneshot::Canceled| unreachable!())
+                .and_then(FutureResult::from),
+        )
     }
 
     fn paid_invoices(
         &self,
     ) -> crate::lighting_node::DynStream<PaidInvoice, SubscribePaidInvoicesError> {
-        let mut pivs = self.paid_ivs.lock().unwrap();
-        // paid_invoices() being called twice likely indicates a bug somewhere
-        assert!(pivs.is_none());
-        let (tx, rx) = channel(PAID_CHANNEL_BUF_SIZE);
-        *pivs = Some(tx);
+        self.paid_ivs.subscribe()
+    }
+
+    fn keysend(
+        &self,
+        _pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        // Yup, looks delivered to me.
+        Box::new(FutureResult::from(Ok(KeysendOutgoing {
+            preimage: Preimage(U256::random()),
+            amount,
+            fees_offered: max_fee,
+            fees_paid: max_fee / Fee(Satoshis(2)),
+        })))
+    }
+
+    fn received_keysends(
+        &self,
+    ) -> crate::lighting_node::DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        self.keysends.subscribe()
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        let res = self.build_invoice(spec, payment_hash).map(|invoice| {
+            self.holds.lock().unwrap().insert(payment_hash, Hold::Open);
+            invoice
+        });
+        Box::new(FutureResult::from(res))
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        Box::new(FutureResult::from(self._settle_hold_invoice(preimage)))
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        Box::new(FutureResult::from(self._cancel_hold_invoice(payment_hash)))
+    }
+
+    fn accepted_invoices(
+        &self,
+    ) -> crate::lighting_node::DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        self.accepted.subscribe()
+    }
+
+    /// Quotes depend only on amount: 1 satoshi plus 0.1% of amount expected, twice that at
+    /// worst.
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        // This node only has routes to itself.
+        let payment_hash = get_payment_hash(&invoice);
+        let ours = self.get_preimage(payment_hash).is_some()
+            || self.holds.lock().unwrap().contains_key(&payment_hash);
+        if !ours {
+            return Box::new(FutureResult::from(Err(EstimateFeeError::NoRoute)));
+        }
+        let expected = Satoshis(1) + amount / Satoshis(1000);
+        Box::new(FutureResult::from(Ok(FeeQuote {
+            expected: Fee(expected),
+            worst_case: Fee(expected.saturating_mul(Satoshis(2))),
+        })))
+    }
+
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        Box::new(FutureResult::from(Ok(NodeInfo {
+            alias: "fake".to_owned(),
+            pubkey: PublicKey::from_secret_key(&Secp256k1::new(), &private_key()),
+            capacity: *self.capacity.lock().unwrap(),
+        })))
+    }
+
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        Box::new(FutureResult::from(Ok(Address(format!(
+            "fake1{}",
+            U256::random()
+        )))))
+    }
+
+    fn incoming_transactions(
+        &self,
+    ) -> crate::lighting_node::DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        self.transactions.subscribe()
+    }
+
+    /// Estimates depend only on target_blocks: a feerate of 1 sat/vB plus 24 sat/vB divided by
+    /// target_blocks. Any non-empty address is valid.
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        _amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        Box::new(FutureResult::from(
+            estimate_onchain_fee(&address, target_blocks)
+                .ok_or(EstimateOnchainFeeError::InvalidAddress),
+        ))
+    }
+
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        let outcome = self
+            .outcomes
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(PayOutcome::Succeed);
+        let fees_paid = match outcome {
+            PayOutcome::Aborted => {
+                return Box::new(FutureResult::from(Err(PayError::PaymentAborted)))
+            }
+            PayOutcome::Unknown(err) => {
+                return Box::new(FutureResult::from(Err(PayError::Unknown(err))))
+            }
+            PayOutcome::Unsupported => {
+                return Box::new(FutureResult::from(Err(PayError::Unsupported(
+                    "the fake was told not to send on-chain",
+                ))))
+            }
+            PayOutcome::FeesPaid(fees_paid) => fees_paid,
+            PayOutcome::Succeed
+            | PayOutcome::InvalidPreimage
+            | PayOutcome::DelayedSettlement(_) => max_fee / Fee(Satoshis(2)),
+        };
+        match estimate_onchain_fee(&address, target_blocks) {
+            Some(estimate) if estimate <= max_fee => {}
+            _ => return Box::new(FutureResult::from(Err(PayError::PaymentAborted))),
+        }
+        Box::new(FutureResult::from(Ok(OnchainOutgoing {
+            txid: U256::random(),
+            amount,
+            fees_offered: max_fee,
+            fees_paid,
+        })))
+    }
+
+    /// The fake does not track which of its plain invoices were paid, so a paid invoice is
+    /// cancelled like any other.
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        Box::new(FutureResult::from(self._cancel_invoice(payment_hash)))
+    }
+
+    /// Invoice requests are made with simulate_invoice_request.
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        Box::new(FutureResult::from(self._create_offer(spec)))
+    }
+
+    fn offer_invoices(
+        &self,
+    ) -> crate::lighting_node::DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        self.offer_invoices.subscribe()
+    }
+}
+
+/// None if address is invalid.
+fn estimate_onchain_fee(address: &Address, target_blocks: u32) -> Option<Fee<Satoshis>> {
+    if address.0.is_empty() {
+        return None;
+    }
+    let feerate = 1 + 24 / u64::from(target_blocks.max(1));
+    Some(Fee(Satoshis(TRANSACTION_VSIZE * feerate)))
+}
+
+/// Incoming events. Events published while nobody is subscribed are held for the next
+/// subscriber.
+struct Feed<T> {
+    inner: Mutex<FeedInner<T>>,
+}
+
+struct FeedInner<T> {
+    subscriber: Option<Sender<Result<T, SubscribePaidInvoicesError>>>,
+    backlog: Vec<T>,
+}
+
+impl<T: Send + 'static> Feed<T> {
+    fn new() -> Self {
+        Feed {
+            inner: Mutex::new(FeedInner {
+                subscriber: None,
+                backlog: Vec::new(),
+            }),
+        }
+    }
+
+    /// A new subscription replaces the old one, ending the old stream.
+    fn subscribe(&self) -> crate::lighting_node::DynStream<T, SubscribePaidInvoicesError> {
+        let (mut tx, rx) = channel(PAID_CHANNEL_BUF_SIZE);
+        let mut inner = self.inner.lock().unwrap();
+        for item in inner.backlog.drain(..) {
+            tx = tx
+                .send(Ok(item))
+                .wait()
+                .expect("receiver is still in scope");
+        }
+        inner.subscriber = Some(tx);
         Box::new(
             rx.map_err(|()| unreachable!())
                 .and_then(|resres| FutureResult::from(resres)),
         )
     }
+
+    fn drop_subscriber(&self, err: SubscribePaidInvoicesError) {
+        if let Some(tx) = self.inner.lock().unwrap().subscriber.take() {
+            // the subscriber may already be gone
+            let _ = tx.send(Err(err)).wait();
+        }
+    }
+
+    fn publish(&self, item: T) {
+        let mut inner = self.inner.lock().unwrap();
+        let undelivered = match inner.subscriber.take() {
+            Some(tx) => match tx.send(Ok(item)).wait() {
+                Ok(tx) => {
+                    inner.subscriber = Some(tx);
+                    None
+                }
+                // the subscriber went away
+                Err(err) => err.into_inner().ok(),
+            },
+            None => Some(item),
+        };
+        inner.backlog.extend(undelivered);
+    }
 }
 
 impl FakeLightningNode {
+    /// A node on mainnet.
     pub fn new() -> Self {
+        FakeLightningNode::on(Network::Mainnet)
+    }
+
+    /// A node whose invoices are for network.
+    pub fn on(network: Network) -> Self {
         FakeLightningNode {
             preimages: Mutex::new(BTreeMap::new()),
-            paid_ivs: Mutex::new(None),
+            paid_ivs: Arc::new(Feed::new()),
+            outcomes: Mutex::new(VecDeque::new()),
+            latency: Mutex::new(Duration::from_secs(0)),
+            keysends: Feed::new(),
+            holds: Mutex::new(BTreeMap::new()),
+            accepted: Feed::new(),
+            capacity: Mutex::new(DEFAULT_CAPACITY),
+            outputs: Mutex::new(Vec::new()),
+            transactions: Feed::new(),
+            private_channels: Mutex::new(Vec::new()),
+            private_route_hints: Mutex::new(false),
+            offers: Mutex::new(BTreeMap::new()),
+            offer_invoices: Feed::new(),
+            network,
         }
     }
 
+    /// Report capacity from node_info. Nothing else is limited by it.
+    pub fn set_capacity(&self, capacity: Capacity) {
+        *self.capacity.lock().unwrap() = capacity;
+    }
+
+    /// Script the outcome of a future payment. Outcomes are used by calls to pay_invoice and
+    /// send_onchain in the order they were queued. Once the queue is empty, payments succeed.
+    pub fn queue_outcome(&self, outcome: PayOutcome) {
+        self.outcomes.lock().unwrap().push_back(outcome);
+    }
+
+    /// Open a private channel to this node. channel.pubkey is the peer at the other end.
+    pub fn add_private_channel(&self, channel: RouteHintHop) {
+        self.private_channels.lock().unwrap().push(channel);
+    }
+
+    /// Add a route hint for each private channel to every invoice created from now on.
+    pub fn set_private_route_hints(&self, include: bool) {
+        *self.private_route_hints.lock().unwrap() = include;
+    }
+
+    /// Delay every response to pay_invoice by latency.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.latency.lock().unwrap() = latency;
+    }
+
+    /// End the current paid_invoices stream with an error, as if the connection to the node
+    /// was lost. Settlements made before the next subscription are delivered to it.
+    pub fn drop_paid_invoices(&self) {
+        self.paid_ivs
+            .drop_subscriber(SubscribePaidInvoicesError::Unknown(
+                "subscription dropped".to_owned(),
+            ));
+    }
+
+    /// Simulate a keysend from some other node. lesser is the content of the payer's
+    /// LESSER_RECORD_TYPE record.
+    pub fn simulate_keysend(&self, amount: Satoshis, lesser: Option<Lesser>) -> ReceivedKeysend {
+        let keysend = ReceivedKeysend {
+            preimage: Preimage(U256::random()),
+            amount,
+            lesser,
+        };
+        self.keysends.publish(keysend.clone());
+        keysend
+    }
+
+    /// Simulate someone sending amount to address. The transaction is reported with no
+    /// confirmations until blocks are mined.
+    pub fn simulate_deposit(&self, address: Address, amount: Satoshis) -> OutPoint {
+        let transaction = IncomingTransaction {
+            outpoint: OutPoint {
+                txid: U256::random(),
+                vout: 0,
+            },
+            address,
+            amount,
+            confirmations: 0,
+        };
+        self.outputs.lock().unwrap().push(transaction.clone());
+        self.transactions.publish(transaction.clone());
+        transaction.outpoint
+    }
+
+    /// Mine count blocks, reporting every output to this node again with one more
+    /// confirmation per block.
+    pub fn mine_blocks(&self, count: u32) {
+        let mut outputs = self.outputs.lock().unwrap();
+        for _ in 0..count {
+            for output in outputs.iter_mut() {
+                output.confirmations += 1;
+                self.transactions.publish(output.clone());
+            }
+        }
+    }
+
+    /// Simulate a payer's invoice request for offer. amount is what the payer offers to pay,
+    /// required when the offer names no amount. The invoice is answered as bolt11, since
+    /// Invoice can't represent a bolt12 invoice, and reported on offer_invoices.
+    pub fn simulate_invoice_request(
+        &self,
+        offer: &Offer,
+        amount: Option<Satoshis>,
+    ) -> Result<Invoice, InvoiceRequestRefused> {
+        let offer = self
+            .offers
+            .lock()
+            .unwrap()
+            .get(&offer.id)
+            .cloned()
+            .ok_or(InvoiceRequestRefused::UnknownOffer)?;
+        let amount = match (offer.amount, amount) {
+            (Some(asked), Some(offered)) if offered.0 >= asked.0 => offered,
+            (Some(asked), None) => asked,
+            (None, Some(offered)) => offered,
+            _ => return Err(InvoiceRequestRefused::Amount),
+        };
+        let spec = InvoiceSpec::create(
+            Some(amount),
+            Description::Direct(offer.description),
+            OFFER_INVOICE_EXPIRY,
+        )
+        .expect("offer descriptions are checked when the offer is created");
+        let invoice = self
+            ._create_invoice(spec)
+            .map_err(InvoiceRequestRefused::Create)?;
+        self.offer_invoices.publish(OfferInvoice {
+            offer_id: offer.id,
+            invoice: invoice.clone(),
+        });
+        Ok(invoice)
+    }
+
     fn put_preimage(&self, preimage: Preimage) {
         self.preimages
             .lock()
@@ -64,7 +539,7 @@ impl FakeLightningNode {
             .insert(preimage.hash(), preimage);
     }
 
-    fn get_preimage(&self, payment_hash: PaymentHash) -> Option<Preimage> {
+    pub fn get_preimage(&self, payment_hash: PaymentHash) -> Option<Preimage> {
         self.preimages
             .lock()
             .unwrap()
@@ -72,29 +547,75 @@ impl FakeLightningNode {
             .map(|pre| pre.clone())
     }
 
-    fn _create_invoice(&self, satoshis: Satoshis) -> Result<Invoice, CreateInvoiceError> {
-        let private_key = SecretKey::from_slice(&[
-            0xe1, 0x26, 0xf6, 0x8f, 0x7e, 0xaf, 0xcc, 0x8b, 0x74, 0xf5, 0x4d, 0x26, 0x9f, 0xe2,
-            0x06, 0xbe, 0x71, 0x50, 0x00, 0xf9, 0x4d, 0xac, 0x06, 0x7d, 0x1c, 0x04, 0xa8, 0xca,
-            0x3b, 0x2d, 0xb7, 0x34,
-        ])
-        .unwrap();
+    fn _create_offer(&self, spec: OfferSpec) -> Result<Offer, CreateOfferError> {
+        // invoices for the offer must fit a bolt11 invoice
+        if spec.description.len() > MAX_DESCRIPTION_BYTES {
+            return Err(CreateOfferError::Unknown("description too long".to_owned()));
+        }
+        if let Some(amount) = spec.amount {
+            amount.checked_to_pico_btc().ok_or_else(|| {
+                CreateOfferError::Unknown(format!("amount {} too large", amount.0))
+            })?;
+        }
+        let offer = Offer {
+            id: U256::random(),
+            description: spec.description,
+            amount: spec.amount,
+            issuer: PublicKey::from_secret_key(&Secp256k1::new(), &private_key()),
+            network: self.network,
+        };
+        self.offers.lock().unwrap().insert(offer.id, offer.clone());
+        Ok(offer)
+    }
+
+    fn _create_invoice(&self, spec: InvoiceSpec) -> Result<Invoice, CreateInvoiceError> {
         let random_pre = Preimage(U256::random());
         self.put_preimage(random_pre.clone());
-        let payment_hash = sha256::Hash::from_slice(&random_pre.hash().0).unwrap();
-        let amount_pico_btc = satoshis
-            .checked_to_pico_btc()
-            .ok_or(CreateInvoiceError::Unknown(format!(
-                "invoice amount {} overflowed max value for lnd",
-                satoshis.0
-            )))?;
-        Ok(InvoiceBuilder::new(Currency::Bitcoin)
-            .amount_pico_btc(amount_pico_btc)
-            .description("Test invoice. Do not fill.".into())
+        self.build_invoice(spec, random_pre.hash())
+    }
+
+    fn build_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> Result<Invoice, CreateInvoiceError> {
+        let private_key = private_key();
+        let payment_hash = sha256::Hash::from_slice(&payment_hash.0).unwrap();
+        // bolt11 timestamps are whole seconds, so invoices are the same after a round trip
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let builder = InvoiceBuilder::new(self.network.currency())
             .payment_hash(payment_hash)
-            .current_timestamp()
-            .build_signed(|hash| Secp256k1::new().sign_recoverable(hash, &private_key))
-            .unwrap())
+            .timestamp(UNIX_EPOCH + Duration::from_secs(now.as_secs()))
+            .expiry_time(*spec.expiry());
+        let builder = match spec.satoshis() {
+            Some(satoshis) => {
+                let amount_pico_btc =
+                    satoshis
+                        .checked_to_pico_btc()
+                        .ok_or(CreateInvoiceError::Unknown(format!(
+                            "invoice amount {} overflowed max value for lnd",
+                            satoshis.0
+                        )))?;
+                builder.amount_pico_btc(amount_pico_btc)
+            }
+            None => builder,
+        };
+        let builder = if *self.private_route_hints.lock().unwrap() {
+            let channels = self.private_channels.lock().unwrap();
+            channels.iter().fold(builder, |builder, channel| {
+                builder.route(vec![channel.into()])
+            })
+        } else {
+            builder
+        };
+        let sign = |hash: &Message| Secp256k1::new().sign_recoverable(hash, &private_key);
+        match spec.description() {
+            Description::Direct(memo) => builder.description(memo.clone()).build_signed(sign),
+            Description::Hash(hash) => builder
+                .description_hash(sha256::Hash::from_slice(&hash.0).unwrap())
+                .build_signed(sign),
+        }
+        .map_err(|err| CreateInvoiceError::Unknown(format!("{:?}", err)))
     }
 
     fn _pay_invoice(
@@ -102,22 +623,301 @@ impl FakeLightningNode {
         invoice: Invoice,
         amount: Satoshis,
         max_fee: Fee<Satoshis>,
+        outcome: PayOutcome,
     ) -> Result<PaidInvoiceOutgoing, PayError> {
-        // Yup, looks paid to me.
-        let preimage = self.get_preimage(get_payment_hash(&invoice)).unwrap();
-        let paid_invoice = PaidInvoice::create(invoice, preimage, amount).unwrap();
-        self.paid_ivs
-            .lock()
-            .unwrap()
-            .as_mut()
-            .unwrap()
-            .send(Ok(paid_invoice.clone()))
-            .wait()
-            .unwrap();
+        let fees_paid = match &outcome {
+            PayOutcome::Aborted => return Err(PayError::PaymentAborted),
+            PayOutcome::Unknown(err) => return Err(PayError::Unknown(err.clone())),
+            PayOutcome::Unsupported => {
+                return Err(PayError::Unsupported(
+                    "the fake was told not to pay invoices",
+                ))
+            }
+            PayOutcome::InvalidPreimage => {
+                PaidInvoice::create(invoice, Preimage(U256::random()), amount)?;
+                unreachable!("a random preimage matched the invoice");
+            }
+            PayOutcome::FeesPaid(fees_paid) => *fees_paid,
+            PayOutcome::Succeed | PayOutcome::DelayedSettlement(_) => max_fee / Fee(Satoshis(2)),
+        };
+        // This node only has routes to itself.
+        let preimage = self
+            .get_preimage(get_payment_hash(&invoice))
+            .ok_or(PayError::PaymentAborted)?;
+        let paid_invoice = PaidInvoice::create(invoice, preimage, amount)?;
+        match outcome {
+            PayOutcome::DelayedSettlement(delay) => {
+                let paid_ivs = self.paid_ivs.clone();
+                let paid_invoice = paid_invoice.clone();
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    paid_ivs.publish(paid_invoice);
+                });
+            }
+            _ => self.paid_ivs.publish(paid_invoice.clone()),
+        }
         Ok(PaidInvoiceOutgoing {
             paid_invoice,
             fees_offered: max_fee,
-            fees_paid: max_fee / Fee(Satoshis(2)),
+            fees_paid,
+            parts: 1,
         })
     }
+
+    /// The payment is accepted immediately, but the returned future does not resolve until the
+    /// hold invoice is settled or cancelled.
+    fn pay_hold_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        let payment_hash = get_payment_hash(&invoice);
+        let mut holds = self.holds.lock().unwrap();
+        match holds.get(&payment_hash) {
+            Some(Hold::Open) => {}
+            _ => return Box::new(FutureResult::from(Err(PayError::PaymentAborted))),
+        }
+        let (payer, rx) = oneshot::channel();
+        holds.insert(
+            payment_hash,
+            Hold::Accepted {
+                invoice,
+                amount,
+                max_fee,
+                payer,
+            },
+        );
+        self.accepted.publish(payment_hash);
+        Box::new(
+            rx.map_err(|oneshot::Canceled| PayError::PaymentAborted)
+                .and_then(FutureResult::from),
+        )
+    }
+
+    fn _settle_hold_invoice(&self, preimage: Preimage) -> Result<(), HoldInvoiceError> {
+        let mut holds = self.holds.lock().unwrap();
+        let hold = holds
+            .get_mut(&preimage.hash())
+            .ok_or(HoldInvoiceError::UnknownInvoice)?;
+        match hold {
+            Hold::Open => return Err(HoldInvoiceError::NotAccepted),
+            Hold::Resolved => return Err(HoldInvoiceError::AlreadyResolved),
+            Hold::Accepted { .. } => {}
+        }
+        let (invoice, amount, max_fee, payer) = match std::mem::replace(hold, Hold::Resolved) {
+            Hold::Accepted {
+                invoice,
+                amount,
+                max_fee,
+                payer,
+            } => (invoice, amount, max_fee, payer),
+            _ => unreachable!(),
+        };
+        let paid_invoice = PaidInvoice::create(invoice, preimage, amount)
+            .map_err(|err| HoldInvoiceError::Unknown(format!("{:?}", err)))?;
+        self.paid_ivs.publish(paid_invoice.clone());
+        // The payer may have given up waiting.
+        let _ = payer.send(Ok(PaidInvoiceOutgoing {
+            paid_invoice,
+            fees_offered: max_fee,
+            fees_paid: max_fee / Fee(Satoshis(2)),
+            parts: 1,
+        }));
+        Ok(())
+    }
+
+    fn _cancel_invoice(&self, payment_hash: PaymentHash) -> Result<(), HoldInvoiceError> {
+        let is_hold = self.holds.lock().unwrap().contains_key(&payment_hash);
+        if is_hold {
+            return self._cancel_hold_invoice(payment_hash);
+        }
+        // Without its preimage the invoice can't be paid; payments to it are aborted.
+        self.preimages
+            .lock()
+            .unwrap()
+            .remove(&payment_hash)
+            .map(|_| ())
+            .ok_or(HoldInvoiceError::UnknownInvoice)
+    }
+
+    fn _cancel_hold_invoice(&self, payment_hash: PaymentHash) -> Result<(), HoldInvoiceError> {
+        let mut holds = self.holds.lock().unwrap();
+        let hold = holds
+            .get_mut(&payment_hash)
+            .ok_or(HoldInvoiceError::UnknownInvoice)?;
+        match std::mem::replace(hold, Hold::Resolved) {
+            Hold::Open => Ok(()),
+            Hold::Accepted { payer, .. } => {
+                let _ = payer.send(Err(PayError::PaymentAborted));
+                Ok(())
+            }
+            Hold::Resolved => Err(HoldInvoiceError::AlreadyResolved),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    crate::conformance::conformance_tests!({
+        let node = Arc::new(FakeLightningNode::new());
+        (node.clone(), node)
+    });
+
+    #[test]
+    fn invoice_reflects_spec() {
+        let node = FakeLightningNode::new();
+        let spec = InvoiceSpec::create(
+            Some(Satoshis(21)),
+            Description::Direct("one coffee".to_owned()),
+            Duration::from_secs(600),
+        )
+        .unwrap();
+        let invoice = node.create_invoice(spec).wait().unwrap();
+        let invoice = parse_bolt11(&to_bolt11(&invoice)).unwrap();
+        assert_eq!(
+            invoice.amount_pico_btc(),
+            Satoshis(21).checked_to_pico_btc()
+        );
+        assert_eq!(
+            get_description(&invoice),
+            Description::Direct("one coffee".to_owned())
+        );
+        assert_eq!(
+            invoice.expiry_time().map(|e| e.as_duration()),
+            Some(&Duration::from_secs(600))
+        );
+
+        let hash = U256::random();
+        let spec =
+            InvoiceSpec::create(None, Description::Hash(hash), Duration::from_secs(60)).unwrap();
+        let invoice = node.create_invoice(spec).wait().unwrap();
+        let invoice = parse_bolt11(&to_bolt11(&invoice)).unwrap();
+        assert_eq!(invoice.amount_pico_btc(), None);
+        assert_eq!(get_description(&invoice), Description::Hash(hash));
+        assert_eq!(
+            invoice.expiry_time().map(|e| e.as_duration()),
+            Some(&Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn private_route_hints() {
+        let node = FakeLightningNode::new();
+        let channels = vec![
+            RouteHintHop {
+                pubkey: pubkey_b(),
+                short_channel_id: 0x0009_8a00_0002_0001,
+                fee_base_msat: 1000,
+                fee_proportional_millionths: 1,
+                cltv_expiry_delta: 40,
+            },
+            RouteHintHop {
+                pubkey: pubkey_b(),
+                short_channel_id: 0x0009_8a07_0001_0000,
+                fee_base_msat: 0,
+                fee_proportional_millionths: 250,
+                cltv_expiry_delta: 144,
+            },
+        ];
+        for channel in &channels {
+            node.add_private_channel(channel.clone());
+        }
+        let hints = |node: &FakeLightningNode| {
+            let invoice = node.create_invoice(Satoshis(1).into()).wait().unwrap();
+            get_route_hints(&parse_bolt11(&to_bolt11(&invoice)).unwrap())
+        };
+        assert_eq!(hints(&node), Vec::<Vec<RouteHintHop>>::new());
+        node.set_private_route_hints(true);
+        assert_eq!(
+            hints(&node),
+            channels
+                .into_iter()
+                .map(|channel| vec![channel])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn offer_invoice_requests() {
+        let node = FakeLightningNode::on(Network::Regtest);
+        let offer = node
+            .create_offer(OfferSpec {
+                amount: Some(Satoshis(30)),
+                description: "tea".to_owned(),
+            })
+            .wait()
+            .unwrap();
+        let offer: Offer = offer.to_string().parse().unwrap();
+        assert_eq!(offer.network, Network::Regtest);
+        let issued = node.offer_invoices().wait();
+
+        let first = node.simulate_invoice_request(&offer, None).unwrap();
+        let second = node
+            .simulate_invoice_request(&offer, Some(Satoshis(40)))
+            .unwrap();
+        assert_ne!(get_payment_hash(&first), get_payment_hash(&second));
+        assert_eq!(first.amount_pico_btc(), Satoshis(30).checked_to_pico_btc());
+        assert_eq!(second.amount_pico_btc(), Satoshis(40).checked_to_pico_btc());
+        assert_eq!(
+            get_description(&first),
+            Description::Direct("tea".to_owned())
+        );
+        let issued: Vec<OfferInvoice> = issued.take(2).map(Result::unwrap).collect();
+        assert_eq!(
+            issued,
+            vec![
+                OfferInvoice {
+                    offer_id: offer.id,
+                    invoice: first
+                },
+                OfferInvoice {
+                    offer_id: offer.id,
+                    invoice: second
+                },
+            ]
+        );
+
+        match node.simulate_invoice_request(&offer, Some(Satoshis(29))) {
+            Err(InvoiceRequestRefused::Amount) => {}
+            other => panic!("{:?}", other),
+        }
+        let stranger = Offer {
+            id: U256::random(),
+            ..offer
+        };
+        match node.simulate_invoice_request(&stranger, None) {
+            Err(InvoiceRequestRefused::UnknownOffer) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn slow_payment_times_out() {
+        let node = FakeLightningNode::new();
+        let invoice = node.create_invoice(Satoshis(1).into()).wait().unwrap();
+        node.set_latency(Duration::from_secs(60));
+        let impatient = MultiPath {
+            timeout: Duration::from_millis(10),
+            ..MultiPath::default()
+        };
+        match node
+            .pay_invoice(invoice.clone(), Satoshis(1), Fee(Satoshis(1)), impatient)
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        node.set_latency(Duration::from_millis(1));
+        let outgoing = node
+            .pay_invoice(invoice, Satoshis(1), Fee(Satoshis(1)), impatient)
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.parts, 1);
+    }
 }
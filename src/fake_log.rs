@@ -1,4 +1,6 @@
 use crate::common::{Log, LogErr};
+#[cfg(test)]
+use std::sync::Mutex;
 
 pub struct FakeLog;
 
@@ -7,3 +9,25 @@ impl Log for FakeLog {
         panic!("{:#?}", err);
     }
 }
+
+/// Records errors so tests can assert on them.
+#[cfg(test)]
+pub struct MemLog(Mutex<Vec<LogErr>>);
+
+#[cfg(test)]
+impl MemLog {
+    pub fn new() -> MemLog {
+        MemLog(Mutex::new(Vec::new()))
+    }
+
+    pub fn errors(&self) -> Vec<LogErr> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Log for MemLog {
+    fn _err(&self, err: LogErr) {
+        self.0.lock().unwrap().push(err);
+    }
+}
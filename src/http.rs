@@ -0,0 +1,239 @@
+//! A minimal http client for fetching json from other LNURL services, built on hyper. Each request
+//! runs on its own thread, https connections are secured by the platform's tls. The urls come
+//! from users, so unless private hosts are allowed, hosts on this machine or its private networks
+//! are refused rather than fetched.
+
+use crate::common::*;
+use futures::{future::FutureResult, sync::oneshot, Future, Stream};
+use hyper::{
+    client::{
+        connect::dns::{Name, Resolve},
+        HttpConnector,
+    },
+    header::ACCEPT,
+    Body, Client, Request, StatusCode,
+};
+use hyper_tls::HttpsConnector;
+use serde::de::DeserializeOwned;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+use tokio::{runtime::current_thread::Runtime, timer::Timeout};
+use url::{Host, Url};
+
+/// Responses larger than this are refused.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum HttpError {
+    /// Only http and https urls can be fetched.
+    UnsupportedScheme(String),
+    /// The host is on this machine or a private network, and private hosts are not allowed.
+    PrivateAddress,
+    Io(io::Error),
+    Tls(native_tls::Error),
+    Hyper(hyper::Error),
+    /// The url is not a valid request target, or the response was too large.
+    Malformed,
+    /// The server responded with a status other than 200.
+    Status(u16),
+    Json(serde_json::Error),
+    /// No response arrived within TIMEOUT.
+    Timeout,
+    /// The request thread ended without responding.
+    NoResponse,
+}
+
+/// GET url and parse the body of its response as json. Hosts that resolve to a private address
+/// are refused unless allow_private.
+pub fn get_json<T: DeserializeOwned + Send + 'static>(
+    url: Url,
+    allow_private: bool,
+) -> DynFut<T, HttpError> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        // The receiver may have been dropped, there is nobody to tell.
+        let _ = tx.send(get_json_blocking(url, allow_private));
+    });
+    Box::new(
+        rx.map_err(|oneshot::Canceled| HttpError::NoResponse)
+            .and_then(FutureResult::from),
+    )
+}
+
+fn get_json_blocking<T: DeserializeOwned>(url: Url, allow_private: bool) -> Result<T, HttpError> {
+    match url.scheme() {
+        "http" | "https" => {}
+        other => return Err(HttpError::UnsupportedScheme(other.to_owned())),
+    }
+    // ip addresses are connected to without being resolved
+    let literal = match url.host() {
+        Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+        Some(Host::Domain(_)) => None,
+        None => return Err(HttpError::Malformed),
+    };
+    if !allow_private && literal.map(|ip| !is_public(ip)).unwrap_or(false) {
+        return Err(HttpError::PrivateAddress);
+    }
+    let request = Request::get(url.as_str())
+        .header(ACCEPT, "application/json")
+        .body(Body::empty())
+        .map_err(|_| HttpError::Malformed)?;
+
+    let mut http = HttpConnector::new_with_resolver(PublicResolver { allow_private });
+    http.enforce_http(false);
+    let tls = native_tls::TlsConnector::new().map_err(HttpError::Tls)?;
+    let client = Client::builder().build::<_, Body>(HttpsConnector::from((http, tls)));
+    let response = client
+        .request(request)
+        .map_err(HttpError::Hyper)
+        .and_then(|response| {
+            if response.status() != StatusCode::OK {
+                return Err(HttpError::Status(response.status().as_u16()));
+            }
+            Ok(response)
+        })
+        .and_then(|response| {
+            response
+                .into_body()
+                .map_err(HttpError::Hyper)
+                .fold(Vec::new(), |mut body, chunk| {
+                    if body.len() + chunk.len() > MAX_RESPONSE_BYTES {
+                        return Err(HttpError::Malformed);
+                    }
+                    body.extend_from_slice(&chunk);
+                    Ok(body)
+                })
+        });
+    let body = Runtime::new()
+        .map_err(HttpError::Io)?
+        .block_on(Timeout::new(response, TIMEOUT))
+        .map_err(|err| err.into_inner().unwrap_or(HttpError::Timeout))?;
+    serde_json::from_slice(&body).map_err(HttpError::Json)
+}
+
+/// Resolves hosts like the system does, but leaves out private addresses unless allow_private.
+/// Resolving on the connection itself means a host can't pass a check and then be connected to
+/// at a different address.
+#[derive(Clone)]
+struct PublicResolver {
+    allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+    type Addrs = std::vec::IntoIter<IpAddr>;
+    type Future = FutureResult<Self::Addrs, io::Error>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        // resolving blocks, which is fine as each request has its own thread
+        let allow_private = self.allow_private;
+        let resolved = (name.as_str(), 0).to_socket_addrs().and_then(|addrs| {
+            let ips: Vec<IpAddr> = addrs
+                .map(|addr| addr.ip())
+                .filter(|ip| allow_private || is_public(*ip))
+                .collect();
+            if ips.is_empty() {
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "host resolves only to private addresses",
+                ))
+            } else {
+                Ok(ips.into_iter())
+            }
+        });
+        resolved.into()
+    }
+}
+
+/// Whether ip may be reached from the internet, so is not on this machine, a private network or
+/// a link.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if segments[..5] == [0; 5] && segments[5] == 0xffff {
+                // ipv4 mapped
+                return is_public_v4(Ipv4Addr::new(
+                    (segments[6] >> 8) as u8,
+                    segments[6] as u8,
+                    (segments[7] >> 8) as u8,
+                    segments[7] as u8,
+                ));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local fc00::/7
+                || segments[0] & 0xfe00 == 0xfc00
+                // link local fe80::/10
+                || segments[0] & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // shared address space 100.64.0.0/10, used by carrier grade nat
+        || (octets[0] == 100 && octets[1] & 0xc0 == 64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn public_addresses() {
+        for public in &[
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            assert!(is_public(public.parse().unwrap()), "{}", public);
+        }
+        for private in &[
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(private.parse().unwrap()), "{}", private);
+        }
+    }
+
+    #[test]
+    fn refuses_private_hosts() {
+        let url = Url::parse("http://127.0.0.1:1/.well-known/lnurlp/alice").unwrap();
+        match get_json::<serde_json::Value>(url, false).wait() {
+            Err(HttpError::PrivateAddress) => {}
+            other => panic!("{:?}", other),
+        }
+        let url = Url::parse("http://[::1]:1/").unwrap();
+        match get_json::<serde_json::Value>(url, false).wait() {
+            Err(HttpError::PrivateAddress) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
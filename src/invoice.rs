@@ -1,12 +1,111 @@
 use crate::common::*;
 pub use lightning_invoice::{Invoice, Sha256};
-use lightning_invoice::{ParseOrSemanticError, SignedRawInvoice};
+use lightning_invoice::{InvoiceDescription, ParseOrSemanticError, RouteHop, SignedRawInvoice};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::borrow::Borrow;
+use std::time::Duration;
+
+/// Bolt11 limits a tagged field to 1023 5-bit words, 639 whole bytes.
+pub const MAX_DESCRIPTION_BYTES: usize = 639;
+pub const DEFAULT_EXPIRY: Duration = Duration::from_secs(60 * 60);
+/// Shorter expiries risk the invoice expiring before the payer can act on it.
+pub const MIN_EXPIRY: Duration = Duration::from_secs(60);
+/// Longer expiries leave stale invoices lying around.
+pub const MAX_EXPIRY: Duration = Duration::from_secs(60 * 60 * 24 * 31);
+
+/// Parameters for an invoice that is yet to be created.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InvoiceSpec {
+    /// None for an invoice where the payer chooses the amount.
+    satoshis: Option<Satoshis>,
+    description: Description,
+    /// The description a Description::Hash was computed from, when it is known.
+    hashed: Option<String>,
+    expiry: Duration,
+}
+
+impl InvoiceSpec {
+    pub fn create(
+        satoshis: Option<Satoshis>,
+        description: Description,
+        expiry: Duration,
+    ) -> Result<InvoiceSpec, InvoiceSpecInvalid> {
+        match &description {
+            Description::Direct(memo) if memo.len() > MAX_DESCRIPTION_BYTES => {
+                return Err(InvoiceSpecInvalid::DescriptionTooLong);
+            }
+            _ => {}
+        }
+        if expiry < MIN_EXPIRY || expiry > MAX_EXPIRY {
+            return Err(InvoiceSpecInvalid::ExpiryOutOfRange);
+        }
+        Ok(InvoiceSpec {
+            satoshis,
+            description,
+            hashed: None,
+            expiry,
+        })
+    }
+
+    /// An invoice committing to the hash of description, which is communicated out of band.
+    /// Unlike a Description::Hash alone, this can be created by backends that compute the hash
+    /// themselves.
+    pub fn create_hashed(
+        satoshis: Option<Satoshis>,
+        description: String,
+        expiry: Duration,
+    ) -> Result<InvoiceSpec, InvoiceSpecInvalid> {
+        let hash = sha2::Sha256::digest(description.as_bytes());
+        let hash = U256::try_from_slice(&hash).expect("sha256 is 32 bytes");
+        let spec = InvoiceSpec::create(satoshis, Description::Hash(hash), expiry)?;
+        Ok(InvoiceSpec {
+            hashed: Some(description),
+            ..spec
+        })
+    }
+
+    pub fn satoshis(&self) -> Option<Satoshis> {
+        self.satoshis
+    }
+
+    pub fn description(&self) -> &Description {
+        &self.description
+    }
+
+    /// The description a Description::Hash was computed from, when it is known.
+    pub fn hashed_description(&self) -> Option<&str> {
+        self.hashed.as_ref().map(|description| description.as_str())
+    }
+
+    pub fn expiry(&self) -> &Duration {
+        &self.expiry
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Description {
+    /// Human readable description, included in the invoice as is.
+    Direct(String),
+    /// Sha256 hash of a description that is communicated out of band.
+    Hash(U256),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InvoiceSpecInvalid {
+    DescriptionTooLong,
+    ExpiryOutOfRange,
+}
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum InvoiceStatus {
     Paid(PaidInvoice),
     Unpaid(Invoice),
+    /// Payment for a hold invoice has arrived and is locked, waiting for the invoice owner to
+    /// settle or cancel.
+    Accepted(Invoice),
+    /// The invoice will not be paid.
+    Cancelled(Invoice),
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -22,23 +121,28 @@ impl PaidInvoice {
         preimage: Preimage,
         amount_paid: Satoshis,
     ) -> Result<PaidInvoice, PaidInvoiceInvalid> {
-        let amount_requested_pico = invoice.amount_pico_btc().unwrap_or(0);
         // round amount requested up to the nearest whole satoshi
-        let amount_requested = Satoshis::from_pico_btc(amount_requested_pico)
-            .unwrap_or_else(|NotDivisible { whole, change: _ }| whole + Satoshis(1));
+        let amount_requested = invoice.amount_pico_btc().map(|pico| {
+            Satoshis::from_pico_btc(pico)
+                .unwrap_or_else(|NotDivisible { whole, change: _ }| whole + Satoshis(1))
+        });
 
         if preimage.hash() != get_payment_hash(&invoice) {
-            Err(PaidInvoiceInvalid::PreimageMismatch)
-        } else if amount_requested > amount_paid {
-            Err(PaidInvoiceInvalid::AmountTooSmall)
-        } else if amount_requested.saturating_mul(Satoshis(2)) < amount_paid {
-            Err(PaidInvoiceInvalid::AmountTooLarge)
-        } else {
-            Ok(PaidInvoice {
+            return Err(PaidInvoiceInvalid::PreimageMismatch);
+        }
+        match amount_requested {
+            Some(requested) if requested > amount_paid => Err(PaidInvoiceInvalid::AmountTooSmall),
+            Some(requested) if requested.saturating_mul(Satoshis(2)) < amount_paid => {
+                Err(PaidInvoiceInvalid::AmountTooLarge)
+            }
+            // The payer chooses the amount for amountless invoices, so any amount is accepted,
+            // as long as something was paid.
+            None if amount_paid == Satoshis(0) => Err(PaidInvoiceInvalid::AmountTooSmall),
+            _ => Ok(PaidInvoice {
                 invoice,
                 preimage,
                 amount_paid,
-            })
+            }),
         }
     }
 
@@ -55,7 +159,8 @@ impl PaidInvoice {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PaidInvoiceInvalid {
     PreimageMismatch,
     AmountTooSmall,
@@ -66,7 +171,10 @@ pub enum PaidInvoiceInvalid {
 pub struct PaidInvoiceOutgoing {
     pub paid_invoice: PaidInvoice,
     pub fees_offered: Fee<Satoshis>,
+    /// Total over every part.
     pub fees_paid: Fee<Satoshis>,
+    /// Number of routes the payment was split over.
+    pub parts: u32,
 }
 
 pub fn get_payment_hash(invoice: &Invoice) -> PaymentHash {
@@ -75,6 +183,61 @@ pub fn get_payment_hash(invoice: &Invoice) -> PaymentHash {
     U256::try_from_slice(sl).unwrap()
 }
 
+pub fn get_description(invoice: &Invoice) -> Description {
+    match invoice.description() {
+        InvoiceDescription::Direct(description) => Description::Direct(description.to_string()),
+        InvoiceDescription::Hash(hash) => {
+            let sl: &[u8] = hash.0.borrow();
+            Description::Hash(U256::try_from_slice(sl).unwrap())
+        }
+    }
+}
+
+/// One channel of a route hint, leading towards the payee. A node with only private channels
+/// is reached through the hints in its invoices.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RouteHintHop {
+    /// The node at the near end of the channel.
+    pub pubkey: PublicKey,
+    pub short_channel_id: u64,
+    pub fee_base_msat: u32,
+    pub fee_proportional_millionths: u32,
+    pub cltv_expiry_delta: u16,
+}
+
+impl From<&RouteHop> for RouteHintHop {
+    fn from(hop: &RouteHop) -> RouteHintHop {
+        RouteHintHop {
+            pubkey: hop.pubkey,
+            short_channel_id: u64::from_be_bytes(hop.short_channel_id),
+            fee_base_msat: hop.fee_base_msat,
+            fee_proportional_millionths: hop.fee_proportional_millionths,
+            cltv_expiry_delta: hop.cltv_expiry_delta,
+        }
+    }
+}
+
+impl From<&RouteHintHop> for RouteHop {
+    fn from(hop: &RouteHintHop) -> RouteHop {
+        RouteHop {
+            pubkey: hop.pubkey,
+            short_channel_id: hop.short_channel_id.to_be_bytes(),
+            fee_base_msat: hop.fee_base_msat,
+            fee_proportional_millionths: hop.fee_proportional_millionths,
+            cltv_expiry_delta: hop.cltv_expiry_delta,
+        }
+    }
+}
+
+/// The route hints carried by invoice, each a list of hops ending at the payee.
+pub fn get_route_hints(invoice: &Invoice) -> Vec<Vec<RouteHintHop>> {
+    invoice
+        .routes()
+        .into_iter()
+        .map(|route| route.iter().map(RouteHintHop::from).collect())
+        .collect()
+}
+
 pub fn parse_bolt11(encoded: &str) -> Result<Invoice, ParseOrSemanticError> {
     let raw = encoded.parse::<SignedRawInvoice>()?;
     let invoice = Invoice::from_signed(raw)?;
@@ -88,6 +251,7 @@ pub fn to_bolt11(invoice: &Invoice) -> String {
 #[cfg(test)]
 mod test {
     use super::*;
+    use futures::Future;
 
     #[test]
     fn parse_then_deserialize() {
@@ -100,6 +264,62 @@ mod test {
         assert_eq!(&to_bolt11(&invoice), some_raw_invoice);
     }
 
+    #[test]
+    fn invoice_spec_bounds() {
+        let memo = |len| Description::Direct("a".repeat(len));
+        let one = Some(Satoshis(1));
+        InvoiceSpec::create(one, memo(MAX_DESCRIPTION_BYTES), DEFAULT_EXPIRY).unwrap();
+        assert_eq!(
+            InvoiceSpec::create(one, memo(MAX_DESCRIPTION_BYTES + 1), DEFAULT_EXPIRY),
+            Err(InvoiceSpecInvalid::DescriptionTooLong)
+        );
+        InvoiceSpec::create(one, memo(0), MIN_EXPIRY).unwrap();
+        InvoiceSpec::create(one, memo(0), MAX_EXPIRY).unwrap();
+        assert_eq!(
+            InvoiceSpec::create(one, memo(0), MIN_EXPIRY - Duration::from_secs(1)),
+            Err(InvoiceSpecInvalid::ExpiryOutOfRange)
+        );
+        assert_eq!(
+            InvoiceSpec::create(one, memo(0), MAX_EXPIRY + Duration::from_secs(1)),
+            Err(InvoiceSpecInvalid::ExpiryOutOfRange)
+        );
+        InvoiceSpec::create(one, Description::Hash(U256::random()), DEFAULT_EXPIRY).unwrap();
+        InvoiceSpec::create(None, memo(0), DEFAULT_EXPIRY).unwrap();
+    }
+
+    #[test]
+    fn hashed_spec() {
+        let metadata = "[[\"text/plain\",\"coffee\"]]".to_owned();
+        let spec = InvoiceSpec::create_hashed(None, metadata.clone(), DEFAULT_EXPIRY).unwrap();
+        assert_eq!(
+            spec.description(),
+            &Description::Hash(crate::lnurl::metadata_hash(&metadata))
+        );
+        assert_eq!(spec.hashed_description(), Some(&metadata[..]));
+        let unknown =
+            InvoiceSpec::create(None, spec.description().clone(), DEFAULT_EXPIRY).unwrap();
+        assert_eq!(unknown.hashed_description(), None);
+    }
+
+    #[test]
+    fn amountless_paid_invoice() {
+        let node = FakeLightningNode::new();
+        let spec =
+            InvoiceSpec::create(None, Description::Direct("".to_owned()), DEFAULT_EXPIRY).unwrap();
+        let invoice = node.create_invoice(spec).wait().unwrap();
+        let preimage = node.get_preimage(get_payment_hash(&invoice)).unwrap();
+        assert_eq!(
+            PaidInvoice::create(invoice.clone(), preimage, Satoshis(1_000_000))
+                .unwrap()
+                .amount_paid(),
+            &Satoshis(1_000_000)
+        );
+        assert_eq!(
+            PaidInvoice::create(invoice, preimage, Satoshis(0)),
+            Err(PaidInvoiceInvalid::AmountTooSmall)
+        );
+    }
+
     #[test]
     fn invalid_bolt11() {
         let some_raw_invoice_invalid =
@@ -0,0 +1,41 @@
+//! Spontaneous payments. The payer generates the preimage and sends it to the payee inside the
+//! payment's onion, so no invoice is needed.
+
+use crate::common::*;
+pub use secp256k1::PublicKey;
+
+/// Custom TLV record type carrying the Lesser to credit for an incoming keysend.
+/// Record types above 65535 are reserved for custom use. Odd types are optional for the
+/// recipient to understand, so nodes that don't know about lapi still accept the payment.
+pub const LESSER_RECORD_TYPE: u64 = 0x6c61_7069; // "lapi"
+
+/// Custom TLV record type in which keysend payers send the preimage, as lnd and cln expect.
+pub const PREIMAGE_RECORD_TYPE: u64 = 5_482_373_484;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeysendOutgoing {
+    pub preimage: Preimage,
+    pub amount: Satoshis,
+    pub fees_offered: Fee<Satoshis>,
+    pub fees_paid: Fee<Satoshis>,
+}
+
+/// A keysend payment received by our node.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ReceivedKeysend {
+    pub preimage: Preimage,
+    pub amount: Satoshis,
+    /// Parsed from the LESSER_RECORD_TYPE record, if the payer included one.
+    pub lesser: Option<Lesser>,
+}
+
+impl ReceivedKeysend {
+    pub fn payment_hash(&self) -> PaymentHash {
+        self.preimage.hash()
+    }
+}
+
+/// Parse the value of a LESSER_RECORD_TYPE record.
+pub fn parse_lesser_record(value: &[u8]) -> Option<Lesser> {
+    U256::try_from_slice(value).map(Lesser)
+}
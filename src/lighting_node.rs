@@ -1,22 +1,210 @@
 use crate::common::*;
 use futures::{future::FutureResult, Stream};
 use lightning_invoice::ParseOrSemanticError;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub type DynStream<I, E> = Box<dyn Stream<Item = I, Error = E> + Send>;
 
 pub trait LightningNode: Sync + Send {
-    /// Generate a unique invoice for n satoshis.
-    fn create_invoice(&self, satoshis: Satoshis) -> DynFut<Invoice, CreateInvoiceError>;
+    /// Generate a unique invoice for spec.satoshis() satoshis. The returned invoice carries
+    /// the description and expiry from spec.
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError>;
 
-    /// Send to invoice. If invoice does not specfy an amount, return a PayError.
+    /// Send to invoice. If invoice does not specfy an amount, return a PayError. The payment may
+    /// be split over several routes, within the limits of multi_path.
     fn pay_invoice(
         &self,
         invoice: Invoice,
         amount: Satoshis,
         max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
     ) -> DynFut<PaidInvoiceOutgoing, PayError>;
 
     fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError>;
+
+    /// Pay amount to the node with pubkey without an invoice. The preimage is generated locally.
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError>;
+
+    /// Keysend payments received by this node.
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError>;
+
+    /// Generate an invoice for a payment_hash chosen by the caller. When the invoice is paid,
+    /// the payment is held, appearing on accepted_invoices, until the invoice is settled or
+    /// cancelled.
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError>;
+
+    /// Claim the payment held for preimage.hash(). The settled invoice appears on
+    /// paid_invoices.
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError>;
+
+    /// Refuse payment for a hold invoice, returning any held payment to the payer.
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError>;
+
+    /// Payment hashes of hold invoices whose payment has arrived and is being held.
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError>;
+
+    /// Estimate the routing fee for paying amount to invoice, without paying.
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError>;
+
+    /// Identity of the node and the capacity of its channels.
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError>;
+
+    /// Generate an unused address paying to the node's on-chain wallet.
+    fn new_address(&self) -> DynFut<Address, NewAddressError>;
+
+    /// Outputs paying to the node's on-chain wallet. Each output is reported when first seen,
+    /// then again every time its number of confirmations changes.
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError>;
+
+    /// Estimate the miner fee for sending amount to address, paying enough to be mined within
+    /// target_blocks blocks.
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError>;
+
+    /// Send amount to address from the node's wallet, aiming to be mined within target_blocks
+    /// blocks. If the miner fee would be more than max_fee, nothing is sent and PaymentAborted
+    /// is returned.
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError>;
+
+    /// Cancel an unpaid invoice, hold or not. Later attempts to pay it fail. A payment held for
+    /// a hold invoice is returned to the payer.
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError>;
+
+    /// Create a BOLT12 offer. The node answers each invoice request for it with a fresh
+    /// invoice, reported on offer_invoices.
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError>;
+
+    /// Invoices issued in answer to invoice requests for the node's offers. Payment for them
+    /// is reported on paid_invoices like that of any other invoice.
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError>;
+}
+
+impl<L: LightningNode + ?Sized> LightningNode for Arc<L> {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        (**self).create_invoice(spec)
+    }
+
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        (**self).pay_invoice(invoice, amount, max_fee, multi_path)
+    }
+
+    fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        (**self).paid_invoices()
+    }
+
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        (**self).keysend(pubkey, amount, max_fee)
+    }
+
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        (**self).received_keysends()
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        (**self).create_hold_invoice(spec, payment_hash)
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        (**self).settle_hold_invoice(preimage)
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        (**self).cancel_hold_invoice(payment_hash)
+    }
+
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        (**self).accepted_invoices()
+    }
+
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        (**self).estimate_fee(invoice, amount)
+    }
+
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        (**self).node_info()
+    }
+
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        (**self).new_address()
+    }
+
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        (**self).incoming_transactions()
+    }
+
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        (**self).estimate_onchain_fee(address, amount, target_blocks)
+    }
+
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        (**self).send_onchain(address, amount, target_blocks, max_fee)
+    }
+
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        (**self).cancel_invoice(payment_hash)
+    }
+
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        (**self).create_offer(spec)
+    }
+
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        (**self).offer_invoices()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +213,8 @@ pub enum CreateInvoiceError {
     Network { backend_name: String, err: String },
     /// Backend created an invoice, but it was not valid.
     InvalidInvoice(ParseOrSemanticError),
+    /// The backend does not support this kind of invoice.
+    Unsupported(&'static str),
     /// Generic server error
     Unknown(String),
 }
@@ -34,9 +224,116 @@ pub enum PayError {
     /// The payment did not succeed. The payment will never be attempted again.
     PaymentAborted,
     InvalidResponse(PaidInvoiceInvalid),
+    /// The backend does not support this kind of payment.
+    Unsupported(&'static str),
     Unknown(String), // TODO, enumerate payment failure modes, remove String, remove Unknown variant
 }
 
+impl PayError {
+    /// Whether the payment certainly sent nothing, so the funds set aside for it can be
+    /// returned. Unknown failures may yet succeed.
+    pub fn nothing_sent(&self) -> bool {
+        match self {
+            PayError::PaymentAborted | PayError::Unsupported(_) => true,
+            PayError::InvalidResponse(_) | PayError::Unknown(_) => false,
+        }
+    }
+}
+
+/// Limits on splitting a payment over several routes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MultiPath {
+    /// Most routes the payment may be split over. 1 sends the whole amount along a single route.
+    pub max_parts: u32,
+    /// How long to keep trying routes before giving up on the payment.
+    pub timeout: Duration,
+}
+
+impl Default for MultiPath {
+    /// lnd's defaults.
+    fn default() -> MultiPath {
+        MultiPath {
+            max_parts: 16,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum HoldInvoiceError {
+    /// The node has no such invoice.
+    UnknownInvoice,
+    /// Settle was called before payment arrived.
+    NotAccepted,
+    /// The invoice was already settled or cancelled.
+    AlreadyResolved,
+    /// The backend does not support hold invoices.
+    Unsupported(&'static str),
+    Unknown(String),
+}
+
+/// Routing fee estimate for a payment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeQuote {
+    /// Fee along the cheapest route known.
+    pub expected: Fee<Satoshis>,
+    /// Highest fee among the routes the payment might take. A max_fee of this much should not
+    /// abort the payment for lack of fee budget.
+    pub worst_case: Fee<Satoshis>,
+}
+
+#[derive(Debug, Clone)]
+pub enum EstimateFeeError {
+    /// No route to the payee with enough capacity is known.
+    NoRoute,
+    Unknown(String),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct NodeInfo {
+    pub alias: String,
+    pub pubkey: PublicKey,
+    pub capacity: Capacity,
+}
+
+/// Totals over the node's active channels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Capacity {
+    /// The most the node can currently receive.
+    pub inbound: Satoshis,
+    /// The most the node can currently send.
+    pub outbound: Satoshis,
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeInfoError {
+    Unknown(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum NewAddressError {
+    /// The backend can't report deposits to its addresses, so it doesn't hand any out.
+    Unsupported(&'static str),
+    Unknown(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum EstimateOnchainFeeError {
+    /// The node could not parse the address, or it is for another network.
+    InvalidAddress,
+    /// The backend can't send on-chain.
+    Unsupported(&'static str),
+    Unknown(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum CreateOfferError {
+    /// The backend can't answer invoice requests.
+    Unsupported(&'static str),
+    Unknown(String),
+}
+
+/// Error from one of the node's incoming payment streams.
 #[derive(Debug, Clone)]
 pub enum SubscribePaidInvoicesError {
     Unknown(String),
@@ -0,0 +1,762 @@
+//! lnd rpcs lnd_rust has no bindings for, mostly those of lnd's sub-servers. Each message
+//! carries only the fields lapi uses, written and read with crate::wire. Field numbers are
+//! those of lnd's .proto files.
+//!
+//! grpc only marshals protobuf messages, so on the wire each message travels as protobuf's
+//! Empty, whose unknown fields hold every field written and are passed along unchanged.
+
+use crate::wire::{Fields, WireError, Writer};
+use grpc::{
+    protobuf::MarshallerProtobuf,
+    rt::{GrpcStreaming, MethodDescriptor},
+    RequestOptions, SingleResponse, StreamingResponse,
+};
+use protobuf::{well_known_types::Empty as Raw, Message};
+use std::sync::Arc;
+
+pub trait Request {
+    fn write(&self) -> Writer;
+}
+
+pub trait Response: Sized {
+    fn read(fields: &Fields) -> Result<Self, WireError>;
+}
+
+/// Call a unary rpc, method is the full path, as in "/invoicesrpc.Invoices/SettleInvoice".
+pub fn unary<Req, Resp>(
+    client: &grpc::Client,
+    options: RequestOptions,
+    method: &'static str,
+    request: Req,
+) -> SingleResponse<Resp>
+where
+    Req: Request,
+    Resp: Response + Send + 'static,
+{
+    match to_raw(&request) {
+        Ok(raw) => client
+            .call_unary(options, raw, descriptor(method, GrpcStreaming::Unary))
+            .into_stream()
+            .and_then_items(from_raw)
+            .single(),
+        Err(err) => SingleResponse::err(err),
+    }
+}
+
+/// Call an rpc which streams its responses.
+pub fn server_streaming<Req, Resp>(
+    client: &grpc::Client,
+    options: RequestOptions,
+    method: &'static str,
+    request: Req,
+) -> StreamingResponse<Resp>
+where
+    Req: Request,
+    Resp: Response + Send + 'static,
+{
+    match to_raw(&request) {
+        Ok(raw) => client
+            .call_server_streaming(
+                options,
+                raw,
+                descriptor(method, GrpcStreaming::ServerStreaming),
+            )
+            .and_then_items(from_raw),
+        Err(err) => StreamingResponse::err(err),
+    }
+}
+
+fn descriptor(method: &'static str, streaming: GrpcStreaming) -> Arc<MethodDescriptor<Raw, Raw>> {
+    Arc::new(MethodDescriptor {
+        name: method.to_owned(),
+        streaming,
+        req_marshaller: Box::new(MarshallerProtobuf),
+        resp_marshaller: Box::new(MarshallerProtobuf),
+    })
+}
+
+fn to_raw<M: Request>(message: &M) -> grpc::Result<Raw> {
+    Ok(protobuf::parse_from_bytes(&message.write().into_bytes())?)
+}
+
+fn from_raw<M: Response>(raw: Raw) -> grpc::Result<M> {
+    Fields::read(&raw.write_to_bytes()?)
+        .and_then(|fields| M::read(&fields))
+        .map_err(|_| grpc::Error::Other("lnd sent a malformed response"))
+}
+
+/// The empty responses of rpcs which report only success or failure.
+impl Response for () {
+    fn read(_fields: &Fields) -> Result<(), WireError> {
+        Ok(())
+    }
+}
+
+pub const SEND_PAYMENT: &str = "/routerrpc.Router/SendPaymentV2";
+pub const ADD_HOLD_INVOICE: &str = "/invoicesrpc.Invoices/AddHoldInvoice";
+pub const SETTLE_INVOICE: &str = "/invoicesrpc.Invoices/SettleInvoice";
+pub const CANCEL_INVOICE: &str = "/invoicesrpc.Invoices/CancelInvoice";
+pub const SUBSCRIBE_SINGLE_INVOICE: &str = "/invoicesrpc.Invoices/SubscribeSingleInvoice";
+/// lnd_rust has bindings, but its Invoice predates the htlcs keysends are credited from.
+pub const SUBSCRIBE_INVOICES: &str = "/lnrpc.Lightning/SubscribeInvoices";
+pub const NEW_ADDRESS: &str = "/lnrpc.Lightning/NewAddress";
+pub const GET_TRANSACTIONS: &str = "/lnrpc.Lightning/GetTransactions";
+pub const REGISTER_BLOCK_EPOCH: &str = "/chainrpc.ChainNotifier/RegisterBlockEpochNtfn";
+pub const ESTIMATE_FEE: &str = "/lnrpc.Lightning/EstimateFee";
+pub const SEND_COINS: &str = "/lnrpc.Lightning/SendCoins";
+
+/// A request with every field left at its default.
+pub struct Empty;
+
+impl Request for Empty {
+    fn write(&self) -> Writer {
+        Writer::new()
+    }
+}
+
+/// routerrpc.SendPaymentRequest. Either payment_request is set, or dest and payment_hash are.
+#[derive(Clone, Default, Debug)]
+pub struct SendPaymentRequest {
+    pub dest: Vec<u8>,
+    pub amt: i64,
+    pub payment_hash: Vec<u8>,
+    pub payment_request: String,
+    /// How long lnd keeps trying routes. lnd requires it be set.
+    pub timeout_seconds: i32,
+    pub fee_limit_sat: i64,
+    pub dest_custom_records: Vec<(u64, Vec<u8>)>,
+    pub max_parts: u32,
+}
+
+impl Request for SendPaymentRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer
+            .bytes(1, &self.dest)
+            .varint(2, self.amt as u64)
+            .bytes(3, &self.payment_hash)
+            .string(5, &self.payment_request)
+            .varint(6, self.timeout_seconds as u64)
+            .varint(7, self.fee_limit_sat as u64);
+        for (key, value) in &self.dest_custom_records {
+            // map entries are messages with the key as field 1 and the value as field 2
+            let mut entry = Writer::new();
+            entry.varint(1, *key).bytes(2, value);
+            writer.message(11, &entry);
+        }
+        writer.varint(17, u64::from(self.max_parts));
+        writer
+    }
+}
+
+/// lnrpc.PaymentStatus
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PaymentStatus {
+    InFlight,
+    Succeeded,
+    Failed,
+    /// UNKNOWN, INITIATED, or a status added since.
+    Other(u64),
+}
+
+/// lnrpc.Payment, as reported by SendPaymentV2 each time the payment changes.
+#[derive(Clone, Debug)]
+pub struct Payment {
+    pub status: PaymentStatus,
+    /// Hex, empty until the payment succeeds.
+    pub payment_preimage: String,
+    pub fee_sat: i64,
+    /// The routes the payment was split over and which reached the destination.
+    pub succeeded_htlcs: u32,
+}
+
+impl Payment {
+    /// Whether lnd is done with the payment. No update follows a final one.
+    pub fn is_final(&self) -> bool {
+        match self.status {
+            PaymentStatus::Succeeded | PaymentStatus::Failed => true,
+            PaymentStatus::InFlight | PaymentStatus::Other(_) => false,
+        }
+    }
+}
+
+impl Response for Payment {
+    fn read(fields: &Fields) -> Result<Payment, WireError> {
+        let status = match fields.varint(10) {
+            1 => PaymentStatus::InFlight,
+            2 => PaymentStatus::Succeeded,
+            3 => PaymentStatus::Failed,
+            other => PaymentStatus::Other(other),
+        };
+        let mut succeeded_htlcs = 0;
+        for htlc in fields.repeated(14) {
+            // lnrpc.HTLCAttempt, status 1 is SUCCEEDED
+            if Fields::read(htlc)?.varint(1) == 1 {
+                succeeded_htlcs += 1;
+            }
+        }
+        Ok(Payment {
+            status,
+            payment_preimage: fields.string(6)?.to_owned(),
+            fee_sat: fields.int(11),
+            succeeded_htlcs,
+        })
+    }
+}
+
+/// invoicesrpc.AddHoldInvoiceRequest
+#[derive(Clone, Default, Debug)]
+pub struct AddHoldInvoiceRequest {
+    pub memo: String,
+    pub hash: Vec<u8>,
+    /// 0 lets the payer choose the amount.
+    pub value: i64,
+    pub description_hash: Vec<u8>,
+    pub expiry: i64,
+    pub private: bool,
+}
+
+impl Request for AddHoldInvoiceRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer
+            .string(1, &self.memo)
+            .bytes(2, &self.hash)
+            .varint(3, self.value as u64)
+            .bytes(4, &self.description_hash)
+            .varint(5, self.expiry as u64)
+            .varint(9, self.private as u64);
+        writer
+    }
+}
+
+/// invoicesrpc.AddHoldInvoiceResp
+#[derive(Clone, Debug)]
+pub struct AddHoldInvoiceResponse {
+    pub payment_request: String,
+}
+
+impl Response for AddHoldInvoiceResponse {
+    fn read(fields: &Fields) -> Result<AddHoldInvoiceResponse, WireError> {
+        Ok(AddHoldInvoiceResponse {
+            payment_request: fields.string(1)?.to_owned(),
+        })
+    }
+}
+
+/// invoicesrpc.SettleInvoiceMsg
+#[derive(Clone, Debug)]
+pub struct SettleInvoiceRequest {
+    pub preimage: Vec<u8>,
+}
+
+impl Request for SettleInvoiceRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer.bytes(1, &self.preimage);
+        writer
+    }
+}
+
+/// invoicesrpc.CancelInvoiceMsg
+#[derive(Clone, Debug)]
+pub struct CancelInvoiceRequest {
+    pub payment_hash: Vec<u8>,
+}
+
+impl Request for CancelInvoiceRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer.bytes(1, &self.payment_hash);
+        writer
+    }
+}
+
+/// invoicesrpc.SubscribeSingleInvoiceRequest
+#[derive(Clone, Debug)]
+pub struct SubscribeSingleInvoiceRequest {
+    pub r_hash: Vec<u8>,
+}
+
+impl Request for SubscribeSingleInvoiceRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer.bytes(2, &self.r_hash);
+        writer
+    }
+}
+
+/// lnrpc.Invoice.InvoiceState
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InvoiceState {
+    Open,
+    Settled,
+    Canceled,
+    /// Payment has arrived and is held.
+    Accepted,
+    Other(u64),
+}
+
+/// The state of an lnrpc.Invoice, the rest of the invoice is not read. SubscribeSingleInvoice
+/// sends the invoice once on subscription, then again on every change.
+#[derive(Clone, Debug)]
+pub struct InvoiceUpdate {
+    pub state: InvoiceState,
+}
+
+impl Response for InvoiceUpdate {
+    fn read(fields: &Fields) -> Result<InvoiceUpdate, WireError> {
+        Ok(InvoiceUpdate {
+            state: invoice_state(fields),
+        })
+    }
+}
+
+/// The state of the lnrpc.Invoice in fields.
+fn invoice_state(fields: &Fields) -> InvoiceState {
+    match fields.varint(21) {
+        0 => InvoiceState::Open,
+        1 => InvoiceState::Settled,
+        2 => InvoiceState::Canceled,
+        3 => InvoiceState::Accepted,
+        other => InvoiceState::Other(other),
+    }
+}
+
+/// lnrpc.InvoiceSubscription
+#[derive(Clone, Debug)]
+pub struct SubscribeInvoicesRequest {
+    /// Invoices settled after this index are sent before any updates, 0 sends none.
+    pub settle_index: u64,
+}
+
+impl Request for SubscribeInvoicesRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer.varint(2, self.settle_index);
+        writer
+    }
+}
+
+/// The fields of an lnrpc.Invoice a keysend is credited from. lnd adds an invoice for each
+/// keysend it receives, SubscribeInvoices sends it along with every other invoice.
+#[derive(Clone, Debug)]
+pub struct KeysendInvoice {
+    pub r_preimage: Vec<u8>,
+    pub settle_index: u64,
+    pub amt_paid_sat: i64,
+    pub state: InvoiceState,
+    pub is_keysend: bool,
+    /// The custom records of each htlc paying the invoice, as type and value.
+    pub htlc_records: Vec<Vec<(u64, Vec<u8>)>>,
+}
+
+impl Response for KeysendInvoice {
+    fn read(fields: &Fields) -> Result<KeysendInvoice, WireError> {
+        let htlc_records = fields
+            .repeated(22)
+            .map(|htlc| {
+                // map entries are messages with the key as field 1 and the value as field 2
+                Fields::read(htlc)?
+                    .repeated(9)
+                    .map(|entry| {
+                        let entry = Fields::read(entry)?;
+                        Ok((entry.varint(1), entry.bytes(2).to_vec()))
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(KeysendInvoice {
+            r_preimage: fields.bytes(3).to_vec(),
+            settle_index: fields.varint(17),
+            amt_paid_sat: fields.int(19),
+            state: invoice_state(fields),
+            is_keysend: fields.varint(25) != 0,
+            htlc_records,
+        })
+    }
+}
+
+// lnrpc.NewAddressRequest for a p2wkh address, AddressType 0, is Empty.
+
+/// lnrpc.NewAddressResponse
+#[derive(Clone, Debug)]
+pub struct NewAddressResponse {
+    pub address: String,
+}
+
+impl Response for NewAddressResponse {
+    fn read(fields: &Fields) -> Result<NewAddressResponse, WireError> {
+        Ok(NewAddressResponse {
+            address: fields.string(1)?.to_owned(),
+        })
+    }
+}
+
+/// lnrpc.GetTransactionsRequest
+#[derive(Clone, Debug)]
+pub struct GetTransactionsRequest {
+    pub start_height: i32,
+    /// -1 includes unconfirmed transactions.
+    pub end_height: i32,
+}
+
+impl Request for GetTransactionsRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer
+            .varint(1, self.start_height as i64 as u64)
+            .varint(2, self.end_height as i64 as u64);
+        writer
+    }
+}
+
+/// lnrpc.TransactionDetails
+#[derive(Clone, Debug)]
+pub struct TransactionDetails {
+    pub transactions: Vec<Transaction>,
+}
+
+impl Response for TransactionDetails {
+    fn read(fields: &Fields) -> Result<TransactionDetails, WireError> {
+        let transactions = fields
+            .repeated(1)
+            .map(|transaction| Transaction::read(&Fields::read(transaction)?))
+            .collect::<Result<_, _>>()?;
+        Ok(TransactionDetails { transactions })
+    }
+}
+
+/// lnrpc.Transaction
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    /// Hex, in the byte order transaction ids are displayed in.
+    pub tx_hash: String,
+    pub num_confirmations: i64,
+    /// Fees the node's wallet paid, for transactions it sent.
+    pub total_fees: i64,
+    pub output_details: Vec<OutputDetail>,
+}
+
+impl Response for Transaction {
+    fn read(fields: &Fields) -> Result<Transaction, WireError> {
+        let output_details = fields
+            .repeated(11)
+            .map(|output| OutputDetail::read(&Fields::read(output)?))
+            .collect::<Result<_, _>>()?;
+        Ok(Transaction {
+            tx_hash: fields.string(1)?.to_owned(),
+            num_confirmations: fields.int(3),
+            total_fees: fields.int(7),
+            output_details,
+        })
+    }
+}
+
+/// lnrpc.OutputDetail
+#[derive(Clone, Debug)]
+pub struct OutputDetail {
+    /// Empty for outputs lnd can't express as an address.
+    pub address: String,
+    pub output_index: i64,
+    pub amount: i64,
+    pub is_our_address: bool,
+}
+
+impl Response for OutputDetail {
+    fn read(fields: &Fields) -> Result<OutputDetail, WireError> {
+        Ok(OutputDetail {
+            address: fields.string(2)?.to_owned(),
+            output_index: fields.int(4),
+            amount: fields.int(5),
+            is_our_address: fields.varint(6) != 0,
+        })
+    }
+}
+
+/// lnrpc.EstimateFeeRequest for a transaction paying a single address.
+#[derive(Clone, Debug)]
+pub struct EstimateFeeRequest {
+    pub addr: String,
+    pub amount: i64,
+    pub target_conf: i32,
+}
+
+impl Request for EstimateFeeRequest {
+    fn write(&self) -> Writer {
+        let mut entry = Writer::new();
+        entry.string(1, &self.addr).varint(2, self.amount as u64);
+        let mut writer = Writer::new();
+        writer
+            .message(1, &entry)
+            .varint(2, self.target_conf as i64 as u64);
+        writer
+    }
+}
+
+/// lnrpc.EstimateFeeResponse
+#[derive(Clone, Debug)]
+pub struct EstimateFeeResponse {
+    pub fee_sat: i64,
+    pub sat_per_vbyte: u64,
+}
+
+impl Response for EstimateFeeResponse {
+    fn read(fields: &Fields) -> Result<EstimateFeeResponse, WireError> {
+        Ok(EstimateFeeResponse {
+            fee_sat: fields.int(1),
+            sat_per_vbyte: fields.varint(3),
+        })
+    }
+}
+
+/// lnrpc.SendCoinsRequest
+#[derive(Clone, Debug)]
+pub struct SendCoinsRequest {
+    pub addr: String,
+    pub amount: i64,
+    pub sat_per_vbyte: u64,
+}
+
+impl Request for SendCoinsRequest {
+    fn write(&self) -> Writer {
+        let mut writer = Writer::new();
+        writer
+            .string(1, &self.addr)
+            .varint(2, self.amount as u64)
+            .varint(4, self.sat_per_vbyte);
+        writer
+    }
+}
+
+/// lnrpc.SendCoinsResponse
+#[derive(Clone, Debug)]
+pub struct SendCoinsResponse {
+    /// Hex, in the byte order transaction ids are displayed in.
+    pub txid: String,
+}
+
+impl Response for SendCoinsResponse {
+    fn read(fields: &Fields) -> Result<SendCoinsResponse, WireError> {
+        Ok(SendCoinsResponse {
+            txid: fields.string(1)?.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn send_payment_request() {
+        let request = SendPaymentRequest {
+            dest: vec![2; 33],
+            amt: 1000,
+            payment_hash: vec![3; 32],
+            timeout_seconds: 60,
+            fee_limit_sat: 10,
+            dest_custom_records: vec![(5_482_373_484, vec![4; 32])],
+            max_parts: 16,
+            ..Default::default()
+        };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        assert_eq!(fields.bytes(1), &[2; 33][..]);
+        assert_eq!(fields.int(2), 1000);
+        assert_eq!(fields.bytes(3), &[3; 32][..]);
+        assert_eq!(fields.bytes(5), b"");
+        assert_eq!(fields.int(6), 60);
+        assert_eq!(fields.int(7), 10);
+        let record = Fields::read(fields.bytes(11)).unwrap();
+        assert_eq!(record.varint(1), 5_482_373_484);
+        assert_eq!(record.bytes(2), &[4; 32][..]);
+        assert_eq!(fields.varint(17), 16);
+    }
+
+    #[test]
+    fn payment() {
+        let (mut succeeded, mut failed) = (Writer::new(), Writer::new());
+        succeeded.varint(1, 1);
+        failed.varint(1, 2);
+        let mut writer = Writer::new();
+        writer
+            .string(6, &"07".repeat(32))
+            .varint(10, 2)
+            .varint(11, 3)
+            .message(14, &failed)
+            .message(14, &succeeded)
+            .message(14, &succeeded);
+        let payment = Payment::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert!(payment.is_final());
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.payment_preimage, "07".repeat(32));
+        assert_eq!(payment.fee_sat, 3);
+        assert_eq!(payment.succeeded_htlcs, 2);
+
+        let mut writer = Writer::new();
+        writer.varint(10, 1);
+        let payment = Payment::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert!(!payment.is_final());
+    }
+
+    #[test]
+    fn hold_invoices() {
+        let request = AddHoldInvoiceRequest {
+            memo: "coffee".to_owned(),
+            hash: vec![5; 32],
+            value: 21,
+            expiry: 3600,
+            private: true,
+            ..Default::default()
+        };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        assert_eq!(fields.string(1), Ok("coffee"));
+        assert_eq!(fields.bytes(2), &[5; 32][..]);
+        assert_eq!(fields.int(3), 21);
+        assert_eq!(fields.bytes(4), b"");
+        assert_eq!(fields.int(5), 3600);
+        assert_eq!(fields.varint(9), 1);
+
+        let mut writer = Writer::new();
+        writer.string(1, "lnbc1").varint(2, 7);
+        let response = AddHoldInvoiceResponse::read(&Fields::read(&writer.into_bytes()).unwrap());
+        assert_eq!(response.unwrap().payment_request, "lnbc1");
+
+        // an invoice in its default state, OPEN, has no state field at all
+        let mut writer = Writer::new();
+        writer.string(1, "coffee").bytes(4, &[5; 32]);
+        let update = InvoiceUpdate::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert_eq!(update.state, InvoiceState::Open);
+        let mut writer = Writer::new();
+        writer.varint(21, 3);
+        let update = InvoiceUpdate::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert_eq!(update.state, InvoiceState::Accepted);
+    }
+
+    #[test]
+    fn keysend_invoices() {
+        let request = SubscribeInvoicesRequest { settle_index: 7 };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        assert_eq!(fields.varint(1), 0);
+        assert_eq!(fields.varint(2), 7);
+
+        let mut lesser = Writer::new();
+        lesser.varint(1, 0x6c61_7069).bytes(2, &[9; 32]);
+        let mut preimage = Writer::new();
+        preimage.varint(1, 5_482_373_484).bytes(2, &[4; 32]);
+        let mut first = Writer::new();
+        first
+            .varint(3, 5000)
+            .message(9, &preimage)
+            .message(9, &lesser);
+        let mut second = Writer::new();
+        second.varint(3, 1000);
+        let mut writer = Writer::new();
+        writer
+            .bytes(3, &[4; 32])
+            .varint(17, 12)
+            .varint(19, 6)
+            .varint(21, 1)
+            .message(22, &first)
+            .message(22, &second)
+            .varint(25, 1);
+        let invoice = KeysendInvoice::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert_eq!(invoice.r_preimage, vec![4; 32]);
+        assert_eq!(invoice.settle_index, 12);
+        assert_eq!(invoice.amt_paid_sat, 6);
+        assert_eq!(invoice.state, InvoiceState::Settled);
+        assert!(invoice.is_keysend);
+        assert_eq!(
+            invoice.htlc_records,
+            vec![
+                vec![(5_482_373_484, vec![4; 32]), (0x6c61_7069, vec![9; 32])],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn onchain_fees() {
+        let request = EstimateFeeRequest {
+            addr: "bc1qdest".to_owned(),
+            amount: 5000,
+            target_conf: 6,
+        };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        let entry = Fields::read(fields.bytes(1)).unwrap();
+        assert_eq!(entry.string(1), Ok("bc1qdest"));
+        assert_eq!(entry.int(2), 5000);
+        assert_eq!(fields.int(2), 6);
+
+        let mut writer = Writer::new();
+        writer.varint(1, 282).varint(2, 2).varint(3, 2);
+        let response =
+            EstimateFeeResponse::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert_eq!(response.fee_sat, 282);
+        assert_eq!(response.sat_per_vbyte, 2);
+
+        let request = SendCoinsRequest {
+            addr: "bc1qdest".to_owned(),
+            amount: 5000,
+            sat_per_vbyte: 2,
+        };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        assert_eq!(fields.string(1), Ok("bc1qdest"));
+        assert_eq!(fields.int(2), 5000);
+        assert_eq!(fields.varint(4), 2);
+    }
+
+    #[test]
+    fn transactions() {
+        let request = GetTransactionsRequest {
+            start_height: 0,
+            end_height: -1,
+        };
+        let fields = Fields::read(&request.write().into_bytes()).unwrap();
+        assert_eq!(fields.int(1), 0);
+        assert_eq!(fields.int(2) as i32, -1);
+
+        let mut ours = Writer::new();
+        ours.string(2, "bc1qours")
+            .varint(4, 1)
+            .varint(5, 5000)
+            .varint(6, 1);
+        let mut theirs = Writer::new();
+        theirs.string(2, "bc1qtheirs").varint(5, 300);
+        let mut transaction = Writer::new();
+        transaction
+            .string(1, &"ab".repeat(32))
+            .varint(3, 2)
+            .varint(7, 141)
+            .message(11, &theirs)
+            .message(11, &ours);
+        let mut writer = Writer::new();
+        writer.message(1, &transaction).message(1, &Writer::new());
+        let details =
+            TransactionDetails::read(&Fields::read(&writer.into_bytes()).unwrap()).unwrap();
+        assert_eq!(details.transactions.len(), 2);
+        let transaction = &details.transactions[0];
+        assert_eq!(transaction.tx_hash, "ab".repeat(32));
+        assert_eq!(transaction.num_confirmations, 2);
+        assert_eq!(transaction.total_fees, 141);
+        let outputs: Vec<(&str, i64, i64, bool)> = transaction
+            .output_details
+            .iter()
+            .map(|output| {
+                (
+                    output.address.as_str(),
+                    output.output_index,
+                    output.amount,
+                    output.is_our_address,
+                )
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![("bc1qtheirs", 0, 300, false), ("bc1qours", 1, 5000, true)]
+        );
+        // an unconfirmed transaction with no outputs of interest
+        assert_eq!(details.transactions[1].num_confirmations, 0);
+        assert!(details.transactions[1].output_details.is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+//! LNURL, lightning payments negotiated over https. Accounts can register names, each of which
+//! is a Lightning Address, name@domain, that wallets pay through LNURL-pay (LUD-06, LUD-16).
+//! The wallet fetches the address' pay request, then calls its callback with an amount in
+//! millisatoshis and receives an invoice committing to the pay request's metadata.
+//!
+//! Accounts can also reserve funds as a voucher, which whoever holds its LNURL-withdraw link
+//! (LUD-03) can withdraw by submitting invoices.
+//!
+//! Wallets log in with LNURL-auth (LUD-04) by signing a challenge with a linking key. A key is
+//! linked to an account's Master or Middle by answering a challenge created with either, after
+//! which the key alone starts sessions with that capability.
+//!
+//! Accounts pay other services' Lightning Addresses and LNURL-pay codes the way a wallet would,
+//! fetching the pay request and an invoice from its callback.
+
+use crate::api_types::{LnurlPayInvoice, LnurlPayRequest, LnurlResponse};
+use crate::bech32;
+use crate::common::*;
+use crate::http::{get_json, HttpError};
+use futures::Future;
+use secp256k1::{Message, Secp256k1, Signature};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+/// Names are at most this long.
+pub const MAX_NAME_LEN: usize = 64;
+/// lapi accounts hold whole satoshis, so no less can be sent or withdrawn.
+pub const MIN_SENDABLE_MSAT: u64 = 1000;
+/// Vouchers expire after a week unless asked otherwise.
+pub const DEFAULT_VOUCHER_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Longer expiries tie up the reservation for too long.
+pub const MAX_VOUCHER_EXPIRY: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+/// Wallets have ten minutes to answer a login challenge.
+pub const AUTH_CHALLENGE_EXPIRY: Duration = Duration::from_secs(10 * 60);
+/// Sessions last a day.
+pub const SESSION_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A name registered to an account, the local part of its Lightning Address. LUD-16 restricts
+/// names to lower case letters, digits and -_.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Name(String);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InvalidName;
+
+impl FromStr for Name {
+    type Err = InvalidName;
+
+    fn from_str(s: &str) -> Result<Name, InvalidName> {
+        let valid_char = |c: char| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.'
+        };
+        if s.is_empty() || s.len() > MAX_NAME_LEN || !s.chars().all(valid_char) {
+            return Err(InvalidName);
+        }
+        Ok(Name(s.to_owned()))
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The Lightning Address of name on the server reachable at public_url.
+pub fn lightning_address(name: &Name, public_url: &Url) -> String {
+    let host = public_url.host_str().unwrap_or_default();
+    match public_url.port() {
+        Some(port) => format!("{}@{}:{}", name, host, port),
+        None => format!("{}@{}", name, host),
+    }
+}
+
+/// Where wallets request invoices for payments to name. public_url should end with '/'.
+pub fn pay_callback(name: &Name, public_url: &Url) -> Url {
+    public_url
+        .join(&format!("lnurlp/{}/callback", name))
+        .expect("names are valid url path segments")
+}
+
+/// Funds reserved from an account, withdrawable by whoever holds the voucher's link.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Voucher {
+    /// The account holding the reserved funds. Only lapi knows it, so the funds can't be spent
+    /// other than through the voucher.
+    pub account: Master,
+    /// Whatever is left of the reservation once the voucher is used up or expires is refunded
+    /// to owner.
+    pub owner: Lesser,
+    /// The most each use may withdraw.
+    pub amount: Satoshis,
+    /// The most each use may spend on routing fees.
+    pub fee: Fee<Satoshis>,
+    /// How many more times the voucher may be used.
+    pub uses: u32,
+    pub expires_at: SystemTime,
+}
+
+impl Voucher {
+    /// The reservation needed to fund every use of the voucher, None on overflow.
+    pub fn reservation(&self) -> Option<Satoshis> {
+        self.amount
+            .checked_add(&self.fee.0)
+            .and_then(|per_use| per_use.0.checked_mul(u64::from(self.uses)))
+            .map(Satoshis)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+}
+
+/// The LNURL-withdraw link of the voucher identified by k1.
+pub fn withdraw_link(k1: U256, public_url: &Url) -> Url {
+    public_url
+        .join(&format!("lnurlw/{}", k1))
+        .expect("hex is a valid url path segment")
+}
+
+/// Where wallets submit invoices for withdrawal from any voucher.
+pub fn withdraw_callback(public_url: &Url) -> Url {
+    public_url
+        .join("lnurlw/callback")
+        .expect("valid url path segment")
+}
+
+/// The bech32 "lnurl1..." encoding of url, for QR codes and links that wallets recognize.
+pub fn encode(url: &Url) -> String {
+    bech32::encode("lnurl", url.as_str().as_bytes())
+}
+
+/// A login challenge, identified by the k1 wallets sign.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuthChallenge {
+    /// Identifies the session started once the challenge is answered. Only whoever created the
+    /// challenge knows it, the wallet only sees k1.
+    pub token: U256,
+    /// Link the wallet's key to this capability. Without it the key must have been linked
+    /// before.
+    pub link: Option<Capability>,
+    pub expires_at: SystemTime,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Session {
+    pub capability: Capability,
+    pub expires_at: SystemTime,
+}
+
+/// The LNURL-auth link for the challenge identified by k1.
+pub fn auth_link(k1: U256, challenge: &AuthChallenge, public_url: &Url) -> Url {
+    let mut link = public_url.join("lnurla").expect("valid url path segment");
+    let action = match challenge.link {
+        Some(_) => "link",
+        None => "login",
+    };
+    link.query_pairs_mut()
+        .append_pair("tag", "login")
+        .append_pair("k1", &k1.to_string())
+        .append_pair("action", action);
+    link
+}
+
+/// Whether sig is a signature of k1 by linking_key.
+pub fn verify_auth(k1: U256, sig: &Signature, linking_key: &PublicKey) -> bool {
+    let message = Message::from_slice(&k1.0).expect("k1 is 32 bytes");
+    Secp256k1::verification_only()
+        .verify(&message, sig, linking_key)
+        .is_ok()
+}
+
+/// Neither a Lightning Address nor the lnurl1 encoding of a url wallets may fetch.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InvalidPayLink;
+
+/// The url of the pay request for target, a Lightning Address name@domain or an lnurl1 code.
+pub fn pay_link(target: &str) -> Result<Url, InvalidPayLink> {
+    let link = match target.rfind('@') {
+        Some(at) => {
+            let name: Name = target[..at].parse().map_err(|InvalidName| InvalidPayLink)?;
+            let domain = &target[at + 1..];
+            let scheme = if is_local_or_onion(domain.split(':').next().unwrap_or_default()) {
+                "http"
+            } else {
+                "https"
+            };
+            let link = Url::parse(&format!(
+                "{}://{}/.well-known/lnurlp/{}",
+                scheme, domain, name
+            ))
+            .map_err(|_| InvalidPayLink)?;
+            // a domain containing '/', '?' or '#' would have changed the path
+            if link.path() != format!("/.well-known/lnurlp/{}", name) || link.query().is_some() {
+                return Err(InvalidPayLink);
+            }
+            link
+        }
+        None => match bech32::decode(target) {
+            Ok((ref hrp, ref data)) if hrp == "lnurl" => std::str::from_utf8(data)
+                .ok()
+                .and_then(|url| Url::parse(url).ok())
+                .ok_or(InvalidPayLink)?,
+            _ => return Err(InvalidPayLink),
+        },
+    };
+    if is_secure(&link) {
+        Ok(link)
+    } else {
+        Err(InvalidPayLink)
+    }
+}
+
+/// LNURL services must use https, unless they are onion services, which use http (LUD-01).
+/// Services on this machine may use http too.
+fn is_secure(url: &Url) -> bool {
+    match url.scheme() {
+        "https" => true,
+        "http" => url.host_str().map(is_local_or_onion).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn is_local_or_onion(host: &str) -> bool {
+    host == "localhost" || host == "127.0.0.1" || host == "[::1]" || host.ends_with(".onion")
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Http(HttpError),
+    /// The service answered with an error, for reason.
+    Refused(String),
+    /// The pay request's callback may not be fetched, see is_secure.
+    InsecureCallback,
+}
+
+/// Fetch the pay request at link. Services on private addresses are refused unless
+/// allow_private.
+pub fn fetch_pay_request(link: Url, allow_private: bool) -> DynFut<LnurlPayRequest, ResolveError> {
+    Box::new(fetch(link, allow_private))
+}
+
+/// Fetch an invoice for amount_msat from the callback of a pay request.
+pub fn fetch_invoice(
+    callback: &Url,
+    amount_msat: u64,
+    allow_private: bool,
+) -> DynFut<Invoice, ResolveError> {
+    if !is_secure(callback) {
+        return Box::new(futures::future::err(ResolveError::InsecureCallback));
+    }
+    let mut callback = callback.clone();
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount_msat.to_string());
+    Box::new(fetch(callback, allow_private).map(|LnurlPayInvoice { pr, .. }| pr.0))
+}
+
+fn fetch<T: serde::de::DeserializeOwned + Send + 'static>(
+    url: Url,
+    allow_private: bool,
+) -> impl Future<Item = T, Error = ResolveError> {
+    get_json::<LnurlResponse<T>>(url, allow_private)
+        .map_err(ResolveError::Http)
+        .and_then(|response| {
+            Into::<Result<T, _>>::into(response).map_err(|err| ResolveError::Refused(err.reason))
+        })
+}
+
+/// The metadata of a payment to address, a json array of [mime type, content] pairs. Invoices
+/// for the payment commit to its hash, so it must be reproduced exactly.
+pub fn pay_metadata(address: &str) -> String {
+    let plain = format!("Payment to {}", address);
+    serde_json::to_string(&[["text/plain", plain.as_str()], ["text/identifier", address]])
+        .expect("strings always serialize")
+}
+
+/// The description hash of invoices for a payment with metadata.
+pub fn metadata_hash(metadata: &str) -> U256 {
+    U256::try_from_slice(&Sha256::digest(metadata.as_bytes())).expect("sha256 is 32 bytes")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for valid in &["alice", "bob.smith", "c-3po", "r2_d2", "0"] {
+            assert_eq!(
+                valid.parse::<Name>().map(|n| n.to_string()),
+                Ok(valid.to_string())
+            );
+        }
+        let too_long = "a".repeat(MAX_NAME_LEN + 1);
+        for invalid in &[
+            "",
+            "Alice",
+            "alice@example.com",
+            "a b",
+            "ünicode",
+            &too_long,
+        ] {
+            assert_eq!(invalid.parse::<Name>(), Err(InvalidName));
+        }
+    }
+
+    #[test]
+    fn addresses() {
+        let alice: Name = "alice".parse().unwrap();
+        let url = Url::parse("https://pay.example.com/").unwrap();
+        assert_eq!(lightning_address(&alice, &url), "alice@pay.example.com");
+        assert_eq!(
+            pay_callback(&alice, &url).as_str(),
+            "https://pay.example.com/lnurlp/alice/callback"
+        );
+        let local = Url::parse("http://127.0.0.1:3030").unwrap();
+        assert_eq!(lightning_address(&alice, &local), "alice@127.0.0.1:3030");
+    }
+
+    #[test]
+    fn withdraw_links() {
+        let url = Url::parse("https://pay.example.com/").unwrap();
+        let k1: U256 = "92ff2aabcd1e070b435c09c50bbc208a45417335329d8fd89b5de6492405cfe4"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            withdraw_link(k1, &url).as_str(),
+            "https://pay.example.com/lnurlw/\
+             92ff2aabcd1e070b435c09c50bbc208a45417335329d8fd89b5de6492405cfe4"
+        );
+        assert_eq!(
+            withdraw_callback(&url).as_str(),
+            "https://pay.example.com/lnurlw/callback"
+        );
+        assert!(encode(&withdraw_link(k1, &url)).starts_with("lnurl1"));
+    }
+
+    #[test]
+    fn reservation() {
+        let voucher = Voucher {
+            account: Master::random(),
+            owner: Master::random().into(),
+            amount: Satoshis(100),
+            fee: Fee(Satoshis(5)),
+            uses: 3,
+            expires_at: SystemTime::now() + DEFAULT_VOUCHER_EXPIRY,
+        };
+        assert_eq!(voucher.reservation(), Some(Satoshis(315)));
+        assert!(!voucher.is_expired());
+        let huge = Voucher {
+            amount: Satoshis(u64::max_value() / 2),
+            ..voucher
+        };
+        assert_eq!(huge.reservation(), None);
+    }
+
+    #[test]
+    fn auth_signatures() {
+        use secp256k1::key::SecretKey;
+
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[0xaa; 32]).unwrap();
+        let linking_key = PublicKey::from_secret_key(&secp, &secret);
+        let k1 = U256::random();
+        let sig = secp.sign(&Message::from_slice(&k1.0).unwrap(), &secret);
+        assert!(verify_auth(k1, &sig, &linking_key));
+        assert!(!verify_auth(U256::random(), &sig, &linking_key));
+        assert!(!verify_auth(k1, &sig, &crate::test_util::pubkey_b()));
+
+        let challenge = AuthChallenge {
+            token: U256::random(),
+            link: None,
+            expires_at: SystemTime::now() + AUTH_CHALLENGE_EXPIRY,
+        };
+        let url = Url::parse("https://pay.example.com/").unwrap();
+        assert_eq!(
+            auth_link(k1, &challenge, &url).as_str(),
+            format!(
+                "https://pay.example.com/lnurla?tag=login&k1={}&action=login",
+                k1
+            )
+        );
+    }
+
+    #[test]
+    fn pay_links() {
+        assert_eq!(
+            pay_link("alice@pay.example.com").map(|url| url.as_str().to_owned()),
+            Ok("https://pay.example.com/.well-known/lnurlp/alice".to_owned())
+        );
+        assert_eq!(
+            pay_link("bob@127.0.0.1:3030").map(|url| url.as_str().to_owned()),
+            Ok("http://127.0.0.1:3030/.well-known/lnurlp/bob".to_owned())
+        );
+        let url = Url::parse("https://service.com/api?q=1").unwrap();
+        assert_eq!(pay_link(&encode(&url)), Ok(url.clone()));
+        assert_eq!(
+            pay_link(&encode(&url).to_ascii_uppercase()),
+            Ok(url.clone())
+        );
+        let insecure = Url::parse("http://service.com/api").unwrap();
+        for invalid in &[
+            "",
+            "alice",
+            "Alice@pay.example.com",
+            "alice@",
+            "alice@pay.example.com/evil",
+            "alice@pay.example.com?x",
+            &encode(&insecure),
+            &bech32::encode("lnbc", url.as_str().as_bytes()),
+            "lnurl1dp68gurn8ghj7",
+        ] {
+            assert_eq!(pay_link(invalid), Err(InvalidPayLink), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let metadata = pay_metadata("alice@pay.example.com");
+        assert_eq!(
+            metadata,
+            r#"[["text/plain","Payment to alice@pay.example.com"],["text/identifier","alice@pay.example.com"]]"#
+        );
+        assert_eq!(
+            metadata_hash("").to_string(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
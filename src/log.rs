@@ -1,5 +1,6 @@
 use crate::common::*;
 use lightning_invoice::ParseOrSemanticError;
+use std::sync::Arc;
 
 pub trait Log: Sync + Send {
     fn _err(&self, err: LogErr);
@@ -19,6 +20,52 @@ pub enum LogErr {
     PayInvoiceOverflowOnRefundFee(DepositError),
     PayError(PayError),
     CreateInvoiceError(CreateInvoiceError),
+    /// The paid invoice stream from the lightning node failed. It will be re-established.
+    SubscribePaidInvoices(SubscribePaidInvoicesError),
+    /// A supervised subscription ended without error. It will be re-established.
+    SubscriptionClosed(&'static str),
+    /// A paid invoice was received from the lightning node but could not be credited.
+    ReceivePaidInvoice(ReceivePaidInvoiceErr),
+    /// A keysend was received by the lightning node but could not be credited.
+    ReceiveKeysend(ReceiveKeysendErr),
+    /// An on-chain deposit was received by the lightning node but could not be credited.
+    ReceiveOnchainDeposit(ReceiveOnchainDepositErr),
+    /// The lightning node could not generate an on-chain address.
+    NewAddress(NewAddressError),
+    DbStoreAddressDuplicate(Lesser, Address),
+    /// A hold invoice was accepted by the lightning node but could not be marked accepted.
+    ReceiveAcceptedInvoice(ReceiveAcceptedInvoiceErr),
+    /// The lightning node failed to settle or cancel a hold invoice.
+    HoldInvoice(HoldInvoiceError),
+    /// A hold invoice was cancelled on the lightning node, but not in the database.
+    CancelInvoice(CancelInvoiceError),
+    /// The lightning node failed to quote a fee for reasons other than a missing route.
+    EstimateFee(EstimateFeeError),
+    /// The lightning node failed to estimate a miner fee for reasons other than a bad address.
+    EstimateOnchainFee(EstimateOnchainFeeError),
+    /// The lightning node could not report its identity or capacity.
+    NodeInfo(NodeInfoError),
+    /// The lightning node could not create an offer.
+    CreateOffer(CreateOfferError),
+    DbStoreOfferDuplicate(Lesser, U256),
+    /// The lightning node issued an invoice for an offer, but it could not be stored.
+    ReceiveOfferInvoice(ReceiveOfferInvoiceErr),
+    /// Funds were withdrawn for a voucher, but could not be moved to the voucher's account.
+    ReserveVoucher(DepositError),
+    StoreVoucher(StoreVoucherError),
+    /// What was left of a used up or expired voucher could not be refunded to its owner.
+    RefundVoucher(RefundVoucherError),
+    StoreAuthChallenge(StoreAuthChallengeError),
+    /// A wallet answered a login challenge, but its session could not be stored.
+    StoreSession(StoreSessionError),
+    /// Calls to the lightning node could not be written to the recording.
+    Recording(String),
+}
+
+impl<G: Log + ?Sized> Log for Arc<G> {
+    fn _err(&self, err: LogErr) {
+        (**self)._err(err)
+    }
 }
 
 /// This type is not constructable outside this file.
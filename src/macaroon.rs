@@ -0,0 +1,257 @@
+//! Reading the permissions granted by an lnd macaroon, so lapi can refuse to start with one
+//! that lacks a permission it needs, and can be run with one that grants nothing more.
+//!
+//! lnd bakes the permissions into the macaroon's identifier: a version byte followed by a
+//! protobuf MacaroonId { nonce = 1, storageId = 2, repeated Op ops = 3 }, where
+//! Op { entity = 1, repeated actions = 2 }. Caveats can only narrow what the identifier grants,
+//! so they are not inspected.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// First byte of a macaroon in the v2 binary format lnd writes to disk.
+const MACAROON_V2: u8 = 2;
+/// Field types of the v2 format.
+const FIELD_EOS: u64 = 0;
+const FIELD_IDENTIFIER: u64 = 2;
+/// Version byte lnd prefixes its macaroon identifiers with.
+const LND_ID_VERSION: u8 = 3;
+/// Protobuf wire types.
+const WIRE_VARINT: u64 = 0;
+const WIRE_LENGTH_DELIMITED: u64 = 2;
+
+/// An action on an entity, e.g. invoices:write.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Permission {
+    pub entity: String,
+    pub action: String,
+}
+
+impl Permission {
+    pub fn new(entity: &str, action: &str) -> Permission {
+        Permission {
+            entity: entity.to_owned(),
+            action: action.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.entity, self.action)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MacaroonError {
+    /// Not a v2 binary macaroon, or cut short.
+    Malformed,
+    /// The macaroon has no identifier.
+    NoIdentifier,
+    /// The identifier is not one baked by lnd.
+    UnknownIdentifier,
+}
+
+/// Every permission granted by the lnd macaroon serialized in bytes.
+pub fn permissions(bytes: &[u8]) -> Result<BTreeSet<Permission>, MacaroonError> {
+    let id = identifier(bytes)?;
+    match id.split_first() {
+        Some((&LND_ID_VERSION, id)) => {
+            parse_macaroon_id(id).ok_or(MacaroonError::UnknownIdentifier)
+        }
+        _ => Err(MacaroonError::UnknownIdentifier),
+    }
+}
+
+/// Those of required which granted lacks.
+pub fn missing<'a>(
+    granted: &BTreeSet<Permission>,
+    required: impl IntoIterator<Item = &'a Permission>,
+) -> Vec<Permission> {
+    required
+        .into_iter()
+        .filter(|permission| !granted.contains(permission))
+        .cloned()
+        .collect()
+}
+
+/// The identifier from the header of a v2 macaroon.
+fn identifier(bytes: &[u8]) -> Result<&[u8], MacaroonError> {
+    let mut rest = match bytes.split_first() {
+        Some((&MACAROON_V2, rest)) => rest,
+        _ => return Err(MacaroonError::Malformed),
+    };
+    loop {
+        let (field_type, after) = varint(rest).ok_or(MacaroonError::Malformed)?;
+        if field_type == FIELD_EOS {
+            return Err(MacaroonError::NoIdentifier);
+        }
+        let (data, after) = length_prefixed(after).ok_or(MacaroonError::Malformed)?;
+        if field_type == FIELD_IDENTIFIER {
+            return Ok(data);
+        }
+        rest = after;
+    }
+}
+
+/// The permissions listed by MacaroonId. None if it is not valid protobuf.
+fn parse_macaroon_id(id: &[u8]) -> Option<BTreeSet<Permission>> {
+    let mut granted = BTreeSet::new();
+    for (field, value) in protobuf_fields(id)? {
+        if field == 3 {
+            granted.extend(parse_op(value?)?);
+        }
+    }
+    Some(granted)
+}
+
+fn parse_op(op: &[u8]) -> Option<Vec<Permission>> {
+    let mut entity = None;
+    let mut actions = Vec::new();
+    for (field, value) in protobuf_fields(op)? {
+        match field {
+            1 => entity = Some(String::from_utf8(value?.to_vec()).ok()?),
+            2 => actions.push(String::from_utf8(value?.to_vec()).ok()?),
+            _ => {}
+        }
+    }
+    let entity = entity?;
+    Some(
+        actions
+            .into_iter()
+            .map(|action| Permission {
+                entity: entity.clone(),
+                action,
+            })
+            .collect(),
+    )
+}
+
+/// The fields of a protobuf message, each with its data if length delimited. None if message
+/// is not valid protobuf.
+fn protobuf_fields(mut message: &[u8]) -> Option<Vec<(u64, Option<&[u8]>)>> {
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let (key, rest) = varint(message)?;
+        let (field, wire_type) = (key >> 3, key & 7);
+        message = match wire_type {
+            WIRE_VARINT => {
+                let (_, rest) = varint(rest)?;
+                fields.push((field, None));
+                rest
+            }
+            WIRE_LENGTH_DELIMITED => {
+                let (data, rest) = length_prefixed(rest)?;
+                fields.push((field, Some(data)));
+                rest
+            }
+            _ => return None,
+        };
+    }
+    Some(fields)
+}
+
+fn length_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = varint(bytes)?;
+    if (rest.len() as u64) < len {
+        return None;
+    }
+    Some(rest.split_at(len as usize))
+}
+
+/// Little endian base 128 varint, as used by both protobuf and the macaroon v2 format.
+fn varint(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
+/// Serialize a macaroon like those lnd bakes, granting ops. The signature is not valid.
+#[cfg(test)]
+pub fn bake(ops: &[(&str, &[&str])]) -> Vec<u8> {
+    fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+    fn put_field(out: &mut Vec<u8>, key: u64, data: &[u8]) {
+        put_varint(out, key);
+        put_varint(out, data.len() as u64);
+        out.extend_from_slice(data);
+    }
+
+    let mut id = vec![LND_ID_VERSION];
+    put_field(&mut id, 1 << 3 | WIRE_LENGTH_DELIMITED, &[0xab; 16]); // nonce
+    put_field(&mut id, 2 << 3 | WIRE_LENGTH_DELIMITED, b"0"); // storageId
+    for (entity, actions) in ops {
+        let mut op = Vec::new();
+        put_field(&mut op, 1 << 3 | WIRE_LENGTH_DELIMITED, entity.as_bytes());
+        for action in actions.iter() {
+            put_field(&mut op, 2 << 3 | WIRE_LENGTH_DELIMITED, action.as_bytes());
+        }
+        put_field(&mut id, 3 << 3 | WIRE_LENGTH_DELIMITED, &op);
+    }
+
+    let mut macaroon = vec![MACAROON_V2];
+    put_field(&mut macaroon, 1, b"lnd"); // location
+    put_field(&mut macaroon, FIELD_IDENTIFIER, &id);
+    macaroon.push(FIELD_EOS as u8); // end of header
+    macaroon.push(FIELD_EOS as u8); // no caveats
+    put_field(&mut macaroon, 6, &[0; 32]); // signature
+    macaroon
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn permissions_from_identifier() {
+        let macaroon = bake(&[
+            ("invoices", &["read", "write"][..]),
+            ("info", &["read"][..]),
+        ]);
+        let granted = permissions(&macaroon).unwrap();
+        let expected: BTreeSet<Permission> = vec![
+            Permission::new("invoices", "read"),
+            Permission::new("invoices", "write"),
+            Permission::new("info", "read"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(granted, expected);
+        assert_eq!(
+            missing(
+                &granted,
+                &[
+                    Permission::new("info", "read"),
+                    Permission::new("offchain", "write")
+                ]
+            ),
+            vec![Permission::new("offchain", "write")]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let macaroon = bake(&[("invoices", &["read"][..])]);
+        assert_eq!(permissions(&[]), Err(MacaroonError::Malformed));
+        assert_eq!(permissions(&macaroon[..10]), Err(MacaroonError::Malformed));
+        assert_eq!(
+            permissions(&[MACAROON_V2, 0]),
+            Err(MacaroonError::NoIdentifier)
+        );
+        // v1 macaroons are base64 text
+        assert_eq!(
+            permissions(b"MDAxY2xvY2F0aW9u"),
+            Err(MacaroonError::Malformed)
+        );
+    }
+}
@@ -2,25 +2,42 @@ mod api_highlevel;
 mod api_lowlevel;
 mod api_types;
 mod auth;
+mod bech32;
+mod cln_client;
 mod common;
+mod conformance;
 mod convert;
 mod db;
 mod fake_db;
 mod fake_lighting_node;
 mod fake_log;
 mod future;
+mod http;
 mod invoice;
+mod keysend;
 mod lighting_node;
 mod lnd_client;
+mod lnd_rpc;
+mod lnurl;
 mod log;
+mod macaroon;
+mod multi_node;
+mod network;
+mod offer;
+mod onchain;
 mod payment_hash;
 mod preimage;
+mod recording;
 mod satoshis;
 mod semantics;
 mod ser_de;
+mod sim_network;
+mod stderr_log;
+mod subscription;
 mod test_util;
 mod u256;
 mod webserver;
+mod wire;
 
 fn main() {
     let result = webserver::serve();
@@ -0,0 +1,704 @@
+//! A LightningNode backed by several nodes. Invoices are spread across the nodes, incoming
+//! payment streams are merged, and payments fail over to another node when it is safe to do so.
+
+use crate::common::*;
+use crate::lighting_node::DynStream;
+use futures::{
+    future::{join_all, loop_fn, FutureResult, Loop},
+    stream, Future, Stream,
+};
+use std::collections::BTreeMap;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+pub struct MultiNode {
+    nodes: Arc<Vec<Box<dyn LightningNode>>>,
+    /// Whether each node's last invoice creation succeeded. Healthy nodes are tried first.
+    healthy: Arc<Vec<AtomicBool>>,
+    /// Rotates the node tried first, spreading load.
+    next: AtomicUsize,
+    /// Hold invoices must be resolved on the node that created them.
+    hold_owners: Arc<Mutex<BTreeMap<PaymentHash, usize>>>,
+}
+
+impl MultiNode {
+    pub fn new(nodes: Vec<Box<dyn LightningNode>>) -> MultiNode {
+        assert!(!nodes.is_empty(), "MultiNode needs at least one node");
+        MultiNode {
+            healthy: Arc::new(nodes.iter().map(|_| AtomicBool::new(true)).collect()),
+            nodes: Arc::new(nodes),
+            next: AtomicUsize::new(0),
+            hold_owners: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Indices of every node in the order they should be tried. Starts from a different node
+    /// each call. Healthy nodes come before unhealthy ones.
+    fn order(&self) -> Vec<usize> {
+        let len = self.nodes.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..len)
+            .map(|i| (start + i) % len)
+            .partition(|&i| self.healthy[i].load(Ordering::Relaxed));
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Create an invoice on the first node able to, marking nodes which fail unhealthy.
+    fn create_with<F>(&self, create: F) -> DynFut<(usize, Invoice), CreateInvoiceError>
+    where
+        F: Fn(&dyn LightningNode) -> DynFut<Invoice, CreateInvoiceError> + Send + 'static,
+    {
+        let healthy = self.healthy.clone();
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |index, node| {
+                let healthy = healthy.clone();
+                Box::new(create(node).then(move |res| {
+                    match &res {
+                        Ok(_) => healthy[index].store(true, Ordering::Relaxed),
+                        // The node works, it just can't do what was asked.
+                        Err(CreateInvoiceError::Unsupported(_)) => {}
+                        Err(_) => healthy[index].store(false, Ordering::Relaxed),
+                    }
+                    res.map(|invoice| (index, invoice))
+                }))
+            },
+            |_err| true,
+        )
+    }
+
+    /// Merge a stream from every node. An error on any node's stream is passed on, ending a
+    /// supervised subscription, which then resubscribes to every node.
+    fn merge<T, F>(&self, subscribe: F) -> DynStream<T, SubscribePaidInvoicesError>
+    where
+        T: Send + 'static,
+        F: Fn(&dyn LightningNode) -> DynStream<T, SubscribePaidInvoicesError>,
+    {
+        let empty: DynStream<T, SubscribePaidInvoicesError> = Box::new(stream::empty());
+        self.nodes.iter().fold(empty, |merged, node| {
+            Box::new(merged.select(subscribe(&**node)))
+        })
+    }
+
+    fn hold_owner(&self, payment_hash: &PaymentHash) -> Option<usize> {
+        self.hold_owners.lock().unwrap().get(payment_hash).cloned()
+    }
+}
+
+impl LightningNode for MultiNode {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        Box::new(
+            self.create_with(move |node| node.create_invoice(spec.clone()))
+                .map(|(_, invoice)| invoice),
+        )
+    }
+
+    /// Only a PaymentAborted is retried on another node. Any other failure may leave the
+    /// payment in flight, so retrying could pay twice.
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.pay_invoice(invoice.clone(), amount, max_fee, multi_path),
+            is_aborted,
+        )
+    }
+
+    fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        self.merge(|node| node.paid_invoices())
+    }
+
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.keysend(pubkey, amount, max_fee),
+            // a node without keysend support can safely be skipped
+            |err| match err {
+                PayError::Unsupported(_) => true,
+                other => is_aborted(other),
+            },
+        )
+    }
+
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        self.merge(|node| node.received_keysends())
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        let hold_owners = self.hold_owners.clone();
+        Box::new(
+            self.create_with(move |node| node.create_hold_invoice(spec.clone(), payment_hash))
+                .map(move |(index, invoice)| {
+                    hold_owners.lock().unwrap().insert(payment_hash, index);
+                    invoice
+                }),
+        )
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        match self.hold_owner(&preimage.hash()) {
+            Some(index) => self.nodes[index].settle_hold_invoice(preimage),
+            None => Box::new(FutureResult::from(Err(HoldInvoiceError::UnknownInvoice))),
+        }
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        match self.hold_owner(&payment_hash) {
+            Some(index) => self.nodes[index].cancel_hold_invoice(payment_hash),
+            None => Box::new(FutureResult::from(Err(HoldInvoiceError::UnknownInvoice))),
+        }
+    }
+
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        self.merge(|node| node.accepted_invoices())
+    }
+
+    /// Quote from the first node with a route, which is the node pay_invoice will use when
+    /// tried in the same order.
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.estimate_fee(invoice.clone(), amount),
+            |err| match err {
+                EstimateFeeError::NoRoute => true,
+                EstimateFeeError::Unknown(_) => false,
+            },
+        )
+    }
+
+    /// Identity of the first node which responds. Each payment is sent or received by a single
+    /// node, so capacity is the largest of any one node's.
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        let infos: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| node.node_info().then(Ok::<_, ()>))
+            .collect();
+        Box::new(
+            join_all(infos)
+                .map_err(|()| unreachable!())
+                .and_then(|results| {
+                    let mut found: Option<NodeInfo> = None;
+                    let mut last_err = None;
+                    for result in results {
+                        match (result, &mut found) {
+                            (Ok(info), Some(found)) => {
+                                found.capacity.inbound =
+                                    found.capacity.inbound.max(info.capacity.inbound);
+                                found.capacity.outbound =
+                                    found.capacity.outbound.max(info.capacity.outbound);
+                            }
+                            (Ok(info), None) => found = Some(info),
+                            (Err(err), _) => last_err = Some(err),
+                        }
+                    }
+                    found.ok_or_else(|| last_err.expect("there is at least one node"))
+                }),
+        )
+    }
+
+    /// Any node's wallet will do, deposits to every node are reported.
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.new_address(),
+            |_err| true,
+        )
+    }
+
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        self.merge(|node| node.incoming_transactions())
+    }
+
+    /// Estimate from the first node able to send on-chain, which is the node send_onchain will
+    /// use when tried in the same order.
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.estimate_onchain_fee(address.clone(), amount, target_blocks),
+            |err| match err {
+                EstimateOnchainFeeError::InvalidAddress => false,
+                EstimateOnchainFeeError::Unsupported(_) | EstimateOnchainFeeError::Unknown(_) => {
+                    true
+                }
+            },
+        )
+    }
+
+    /// Retried like keysend; a node which can't send on-chain is skipped.
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.send_onchain(address.clone(), amount, target_blocks, max_fee),
+            |err| match err {
+                PayError::Unsupported(_) => true,
+                other => is_aborted(other),
+            },
+        )
+    }
+
+    /// Plain invoices aren't tracked by node, so each node is asked until one knows the
+    /// invoice.
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        if let Some(index) = self.hold_owner(&payment_hash) {
+            return self.nodes[index].cancel_invoice(payment_hash);
+        }
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.cancel_invoice(payment_hash),
+            |err| match err {
+                HoldInvoiceError::UnknownInvoice | HoldInvoiceError::Unsupported(_) => true,
+                _ => false,
+            },
+        )
+    }
+
+    /// The offer is created on the first node able to, which answers every invoice request for
+    /// it.
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        first_success(
+            self.nodes.clone(),
+            self.order(),
+            move |_, node| node.create_offer(spec.clone()),
+            |_err| true,
+        )
+    }
+
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        self.merge(|node| node.offer_invoices())
+    }
+}
+
+fn is_aborted(err: &PayError) -> bool {
+    match err {
+        PayError::PaymentAborted => true,
+        _ => false,
+    }
+}
+
+/// Call each node in order until one succeeds. Stop early if retry says an error must not be
+/// retried. Returns the last error if no node succeeds.
+fn first_success<T, E, F>(
+    nodes: Arc<Vec<Box<dyn LightningNode>>>,
+    order: Vec<usize>,
+    call: F,
+    retry: fn(&E) -> bool,
+) -> DynFut<T, E>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+    F: Fn(usize, &dyn LightningNode) -> DynFut<T, E> + Send + 'static,
+{
+    Box::new(loop_fn(0, move |attempt: usize| {
+        let index = order[attempt];
+        let last = attempt + 1 == order.len();
+        call(index, &*nodes[index]).then(move |res| match res {
+            Ok(ok) => Ok(Loop::Break(ok)),
+            Err(err) => {
+                if !last && retry(&err) {
+                    Ok(Loop::Continue(attempt + 1))
+                } else {
+                    Err(err)
+                }
+            }
+        })
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    /// Fails every request.
+    struct BrokenNode {
+        pay_error: PayError,
+    }
+
+    impl LightningNode for BrokenNode {
+        fn create_invoice(&self, _spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+            Box::new(FutureResult::from(Err(CreateInvoiceError::Network {
+                backend_name: "broken".to_owned(),
+                err: "connection refused".to_owned(),
+            })))
+        }
+
+        fn pay_invoice(
+            &self,
+            _invoice: Invoice,
+            _amount: Satoshis,
+            _max_fee: Fee<Satoshis>,
+            _multi_path: MultiPath,
+        ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+            Box::new(FutureResult::from(Err(self.pay_error.clone())))
+        }
+
+        fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+            Box::new(stream::empty())
+        }
+
+        fn keysend(
+            &self,
+            _pubkey: PublicKey,
+            _amount: Satoshis,
+            _max_fee: Fee<Satoshis>,
+        ) -> DynFut<KeysendOutgoing, PayError> {
+            Box::new(FutureResult::from(Err(self.pay_error.clone())))
+        }
+
+        fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+            Box::new(stream::empty())
+        }
+
+        fn create_hold_invoice(
+            &self,
+            spec: InvoiceSpec,
+            _payment_hash: PaymentHash,
+        ) -> DynFut<Invoice, CreateInvoiceError> {
+            self.create_invoice(spec)
+        }
+
+        fn settle_hold_invoice(&self, _preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+            Box::new(FutureResult::from(Err(HoldInvoiceError::UnknownInvoice)))
+        }
+
+        fn cancel_hold_invoice(&self, _payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+            Box::new(FutureResult::from(Err(HoldInvoiceError::UnknownInvoice)))
+        }
+
+        fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+            Box::new(stream::empty())
+        }
+
+        fn estimate_fee(
+            &self,
+            _invoice: Invoice,
+            _amount: Satoshis,
+        ) -> DynFut<FeeQuote, EstimateFeeError> {
+            Box::new(FutureResult::from(Err(EstimateFeeError::NoRoute)))
+        }
+
+        fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+            Box::new(FutureResult::from(Err(NodeInfoError::Unknown(
+                "node is down".to_owned(),
+            ))))
+        }
+
+        fn new_address(&self) -> DynFut<Address, NewAddressError> {
+            Box::new(FutureResult::from(Err(NewAddressError::Unknown(
+                "node is down".to_owned(),
+            ))))
+        }
+
+        fn incoming_transactions(
+            &self,
+        ) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+            Box::new(stream::empty())
+        }
+
+        fn estimate_onchain_fee(
+            &self,
+            _address: Address,
+            _amount: Satoshis,
+            _target_blocks: u32,
+        ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+            Box::new(FutureResult::from(Err(EstimateOnchainFeeError::Unknown(
+                "node is down".to_owned(),
+            ))))
+        }
+
+        fn send_onchain(
+            &self,
+            _address: Address,
+            _amount: Satoshis,
+            _target_blocks: u32,
+            _max_fee: Fee<Satoshis>,
+        ) -> DynFut<OnchainOutgoing, PayError> {
+            Box::new(FutureResult::from(Err(self.pay_error.clone())))
+        }
+
+        fn cancel_invoice(&self, _payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+            Box::new(FutureResult::from(Err(HoldInvoiceError::UnknownInvoice)))
+        }
+
+        fn create_offer(&self, _spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+            Box::new(FutureResult::from(Err(CreateOfferError::Unknown(
+                "node is down".to_owned(),
+            ))))
+        }
+
+        fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+            Box::new(stream::empty())
+        }
+    }
+
+    // Each fake can only pay its own invoices, any other payment is aborted and retried on the
+    // next node.
+    crate::conformance::conformance_tests!({
+        let multi = Arc::new(MultiNode::new(vec![
+            Box::new(FakeLightningNode::new()),
+            Box::new(FakeLightningNode::new()),
+        ]));
+        (multi.clone(), multi)
+    });
+
+    fn broken(pay_error: PayError) -> Box<dyn LightningNode> {
+        Box::new(BrokenNode { pay_error })
+    }
+
+    #[test]
+    fn invoices_spread_across_nodes() {
+        let a = Arc::new(FakeLightningNode::new());
+        let b = Arc::new(FakeLightningNode::new());
+        let multi = MultiNode::new(vec![Box::new(a.clone()), Box::new(b.clone())]);
+        let paid = multi.paid_invoices().wait();
+        let invoices: Vec<Invoice> = (0..4)
+            .map(|_| multi.create_invoice(Satoshis(1).into()).wait().unwrap())
+            .collect();
+        let created_by_a = |invoice: &Invoice| a.get_preimage(get_payment_hash(invoice)).is_some();
+        assert!(invoices.iter().any(created_by_a));
+        assert!(!invoices.iter().all(created_by_a));
+
+        // a fake node can only pay its own invoices
+        for invoice in &invoices {
+            let owner = if created_by_a(invoice) { &a } else { &b };
+            owner
+                .pay_invoice(
+                    invoice.clone(),
+                    Satoshis(1),
+                    DEFAULT_FEE,
+                    MultiPath::default(),
+                )
+                .wait()
+                .unwrap();
+        }
+        let settled: Vec<PaymentHash> = paid
+            .take(4)
+            .map(|paid| get_payment_hash(paid.unwrap().invoice()))
+            .collect();
+        for invoice in &invoices {
+            assert!(settled.contains(&get_payment_hash(invoice)));
+        }
+    }
+
+    #[test]
+    fn unhealthy_node_skipped() {
+        let multi = MultiNode::new(vec![
+            broken(PayError::PaymentAborted),
+            Box::new(FakeLightningNode::new()),
+        ]);
+        for _ in 0..4 {
+            multi.create_invoice(Satoshis(1).into()).wait().unwrap();
+        }
+        assert!(!multi.healthy[0].load(Ordering::Relaxed));
+        assert!(multi.healthy[1].load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn aborted_payment_retried() {
+        let fake = FakeLightningNode::new();
+        let invoice = fake.create_invoice(Satoshis(1).into()).wait().unwrap();
+        let multi = MultiNode::new(vec![broken(PayError::PaymentAborted), Box::new(fake)]);
+        // whichever node is tried first, the payment ends up on the fake
+        for _ in 0..2 {
+            multi
+                .pay_invoice(
+                    invoice.clone(),
+                    Satoshis(1),
+                    DEFAULT_FEE,
+                    MultiPath::default(),
+                )
+                .wait()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn quote_from_node_with_route() {
+        let fake = FakeLightningNode::new();
+        let invoice = fake.create_invoice(Satoshis(1).into()).wait().unwrap();
+        let expected = fake
+            .estimate_fee(invoice.clone(), Satoshis(1))
+            .wait()
+            .unwrap();
+        let multi = MultiNode::new(vec![broken(PayError::PaymentAborted), Box::new(fake)]);
+        for _ in 0..2 {
+            let quote = multi
+                .estimate_fee(invoice.clone(), Satoshis(1))
+                .wait()
+                .unwrap();
+            assert_eq!(quote, expected);
+        }
+    }
+
+    #[test]
+    fn largest_capacity_reported() {
+        let capacity = |inbound, outbound| Capacity {
+            inbound: Satoshis(inbound),
+            outbound: Satoshis(outbound),
+        };
+        let a = FakeLightningNode::new();
+        a.set_capacity(capacity(10, 500));
+        let b = FakeLightningNode::new();
+        b.set_capacity(capacity(300, 20));
+        let multi = MultiNode::new(vec![
+            broken(PayError::PaymentAborted),
+            Box::new(a),
+            Box::new(b),
+        ]);
+        let info = multi.node_info().wait().unwrap();
+        assert_eq!(info.capacity, capacity(300, 500));
+    }
+
+    #[test]
+    fn deposits_reported_from_every_node() {
+        let a = Arc::new(FakeLightningNode::new());
+        let b = Arc::new(FakeLightningNode::new());
+        let multi = MultiNode::new(vec![
+            broken(PayError::PaymentAborted),
+            Box::new(a.clone()),
+            Box::new(b.clone()),
+        ]);
+        let address = multi.new_address().wait().unwrap();
+        let transactions = multi.incoming_transactions().wait();
+        let to_a = a.simulate_deposit(address.clone(), Satoshis(1));
+        let to_b = b.simulate_deposit(address, Satoshis(2));
+        let reported: Vec<OutPoint> = transactions
+            .take(2)
+            .map(|transaction| transaction.unwrap().outpoint)
+            .collect();
+        assert!(reported.contains(&to_a));
+        assert!(reported.contains(&to_b));
+    }
+
+    #[test]
+    fn offer_created_on_working_node() {
+        let fake = Arc::new(FakeLightningNode::new());
+        let multi = MultiNode::new(vec![
+            broken(PayError::PaymentAborted),
+            Box::new(fake.clone()),
+        ]);
+        multi.next.store(0, Ordering::Relaxed);
+        let offer = multi
+            .create_offer(OfferSpec {
+                amount: None,
+                description: "tips".to_owned(),
+            })
+            .wait()
+            .unwrap();
+        let issued = multi.offer_invoices().wait();
+        let invoice = fake
+            .simulate_invoice_request(&offer, Some(Satoshis(3)))
+            .unwrap();
+        assert_eq!(
+            issued.take(1).next().unwrap().unwrap(),
+            OfferInvoice {
+                offer_id: offer.id,
+                invoice
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_payment_failure_not_retried() {
+        let fake = FakeLightningNode::new();
+        let invoice = fake.create_invoice(Satoshis(1).into()).wait().unwrap();
+        let multi = MultiNode::new(vec![
+            broken(PayError::Unknown("timed out".to_owned())),
+            Box::new(fake),
+        ]);
+        // start with the broken node
+        multi.next.store(0, Ordering::Relaxed);
+        match multi
+            .pay_invoice(invoice, Satoshis(1), DEFAULT_FEE, MultiPath::default())
+            .wait()
+        {
+            Err(PayError::Unknown(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn hold_invoice_resolved_on_creating_node() {
+        let multi = MultiNode::new(vec![
+            Box::new(FakeLightningNode::new()),
+            Box::new(FakeLightningNode::new()),
+        ]);
+        let hashes: Vec<PaymentHash> = (0..2)
+            .map(|_| {
+                let payment_hash = U256::random();
+                multi
+                    .create_hold_invoice(Satoshis(1).into(), payment_hash)
+                    .wait()
+                    .unwrap();
+                payment_hash
+            })
+            .collect();
+        for payment_hash in hashes {
+            multi.cancel_hold_invoice(payment_hash).wait().unwrap();
+        }
+        match multi.cancel_hold_invoice(U256::random()).wait() {
+            Err(HoldInvoiceError::UnknownInvoice) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn invoice_cancelled_on_whichever_node_knows_it() {
+        let fake = Arc::new(FakeLightningNode::new());
+        let invoice = fake.create_invoice(Satoshis(1).into()).wait().unwrap();
+        let payment_hash = get_payment_hash(&invoice);
+        let multi = MultiNode::new(vec![
+            Box::new(FakeLightningNode::new()),
+            Box::new(fake.clone()),
+        ]);
+        // start with the node which doesn't know the invoice
+        multi.next.store(0, Ordering::Relaxed);
+        multi.cancel_invoice(payment_hash).wait().unwrap();
+        assert_eq!(fake.get_preimage(payment_hash), None);
+        match multi.cancel_invoice(payment_hash).wait() {
+            Err(HoldInvoiceError::UnknownInvoice) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
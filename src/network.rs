@@ -0,0 +1,158 @@
+//! The bitcoin network a deployment runs on. Invoices and addresses for any other network are
+//! refused. Signet is not supported: lightning_invoice predates its lntbs invoices.
+
+use crate::common::*;
+use lightning_invoice::Currency;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+pub const NETWORKS: [Network; 3] = [Network::Mainnet, Network::Testnet, Network::Regtest];
+
+impl Network {
+    /// The currency invoices on this network are denominated in. lightning_invoice has no
+    /// regtest currency, so regtest shares testnet's and its invoices are told apart by their
+    /// human readable part.
+    pub fn currency(self) -> Currency {
+        match self {
+            Network::Mainnet => Currency::Bitcoin,
+            Network::Testnet | Network::Regtest => Currency::BitcoinTestnet,
+        }
+    }
+
+    /// The prefix of bolt11 invoices on this network, before their amount.
+    pub fn invoice_hrp(self) -> &'static str {
+        match self {
+            Network::Mainnet => "lnbc",
+            Network::Testnet => "lntb",
+            Network::Regtest => "lnbcrt",
+        }
+    }
+
+    /// Hash of the network's genesis block, in the byte order BOLT12 offers name chains by.
+    pub fn chain_hash(self) -> [u8; 32] {
+        let genesis = match self {
+            Network::Mainnet => "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+            Network::Testnet => "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+            Network::Regtest => "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+        };
+        // block hashes are displayed byte reversed
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&hex::decode(genesis).unwrap());
+        hash.reverse();
+        hash
+    }
+
+    /// Whether invoice is payable on this network.
+    pub fn accepts(self, invoice: &Invoice) -> bool {
+        let bolt11 = to_bolt11(invoice);
+        // regtest's lnbcrt starts with mainnet's lnbc, the longest prefix decides
+        NETWORKS
+            .iter()
+            .filter(|network| bolt11.starts_with(network.invoice_hrp()))
+            .max_by_key(|network| network.invoice_hrp().len())
+            == Some(&self)
+    }
+
+    /// The human readable part of segwit addresses on this network.
+    pub fn bech32_hrp(self) -> &'static str {
+        match self {
+            Network::Mainnet => "bc",
+            Network::Testnet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+
+    /// Whether address may be on this network. Only addresses recognisably for another network
+    /// are refused, whether the address is valid at all is left to the node.
+    pub fn accepts_address(self, address: &Address) -> bool {
+        let on = |network: Network| {
+            // base58 addresses start with their version byte, mainnet's encode to 1 or 3,
+            // testnet's and regtest's to m, n or 2
+            let base58: &[char] = match network {
+                Network::Mainnet => &['1', '3'],
+                Network::Testnet | Network::Regtest => &['m', 'n', '2'],
+            };
+            let hrp = format!("{}1", network.bech32_hrp());
+            address.0.to_ascii_lowercase().starts_with(&hrp) || address.0.starts_with(base58)
+        };
+        on(self) || !NETWORKS.iter().any(|network| on(*network))
+    }
+}
+
+impl Default for Network {
+    fn default() -> Network {
+        Network::Mainnet
+    }
+}
+
+/// Formatted as lnd names the network's data directory.
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct UnknownNetwork;
+
+impl FromStr for Network {
+    type Err = UnknownNetwork;
+
+    fn from_str(s: &str) -> Result<Network, UnknownNetwork> {
+        match s {
+            "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(UnknownNetwork),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_round_trip() {
+        for network in &NETWORKS {
+            assert_eq!(network.to_string().parse(), Ok(*network));
+        }
+        assert_eq!("bitcoin".parse(), Ok(Network::Mainnet));
+        assert_eq!("simnet".parse::<Network>(), Err(UnknownNetwork));
+        assert_eq!("signet".parse::<Network>(), Err(UnknownNetwork));
+    }
+
+    #[test]
+    fn addresses() {
+        let address = |s: &str| Address(s.to_owned());
+        let mainnet = address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
+        let testnet = address("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx");
+        let regtest = address("bcrt1qs758ursh4q9z627kt3pp5yysm78ddny6txaqgw");
+        assert!(Network::Mainnet.accepts_address(&mainnet));
+        assert!(Network::Mainnet
+            .accepts_address(&address("BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ")));
+        assert!(Network::Mainnet.accepts_address(&address("1BoatSLRHtKNngkdXEeobR76b53LETtpyT")));
+        assert!(!Network::Mainnet.accepts_address(&testnet));
+        assert!(!Network::Mainnet.accepts_address(&regtest));
+        assert!(!Network::Mainnet.accepts_address(&address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn")));
+        assert!(Network::Testnet.accepts_address(&testnet));
+        assert!(!Network::Testnet.accepts_address(&mainnet));
+        assert!(Network::Regtest.accepts_address(&regtest));
+        assert!(Network::Regtest.accepts_address(&address("2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc")));
+        assert!(!Network::Regtest.accepts_address(&mainnet));
+        // not an address on any network, the node decides
+        assert!(Network::Mainnet.accepts_address(&address("")));
+    }
+}
@@ -0,0 +1,365 @@
+//! BOLT12 offers. An offer is a static payment code: it can be published once, and every payer
+//! sends the node an invoice request over lightning, which the node answers with a fresh
+//! invoice. Each offer lapi creates is bound to an account, and payment for any invoice issued
+//! for it is credited to that account.
+//!
+//! Offers are encoded as BOLT12 specifies: a TLV stream in bech32's character set, without a
+//! checksum, behind the prefix lno. Only the fields lapi uses are understood.
+
+use crate::bech32::{decode_unchecked, encode_unchecked, Bech32Error};
+use crate::common::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+const HRP: &str = "lno";
+/// TLV types of the offer fields lapi writes.
+const OFFER_CHAINS: u64 = 2;
+const OFFER_METADATA: u64 = 4;
+const OFFER_AMOUNT: u64 = 8;
+const OFFER_DESCRIPTION: u64 = 10;
+const OFFER_ISSUER_ID: u64 = 22;
+
+/// An offer created by the node.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Offer {
+    /// Random, distinguishes this offer from every other. Carried in offer_metadata, which the
+    /// node gets back in every invoice request. This is not BOLT12's offer_id, a merkle root
+    /// over every field.
+    pub id: U256,
+    pub description: String,
+    /// What the payer must pay. Without one the payer picks the amount.
+    pub amount: Option<Satoshis>,
+    /// Key of the node, which invoice requests are sent to.
+    pub issuer: PublicKey,
+    pub network: Network,
+}
+
+/// What the node is asked to create an offer for.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OfferSpec {
+    pub amount: Option<Satoshis>,
+    pub description: String,
+}
+
+/// An invoice the node issued in answer to an invoice request for one of its offers.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OfferInvoice {
+    pub offer_id: U256,
+    pub invoice: Invoice,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ParseOfferError {
+    Bech32(Bech32Error),
+    /// The human readable part was not lno.
+    NotAnOffer,
+    /// The TLV stream ended inside a record.
+    Truncated,
+    /// Record types did not strictly increase.
+    OutOfOrder,
+    /// A record lapi does not understand, and whose even type says it must be.
+    UnknownEvenType(u64),
+    /// A record was present but not valid.
+    Invalid(&'static str),
+    /// A record lapi needs was absent.
+    Missing(&'static str),
+}
+
+impl Offer {
+    fn to_tlv(&self) -> Vec<u8> {
+        let mut stream = Vec::new();
+        if self.network != Network::Mainnet {
+            put_record(&mut stream, OFFER_CHAINS, &self.network.chain_hash());
+        }
+        put_record(&mut stream, OFFER_METADATA, &self.id.0);
+        if let Some(amount) = self.amount {
+            // nodes refuse to create offers for more than the msat amount can hold
+            let msat = amount.0.saturating_mul(1000);
+            put_record(&mut stream, OFFER_AMOUNT, &truncated(msat));
+        }
+        put_record(&mut stream, OFFER_DESCRIPTION, self.description.as_bytes());
+        put_record(&mut stream, OFFER_ISSUER_ID, &self.issuer.serialize());
+        stream
+    }
+
+    fn from_tlv(mut stream: &[u8]) -> Result<Offer, ParseOfferError> {
+        let mut network = Network::Mainnet;
+        let mut id = None;
+        let mut amount = None;
+        let mut description = None;
+        let mut issuer = None;
+        let mut last_type = None;
+        while !stream.is_empty() {
+            let (typ, rest) = big_size(stream).ok_or(ParseOfferError::Truncated)?;
+            let (len, rest) = big_size(rest).ok_or(ParseOfferError::Truncated)?;
+            if (rest.len() as u64) < len {
+                return Err(ParseOfferError::Truncated);
+            }
+            let (value, rest) = rest.split_at(len as usize);
+            stream = rest;
+            if last_type.map_or(false, |last| typ <= last) {
+                return Err(ParseOfferError::OutOfOrder);
+            }
+            last_type = Some(typ);
+            match typ {
+                OFFER_CHAINS => network = chain(value)?,
+                OFFER_METADATA => {
+                    id = Some(U256::try_from_slice(value).ok_or(ParseOfferError::Invalid(
+                        "offer_metadata was not an offer id",
+                    ))?)
+                }
+                OFFER_AMOUNT => {
+                    let msat =
+                        untruncated(value).ok_or(ParseOfferError::Invalid("offer_amount"))?;
+                    if msat % 1000 != 0 {
+                        return Err(ParseOfferError::Invalid(
+                            "offer_amount was not whole satoshis",
+                        ));
+                    }
+                    amount = Some(Satoshis(msat / 1000));
+                }
+                OFFER_DESCRIPTION => {
+                    description = Some(
+                        String::from_utf8(value.to_vec())
+                            .map_err(|_| ParseOfferError::Invalid("offer_description"))?,
+                    )
+                }
+                OFFER_ISSUER_ID => {
+                    issuer = Some(
+                        PublicKey::from_slice(value)
+                            .map_err(|_| ParseOfferError::Invalid("offer_issuer_id"))?,
+                    )
+                }
+                even if even % 2 == 0 => return Err(ParseOfferError::UnknownEvenType(even)),
+                _odd => {}
+            }
+        }
+        Ok(Offer {
+            id: id.ok_or(ParseOfferError::Missing("offer_metadata"))?,
+            // BOLT12 only requires a description alongside an amount
+            description: match (description, amount) {
+                (Some(description), _) => description,
+                (None, None) => String::new(),
+                (None, Some(_)) => return Err(ParseOfferError::Missing("offer_description")),
+            },
+            amount,
+            issuer: issuer.ok_or(ParseOfferError::Missing("offer_issuer_id"))?,
+            network,
+        })
+    }
+}
+
+/// The only chain an offer_chains record names. Offers payable on several chains are not
+/// supported.
+fn chain(value: &[u8]) -> Result<Network, ParseOfferError> {
+    NETWORKS
+        .iter()
+        .cloned()
+        .find(|network| network.chain_hash()[..] == *value)
+        .ok_or(ParseOfferError::Invalid("offer_chains"))
+}
+
+fn put_record(stream: &mut Vec<u8>, typ: u64, value: &[u8]) {
+    put_big_size(stream, typ);
+    put_big_size(stream, value.len() as u64);
+    stream.extend_from_slice(value);
+}
+
+/// BOLT1's BigSize, a big endian integer prefixed by its width.
+fn put_big_size(stream: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => stream.push(value as u8),
+        0xfd..=0xffff => {
+            stream.push(0xfd);
+            stream.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            stream.push(0xfe);
+            stream.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            stream.push(0xff);
+            stream.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// None unless bytes starts with a minimally encoded BigSize.
+fn big_size(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (first, rest) = bytes.split_first()?;
+    let (width, min) = match first {
+        0xfd => (2, 0xfd),
+        0xfe => (4, 0x1_0000),
+        0xff => (8, 0x1_0000_0000),
+        _ => return Some((u64::from(*first), rest)),
+    };
+    if rest.len() < width {
+        return None;
+    }
+    let (value, rest) = rest.split_at(width);
+    let value = value
+        .iter()
+        .fold(0u64, |acc, byte| acc << 8 | u64::from(*byte));
+    if value < min {
+        return None;
+    }
+    Some((value, rest))
+}
+
+/// BOLT1's tu64, a big endian integer without leading zeros.
+fn truncated(value: u64) -> Vec<u8> {
+    value
+        .to_be_bytes()
+        .iter()
+        .cloned()
+        .skip_while(|byte| *byte == 0)
+        .collect()
+}
+
+fn untruncated(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 8 || bytes.first() == Some(&0) {
+        return None;
+    }
+    Some(
+        bytes
+            .iter()
+            .fold(0u64, |acc, byte| acc << 8 | u64::from(*byte)),
+    )
+}
+
+impl fmt::Display for Offer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", encode_unchecked(HRP, &self.to_tlv()))
+    }
+}
+
+impl FromStr for Offer {
+    type Err = ParseOfferError;
+
+    /// Offers may be split with '+' and whitespace, to fit where long strings don't.
+    fn from_str(s: &str) -> Result<Offer, ParseOfferError> {
+        let joined: String = s.split('+').map(str::trim).collect::<Vec<&str>>().concat();
+        let (hrp, tlv) = decode_unchecked(&joined).map_err(ParseOfferError::Bech32)?;
+        if hrp != HRP {
+            return Err(ParseOfferError::NotAnOffer);
+        }
+        Offer::from_tlv(&tlv)
+    }
+}
+
+impl Serialize for Offer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Offer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <Cow<str>>::deserialize(deserializer)?
+            .parse()
+            .map_err(|err| de::Error::custom(format!("{:?}", err)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn offer() -> Offer {
+        Offer {
+            id: U256([7; 32]),
+            description: "coffee".to_owned(),
+            amount: Some(Satoshis(2100)),
+            issuer: pubkey_b(),
+            network: Network::Mainnet,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let offers = vec![
+            offer(),
+            Offer {
+                amount: None,
+                description: String::new(),
+                network: Network::Regtest,
+                ..offer()
+            },
+            Offer {
+                amount: Some(Satoshis(21_000_000 * 100_000_000)),
+                network: Network::Testnet,
+                ..offer()
+            },
+        ];
+        for offer in offers {
+            let encoded = offer.to_string();
+            assert!(encoded.starts_with("lno1"));
+            assert_eq!(encoded.parse(), Ok(offer.clone()));
+            assert_eq!(encoded.to_ascii_uppercase().parse(), Ok(offer.clone()));
+            let json = serde_json::to_string(&offer).unwrap();
+            assert_eq!(serde_json::from_str::<Offer>(&json).unwrap(), offer);
+        }
+    }
+
+    #[test]
+    fn split_with_plus() {
+        let encoded = offer().to_string();
+        let (first, second) = encoded.split_at(20);
+        let split = format!("{}+\n  {}", first, second);
+        assert_eq!(split.parse(), Ok(offer()));
+    }
+
+    #[test]
+    fn records_in_bolt12_order() {
+        let tlv = offer().to_tlv();
+        let mut types = Vec::new();
+        let mut stream = &tlv[..];
+        while !stream.is_empty() {
+            let (typ, rest) = big_size(stream).unwrap();
+            let (len, rest) = big_size(rest).unwrap();
+            types.push(typ);
+            stream = &rest[len as usize..];
+        }
+        assert_eq!(types, vec![4, 8, 10, 22]);
+        // 2100 sat is 2_100_000 msat, three bytes once truncated
+        assert_eq!(truncated(2_100_000), vec![0x20, 0x0b, 0x20]);
+    }
+
+    #[test]
+    fn unknown_records() {
+        let tlv = offer().to_tlv();
+        let with = |typ: u64| {
+            let mut stream = tlv.clone();
+            put_record(&mut stream, typ, b"?");
+            encode_unchecked(HRP, &stream).parse::<Offer>()
+        };
+        assert_eq!(with(23), Ok(offer()));
+        assert_eq!(with(24), Err(ParseOfferError::UnknownEvenType(24)));
+        assert_eq!(with(21), Err(ParseOfferError::OutOfOrder));
+
+        let mut stream = tlv.clone();
+        stream.pop();
+        assert_eq!(
+            encode_unchecked(HRP, &stream).parse::<Offer>(),
+            Err(ParseOfferError::Truncated)
+        );
+        assert_eq!(
+            encode_unchecked("lni", &tlv).parse::<Offer>(),
+            Err(ParseOfferError::NotAnOffer)
+        );
+    }
+
+    #[test]
+    fn big_size_minimal() {
+        for value in &[0, 0xfc, 0xfd, 0xffff, 0x1_0000, 0xffff_ffff, 0x1_0000_0000] {
+            let mut encoded = Vec::new();
+            put_big_size(&mut encoded, *value);
+            assert_eq!(big_size(&encoded), Some((*value, &[][..])));
+        }
+        assert_eq!(big_size(&[0xfd, 0x00, 0xfc]), None);
+        assert_eq!(big_size(&[0xfe, 0x00, 0x00, 0xff, 0xff]), None);
+    }
+}
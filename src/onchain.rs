@@ -0,0 +1,47 @@
+//! On-chain deposits and withdrawals. Accounts are handed addresses belonging to the node's
+//! wallet. Funds sent to one of those addresses are credited to its account once buried under
+//! enough blocks. Withdrawals are paid from the node's wallet, the account paying the miner fee.
+
+use crate::common::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Confirmations needed before a deposit is credited, unless configured otherwise.
+pub const DEFAULT_CONFIRMATIONS: u32 = 3;
+
+/// An address paying to the node's on-chain wallet, as formatted by the node.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Address(pub String);
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Identifies a single transaction output.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct OutPoint {
+    pub txid: U256,
+    pub vout: u32,
+}
+
+/// An output paying to the node's wallet.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IncomingTransaction {
+    pub outpoint: OutPoint,
+    pub address: Address,
+    pub amount: Satoshis,
+    /// 0 while in the mempool, 1 once mined, 2 after one more block and so on.
+    pub confirmations: u32,
+}
+
+/// A transaction broadcast by the node.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OnchainOutgoing {
+    pub txid: U256,
+    pub amount: Satoshis,
+    pub fees_offered: Fee<Satoshis>,
+    /// The miner fee.
+    pub fees_paid: Fee<Satoshis>,
+}
@@ -0,0 +1,1391 @@
+//! Capture and replay of LightningNode behaviour. RecordingNode wraps a node, writing every call,
+//! its result and every incoming event to a file, one json object per line. ReplayNode serves a
+//! recording back without a node, so an incident captured in production can be turned into a
+//! regression test.
+
+use crate::common::*;
+use crate::lighting_node::DynStream;
+use futures::{future::FutureResult, sync::mpsc, Future, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One line of a recording.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    /// A call completed. Calls are recorded in order of completion.
+    Call { call: Call, result: CallResult },
+    /// An item was taken from one of the node's incoming streams.
+    Event(Event),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Call {
+    CreateInvoice {
+        spec: SpecRecord,
+    },
+    PayInvoice {
+        invoice: InvoiceSerDe,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        max_parts: u32,
+        timeout: Duration,
+    },
+    Keysend {
+        pubkey: PubKeySerDe,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    },
+    CreateHoldInvoice {
+        spec: SpecRecord,
+        payment_hash: PaymentHash,
+    },
+    SettleHoldInvoice {
+        preimage: Preimage,
+    },
+    CancelHoldInvoice {
+        payment_hash: PaymentHash,
+    },
+    EstimateFee {
+        invoice: InvoiceSerDe,
+        amount: Satoshis,
+    },
+    NodeInfo,
+    NewAddress,
+    EstimateOnchainFee {
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    },
+    SendOnchain {
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    },
+    CancelInvoice {
+        payment_hash: PaymentHash,
+    },
+    CreateOffer {
+        amount: Option<Satoshis>,
+        description: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CallResult {
+    Invoice(ResultSerDe<InvoiceSerDe, CreateInvoiceErrorRecord>),
+    Payment(ResultSerDe<PaidOutgoingRecord, PayErrorRecord>),
+    Keysend(ResultSerDe<KeysendOutgoingRecord, PayErrorRecord>),
+    Hold(ResultSerDe<(), HoldInvoiceErrorRecord>),
+    Quote(ResultSerDe<FeeQuoteRecord, EstimateFeeErrorRecord>),
+    NodeInfo(ResultSerDe<NodeInfoRecord, String>),
+    Address(ResultSerDe<Address, NewAddressErrorRecord>),
+    OnchainFee(ResultSerDe<Fee<Satoshis>, EstimateOnchainFeeErrorRecord>),
+    Onchain(ResultSerDe<OnchainOutgoingRecord, PayErrorRecord>),
+    Offer(ResultSerDe<Offer, CreateOfferErrorRecord>),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Event {
+    PaidInvoice(ResultSerDe<PaidInvoiceRecord, String>),
+    ReceivedKeysend(ResultSerDe<ReceivedKeysendRecord, String>),
+    AcceptedInvoice(ResultSerDe<PaymentHash, String>),
+    IncomingTransaction(ResultSerDe<IncomingTransactionRecord, String>),
+    OfferInvoice(ResultSerDe<OfferInvoiceRecord, String>),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct SpecRecord {
+    satoshis: Option<Satoshis>,
+    description: DescriptionRecord,
+    expiry_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum DescriptionRecord {
+    Direct(String),
+    Hash(U256),
+}
+
+impl From<&InvoiceSpec> for SpecRecord {
+    fn from(spec: &InvoiceSpec) -> SpecRecord {
+        SpecRecord {
+            satoshis: spec.satoshis(),
+            description: match spec.description() {
+                Description::Direct(memo) => DescriptionRecord::Direct(memo.clone()),
+                Description::Hash(hash) => DescriptionRecord::Hash(*hash),
+            },
+            expiry_seconds: spec.expiry().as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct PaidInvoiceRecord {
+    invoice: InvoiceSerDe,
+    preimage: Preimage,
+    amount_paid: Satoshis,
+}
+
+impl From<&PaidInvoice> for PaidInvoiceRecord {
+    fn from(paid: &PaidInvoice) -> PaidInvoiceRecord {
+        PaidInvoiceRecord {
+            invoice: InvoiceSerDe(paid.invoice().clone()),
+            preimage: *paid.preimage(),
+            amount_paid: *paid.amount_paid(),
+        }
+    }
+}
+
+impl From<PaidInvoiceRecord> for PaidInvoice {
+    fn from(record: PaidInvoiceRecord) -> PaidInvoice {
+        PaidInvoice::create(record.invoice.0, record.preimage, record.amount_paid)
+            .expect("only valid paid invoices are recorded")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct PaidOutgoingRecord {
+    paid_invoice: PaidInvoiceRecord,
+    fees_offered: Fee<Satoshis>,
+    fees_paid: Fee<Satoshis>,
+    parts: u32,
+}
+
+impl From<&PaidInvoiceOutgoing> for PaidOutgoingRecord {
+    fn from(outgoing: &PaidInvoiceOutgoing) -> PaidOutgoingRecord {
+        PaidOutgoingRecord {
+            paid_invoice: (&outgoing.paid_invoice).into(),
+            fees_offered: outgoing.fees_offered,
+            fees_paid: outgoing.fees_paid,
+            parts: outgoing.parts,
+        }
+    }
+}
+
+impl From<PaidOutgoingRecord> for PaidInvoiceOutgoing {
+    fn from(record: PaidOutgoingRecord) -> PaidInvoiceOutgoing {
+        PaidInvoiceOutgoing {
+            paid_invoice: record.paid_invoice.into(),
+            fees_offered: record.fees_offered,
+            fees_paid: record.fees_paid,
+            parts: record.parts,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct KeysendOutgoingRecord {
+    preimage: Preimage,
+    amount: Satoshis,
+    fees_offered: Fee<Satoshis>,
+    fees_paid: Fee<Satoshis>,
+}
+
+impl From<&KeysendOutgoing> for KeysendOutgoingRecord {
+    fn from(outgoing: &KeysendOutgoing) -> KeysendOutgoingRecord {
+        KeysendOutgoingRecord {
+            preimage: outgoing.preimage,
+            amount: outgoing.amount,
+            fees_offered: outgoing.fees_offered,
+            fees_paid: outgoing.fees_paid,
+        }
+    }
+}
+
+impl From<KeysendOutgoingRecord> for KeysendOutgoing {
+    fn from(record: KeysendOutgoingRecord) -> KeysendOutgoing {
+        KeysendOutgoing {
+            preimage: record.preimage,
+            amount: record.amount,
+            fees_offered: record.fees_offered,
+            fees_paid: record.fees_paid,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct OnchainOutgoingRecord {
+    txid: U256,
+    amount: Satoshis,
+    fees_offered: Fee<Satoshis>,
+    fees_paid: Fee<Satoshis>,
+}
+
+impl From<&OnchainOutgoing> for OnchainOutgoingRecord {
+    fn from(outgoing: &OnchainOutgoing) -> OnchainOutgoingRecord {
+        OnchainOutgoingRecord {
+            txid: outgoing.txid,
+            amount: outgoing.amount,
+            fees_offered: outgoing.fees_offered,
+            fees_paid: outgoing.fees_paid,
+        }
+    }
+}
+
+impl From<OnchainOutgoingRecord> for OnchainOutgoing {
+    fn from(record: OnchainOutgoingRecord) -> OnchainOutgoing {
+        OnchainOutgoing {
+            txid: record.txid,
+            amount: record.amount,
+            fees_offered: record.fees_offered,
+            fees_paid: record.fees_paid,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct ReceivedKeysendRecord {
+    preimage: Preimage,
+    amount: Satoshis,
+    lesser: Option<Lesser>,
+}
+
+impl From<&ReceivedKeysend> for ReceivedKeysendRecord {
+    fn from(keysend: &ReceivedKeysend) -> ReceivedKeysendRecord {
+        ReceivedKeysendRecord {
+            preimage: keysend.preimage,
+            amount: keysend.amount,
+            lesser: keysend.lesser,
+        }
+    }
+}
+
+impl From<ReceivedKeysendRecord> for ReceivedKeysend {
+    fn from(record: ReceivedKeysendRecord) -> ReceivedKeysend {
+        ReceivedKeysend {
+            preimage: record.preimage,
+            amount: record.amount,
+            lesser: record.lesser,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct FeeQuoteRecord {
+    expected: Fee<Satoshis>,
+    worst_case: Fee<Satoshis>,
+}
+
+impl From<&FeeQuote> for FeeQuoteRecord {
+    fn from(quote: &FeeQuote) -> FeeQuoteRecord {
+        FeeQuoteRecord {
+            expected: quote.expected,
+            worst_case: quote.worst_case,
+        }
+    }
+}
+
+impl From<FeeQuoteRecord> for FeeQuote {
+    fn from(record: FeeQuoteRecord) -> FeeQuote {
+        FeeQuote {
+            expected: record.expected,
+            worst_case: record.worst_case,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct NodeInfoRecord {
+    alias: String,
+    pubkey: PubKeySerDe,
+    inbound: Satoshis,
+    outbound: Satoshis,
+}
+
+impl From<&NodeInfo> for NodeInfoRecord {
+    fn from(info: &NodeInfo) -> NodeInfoRecord {
+        NodeInfoRecord {
+            alias: info.alias.clone(),
+            pubkey: PubKeySerDe(info.pubkey),
+            inbound: info.capacity.inbound,
+            outbound: info.capacity.outbound,
+        }
+    }
+}
+
+impl From<NodeInfoRecord> for NodeInfo {
+    fn from(record: NodeInfoRecord) -> NodeInfo {
+        NodeInfo {
+            alias: record.alias,
+            pubkey: record.pubkey.0,
+            capacity: Capacity {
+                inbound: record.inbound,
+                outbound: record.outbound,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct IncomingTransactionRecord {
+    outpoint: OutPoint,
+    address: Address,
+    amount: Satoshis,
+    confirmations: u32,
+}
+
+impl From<&IncomingTransaction> for IncomingTransactionRecord {
+    fn from(transaction: &IncomingTransaction) -> IncomingTransactionRecord {
+        IncomingTransactionRecord {
+            outpoint: transaction.outpoint,
+            address: transaction.address.clone(),
+            amount: transaction.amount,
+            confirmations: transaction.confirmations,
+        }
+    }
+}
+
+impl From<IncomingTransactionRecord> for IncomingTransaction {
+    fn from(record: IncomingTransactionRecord) -> IncomingTransaction {
+        IncomingTransaction {
+            outpoint: record.outpoint,
+            address: record.address,
+            amount: record.amount,
+            confirmations: record.confirmations,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct OfferInvoiceRecord {
+    offer_id: U256,
+    invoice: InvoiceSerDe,
+}
+
+impl From<&OfferInvoice> for OfferInvoiceRecord {
+    fn from(issued: &OfferInvoice) -> OfferInvoiceRecord {
+        OfferInvoiceRecord {
+            offer_id: issued.offer_id,
+            invoice: InvoiceSerDe(issued.invoice.clone()),
+        }
+    }
+}
+
+impl From<OfferInvoiceRecord> for OfferInvoice {
+    fn from(record: OfferInvoiceRecord) -> OfferInvoice {
+        OfferInvoice {
+            offer_id: record.offer_id,
+            invoice: record.invoice.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CreateInvoiceErrorRecord {
+    Network {
+        backend_name: String,
+        err: String,
+    },
+    /// The parse error itself can't be reconstructed, so it is replayed as Unknown.
+    InvalidInvoice(String),
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&CreateInvoiceError> for CreateInvoiceErrorRecord {
+    fn from(err: &CreateInvoiceError) -> CreateInvoiceErrorRecord {
+        match err {
+            CreateInvoiceError::Network { backend_name, err } => {
+                CreateInvoiceErrorRecord::Network {
+                    backend_name: backend_name.clone(),
+                    err: err.clone(),
+                }
+            }
+            CreateInvoiceError::InvalidInvoice(err) => {
+                CreateInvoiceErrorRecord::InvalidInvoice(format!("{:?}", err))
+            }
+            CreateInvoiceError::Unsupported(reason) => {
+                CreateInvoiceErrorRecord::Unsupported(reason.to_string())
+            }
+            CreateInvoiceError::Unknown(err) => CreateInvoiceErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<CreateInvoiceErrorRecord> for CreateInvoiceError {
+    fn from(record: CreateInvoiceErrorRecord) -> CreateInvoiceError {
+        match record {
+            CreateInvoiceErrorRecord::Network { backend_name, err } => {
+                CreateInvoiceError::Network { backend_name, err }
+            }
+            CreateInvoiceErrorRecord::InvalidInvoice(err) => {
+                CreateInvoiceError::Unknown(format!("invalid invoice: {}", err))
+            }
+            CreateInvoiceErrorRecord::Unsupported(reason) => {
+                CreateInvoiceError::Unsupported(leak(reason))
+            }
+            CreateInvoiceErrorRecord::Unknown(err) => CreateInvoiceError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum PayErrorRecord {
+    PaymentAborted,
+    InvalidResponse(PaidInvoiceInvalid),
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&PayError> for PayErrorRecord {
+    fn from(err: &PayError) -> PayErrorRecord {
+        match err {
+            PayError::PaymentAborted => PayErrorRecord::PaymentAborted,
+            PayError::InvalidResponse(invalid) => PayErrorRecord::InvalidResponse(invalid.clone()),
+            PayError::Unsupported(reason) => PayErrorRecord::Unsupported(reason.to_string()),
+            PayError::Unknown(err) => PayErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<PayErrorRecord> for PayError {
+    fn from(record: PayErrorRecord) -> PayError {
+        match record {
+            PayErrorRecord::PaymentAborted => PayError::PaymentAborted,
+            PayErrorRecord::InvalidResponse(invalid) => PayError::InvalidResponse(invalid),
+            PayErrorRecord::Unsupported(reason) => PayError::Unsupported(leak(reason)),
+            PayErrorRecord::Unknown(err) => PayError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum HoldInvoiceErrorRecord {
+    UnknownInvoice,
+    NotAccepted,
+    AlreadyResolved,
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&HoldInvoiceError> for HoldInvoiceErrorRecord {
+    fn from(err: &HoldInvoiceError) -> HoldInvoiceErrorRecord {
+        match err {
+            HoldInvoiceError::UnknownInvoice => HoldInvoiceErrorRecord::UnknownInvoice,
+            HoldInvoiceError::NotAccepted => HoldInvoiceErrorRecord::NotAccepted,
+            HoldInvoiceError::AlreadyResolved => HoldInvoiceErrorRecord::AlreadyResolved,
+            HoldInvoiceError::Unsupported(reason) => {
+                HoldInvoiceErrorRecord::Unsupported(reason.to_string())
+            }
+            HoldInvoiceError::Unknown(err) => HoldInvoiceErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<HoldInvoiceErrorRecord> for HoldInvoiceError {
+    fn from(record: HoldInvoiceErrorRecord) -> HoldInvoiceError {
+        match record {
+            HoldInvoiceErrorRecord::UnknownInvoice => HoldInvoiceError::UnknownInvoice,
+            HoldInvoiceErrorRecord::NotAccepted => HoldInvoiceError::NotAccepted,
+            HoldInvoiceErrorRecord::AlreadyResolved => HoldInvoiceError::AlreadyResolved,
+            HoldInvoiceErrorRecord::Unsupported(reason) => {
+                HoldInvoiceError::Unsupported(leak(reason))
+            }
+            HoldInvoiceErrorRecord::Unknown(err) => HoldInvoiceError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum EstimateFeeErrorRecord {
+    NoRoute,
+    Unknown(String),
+}
+
+impl From<&EstimateFeeError> for EstimateFeeErrorRecord {
+    fn from(err: &EstimateFeeError) -> EstimateFeeErrorRecord {
+        match err {
+            EstimateFeeError::NoRoute => EstimateFeeErrorRecord::NoRoute,
+            EstimateFeeError::Unknown(err) => EstimateFeeErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<EstimateFeeErrorRecord> for EstimateFeeError {
+    fn from(record: EstimateFeeErrorRecord) -> EstimateFeeError {
+        match record {
+            EstimateFeeErrorRecord::NoRoute => EstimateFeeError::NoRoute,
+            EstimateFeeErrorRecord::Unknown(err) => EstimateFeeError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum NewAddressErrorRecord {
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&NewAddressError> for NewAddressErrorRecord {
+    fn from(err: &NewAddressError) -> NewAddressErrorRecord {
+        match err {
+            NewAddressError::Unsupported(reason) => {
+                NewAddressErrorRecord::Unsupported(reason.to_string())
+            }
+            NewAddressError::Unknown(err) => NewAddressErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<NewAddressErrorRecord> for NewAddressError {
+    fn from(record: NewAddressErrorRecord) -> NewAddressError {
+        match record {
+            NewAddressErrorRecord::Unsupported(reason) => {
+                NewAddressError::Unsupported(leak(reason))
+            }
+            NewAddressErrorRecord::Unknown(err) => NewAddressError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum EstimateOnchainFeeErrorRecord {
+    InvalidAddress,
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&EstimateOnchainFeeError> for EstimateOnchainFeeErrorRecord {
+    fn from(err: &EstimateOnchainFeeError) -> EstimateOnchainFeeErrorRecord {
+        match err {
+            EstimateOnchainFeeError::InvalidAddress => {
+                EstimateOnchainFeeErrorRecord::InvalidAddress
+            }
+            EstimateOnchainFeeError::Unsupported(reason) => {
+                EstimateOnchainFeeErrorRecord::Unsupported(reason.to_string())
+            }
+            EstimateOnchainFeeError::Unknown(err) => {
+                EstimateOnchainFeeErrorRecord::Unknown(err.clone())
+            }
+        }
+    }
+}
+
+impl From<EstimateOnchainFeeErrorRecord> for EstimateOnchainFeeError {
+    fn from(record: EstimateOnchainFeeErrorRecord) -> EstimateOnchainFeeError {
+        match record {
+            EstimateOnchainFeeErrorRecord::InvalidAddress => {
+                EstimateOnchainFeeError::InvalidAddress
+            }
+            EstimateOnchainFeeErrorRecord::Unsupported(reason) => {
+                EstimateOnchainFeeError::Unsupported(leak(reason))
+            }
+            EstimateOnchainFeeErrorRecord::Unknown(err) => EstimateOnchainFeeError::Unknown(err),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum CreateOfferErrorRecord {
+    Unsupported(String),
+    Unknown(String),
+}
+
+impl From<&CreateOfferError> for CreateOfferErrorRecord {
+    fn from(err: &CreateOfferError) -> CreateOfferErrorRecord {
+        match err {
+            CreateOfferError::Unsupported(reason) => {
+                CreateOfferErrorRecord::Unsupported(reason.to_string())
+            }
+            CreateOfferError::Unknown(err) => CreateOfferErrorRecord::Unknown(err.clone()),
+        }
+    }
+}
+
+impl From<CreateOfferErrorRecord> for CreateOfferError {
+    fn from(record: CreateOfferErrorRecord) -> CreateOfferError {
+        match record {
+            CreateOfferErrorRecord::Unsupported(reason) => {
+                CreateOfferError::Unsupported(leak(reason))
+            }
+            CreateOfferErrorRecord::Unknown(err) => CreateOfferError::Unknown(err),
+        }
+    }
+}
+
+/// Unsupported errors carry a &'static str. Replayed errors are few, so leaking them is harmless.
+fn leak(reason: String) -> &'static str {
+    Box::leak(reason.into_boxed_str())
+}
+
+fn record<'a, T, E, A, B>(
+    result: &'a Result<T, E>,
+    ok: impl FnOnce(&'a T) -> A,
+    err: impl FnOnce(&'a E) -> B,
+) -> ResultSerDe<A, B> {
+    match result {
+        Ok(t) => ResultSerDe::Ok(ok(t)),
+        Err(e) => ResultSerDe::Error(err(e)),
+    }
+}
+
+fn record_stream_err(err: &SubscribePaidInvoicesError) -> String {
+    let SubscribePaidInvoicesError::Unknown(err) = err;
+    err.clone()
+}
+
+struct Recorder<G: Log> {
+    out: Mutex<File>,
+    log: G,
+}
+
+impl<G: Log> Recorder<G> {
+    fn write(&self, entry: &Entry) {
+        let mut line = serde_json::to_vec(entry).expect("entries always serialize");
+        line.push(b'\n');
+        let mut out = self.out.lock().unwrap();
+        if let Err(err) = out.write_all(&line).and_then(|()| out.flush()) {
+            self.log.err(LogErr::Recording(err.to_string()));
+        }
+    }
+}
+
+/// Passes calls through to a node, appending each call and incoming event to a recording.
+pub struct RecordingNode<L: LightningNode, G: Log> {
+    inner: L,
+    recorder: Arc<Recorder<G>>,
+}
+
+impl<L: LightningNode, G: Log + 'static> RecordingNode<L, G> {
+    /// Append to the recording at path, creating it if needed. Failures to write are logged and
+    /// do not affect the node.
+    pub fn create<P: AsRef<Path>>(inner: L, path: P, log: G) -> io::Result<Self> {
+        let out = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RecordingNode {
+            inner,
+            recorder: Arc::new(Recorder {
+                out: Mutex::new(out),
+                log,
+            }),
+        })
+    }
+
+    fn record_call<T: Send + 'static, E: Send + 'static>(
+        &self,
+        call: Call,
+        future: DynFut<T, E>,
+        to_result: impl FnOnce(&Result<T, E>) -> CallResult + Send + 'static,
+    ) -> DynFut<T, E> {
+        let recorder = self.recorder.clone();
+        Box::new(future.then(move |result| {
+            recorder.write(&Entry::Call {
+                call,
+                result: to_result(&result),
+            });
+            result
+        }))
+    }
+
+    fn record_events<T: Send + 'static>(
+        &self,
+        stream: DynStream<T, SubscribePaidInvoicesError>,
+        to_event: impl Fn(&Result<T, SubscribePaidInvoicesError>) -> Event + Send + 'static,
+    ) -> DynStream<T, SubscribePaidInvoicesError> {
+        let recorder = self.recorder.clone();
+        Box::new(stream.then(move |item| {
+            recorder.write(&Entry::Event(to_event(&item)));
+            item
+        }))
+    }
+}
+
+impl<L: LightningNode, G: Log + 'static> LightningNode for RecordingNode<L, G> {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        let call = Call::CreateInvoice {
+            spec: (&spec).into(),
+        };
+        self.record_call(call, self.inner.create_invoice(spec), |result| {
+            CallResult::Invoice(record(result, |iv| InvoiceSerDe(iv.clone()), Into::into))
+        })
+    }
+
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        let call = Call::PayInvoice {
+            invoice: InvoiceSerDe(invoice.clone()),
+            amount,
+            max_fee,
+            max_parts: multi_path.max_parts,
+            timeout: multi_path.timeout,
+        };
+        let future = self.inner.pay_invoice(invoice, amount, max_fee, multi_path);
+        self.record_call(call, future, |result| {
+            CallResult::Payment(record(result, Into::into, Into::into))
+        })
+    }
+
+    fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        self.record_events(self.inner.paid_invoices(), |item| {
+            Event::PaidInvoice(record(item, Into::into, record_stream_err))
+        })
+    }
+
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        let call = Call::Keysend {
+            pubkey: PubKeySerDe(pubkey),
+            amount,
+            max_fee,
+        };
+        let future = self.inner.keysend(pubkey, amount, max_fee);
+        self.record_call(call, future, |result| {
+            CallResult::Keysend(record(result, Into::into, Into::into))
+        })
+    }
+
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        self.record_events(self.inner.received_keysends(), |item| {
+            Event::ReceivedKeysend(record(item, Into::into, record_stream_err))
+        })
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        let call = Call::CreateHoldInvoice {
+            spec: (&spec).into(),
+            payment_hash,
+        };
+        let future = self.inner.create_hold_invoice(spec, payment_hash);
+        self.record_call(call, future, |result| {
+            CallResult::Invoice(record(result, |iv| InvoiceSerDe(iv.clone()), Into::into))
+        })
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        let call = Call::SettleHoldInvoice { preimage };
+        self.record_call(call, self.inner.settle_hold_invoice(preimage), |result| {
+            CallResult::Hold(record(result, |()| (), Into::into))
+        })
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        let call = Call::CancelHoldInvoice { payment_hash };
+        let future = self.inner.cancel_hold_invoice(payment_hash);
+        self.record_call(call, future, |result| {
+            CallResult::Hold(record(result, |()| (), Into::into))
+        })
+    }
+
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        self.record_events(self.inner.accepted_invoices(), |item| {
+            Event::AcceptedInvoice(record(item, |hash| *hash, record_stream_err))
+        })
+    }
+
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        let call = Call::EstimateFee {
+            invoice: InvoiceSerDe(invoice.clone()),
+            amount,
+        };
+        let future = self.inner.estimate_fee(invoice, amount);
+        self.record_call(call, future, |result| {
+            CallResult::Quote(record(result, Into::into, Into::into))
+        })
+    }
+
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        self.record_call(Call::NodeInfo, self.inner.node_info(), |result| {
+            CallResult::NodeInfo(record(result, Into::into, |NodeInfoError::Unknown(err)| {
+                err.clone()
+            }))
+        })
+    }
+
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        self.record_call(Call::NewAddress, self.inner.new_address(), |result| {
+            CallResult::Address(record(result, Clone::clone, Into::into))
+        })
+    }
+
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        self.record_events(self.inner.incoming_transactions(), |item| {
+            Event::IncomingTransaction(record(item, Into::into, record_stream_err))
+        })
+    }
+
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        let call = Call::EstimateOnchainFee {
+            address: address.clone(),
+            amount,
+            target_blocks,
+        };
+        let future = self
+            .inner
+            .estimate_onchain_fee(address, amount, target_blocks);
+        self.record_call(call, future, |result| {
+            CallResult::OnchainFee(record(result, Clone::clone, Into::into))
+        })
+    }
+
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        let call = Call::SendOnchain {
+            address: address.clone(),
+            amount,
+            target_blocks,
+            max_fee,
+        };
+        let future = self
+            .inner
+            .send_onchain(address, amount, target_blocks, max_fee);
+        self.record_call(call, future, |result| {
+            CallResult::Onchain(record(result, Into::into, Into::into))
+        })
+    }
+
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        let call = Call::CancelInvoice { payment_hash };
+        let future = self.inner.cancel_invoice(payment_hash);
+        self.record_call(call, future, |result| {
+            CallResult::Hold(record(result, |()| (), Into::into))
+        })
+    }
+
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        let call = Call::CreateOffer {
+            amount: spec.amount,
+            description: spec.description.clone(),
+        };
+        self.record_call(call, self.inner.create_offer(spec), |result| {
+            CallResult::Offer(record(result, Clone::clone, Into::into))
+        })
+    }
+
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        self.record_events(self.inner.offer_invoices(), |item| {
+            Event::OfferInvoice(record(item, Into::into, record_stream_err))
+        })
+    }
+}
+
+/// Events from a recording, waiting to be delivered.
+struct ReplayFeed<T> {
+    /// Each event along with the number of calls recorded before it.
+    pending: VecDeque<(usize, Result<T, SubscribePaidInvoicesError>)>,
+    subscriber: Option<mpsc::UnboundedSender<Result<T, SubscribePaidInvoicesError>>>,
+}
+
+impl<T: Send + 'static> ReplayFeed<T> {
+    fn new() -> Self {
+        ReplayFeed {
+            pending: VecDeque::new(),
+            subscriber: None,
+        }
+    }
+
+    /// A new subscription replaces the old one. Events already delivered are not repeated.
+    fn subscribe(&mut self, replayed: usize) -> DynStream<T, SubscribePaidInvoicesError> {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscriber = Some(tx);
+        self.release(replayed);
+        Box::new(
+            rx.map_err(|()| unreachable!())
+                .and_then(|resres| FutureResult::from(resres)),
+        )
+    }
+
+    /// Deliver the events which were recorded before the replayed'th call.
+    fn release(&mut self, replayed: usize) {
+        while let Some(tx) = self.subscriber.as_ref() {
+            match self.pending.front() {
+                Some((after, _)) if *after <= replayed => {}
+                _ => return,
+            }
+            let (after, item) = self.pending.pop_front().unwrap();
+            if let Err(err) = tx.unbounded_send(item) {
+                // the subscriber went away, hold the event for the next one
+                self.pending.push_front((after, err.into_inner()));
+                self.subscriber = None;
+            }
+        }
+    }
+}
+
+struct ReplayState {
+    /// Recorded calls, taken as they are replayed.
+    calls: Vec<Option<(Call, CallResult)>>,
+    replayed: usize,
+    paid_invoices: ReplayFeed<PaidInvoice>,
+    keysends: ReplayFeed<ReceivedKeysend>,
+    accepted: ReplayFeed<PaymentHash>,
+    transactions: ReplayFeed<IncomingTransaction>,
+    offer_invoices: ReplayFeed<OfferInvoice>,
+}
+
+/// Serves a recording made by RecordingNode. Each call returns the result recorded for the first
+/// unused call with the same arguments, and panics if there is none. Incoming events are
+/// delivered once the calls recorded before them have been replayed.
+pub struct ReplayNode {
+    state: Mutex<ReplayState>,
+}
+
+impl ReplayNode {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ReplayNode> {
+        let mut state = ReplayState {
+            calls: Vec::new(),
+            replayed: 0,
+            paid_invoices: ReplayFeed::new(),
+            keysends: ReplayFeed::new(),
+            accepted: ReplayFeed::new(),
+            transactions: ReplayFeed::new(),
+            offer_invoices: ReplayFeed::new(),
+        };
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let after = state.calls.len();
+            match serde_json::from_str(&line)? {
+                Entry::Call { call, result } => state.calls.push(Some((call, result))),
+                Entry::Event(Event::PaidInvoice(item)) => state
+                    .paid_invoices
+                    .pending
+                    .push_back((after, replay_item(item, Into::into))),
+                Entry::Event(Event::ReceivedKeysend(item)) => state
+                    .keysends
+                    .pending
+                    .push_back((after, replay_item(item, Into::into))),
+                Entry::Event(Event::AcceptedInvoice(item)) => state
+                    .accepted
+                    .pending
+                    .push_back((after, replay_item(item, |hash| hash))),
+                Entry::Event(Event::IncomingTransaction(item)) => state
+                    .transactions
+                    .pending
+                    .push_back((after, replay_item(item, Into::into))),
+                Entry::Event(Event::OfferInvoice(item)) => state
+                    .offer_invoices
+                    .pending
+                    .push_back((after, replay_item(item, Into::into))),
+            }
+        }
+        Ok(ReplayNode {
+            state: Mutex::new(state),
+        })
+    }
+
+    fn replay(&self, call: Call) -> CallResult {
+        let mut state = self.state.lock().unwrap();
+        let recorded = state
+            .calls
+            .iter_mut()
+            .find(|slot| match slot {
+                Some((recorded, _)) => *recorded == call,
+                None => false,
+            })
+            .and_then(|slot| slot.take());
+        let result = match recorded {
+            Some((_, result)) => result,
+            None => panic!("no recorded call matches {:?}", call),
+        };
+        state.replayed += 1;
+        let replayed = state.replayed;
+        state.paid_invoices.release(replayed);
+        state.keysends.release(replayed);
+        state.accepted.release(replayed);
+        state.transactions.release(replayed);
+        state.offer_invoices.release(replayed);
+        result
+    }
+}
+
+fn replay_item<A, T>(
+    item: ResultSerDe<A, String>,
+    ok: impl FnOnce(A) -> T,
+) -> Result<T, SubscribePaidInvoicesError> {
+    let item: Result<A, String> = item.into();
+    item.map(ok).map_err(SubscribePaidInvoicesError::Unknown)
+}
+
+fn replayed<A, B, T, E>(result: ResultSerDe<A, B>, ok: impl FnOnce(A) -> T) -> DynFut<T, E>
+where
+    B: Into<E>,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    let result: Result<A, B> = result.into();
+    Box::new(FutureResult::from(result.map(ok).map_err(Into::into)))
+}
+
+fn mismatched(result: CallResult) -> ! {
+    panic!("recorded result {:?} does not fit the call", result)
+}
+
+impl LightningNode for ReplayNode {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        match self.replay(Call::CreateInvoice {
+            spec: (&spec).into(),
+        }) {
+            CallResult::Invoice(result) => replayed(result, |iv| iv.0),
+            other => mismatched(other),
+        }
+    }
+
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        match self.replay(Call::PayInvoice {
+            invoice: InvoiceSerDe(invoice),
+            amount,
+            max_fee,
+            max_parts: multi_path.max_parts,
+            timeout: multi_path.timeout,
+        }) {
+            CallResult::Payment(result) => replayed(result, Into::into),
+            other => mismatched(other),
+        }
+    }
+
+    fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        let mut state = self.state.lock().unwrap();
+        let replayed = state.replayed;
+        state.paid_invoices.subscribe(replayed)
+    }
+
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        match self.replay(Call::Keysend {
+            pubkey: PubKeySerDe(pubkey),
+            amount,
+            max_fee,
+        }) {
+            CallResult::Keysend(result) => replayed(result, Into::into),
+            other => mismatched(other),
+        }
+    }
+
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        let mut state = self.state.lock().unwrap();
+        let replayed = state.replayed;
+        state.keysends.subscribe(replayed)
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        match self.replay(Call::CreateHoldInvoice {
+            spec: (&spec).into(),
+            payment_hash,
+        }) {
+            CallResult::Invoice(result) => replayed(result, |iv| iv.0),
+            other => mismatched(other),
+        }
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        match self.replay(Call::SettleHoldInvoice { preimage }) {
+            CallResult::Hold(result) => replayed(result, |()| ()),
+            other => mismatched(other),
+        }
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        match self.replay(Call::CancelHoldInvoice { payment_hash }) {
+            CallResult::Hold(result) => replayed(result, |()| ()),
+            other => mismatched(other),
+        }
+    }
+
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        let mut state = self.state.lock().unwrap();
+        let replayed = state.replayed;
+        state.accepted.subscribe(replayed)
+    }
+
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        match self.replay(Call::EstimateFee {
+            invoice: InvoiceSerDe(invoice),
+            amount,
+        }) {
+            CallResult::Quote(result) => replayed(result, Into::into),
+            other => mismatched(other),
+        }
+    }
+
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        match self.replay(Call::NodeInfo) {
+            CallResult::NodeInfo(result) => {
+                let result: Result<NodeInfoRecord, String> = result.into();
+                Box::new(FutureResult::from(
+                    result.map(Into::into).map_err(NodeInfoError::Unknown),
+                ))
+            }
+            other => mismatched(other),
+        }
+    }
+
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        match self.replay(Call::NewAddress) {
+            CallResult::Address(result) => replayed(result, |address| address),
+            other => mismatched(other),
+        }
+    }
+
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        let mut state = self.state.lock().unwrap();
+        let replayed = state.replayed;
+        state.transactions.subscribe(replayed)
+    }
+
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        match self.replay(Call::EstimateOnchainFee {
+            address,
+            amount,
+            target_blocks,
+        }) {
+            CallResult::OnchainFee(result) => replayed(result, |fee| fee),
+            other => mismatched(other),
+        }
+    }
+
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        match self.replay(Call::SendOnchain {
+            address,
+            amount,
+            target_blocks,
+            max_fee,
+        }) {
+            CallResult::Onchain(result) => replayed(result, Into::into),
+            other => mismatched(other),
+        }
+    }
+
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        match self.replay(Call::CancelInvoice { payment_hash }) {
+            CallResult::Hold(result) => replayed(result, |()| ()),
+            other => mismatched(other),
+        }
+    }
+
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        match self.replay(Call::CreateOffer {
+            amount: spec.amount,
+            description: spec.description,
+        }) {
+            CallResult::Offer(result) => replayed(result, |offer| offer),
+            other => mismatched(other),
+        }
+    }
+
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        let mut state = self.state.lock().unwrap();
+        let replayed = state.replayed;
+        state.offer_invoices.subscribe(replayed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use std::path::PathBuf;
+
+    fn recording_path() -> PathBuf {
+        std::env::temp_dir().join(format!("lapi-recording-{}", U256::random()))
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let path = recording_path();
+        let fake = Arc::new(FakeLightningNode::new());
+        let (invoice, outgoing, settled) = {
+            let node = RecordingNode::create(fake.clone(), &path, FakeLog).unwrap();
+            let mut paid = node.paid_invoices().wait();
+            let invoice = node.create_invoice(Satoshis(5).into()).wait().unwrap();
+            let outgoing = node
+                .pay_invoice(
+                    invoice.clone(),
+                    Satoshis(5),
+                    DEFAULT_FEE,
+                    MultiPath::default(),
+                )
+                .wait()
+                .unwrap();
+            let settled = paid.next().unwrap().unwrap();
+            fake.queue_outcome(PayOutcome::Aborted);
+            match node
+                .pay_invoice(
+                    invoice.clone(),
+                    Satoshis(6),
+                    DEFAULT_FEE,
+                    MultiPath::default(),
+                )
+                .wait()
+            {
+                Err(PayError::PaymentAborted) => {}
+                other => panic!("{:?}", other),
+            }
+            (invoice, outgoing, settled)
+        };
+
+        let replay = ReplayNode::open(&path).unwrap();
+        let mut paid = replay.paid_invoices().wait();
+        assert_eq!(
+            replay.create_invoice(Satoshis(5).into()).wait().unwrap(),
+            invoice
+        );
+        assert_eq!(
+            replay
+                .pay_invoice(
+                    invoice.clone(),
+                    Satoshis(5),
+                    DEFAULT_FEE,
+                    MultiPath::default(),
+                )
+                .wait()
+                .unwrap(),
+            outgoing
+        );
+        assert_eq!(paid.next().unwrap().unwrap(), settled);
+        match replay
+            .pay_invoice(
+                invoice.clone(),
+                Satoshis(6),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unsupported_reason_replayed() {
+        let path = recording_path();
+        let entry = Entry::Call {
+            call: Call::SettleHoldInvoice {
+                preimage: PREIMAGE_A,
+            },
+            result: CallResult::Hold(ResultSerDe::Error(HoldInvoiceErrorRecord::Unsupported(
+                "no hold invoices here".to_owned(),
+            ))),
+        };
+        std::fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+        let replay = ReplayNode::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match replay.settle_hold_invoice(PREIMAGE_A).wait() {
+            Err(HoldInvoiceError::Unsupported("no hold invoices here")) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "no recorded call matches")]
+    fn unrecorded_call_panics() {
+        let path = recording_path();
+        File::create(&path).unwrap();
+        let replay = ReplayNode::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let _ = replay.create_invoice(Satoshis(1).into());
+    }
+
+    #[test]
+    fn events_wait_for_earlier_calls() {
+        let path = recording_path();
+        let fake = Arc::new(FakeLightningNode::new());
+        let invoice = {
+            let node = RecordingNode::create(fake.clone(), &path, FakeLog).unwrap();
+            let mut paid = node.paid_invoices().wait();
+            let invoice = node.create_invoice(Satoshis(1).into()).wait().unwrap();
+            fake.pay_invoice(
+                invoice.clone(),
+                Satoshis(1),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+            paid.next().unwrap().unwrap();
+            invoice
+        };
+
+        let replay = ReplayNode::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let paid = replay.paid_invoices();
+        std::thread::spawn(move || {
+            for item in paid.wait() {
+                tx.send(item).unwrap();
+            }
+        });
+        // the settlement was recorded after create_invoice, so it waits for the replay to get
+        // that far
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        replay.create_invoice(Satoshis(1).into()).wait().unwrap();
+        let settled = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert_eq!(settled.invoice(), &invoice);
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::common::*;
 use core::str::FromStr;
+use secp256k1::Signature;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::Cow;
 use url::Url;
@@ -32,6 +33,8 @@ impl FromStr for UrlSerDe {
     }
 }
 
+/// Bolt11 encoded invoice. Invoices for every network are accepted here; ApiLow refuses to pay
+/// one for a network other than its own.
 #[derive(PartialEq, Clone, Debug)]
 pub struct InvoiceSerDe(pub Invoice);
 
@@ -51,6 +54,45 @@ impl<'de> Deserialize<'de> for InvoiceSerDe {
     }
 }
 
+/// Hex encoded, compressed secp256k1 public key.
+#[derive(PartialEq, Clone, Debug)]
+pub struct PubKeySerDe(pub PublicKey);
+
+impl Serialize for PubKeySerDe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.0.serialize()[..]))
+    }
+}
+
+impl<'de> Deserialize<'de> for PubKeySerDe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = <Cow<str>>::deserialize(deserializer)?;
+        let bytes = hex::decode(&*encoded).map_err(de::Error::custom)?;
+        let pubkey = PublicKey::from_slice(&bytes).map_err(de::Error::custom)?;
+        Ok(PubKeySerDe(pubkey))
+    }
+}
+
+/// Hex encoded, DER secp256k1 signature. A signature that does not decode is refused along with
+/// the rest of the request.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SignatureSerDe(pub Signature);
+
+impl Serialize for SignatureSerDe {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&*self.0.serialize_der()))
+    }
+}
+
+impl<'de> Deserialize<'de> for SignatureSerDe {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = <Cow<str>>::deserialize(deserializer)?;
+        let bytes = hex::decode(&*encoded).map_err(de::Error::custom)?;
+        let sig = Signature::from_der(&bytes).map_err(de::Error::custom)?;
+        Ok(SignatureSerDe(sig))
+    }
+}
+
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ResultSerDe<K, E> {
@@ -0,0 +1,886 @@
+//! A simulated Lightning network for tests. Nodes are joined by channels with balances and fee
+//! policies. Payments between nodes are routed along the cheapest path with enough liquidity,
+//! or split over several paths when no one path has enough, moving balances and charging
+//! forwarding fees along the way.
+//!
+//! Each SimNode wraps a FakeLightningNode, which creates its invoices and reports its
+//! settlements.
+
+use crate::common::*;
+use crate::lighting_node::DynStream;
+use futures::{future::FutureResult, Future};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+/// Fee charged by a node for forwarding a payment over one of its channels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeePolicy {
+    pub base: Satoshis,
+    /// Proportional fee in millionths of the amount forwarded.
+    pub rate_ppm: u64,
+}
+
+impl FeePolicy {
+    pub fn free() -> FeePolicy {
+        FeePolicy {
+            base: Satoshis(0),
+            rate_ppm: 0,
+        }
+    }
+
+    pub fn fee(&self, forwarded: Satoshis) -> Satoshis {
+        // rounded down, as lnd does
+        self.base + Satoshis((forwarded.0 as u128 * self.rate_ppm as u128 / 1_000_000) as u64)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ChannelId(usize);
+
+struct Channel {
+    /// The two ends of the channel, indices into NetworkState::nodes.
+    ends: [usize; 2],
+    /// Satoshis spendable by each end.
+    balances: [Satoshis; 2],
+    /// Fee each end charges to forward over this channel.
+    policies: [FeePolicy; 2],
+}
+
+impl Channel {
+    /// The end of this channel at node, if node is an end.
+    fn side(&self, node: usize) -> Option<usize> {
+        self.ends.iter().position(|&end| end == node)
+    }
+}
+
+#[derive(Default)]
+struct NetworkState {
+    nodes: Vec<Arc<FakeLightningNode>>,
+    channels: Vec<Channel>,
+}
+
+/// One hop of a route. from sends amount to the other end of channel.
+#[derive(Clone, Copy, Debug)]
+struct Hop {
+    channel: usize,
+    from_side: usize,
+    amount: Satoshis,
+}
+
+impl NetworkState {
+    /// The node which can claim payment_hash.
+    fn destination(&self, payment_hash: PaymentHash) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.get_preimage(payment_hash).is_some())
+    }
+
+    /// Find the route from source delivering amount to destination with the lowest total fee.
+    /// Returns the hops in order from source, along with the fee.
+    ///
+    /// Searches backwards from the destination, since the amount a hop must carry depends on the
+    /// fees charged further along the route.
+    fn route(
+        &self,
+        source: usize,
+        destination: usize,
+        amount: Satoshis,
+    ) -> Option<(Vec<Hop>, Satoshis)> {
+        // required[n] is the amount which must arrive at n for the payment to reach the
+        // destination, and the hop n must forward it over.
+        let mut required: Vec<Option<(Satoshis, Option<Hop>)>> = vec![None; self.nodes.len()];
+        let mut done = BTreeSet::new();
+        required[destination] = Some((amount, None));
+
+        loop {
+            let next = (0..self.nodes.len())
+                .filter(|n| !done.contains(n))
+                .filter_map(|n| required[n].map(|(req, _)| (req, n)))
+                .min();
+            let (arriving, node) = match next {
+                Some(next) => next,
+                None => return None,
+            };
+            if node == source {
+                break;
+            }
+            done.insert(node);
+            for (index, channel) in self.channels.iter().enumerate() {
+                let node_side = match channel.side(node) {
+                    Some(side) => side,
+                    None => continue,
+                };
+                let from_side = 1 - node_side;
+                let from = channel.ends[from_side];
+                if done.contains(&from) || channel.balances[from_side] < arriving {
+                    continue;
+                }
+                // the source pays no fee to itself
+                let fee = if from == source {
+                    Satoshis(0)
+                } else {
+                    channel.policies[from_side].fee(arriving)
+                };
+                let candidate = arriving + fee;
+                let better = match required[from] {
+                    Some((existing, _)) => candidate < existing,
+                    None => true,
+                };
+                if better {
+                    let hop = Hop {
+                        channel: index,
+                        from_side,
+                        amount: arriving,
+                    };
+                    required[from] = Some((candidate, Some(hop)));
+                }
+            }
+        }
+
+        let total = required[source]?.0;
+        let mut hops = Vec::new();
+        let mut node = source;
+        while let Some((_, Some(hop))) = required[node] {
+            hops.push(hop);
+            let channel = &self.channels[hop.channel];
+            node = channel.ends[1 - hop.from_side];
+        }
+        Some((hops, total - amount))
+    }
+
+    /// Split amount into at most max_parts parts, each with a route from source to destination.
+    /// Returns the route of each part, along with the total fee. Parts are found by trying the
+    /// whole amount, then halving any part without a route, as lnd does. Each part is routed
+    /// over the liquidity left by the parts before it.
+    fn split(
+        &mut self,
+        source: usize,
+        destination: usize,
+        amount: Satoshis,
+        max_parts: u32,
+    ) -> Option<(Vec<Vec<Hop>>, Satoshis)> {
+        let mut unrouted = vec![amount];
+        let mut routed: Vec<Vec<Hop>> = Vec::new();
+        let mut fee = Satoshis(0);
+        while let Some(part) = unrouted.pop() {
+            match self.route(source, destination, part) {
+                Some((hops, part_fee)) => {
+                    // hold the liquidity so later parts route around it
+                    self.commit(&hops);
+                    routed.push(hops);
+                    fee = fee + part_fee;
+                }
+                None if part.0 >= 2 && routed.len() + unrouted.len() + 2 <= max_parts as usize => {
+                    let half = part / Satoshis(2);
+                    unrouted.push(part - half);
+                    unrouted.push(half);
+                }
+                None => {
+                    unrouted.push(part);
+                    break;
+                }
+            }
+        }
+        for hops in routed.iter().rev() {
+            self.revert(hops);
+        }
+        if unrouted.is_empty() {
+            Some((routed, fee))
+        } else {
+            None
+        }
+    }
+
+    /// Move liquidity along every part, or none of them.
+    fn commit_parts(&mut self, parts: &[Vec<Hop>]) -> bool {
+        for (index, hops) in parts.iter().enumerate() {
+            if !self.commit(hops) {
+                for hops in parts[..index].iter().rev() {
+                    self.revert(hops);
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Undo commit(hops).
+    fn revert(&mut self, hops: &[Hop]) {
+        for hop in hops {
+            let channel = &mut self.channels[hop.channel];
+            channel.balances[1 - hop.from_side] = channel.balances[1 - hop.from_side] - hop.amount;
+            channel.balances[hop.from_side] = channel.balances[hop.from_side] + hop.amount;
+        }
+    }
+
+    /// Move liquidity along hops. Fails without moving anything if a hop lacks the balance,
+    /// e.g. because another payment used it since the route was found.
+    fn commit(&mut self, hops: &[Hop]) -> bool {
+        let sufficient = hops
+            .iter()
+            .all(|hop| self.channels[hop.channel].balances[hop.from_side] >= hop.amount);
+        if sufficient {
+            for hop in hops {
+                let channel = &mut self.channels[hop.channel];
+                channel.balances[hop.from_side] = channel.balances[hop.from_side] - hop.amount;
+                channel.balances[1 - hop.from_side] =
+                    channel.balances[1 - hop.from_side] + hop.amount;
+            }
+        }
+        sufficient
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct SimNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl SimNetwork {
+    pub fn new() -> SimNetwork {
+        SimNetwork::default()
+    }
+
+    pub fn add_node(&self) -> SimNode {
+        let node = Arc::new(FakeLightningNode::new());
+        let mut state = self.state.lock().unwrap();
+        state.nodes.push(node.clone());
+        SimNode {
+            index: state.nodes.len() - 1,
+            node,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Open a channel between a and b, with a_balance spendable by a and b_balance spendable
+    /// by b. Both ends charge policy to forward over the channel.
+    pub fn open_channel(
+        &self,
+        a: &SimNode,
+        b: &SimNode,
+        a_balance: Satoshis,
+        b_balance: Satoshis,
+        policy: FeePolicy,
+    ) -> ChannelId {
+        let mut state = self.state.lock().unwrap();
+        state.channels.push(Channel {
+            ends: [a.index, b.index],
+            balances: [a_balance, b_balance],
+            policies: [policy, policy],
+        });
+        ChannelId(state.channels.len() - 1)
+    }
+
+    /// Set the fee node charges to forward over channel.
+    pub fn set_policy(&self, channel: ChannelId, node: &SimNode, policy: FeePolicy) {
+        let mut state = self.state.lock().unwrap();
+        let channel = &mut state.channels[channel.0];
+        let side = channel
+            .side(node.index)
+            .expect("node is not an end of channel");
+        channel.policies[side] = policy;
+    }
+
+    /// The balance node can spend over channel.
+    pub fn balance(&self, channel: ChannelId, node: &SimNode) -> Satoshis {
+        let state = self.state.lock().unwrap();
+        let channel = &state.channels[channel.0];
+        let side = channel
+            .side(node.index)
+            .expect("node is not an end of channel");
+        channel.balances[side]
+    }
+}
+
+/// A node in a SimNetwork.
+pub struct SimNode {
+    index: usize,
+    node: Arc<FakeLightningNode>,
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl SimNode {
+    /// The underlying fake, for scripting outcomes or simulating incoming payments.
+    pub fn fake(&self) -> &FakeLightningNode {
+        &self.node
+    }
+}
+
+impl LightningNode for SimNode {
+    fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+        self.node.create_invoice(spec)
+    }
+
+    /// A payment with no single route carrying the whole amount is split, within
+    /// multi_path.max_parts. If only some of the parts can be routed, the payment is aborted
+    /// and no liquidity moves.
+    fn pay_invoice(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+        multi_path: MultiPath,
+    ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+        let mut state = self.state.lock().unwrap();
+        let destination = match state.destination(get_payment_hash(&invoice)) {
+            Some(destination) => destination,
+            None => return Box::new(FutureResult::from(Err(PayError::PaymentAborted))),
+        };
+        if destination == self.index {
+            return self.node.pay_invoice(invoice, amount, max_fee, multi_path);
+        }
+        let (parts, fee) = match state.split(self.index, destination, amount, multi_path.max_parts)
+        {
+            Some((parts, fee)) if fee <= max_fee.0 => (parts, fee),
+            // no route, or none cheap enough
+            _ => return Box::new(FutureResult::from(Err(PayError::PaymentAborted))),
+        };
+        let payee = state.nodes[destination].clone();
+        drop(state);
+
+        // The payee settles the invoice, reporting it on its own paid_invoices.
+        let shared = self.state.clone();
+        Box::new(
+            payee
+                .pay_invoice(invoice, amount, Fee(Satoshis(0)), multi_path)
+                .and_then(move |settled| {
+                    if shared.lock().unwrap().commit_parts(&parts) {
+                        Ok(PaidInvoiceOutgoing {
+                            paid_invoice: settled.paid_invoice,
+                            fees_offered: max_fee,
+                            fees_paid: Fee(fee),
+                            parts: parts.len() as u32,
+                        })
+                    } else {
+                        Err(PayError::Unknown(
+                            "liquidity used by a concurrent payment".to_owned(),
+                        ))
+                    }
+                }),
+        )
+    }
+
+    fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+        self.node.paid_invoices()
+    }
+
+    /// Keysends are not routed, they are delivered without moving liquidity.
+    fn keysend(
+        &self,
+        pubkey: PublicKey,
+        amount: Satoshis,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<KeysendOutgoing, PayError> {
+        self.node.keysend(pubkey, amount, max_fee)
+    }
+
+    fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+        self.node.received_keysends()
+    }
+
+    fn create_hold_invoice(
+        &self,
+        spec: InvoiceSpec,
+        payment_hash: PaymentHash,
+    ) -> DynFut<Invoice, CreateInvoiceError> {
+        self.node.create_hold_invoice(spec, payment_hash)
+    }
+
+    fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+        self.node.settle_hold_invoice(preimage)
+    }
+
+    fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        self.node.cancel_hold_invoice(payment_hash)
+    }
+
+    fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+        self.node.accepted_invoices()
+    }
+
+    /// Payments always take the cheapest route, so the expected fee is also the worst case.
+    fn estimate_fee(
+        &self,
+        invoice: Invoice,
+        amount: Satoshis,
+    ) -> DynFut<FeeQuote, EstimateFeeError> {
+        let state = self.state.lock().unwrap();
+        let destination = state.destination(get_payment_hash(&invoice));
+        if destination == Some(self.index) {
+            return self.node.estimate_fee(invoice, amount);
+        }
+        let quote = destination
+            .and_then(|destination| state.route(self.index, destination, amount))
+            .map(|(_, fee)| FeeQuote {
+                expected: Fee(fee),
+                worst_case: Fee(fee),
+            })
+            .ok_or(EstimateFeeError::NoRoute);
+        Box::new(FutureResult::from(quote))
+    }
+
+    /// Capacity is the sum of balances over this node's channels.
+    fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+        let state = self.state.lock().unwrap();
+        let mut capacity = Capacity {
+            inbound: Satoshis(0),
+            outbound: Satoshis(0),
+        };
+        for channel in &state.channels {
+            if let Some(side) = channel.side(self.index) {
+                capacity.outbound = capacity.outbound + channel.balances[side];
+                capacity.inbound = capacity.inbound + channel.balances[1 - side];
+            }
+        }
+        let alias = format!("sim-{}", self.index);
+        Box::new(self.node.node_info().map(move |info| NodeInfo {
+            alias,
+            capacity,
+            ..info
+        }))
+    }
+
+    /// The chain is not simulated, deposits are made with the fake's simulate_deposit.
+    fn new_address(&self) -> DynFut<Address, NewAddressError> {
+        self.node.new_address()
+    }
+
+    fn incoming_transactions(&self) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+        self.node.incoming_transactions()
+    }
+
+    fn estimate_onchain_fee(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+    ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+        self.node
+            .estimate_onchain_fee(address, amount, target_blocks)
+    }
+
+    fn send_onchain(
+        &self,
+        address: Address,
+        amount: Satoshis,
+        target_blocks: u32,
+        max_fee: Fee<Satoshis>,
+    ) -> DynFut<OnchainOutgoing, PayError> {
+        self.node
+            .send_onchain(address, amount, target_blocks, max_fee)
+    }
+
+    fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+        self.node.cancel_invoice(payment_hash)
+    }
+
+    fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+        self.node.create_offer(spec)
+    }
+
+    fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+        self.node.offer_invoices()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake_log::MemLog;
+    use crate::test_util::*;
+    use futures::Stream;
+
+    fn ppm(rate_ppm: u64) -> FeePolicy {
+        FeePolicy {
+            base: Satoshis(1),
+            rate_ppm,
+        }
+    }
+
+    fn invoice(node: &SimNode, satoshis: u64) -> Invoice {
+        node.create_invoice(Satoshis(satoshis).into())
+            .wait()
+            .unwrap()
+    }
+
+    crate::conformance::conformance_tests!({
+        let network = SimNetwork::new();
+        let payer = Arc::new(network.add_node());
+        let hub = network.add_node();
+        let node = Arc::new(network.add_node());
+        network.open_channel(&payer, &hub, Satoshis(1_000_000), Satoshis(0), ppm(1000));
+        network.open_channel(&hub, &node, Satoshis(1_000_000), Satoshis(0), ppm(1000));
+        (node, payer)
+    });
+
+    #[test]
+    fn direct_payment_moves_liquidity() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let b = network.add_node();
+        let ab = network.open_channel(&a, &b, Satoshis(100), Satoshis(0), ppm(1000));
+        let paid = b.paid_invoices().wait();
+        let outgoing = a
+            .pay_invoice(
+                invoice(&b, 40),
+                Satoshis(40),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        // no forwarding nodes, no fee
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(0)));
+        assert_eq!(network.balance(ab, &a), Satoshis(60));
+        assert_eq!(network.balance(ab, &b), Satoshis(40));
+        let settled = paid.take(1).next().unwrap().unwrap();
+        assert_eq!(settled.amount_paid(), &Satoshis(40));
+    }
+
+    #[test]
+    fn cheapest_route_chosen() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let cheap = network.add_node();
+        let pricey = network.add_node();
+        let c = network.add_node();
+        let a_cheap = network.open_channel(&a, &cheap, Satoshis(5000), Satoshis(0), ppm(0));
+        network.open_channel(&cheap, &c, Satoshis(5000), Satoshis(0), ppm(10_000));
+        let a_pricey = network.open_channel(&a, &pricey, Satoshis(5000), Satoshis(0), ppm(0));
+        network.open_channel(&pricey, &c, Satoshis(5000), Satoshis(0), ppm(50_000));
+
+        let outgoing = a
+            .pay_invoice(
+                invoice(&c, 1000),
+                Satoshis(1000),
+                Fee(Satoshis(100)),
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        // base 1 + 1% of 1000
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(11)));
+        assert_eq!(network.balance(a_cheap, &a), Satoshis(5000 - 1011));
+        assert_eq!(network.balance(a_pricey, &a), Satoshis(5000));
+    }
+
+    #[test]
+    fn fee_policy_per_direction() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let hub = network.add_node();
+        let c = network.add_node();
+        network.open_channel(&a, &hub, Satoshis(5000), Satoshis(5000), FeePolicy::free());
+        let hub_c =
+            network.open_channel(&hub, &c, Satoshis(5000), Satoshis(5000), FeePolicy::free());
+        network.set_policy(hub_c, &hub, ppm(0));
+        let outgoing = a
+            .pay_invoice(
+                invoice(&c, 100),
+                Satoshis(100),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(1)));
+        // hub forwards back over the free channel to a
+        let outgoing = c
+            .pay_invoice(
+                invoice(&a, 100),
+                Satoshis(100),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(0)));
+    }
+
+    #[test]
+    fn quote_matches_payment() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let hub = network.add_node();
+        let c = network.add_node();
+        network.open_channel(&a, &hub, Satoshis(5000), Satoshis(0), ppm(0));
+        network.open_channel(&hub, &c, Satoshis(5000), Satoshis(0), ppm(10_000));
+        match c.estimate_fee(invoice(&a, 1), Satoshis(1)).wait() {
+            Err(EstimateFeeError::NoRoute) => {}
+            other => panic!("{:?}", other),
+        }
+        let to_c = invoice(&c, 1000);
+        let quote = a.estimate_fee(to_c.clone(), Satoshis(1000)).wait().unwrap();
+        assert_eq!(quote.expected, Fee(Satoshis(11)));
+        let outgoing = a
+            .pay_invoice(to_c, Satoshis(1000), quote.worst_case, MultiPath::default())
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.fees_paid, quote.expected);
+    }
+
+    #[test]
+    fn capacity_follows_liquidity() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let b = network.add_node();
+        let c = network.add_node();
+        network.open_channel(&a, &b, Satoshis(100), Satoshis(20), FeePolicy::free());
+        network.open_channel(&c, &a, Satoshis(5), Satoshis(7), FeePolicy::free());
+        let capacity = |node: &SimNode| node.node_info().wait().unwrap().capacity;
+        assert_eq!(
+            capacity(&a),
+            Capacity {
+                inbound: Satoshis(25),
+                outbound: Satoshis(107),
+            }
+        );
+        a.pay_invoice(
+            invoice(&b, 30),
+            Satoshis(30),
+            DEFAULT_FEE,
+            MultiPath::default(),
+        )
+        .wait()
+        .unwrap();
+        assert_eq!(
+            capacity(&a),
+            Capacity {
+                inbound: Satoshis(55),
+                outbound: Satoshis(77),
+            }
+        );
+    }
+
+    #[test]
+    fn insufficient_liquidity_aborted() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let b = network.add_node();
+        let ab = network.open_channel(&a, &b, Satoshis(10), Satoshis(1000), FeePolicy::free());
+        match a
+            .pay_invoice(
+                invoice(&b, 11),
+                Satoshis(11),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(network.balance(ab, &a), Satoshis(10));
+        // b's side of the channel can't be used to pay b
+        network.open_channel(&a, &b, Satoshis(0), Satoshis(1000), FeePolicy::free());
+        match a
+            .pay_invoice(
+                invoice(&b, 11),
+                Satoshis(11),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    /// a reaches c through two hubs, each path able to carry 60.
+    fn two_paths() -> (SimNetwork, SimNode, SimNode, [ChannelId; 4]) {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let one = network.add_node();
+        let two = network.add_node();
+        let c = network.add_node();
+        let channels = [
+            network.open_channel(&a, &one, Satoshis(60), Satoshis(0), ppm(0)),
+            network.open_channel(&one, &c, Satoshis(60), Satoshis(0), ppm(0)),
+            network.open_channel(&a, &two, Satoshis(60), Satoshis(0), ppm(0)),
+            network.open_channel(&two, &c, Satoshis(60), Satoshis(0), ppm(0)),
+        ];
+        (network, a, c, channels)
+    }
+
+    #[test]
+    fn payment_split_over_paths() {
+        let (network, a, c, [a_one, one_c, a_two, two_c]) = two_paths();
+        let outgoing = a
+            .pay_invoice(
+                invoice(&c, 100),
+                Satoshis(100),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.parts, 2);
+        // a base fee of 1 at each hub
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(2)));
+        assert_eq!(network.balance(a_one, &a), Satoshis(60 - 51));
+        assert_eq!(network.balance(a_two, &a), Satoshis(60 - 51));
+        assert_eq!(network.balance(one_c, &c), Satoshis(50));
+        assert_eq!(network.balance(two_c, &c), Satoshis(50));
+    }
+
+    #[test]
+    fn split_limited_by_max_parts() {
+        let (network, a, c, [a_one, _, a_two, _]) = two_paths();
+        let single = MultiPath {
+            max_parts: 1,
+            ..MultiPath::default()
+        };
+        match a
+            .pay_invoice(invoice(&c, 100), Satoshis(100), DEFAULT_FEE, single)
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(network.balance(a_one, &a), Satoshis(60));
+        assert_eq!(network.balance(a_two, &a), Satoshis(60));
+    }
+
+    #[test]
+    fn partly_routed_payment_moves_nothing() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let one = network.add_node();
+        let two = network.add_node();
+        let c = network.add_node();
+        // the two paths can carry 60 and 20, less fees
+        let channels = [
+            (
+                network.open_channel(&a, &one, Satoshis(60), Satoshis(0), ppm(0)),
+                &a,
+            ),
+            (
+                network.open_channel(&one, &c, Satoshis(60), Satoshis(0), ppm(0)),
+                &one,
+            ),
+            (
+                network.open_channel(&a, &two, Satoshis(20), Satoshis(0), ppm(0)),
+                &a,
+            ),
+            (
+                network.open_channel(&two, &c, Satoshis(20), Satoshis(0), ppm(0)),
+                &two,
+            ),
+        ];
+        let balances = || -> Vec<Satoshis> {
+            channels
+                .iter()
+                .map(|(channel, from)| network.balance(*channel, from))
+                .collect()
+        };
+        match a
+            .pay_invoice(
+                invoice(&c, 90),
+                Satoshis(90),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            balances(),
+            vec![Satoshis(60), Satoshis(60), Satoshis(20), Satoshis(20)]
+        );
+    }
+
+    #[test]
+    fn no_route_aborted() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let b = network.add_node();
+        match a
+            .pay_invoice(
+                invoice(&b, 1),
+                Satoshis(1),
+                DEFAULT_FEE,
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn fee_above_max_aborted() {
+        let network = SimNetwork::new();
+        let a = network.add_node();
+        let hub = network.add_node();
+        let c = network.add_node();
+        network.open_channel(&a, &hub, Satoshis(5000), Satoshis(0), ppm(0));
+        network.open_channel(&hub, &c, Satoshis(5000), Satoshis(0), ppm(100_000));
+        match a
+            .pay_invoice(
+                invoice(&c, 1000),
+                Satoshis(1000),
+                Fee(Satoshis(100)),
+                MultiPath::default(),
+            )
+            .wait()
+        {
+            Err(PayError::PaymentAborted) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn account_pays_across_network() {
+        let network = SimNetwork::new();
+        let ours = network.add_node();
+        let hub = network.add_node();
+        let theirs = network.add_node();
+        network.open_channel(&ours, &hub, Satoshis(5000), Satoshis(0), ppm(0));
+        network.open_channel(&hub, &theirs, Satoshis(5000), Satoshis(0), ppm(10_000));
+        let foreign = invoice(&theirs, 100);
+        let api = ApiLow::create(db_with_account_a_balance(), ours, MemLog::new());
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        let outgoing = api
+            .pay_invoice(ACCOUNT_A, foreign, Satoshis(100), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.fees_paid, Fee(Satoshis(2)));
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance - Satoshis(102)
+        );
+    }
+
+    #[test]
+    fn account_payment_split_within_configured_parts() {
+        let (_network, a, c, _) = two_paths();
+        let to_c = invoice(&c, 100);
+        let api = ApiLow::create(db_with_account_a_balance(), a, MemLog::new());
+        let initial_a_balance = api.check_balance(ACCOUNT_A.into()).wait().unwrap();
+        api.set_multi_path(MultiPath {
+            max_parts: 1,
+            ..MultiPath::default()
+        });
+        match api
+            .pay_invoice(ACCOUNT_A, to_c.clone(), Satoshis(100), DEFAULT_FEE)
+            .wait()
+        {
+            Err(PayInvoiceError::Pay(PayError::PaymentAborted)) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            api.check_balance(ACCOUNT_A.into()).wait().unwrap(),
+            initial_a_balance
+        );
+        api.set_multi_path(MultiPath::default());
+        let outgoing = api
+            .pay_invoice(ACCOUNT_A, to_c, Satoshis(100), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_eq!(outgoing.parts, 2);
+    }
+}
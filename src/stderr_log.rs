@@ -0,0 +1,10 @@
+use crate::common::{Log, LogErr};
+
+/// Writes errors to stderr. Used by the server, where panicking would stop background tasks.
+pub struct StderrLog;
+
+impl Log for StderrLog {
+    fn _err(&self, err: LogErr) {
+        eprintln!("{:#?}", err);
+    }
+}
@@ -0,0 +1,398 @@
+//! Keeps long lived subscriptions to the lightning node alive.
+//!
+//! A subscription is run on its own thread. When the subscription errors or ends, the
+//! error is logged and the subscription is re-established after an exponential backoff.
+//! The thread exits once the lightning node it subscribes to has been dropped.
+
+use crate::common::*;
+use crate::lighting_node::DynStream;
+use futures::{Future, Stream};
+use std::cmp::min;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Delay between reconnection attempts. The delay doubles after each consecutive failure, up to
+/// max. A subscription that delivers an item is considered healthy and the delay is reset.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Time to wait before making reconnection attempt number `attempt`, starting at 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.checked_pow(attempt).unwrap_or(u32::max_value());
+        self.initial
+            .checked_mul(factor)
+            .map(|delay| min(delay, self.max))
+            .unwrap_or(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SubscriptionHealth {
+    /// The subscription is established and waiting for items.
+    Subscribed { since: SystemTime },
+    /// The subscription failed `attempt` times in a row. It will be re-established after a
+    /// backoff.
+    Reconnecting { attempt: u32, since: SystemTime },
+    /// The lightning node was dropped. The subscription will not be re-established.
+    Stopped,
+}
+
+impl SubscriptionHealth {
+    pub fn is_healthy(&self) -> bool {
+        match self {
+            SubscriptionHealth::Subscribed { .. } => true,
+            SubscriptionHealth::Reconnecting { .. } | SubscriptionHealth::Stopped => false,
+        }
+    }
+}
+
+/// Shared view of a supervised subscription's health.
+#[derive(Clone, Debug)]
+pub struct HealthMonitor(Arc<Mutex<SubscriptionHealth>>);
+
+impl HealthMonitor {
+    fn new() -> HealthMonitor {
+        HealthMonitor(Arc::new(Mutex::new(SubscriptionHealth::Reconnecting {
+            attempt: 0,
+            since: SystemTime::now(),
+        })))
+    }
+
+    pub fn get(&self) -> SubscriptionHealth {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, health: SubscriptionHealth) {
+        *self.0.lock().unwrap() = health;
+    }
+}
+
+/// Spawn a thread which repeatedly calls subscribe and passes every item from the resulting
+/// stream to handle. Errors from the stream and from handle are logged to log.
+///
+/// name identifies the subscription in logs.
+pub fn supervise<L, T, E, R, S, H, G>(
+    name: &'static str,
+    node: Weak<L>,
+    subscribe: S,
+    handle: H,
+    log: G,
+    backoff: Backoff,
+) -> HealthMonitor
+where
+    L: LightningNode + 'static,
+    T: Send + 'static,
+    E: ServerError + Send + 'static,
+    R: ServerError + Send + 'static,
+    S: Fn(&L) -> DynStream<T, E> + Send + 'static,
+    H: Fn(T) -> DynFut<(), R> + Send + 'static,
+    G: Log + 'static,
+{
+    let health = HealthMonitor::new();
+    let health2 = health.clone();
+    thread::spawn(move || {
+        let health = health2;
+        let mut attempt: u32 = 0;
+        loop {
+            // Only hold a strong reference to node while subscribing so the node is dropped
+            // along with its owner.
+            let stream = match node.upgrade() {
+                Some(node) => subscribe(&node),
+                None => break,
+            };
+            health.set(SubscriptionHealth::Subscribed {
+                since: SystemTime::now(),
+            });
+
+            let log = &log;
+            let result = stream
+                .for_each(|item| {
+                    attempt = 0;
+                    handle(item).then(move |res| {
+                        if let Err(err) = res {
+                            err.log(log);
+                        }
+                        Ok::<(), E>(())
+                    })
+                })
+                .wait();
+
+            if node.upgrade().is_none() {
+                // The stream was closed because the node was dropped.
+                break;
+            }
+            health.set(SubscriptionHealth::Reconnecting {
+                attempt: attempt + 1,
+                since: SystemTime::now(),
+            });
+            match result {
+                Ok(()) => {
+                    log.err(LogErr::SubscriptionClosed(name));
+                }
+                Err(err) => {
+                    err.log(log);
+                }
+            }
+            thread::sleep(backoff.delay(attempt));
+            attempt = attempt.saturating_add(1);
+        }
+        health.set(SubscriptionHealth::Stopped);
+    });
+    health
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fake_log::MemLog;
+    use crate::test_util::*;
+    use futures::stream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const FAST: Backoff = Backoff {
+        initial: Duration::from_millis(1),
+        max: Duration::from_millis(8),
+    };
+
+    /// Fails the first `failures` calls to paid_invoices, then behaves like FakeLightningNode.
+    struct FlakyNode {
+        inner: FakeLightningNode,
+        failures: AtomicUsize,
+    }
+
+    impl LightningNode for FlakyNode {
+        fn create_invoice(&self, spec: InvoiceSpec) -> DynFut<Invoice, CreateInvoiceError> {
+            self.inner.create_invoice(spec)
+        }
+
+        fn pay_invoice(
+            &self,
+            invoice: Invoice,
+            amount: Satoshis,
+            max_fee: Fee<Satoshis>,
+            multi_path: MultiPath,
+        ) -> DynFut<PaidInvoiceOutgoing, PayError> {
+            self.inner.pay_invoice(invoice, amount, max_fee, multi_path)
+        }
+
+        fn paid_invoices(&self) -> DynStream<PaidInvoice, SubscribePaidInvoicesError> {
+            let remaining = self.failures.load(Ordering::SeqCst);
+            if remaining > 0 {
+                self.failures.store(remaining - 1, Ordering::SeqCst);
+                Box::new(stream::once(Err(SubscribePaidInvoicesError::Unknown(
+                    "connection reset".to_owned(),
+                ))))
+            } else {
+                self.inner.paid_invoices()
+            }
+        }
+
+        fn keysend(
+            &self,
+            pubkey: PublicKey,
+            amount: Satoshis,
+            max_fee: Fee<Satoshis>,
+        ) -> DynFut<KeysendOutgoing, PayError> {
+            self.inner.keysend(pubkey, amount, max_fee)
+        }
+
+        fn received_keysends(&self) -> DynStream<ReceivedKeysend, SubscribePaidInvoicesError> {
+            self.inner.received_keysends()
+        }
+
+        fn create_hold_invoice(
+            &self,
+            spec: InvoiceSpec,
+            payment_hash: PaymentHash,
+        ) -> DynFut<Invoice, CreateInvoiceError> {
+            self.inner.create_hold_invoice(spec, payment_hash)
+        }
+
+        fn settle_hold_invoice(&self, preimage: Preimage) -> DynFut<(), HoldInvoiceError> {
+            self.inner.settle_hold_invoice(preimage)
+        }
+
+        fn cancel_hold_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+            self.inner.cancel_hold_invoice(payment_hash)
+        }
+
+        fn accepted_invoices(&self) -> DynStream<PaymentHash, SubscribePaidInvoicesError> {
+            self.inner.accepted_invoices()
+        }
+
+        fn estimate_fee(
+            &self,
+            invoice: Invoice,
+            amount: Satoshis,
+        ) -> DynFut<FeeQuote, EstimateFeeError> {
+            self.inner.estimate_fee(invoice, amount)
+        }
+
+        fn node_info(&self) -> DynFut<NodeInfo, NodeInfoError> {
+            self.inner.node_info()
+        }
+
+        fn new_address(&self) -> DynFut<Address, NewAddressError> {
+            self.inner.new_address()
+        }
+
+        fn incoming_transactions(
+            &self,
+        ) -> DynStream<IncomingTransaction, SubscribePaidInvoicesError> {
+            self.inner.incoming_transactions()
+        }
+
+        fn estimate_onchain_fee(
+            &self,
+            address: Address,
+            amount: Satoshis,
+            target_blocks: u32,
+        ) -> DynFut<Fee<Satoshis>, EstimateOnchainFeeError> {
+            self.inner
+                .estimate_onchain_fee(address, amount, target_blocks)
+        }
+
+        fn send_onchain(
+            &self,
+            address: Address,
+            amount: Satoshis,
+            target_blocks: u32,
+            max_fee: Fee<Satoshis>,
+        ) -> DynFut<OnchainOutgoing, PayError> {
+            self.inner
+                .send_onchain(address, amount, target_blocks, max_fee)
+        }
+
+        fn cancel_invoice(&self, payment_hash: PaymentHash) -> DynFut<(), HoldInvoiceError> {
+            self.inner.cancel_invoice(payment_hash)
+        }
+
+        fn create_offer(&self, spec: OfferSpec) -> DynFut<Offer, CreateOfferError> {
+            self.inner.create_offer(spec)
+        }
+
+        fn offer_invoices(&self) -> DynStream<OfferInvoice, SubscribePaidInvoicesError> {
+            self.inner.offer_invoices()
+        }
+    }
+
+    fn wait_for_balance<D: Db, L: LightningNode>(api: &ApiLow<D, L>, middle: Middle) -> Satoshis {
+        for _ in 0..500 {
+            if let Ok(balance) = api.check_balance(middle).wait() {
+                return balance;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("balance was never credited");
+    }
+
+    #[test]
+    fn backoff_doubles_then_caps() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(u32::max_value()), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reconnects_after_errors() {
+        let log = Arc::new(MemLog::new());
+        let node = FlakyNode {
+            inner: FakeLightningNode::new(),
+            failures: AtomicUsize::new(3),
+        };
+        let api = ApiLow::create_with_backoff(db_with_account_a_balance(), node, log.clone(), FAST);
+        let acct_b = Master::random();
+        let invoice = api
+            .generate_invoice(acct_b.into(), Satoshis(1).into())
+            .wait()
+            .unwrap();
+
+        // wait until the subscription has recovered before paying
+        for _ in 0..500 {
+            if api.paid_invoice_subscription().get().is_healthy() && log.errors().len() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(api.paid_invoice_subscription().get().is_healthy());
+
+        api.pay_invoice(ACCOUNT_A, invoice, Satoshis(1), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+        assert_eq!(wait_for_balance(&api, acct_b.into()), Satoshis(1));
+
+        let errors = log.errors();
+        assert_eq!(errors.len(), 3);
+        for err in errors {
+            match err {
+                LogErr::SubscribePaidInvoices(_) => {}
+                other => panic!("unexpected error logged {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn processing_errors_are_logged() {
+        let log = Arc::new(MemLog::new());
+        let node = FakeLightningNode::new();
+
+        // create an invoice that is never stored in the db
+        let orphan = node.create_invoice(Satoshis(1).into()).wait().unwrap();
+
+        let api = ApiLow::create(db_with_account_a_balance(), node, log.clone());
+        api.pay_invoice(ACCOUNT_A, orphan, Satoshis(1), DEFAULT_FEE)
+            .wait()
+            .unwrap();
+
+        for _ in 0..500 {
+            if !log.errors().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        match log.errors().as_slice() {
+            [LogErr::ReceivePaidInvoice(ReceivePaidInvoiceErr::NoMatch(_))] => {}
+            other => panic!("unexpected errors logged {:?}", other),
+        }
+        assert!(api.paid_invoice_subscription().get().is_healthy());
+    }
+
+    #[test]
+    fn stops_when_node_is_dropped() {
+        let api = ApiLow::create_with_backoff(
+            FakeDb::new(),
+            FakeLightningNode::new(),
+            MemLog::new(),
+            FAST,
+        );
+        let health = api.paid_invoice_subscription();
+        drop(api);
+        for _ in 0..500 {
+            if health.get() == SubscriptionHealth::Stopped {
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("subscription thread did not stop");
+    }
+}
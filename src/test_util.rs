@@ -23,4 +23,41 @@ mod util {
         "f0edb8af31c411108c86c7145fdedd0d3714bc3f030c49245d70748d112e5262";
 
     pub use crate::fake_db::db_with_account_a_balance;
+
+    /// Public key of some node other than ours.
+    pub fn pubkey_b() -> PublicKey {
+        let secret = secp256k1::key::SecretKey::from_slice(&[0xbb; 32]).unwrap();
+        PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), &secret)
+    }
+
+    /// A local stand-in for another service's http server, serving on listener. respond maps
+    /// the path and query of each request to the json body of the response.
+    pub fn stand_in_server(
+        listener: std::net::TcpListener,
+        respond: impl Fn(&str) -> String + Send + 'static,
+    ) {
+        use std::io::{BufRead, BufReader, Write};
+
+        std::thread::spawn(move || {
+            for mut conn in listener.incoming().filter_map(Result::ok) {
+                let mut lines = BufReader::new(conn.try_clone().unwrap()).lines();
+                // "GET <path and query> HTTP/1.1"
+                let target = match lines.next() {
+                    Some(Ok(line)) => line.split_whitespace().nth(1).unwrap_or("").to_owned(),
+                    _ => continue,
+                };
+                // skip the headers
+                lines
+                    .take_while(|line| line.as_ref().map(|l| !l.is_empty()).unwrap_or(false))
+                    .for_each(drop);
+                let body = respond(&target);
+                let _ = write!(
+                    conn,
+                    "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+    }
 }
@@ -1,8 +1,14 @@
 use crate::common::*;
 use futures::{Future, Sink};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
 use warp::{
+    delete2,
     filters::{
         body::{content_length_limit, json as filter_json},
         ws::{ws2, Message, WebSocket, Ws2},
@@ -13,22 +19,118 @@ use warp::{
     Filter,
 };
 
+/// Serve using Core Lightning when LAPI_CLN_RPC names its rpc socket, otherwise lnd. With lnd,
+/// setting LAPI_PRIVATE_ROUTE_HINTS adds hints for private channels to every invoice.
+/// LAPI_NETWORK names the bitcoin network the node is on, mainnet unless set. LAPI_MACAROON names
+/// the macaroon to authenticate to lnd with, by default admin.macaroon. lapi won't start if it
+/// lacks any permission in lnd_client::required_permissions. Neither backend answers BOLT12
+/// invoice requests, so POST /offer is refused as unsupported.
 pub fn serve() -> Result<(), ServeError> {
-    let api_low = ApiLow::create(
-        FakeDb::new(),
-        init_default_lightning_client().map_err(ServeError::Create)?,
-    );
+    let network = match std::env::var_os("LAPI_NETWORK") {
+        Some(name) => match name.to_str().and_then(|n| n.parse().ok()) {
+            Some(network) => network,
+            None => return Err(ServeError::Network(name)),
+        },
+        None => Network::default(),
+    };
+    match std::env::var_os("LAPI_CLN_RPC") {
+        Some(socket) => {
+            let pay_index =
+                std::env::var_os("LAPI_CLN_PAY_INDEX").unwrap_or_else(|| "cln_pay_index".into());
+            serve_recorded(ClnClient::new(socket, pay_index), network)
+        }
+        None => {
+            let macaroon = std::env::var_os("LAPI_MACAROON").map(PathBuf::from);
+            let node = init_network_lightning_client(network, macaroon.as_ref().map(|m| &**m))
+                .map_err(ServeError::Create)?;
+            node.set_private_route_hints(std::env::var_os("LAPI_PRIVATE_ROUTE_HINTS").is_some());
+            serve_recorded(node, network)
+        }
+    }
+}
+
+/// When LAPI_RECORD names a file, record the node's behaviour there for later replay.
+fn serve_recorded<L: LightningNode + 'static>(
+    lighting_node: L,
+    network: Network,
+) -> Result<(), ServeError> {
+    match std::env::var_os("LAPI_RECORD") {
+        Some(path) => serve_with(
+            RecordingNode::create(lighting_node, path, StderrLog).map_err(ServeError::Record)?,
+            network,
+        ),
+        None => serve_with(lighting_node, network),
+    }
+}
+
+/// LAPI_CONFIRMATIONS sets how many confirmations an on-chain deposit needs to be credited.
+/// LAPI_PUBLIC_URL is where wallets reach this server, it determines the domain of Lightning
+/// Addresses. Setting LAPI_ALLOW_PRIVATE_LNURL allows paying LNURL services on this machine or
+/// its private networks.
+fn serve_with<L: LightningNode + 'static>(
+    lighting_node: L,
+    network: Network,
+) -> Result<(), ServeError> {
+    let api_low = ApiLow::create(FakeDb::new(), lighting_node, StderrLog);
+    api_low.set_network(network);
+    api_low.set_allow_private_lnurl(std::env::var_os("LAPI_ALLOW_PRIVATE_LNURL").is_some());
+    if let Some(confirmations) =
+        env_number("LAPI_CONFIRMATIONS").map_err(ServeError::Confirmations)?
+    {
+        api_low.set_required_confirmations(confirmations);
+    }
+    let default = MultiPath::default();
+    api_low.set_multi_path(MultiPath {
+        max_parts: env_number("LAPI_MAX_PARTS")
+            .map_err(ServeError::MaxParts)?
+            .unwrap_or(default.max_parts),
+        timeout: env_number("LAPI_PAY_TIMEOUT")
+            .map_err(ServeError::PayTimeout)?
+            .map(Duration::from_secs)
+            .unwrap_or(default.timeout),
+    });
+    let public_url = match std::env::var_os("LAPI_PUBLIC_URL") {
+        Some(value) => match value.to_str().and_then(|v| parse_public_url(v).ok()) {
+            Some(url) => url,
+            None => return Err(ServeError::PublicUrl(value)),
+        },
+        None => parse_public_url(DEFAULT_PUBLIC_URL).expect("default url is valid"),
+    };
     let api_high = ApiHigh {
         api_low,
         log: FakeLog,
     };
-    let s = server(api_high);
+    let s = server(api_high, public_url);
     warp::serve(s).run(([127, 0, 0, 1], 3030));
     Ok(())
 }
 
-pub fn server<D: Db, L: LightningNode, G: Log>(
+const DEFAULT_PUBLIC_URL: &str = "http://127.0.0.1:3030/";
+
+/// Urls are joined onto the public url, so it is a directory.
+fn parse_public_url(url: &str) -> Result<Url, url::ParseError> {
+    if url.ends_with('/') {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("{}/", url))
+    }
+}
+
+/// The number in environment variable name, if set. Err holds the value if it is not a number.
+fn env_number<T: FromStr>(name: &str) -> Result<Option<T>, OsString> {
+    match std::env::var_os(name) {
+        Some(value) => match value.to_str().and_then(|v| v.parse().ok()) {
+            Some(number) => Ok(Some(number)),
+            None => Err(value),
+        },
+        None => Ok(None),
+    }
+}
+
+/// public_url is where wallets reach the server.
+pub fn server<D: Db, L: LightningNode + 'static, G: Log>(
     api_high: ApiHigh<D, L, G>,
+    public_url: Url,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> {
     let api = Arc::new(api_high);
 
@@ -54,16 +156,165 @@ pub fn server<D: Db, L: LightningNode, G: Log>(
         }
     });
 
+    let post_pay_lnurl = path!("pay" / "lnurl").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.pay_lnurl(req).then(to_warp_result)
+    });
+
+    // must come before post_pay, which would refuse the body
+    let post_session_pay = path("pay")
+        .and(warp::path::end())
+        .and(session_token())
+        .and(filter_json())
+        .and_then({
+            let api = api.clone();
+            move |token, req| api.pay_invoice_session(token, req).then(to_warp_result)
+        });
+
     let post_pay = path("pay").and(filter_json()).and_then({
         let api = api.clone();
         move |req| api.pay_invoice(req).then(to_warp_result)
     });
 
+    let post_keysend = path("keysend").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.keysend(req).then(to_warp_result)
+    });
+
+    let post_quote = path("quote").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.quote(req).then(to_warp_result)
+    });
+
+    let post_address = path("address").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.new_address(req).then(to_warp_result)
+    });
+
+    let post_offer = path("offer").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.create_offer(req).then(to_warp_result)
+    });
+
+    let post_name = path("name").and(filter_json()).and_then({
+        let api = api.clone();
+        let public_url = public_url.clone();
+        move |req| {
+            api.register_name(req, public_url.clone())
+                .then(to_warp_result)
+        }
+    });
+
+    let post_voucher = path("voucher").and(filter_json()).and_then({
+        let api = api.clone();
+        let public_url = public_url.clone();
+        move |req| {
+            api.create_voucher(req, public_url.clone())
+                .then(to_warp_result)
+        }
+    });
+
+    let post_auth = path("auth").and(filter_json()).and_then({
+        let api = api.clone();
+        let public_url = public_url.clone();
+        move |req| {
+            api.create_auth_challenge(req, public_url.clone())
+                .then(to_warp_result)
+        }
+    });
+
+    let post_withdraw_onchain = path!("withdraw" / "onchain").and(filter_json()).and_then({
+        let api = api.clone();
+        move |req| api.withdraw_onchain(req).then(to_warp_result)
+    });
+
+    let post_hold_invoice = path("hold_invoice")
+        .and(warp::path::end())
+        .and(filter_json())
+        .and_then({
+            let api = api.clone();
+            move |req| api.generate_hold_invoice(req).then(to_warp_result)
+        });
+
+    let post_settle = path!("hold_invoice" / "settle")
+        .and(filter_json())
+        .and_then({
+            let api = api.clone();
+            move |req| api.settle_hold_invoice(req).then(to_warp_result)
+        });
+
+    let post_cancel = path!("hold_invoice" / "cancel")
+        .and(filter_json())
+        .and_then({
+            let api = api.clone();
+            move |req| api.cancel_hold_invoice(req).then(to_warp_result)
+        });
+
+    let delete_invoice = delete2()
+        .and(content_length_limit(1024 * 32))
+        .and(path!("invoice" / PaymentHash))
+        .and(filter_json())
+        .and_then({
+            let api = api.clone();
+            move |payment_hash, req| api.cancel_invoice(payment_hash, req).then(to_warp_result)
+        });
+
     let get_balance = path!("balance" / Middle).and_then({
         let api = api.clone();
         move |middle| api.check_balance(middle).then(to_warp_result)
     });
 
+    let get_session_balance = path("balance")
+        .and(warp::path::end())
+        .and(session_token())
+        .and_then({
+            let api = api.clone();
+            move |token| api.check_balance_session(token).then(to_warp_result)
+        });
+
+    let get_lnurla = path("lnurla").and(warp::query()).and_then({
+        let api = api.clone();
+        move |query| api.lnurl_auth(query).then(to_warp_result)
+    });
+
+    let get_node = path("node").and(warp::path::end()).and_then({
+        let api = api.clone();
+        move || api.node_info().then(to_warp_result)
+    });
+
+    let get_lnurlp = path!(".well-known" / "lnurlp" / String).and_then({
+        let api = api.clone();
+        let public_url = public_url.clone();
+        move |name| {
+            api.lnurl_pay_request(name, public_url.clone())
+                .then(to_warp_result)
+        }
+    });
+
+    let get_lnurlw_callback = path!("lnurlw" / "callback").and(warp::query()).and_then({
+        let api = api.clone();
+        move |query| api.lnurl_withdraw_callback(query).then(to_warp_result)
+    });
+
+    let get_lnurlw = path!("lnurlw" / U256).and_then({
+        let api = api.clone();
+        let public_url = public_url.clone();
+        move |k1| {
+            api.lnurl_withdraw_request(k1, public_url.clone())
+                .then(to_warp_result)
+        }
+    });
+
+    let get_lnurlp_callback = path!("lnurlp" / String / "callback")
+        .and(warp::query())
+        .and_then({
+            let api = api.clone();
+            move |name, query| {
+                api.lnurl_pay_callback(name, query, public_url.clone())
+                    .then(to_warp_result)
+            }
+        });
+
     let get_invoice = path!("invoice" / PaymentHash).and_then({
         let api = api.clone();
         move |parm| api.check_invoice_status(parm).then(to_warp_result)
@@ -86,8 +337,46 @@ pub fn server<D: Db, L: LightningNode, G: Log>(
     });
 
     post_json
-        .and(post_invoice.or(post_pay))
-        .or(get2().and(get_balance.or(await_invoice).or(get_invoice)))
+        .and(
+            post_invoice
+                .or(post_pay_lnurl)
+                .or(post_session_pay)
+                .or(post_pay)
+                .or(post_keysend)
+                .or(post_quote)
+                .or(post_address)
+                .or(post_offer)
+                .or(post_name)
+                .or(post_voucher)
+                .or(post_auth)
+                .or(post_withdraw_onchain)
+                .or(post_settle)
+                .or(post_cancel)
+                .or(post_hold_invoice),
+        )
+        .or(get2().and(
+            get_session_balance
+                .or(get_balance)
+                .or(get_node)
+                .or(get_lnurlp)
+                .or(get_lnurlp_callback)
+                .or(get_lnurlw_callback)
+                .or(get_lnurlw)
+                .or(get_lnurla)
+                .or(await_invoice)
+                .or(get_invoice),
+        ))
+        .or(delete_invoice)
+}
+
+/// The session token of a request, from its "Authorization: Bearer <token>" header.
+fn session_token() -> impl Filter<Extract = (U256,), Error = Rejection> + Copy {
+    warp::header::<String>("authorization").and_then(|value: String| {
+        value
+            .trim_start_matches("Bearer ")
+            .parse::<U256>()
+            .map_err(|_| reject::custom("malformed session token"))
+    })
 }
 
 fn to_warp_result<T: Serialize>(r: Result<T, ErrLogged>) -> Result<impl Reply, Rejection> {
@@ -106,12 +395,25 @@ fn to_websocket_message<T: Serialize>(t: T) -> Result<Message, Rejection> {
 #[derive(Debug)]
 pub enum ServeError {
     Create(CreateError),
+    /// The recording named by LAPI_RECORD could not be opened.
+    Record(std::io::Error),
+    /// LAPI_CONFIRMATIONS was not a number.
+    Confirmations(OsString),
+    /// LAPI_MAX_PARTS was not a number.
+    MaxParts(OsString),
+    /// LAPI_PAY_TIMEOUT was not a number of seconds.
+    PayTimeout(OsString),
+    /// LAPI_NETWORK was not one of mainnet, testnet or regtest.
+    Network(OsString),
+    /// LAPI_PUBLIC_URL was not a url.
+    PublicUrl(OsString),
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::api_types::*;
+    use crate::fake_log::MemLog;
     use crate::test_util::*;
 
     macro_rules! server {
@@ -119,12 +421,34 @@ mod test {
     }
 
     fn make_server_with_db<D: 'static + Db>(database: D) -> server!() {
-        let api_low = ApiLow::create(database, init_default_lightning_client().unwrap());
+        let api_low = ApiLow::create(
+            database,
+            init_default_lightning_client().unwrap(),
+            MemLog::new(),
+        );
         let api_high = ApiHigh {
             api_low,
             log: FakeLog,
         };
-        server(api_high)
+        server(api_high, public_url())
+    }
+
+    /// A server backed by a fake lightning node, for flows that need no real payments.
+    fn make_fake_server() -> server!() {
+        let api_low = ApiLow::create(
+            db_with_account_a_balance(),
+            FakeLightningNode::new(),
+            MemLog::new(),
+        );
+        let api_high = ApiHigh {
+            api_low,
+            log: FakeLog,
+        };
+        server(api_high, public_url())
+    }
+
+    fn public_url() -> Url {
+        Url::parse("https://pay.example.com/").unwrap()
     }
 
     fn make_server() -> server!() {
@@ -159,7 +483,10 @@ mod test {
     fn new_invoice(server: &server!(), amount: u8, lesser: Lesser) -> GenerateInvoiceOk {
         let request = GenerateInvoiceRequest {
             lesser,
-            satoshis: Satoshis(amount as u64),
+            satoshis: Some(Satoshis(amount as u64)),
+            description: None,
+            description_hash: None,
+            expiry_seconds: None,
         };
         let resp: GenerateInvoiceResponse = post(server, "/invoice", request);
         Into::<Result<_, _>>::into(resp).unwrap()
@@ -218,7 +545,7 @@ mod test {
 
         // due to a limitation in warp::test, this line must come before wait_for_pay
         // TODO test wait_for_pay, pay in the correct order
-        pay(&server, &invoice, Satoshis(2), ACCOUNT_A).expect("pay failed");
+        pay(&server, &invoice, Satoshis(1), ACCOUNT_A).expect("pay failed");
 
         let mut websocket = warp::test::ws()
             .path(&format!("/invoice/{}", accnt_a_lesser))
@@ -233,7 +560,7 @@ mod test {
                 res,
                 Ok(AwaitInvoiceOk {
                     preimage: Preimage(U256::zero()),
-                    amount_paid_satoshis: Satoshis(2),
+                    amount_paid_satoshis: Satoshis(1),
                 })
             );
             websocket.recv_closed().unwrap(); // assert ws is closed afterward
@@ -257,6 +584,21 @@ mod test {
         res.into()
     }
 
+    /// The balance of middle once its account exists. Payments to it are credited in the
+    /// background.
+    fn await_balance(
+        server: &server!(),
+        middle: Middle,
+    ) -> Result<CheckBalanceOk, CheckBalanceErr> {
+        for _ in 0..500 {
+            if get_balance(server, middle).is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        get_balance(server, middle)
+    }
+
     #[test]
     fn get_invoice() {
         let accnt_b = Master::random();
@@ -276,7 +618,7 @@ mod test {
             Ok(CheckInvoiceOk::Waiting(()))
         );
 
-        pay(&server, &invoice, Satoshis(2), ACCOUNT_A).unwrap();
+        pay(&server, &invoice, Satoshis(1), ACCOUNT_A).unwrap();
 
         // After payment assert paid
         if let CheckInvoiceOk::Paid {
@@ -285,7 +627,7 @@ mod test {
         } = get_invoice_status(&server, get_payment_hash(&invoice)).unwrap()
         {
             assert_eq!(preimage.hash(), get_payment_hash(&invoice));
-            assert_eq!(amount_paid_satoshis, Satoshis(2));
+            assert_eq!(amount_paid_satoshis, Satoshis(1));
         } else {
             panic!()
         }
@@ -293,7 +635,7 @@ mod test {
         assert_eq!(
             get_balance(&server, accnt_b.into()),
             Ok(CheckBalanceOk {
-                balance_satoshis: Satoshis(2)
+                balance_satoshis: Satoshis(1)
             })
         );
     }
@@ -316,11 +658,242 @@ mod test {
     fn fail_with_no_balance() {
         let server = make_server();
         let account_b = Master::random();
-        let invoice = new_invoice(&server, 0, account_b.into()).invoice.0;
+        let invoice = new_invoice(&server, 1, account_b.into()).invoice.0;
         let res = pay(&server, &invoice, Satoshis(1), account_b);
         assert_eq!(res, Err(PayInvoiceErr::InsufficientBalance(())))
     }
 
+    #[test]
+    fn lightning_address() {
+        let server = make_fake_server();
+        let owner = Master::random();
+        let register = |name: &str| -> Result<RegisterNameOk, RegisterNameErr> {
+            let request = RegisterNameRequest {
+                middle: owner.into(),
+                name: name.to_owned(),
+            };
+            post::<_, RegisterNameResponse>(&server, "/name", request).into()
+        };
+        assert_eq!(
+            register("alice"),
+            Ok(RegisterNameOk {
+                address: "alice@pay.example.com".to_owned()
+            })
+        );
+        assert_eq!(register("alice"), Err(RegisterNameErr::Taken(())));
+        assert_eq!(register("Alice"), Err(RegisterNameErr::InvalidName(())));
+
+        // the wallet resolves alice@pay.example.com
+        let pay_request: Result<_, _> =
+            get::<LnurlResponse<LnurlPayRequest>>(&server, "/.well-known/lnurlp/alice").into();
+        let pay_request = pay_request.unwrap();
+        assert_eq!(pay_request.tag, LnurlTag::PayRequest);
+        assert_eq!(
+            pay_request.callback.0.as_str(),
+            "https://pay.example.com/lnurlp/alice/callback"
+        );
+        let unknown: Result<_, _> =
+            get::<LnurlResponse<LnurlPayRequest>>(&server, "/.well-known/lnurlp/bob").into();
+        assert_eq!(unknown, Err(LnurlError::new("unknown name")));
+
+        // then asks the callback for an invoice
+        let callback = |amount_msat: u64| -> Result<LnurlPayInvoice, LnurlError> {
+            let path = format!("{}?amount={}", pay_request.callback.0.path(), amount_msat);
+            get::<LnurlResponse<LnurlPayInvoice>>(&server, &path).into()
+        };
+        assert!(callback(1500).is_err());
+        let invoice = callback(21000).unwrap().pr.0;
+        assert_eq!(
+            get_description(&invoice),
+            Description::Hash(crate::lnurl::metadata_hash(&pay_request.metadata))
+        );
+        assert_eq!(invoice.amount_pico_btc(), Some(21 * 10_000));
+
+        pay(&server, &invoice, Satoshis(21), ACCOUNT_A).unwrap();
+        assert_eq!(
+            await_balance(&server, owner.into()),
+            Ok(CheckBalanceOk {
+                balance_satoshis: Satoshis(21)
+            })
+        );
+    }
+
+    #[test]
+    fn voucher() {
+        let server = make_fake_server();
+        let request = CreateVoucherRequest {
+            master: ACCOUNT_A,
+            amount_satoshis: Satoshis(30),
+            fee_satoshis: DEFAULT_FEE,
+            uses: None,
+            expiry_seconds: None,
+        };
+        let res: Result<_, _> =
+            post::<_, CreateVoucherResponse>(&server, "/voucher", request).into();
+        let CreateVoucherOk { k1, lnurl } = res.unwrap();
+        assert!(lnurl.starts_with("lnurl1"));
+
+        // the wallet scans the voucher
+        let withdraw_request: Result<_, _> =
+            get::<LnurlResponse<LnurlWithdrawRequest>>(&server, &format!("/lnurlw/{}", k1)).into();
+        let withdraw_request = withdraw_request.unwrap();
+        assert_eq!(withdraw_request.tag, LnurlTag::WithdrawRequest);
+        assert_eq!(withdraw_request.k1, k1);
+        assert_eq!(withdraw_request.max_withdrawable, 30_000);
+
+        // and submits an invoice to the callback
+        let recipient = Master::random();
+        let callback = |satoshis: u8| -> Result<LnurlOk, LnurlError> {
+            let invoice = new_invoice(&server, satoshis, recipient.into()).invoice;
+            let path = format!(
+                "{}?k1={}&pr={}",
+                withdraw_request.callback.0.path(),
+                k1,
+                to_bolt11(&invoice.0)
+            );
+            get::<LnurlResponse<LnurlOk>>(&server, &path).into()
+        };
+        assert_eq!(
+            callback(31),
+            Err(LnurlError::new(
+                "invoice must ask for between minWithdrawable and maxWithdrawable"
+            ))
+        );
+        assert_eq!(
+            callback(30),
+            Ok(LnurlOk {
+                status: LnurlStatus::Ok
+            })
+        );
+        assert_eq!(callback(30), Err(LnurlError::new("unknown voucher")));
+        assert_eq!(
+            await_balance(&server, recipient.into()),
+            Ok(CheckBalanceOk {
+                balance_satoshis: Satoshis(30)
+            })
+        );
+    }
+
+    #[test]
+    fn pay_lnurl_refuses_invalid_targets() {
+        let server = make_fake_server();
+        for target in &["alice", "alice@pay.example.com/evil", "lnurl1qqqq"] {
+            let request = PayLnurlRequest {
+                master: ACCOUNT_A,
+                target: target.to_string(),
+                amount_satoshis: Satoshis(21),
+                fee_satoshis: DEFAULT_FEE,
+            };
+            let res: Result<_, _> =
+                post::<_, PayLnurlResponse>(&server, "/pay/lnurl", request).into();
+            assert_eq!(res, Err(PayLnurlErr::InvalidTarget(())));
+        }
+    }
+
+    #[test]
+    fn login_session() {
+        use secp256k1::{key::SecretKey, Message, Secp256k1};
+
+        let server = make_fake_server();
+        let secp = Secp256k1::new();
+        let wallet_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let answer_with = |key: &SecretKey, k1: U256| -> Result<LnurlOk, LnurlError> {
+            let sig = secp.sign(&Message::from_slice(&k1.0).unwrap(), key);
+            let path = format!(
+                "/lnurla?tag=login&k1={}&sig={}&key={}",
+                k1,
+                hex::encode(&*sig.serialize_der()),
+                hex::encode(&PublicKey::from_secret_key(&secp, key).serialize()[..])
+            );
+            get::<LnurlResponse<LnurlOk>>(&server, &path).into()
+        };
+        let answer = |k1: U256| answer_with(&wallet_key, k1);
+        let challenge = |link: Option<Capability>| -> AuthChallengeResponse {
+            post(&server, "/auth", AuthChallengeRequest { link })
+        };
+        let with_token = |request: warp::test::RequestBuilder, token: U256| {
+            request.header("Authorization", format!("Bearer {}", token))
+        };
+        let balance = |token: U256| -> Result<CheckBalanceOk, SessionErr<CheckBalanceErr>> {
+            let raw = with_token(warp::test::request().path("/balance"), token).reply(&server);
+            sj::<SessionCheckBalanceResponse>(std::str::from_utf8(raw.body()).unwrap())
+                .unwrap()
+                .into()
+        };
+
+        // the wallet's key is unknown until linked
+        let login = challenge(None);
+        assert!(login.lnurl.starts_with("lnurl1"));
+        // a signature that is not hex DER is a bad request
+        let path = format!(
+            "/lnurla?tag=login&k1={}&sig=3000&key={}",
+            login.k1,
+            hex::encode(&PublicKey::from_secret_key(&secp, &wallet_key).serialize()[..])
+        );
+        let raw = warp::test::request().path(&path).reply(&server);
+        assert_eq!(raw.status(), 400);
+        assert_eq!(answer(login.k1), Err(LnurlError::new("unknown key")));
+        assert_eq!(
+            balance(login.token),
+            Err(SessionErr::Session(SessionRefused::Unauthorized(())))
+        );
+
+        // link it to account a's master
+        let link = challenge(Some(Capability::Master(ACCOUNT_A)));
+        assert_eq!(
+            answer(link.k1),
+            Ok(LnurlOk {
+                status: LnurlStatus::Ok
+            })
+        );
+        assert_eq!(answer(link.k1), Err(LnurlError::new("unknown k1")));
+        assert_eq!(
+            balance(link.token),
+            Ok(CheckBalanceOk {
+                balance_satoshis: Satoshis(500)
+            })
+        );
+
+        // from then on, logging in with the key alone grants the master
+        let login = challenge(None);
+        answer(login.k1).unwrap();
+        let recipient = Master::random();
+        let invoice = new_invoice(&server, 10, recipient.into()).invoice;
+        let body = js(SessionPayInvoiceRequest {
+            invoice,
+            amount_satoshis: Satoshis(10),
+            fee_satoshis: DEFAULT_FEE,
+        });
+        let pay = |token: U256| -> Result<PayInvoiceOk, SessionErr<PayInvoiceErr>> {
+            let request = warp::test::request()
+                .path("/pay")
+                .method("POST")
+                .header("Content-Length", body.len())
+                .body(&body);
+            let raw = with_token(request, token).reply(&server);
+            sj::<SessionPayInvoiceResponse>(std::str::from_utf8(raw.body()).unwrap())
+                .unwrap()
+                .into()
+        };
+        pay(login.token).unwrap();
+        assert_eq!(
+            await_balance(&server, recipient.into()),
+            Ok(CheckBalanceOk {
+                balance_satoshis: Satoshis(10)
+            })
+        );
+
+        // a session started with a middle may not pay
+        let other_key = SecretKey::from_slice(&[0x43; 32]).unwrap();
+        let view_only = challenge(Some(Capability::Middle(ACCOUNT_A.into())));
+        answer_with(&other_key, view_only.k1).unwrap();
+        assert!(balance(view_only.token).is_ok());
+        assert_eq!(
+            pay(view_only.token),
+            Err(SessionErr::Session(SessionRefused::Unauthorized(())))
+        );
+    }
+
     #[test]
     fn http_500s() {
         unimplemented!()
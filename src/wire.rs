@@ -0,0 +1,249 @@
+//! The protobuf wire format, for the lnd rpcs lnd_rust has no bindings for. Messages are
+//! written field by field, and read into a list of fields which are then picked out by number.
+//! Only the wire types proto3 messages use are understood.
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WireError {
+    /// The message ended inside a field.
+    Truncated,
+    /// A varint was longer than ten bytes.
+    VarintOverflow,
+    /// A field used a wire type proto3 does not.
+    UnknownWireType(u64),
+    /// A field expected to hold a string held bytes that are not utf8.
+    InvalidUtf8(u32),
+}
+
+/// A message being written.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer(Vec::new())
+    }
+
+    /// Write an integer, bool or enum field. Zero is the default, and is not written.
+    pub fn varint(&mut self, field: u32, value: u64) -> &mut Writer {
+        if value != 0 {
+            self.key(field, VARINT);
+            put_varint(&mut self.0, value);
+        }
+        self
+    }
+
+    /// Write a bytes field. Empty is the default, and is not written.
+    pub fn bytes(&mut self, field: u32, value: &[u8]) -> &mut Writer {
+        if !value.is_empty() {
+            self.key(field, LENGTH_DELIMITED);
+            put_varint(&mut self.0, value.len() as u64);
+            self.0.extend_from_slice(value);
+        }
+        self
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) -> &mut Writer {
+        self.bytes(field, value.as_bytes())
+    }
+
+    /// Write an embedded message. Unlike scalars, an empty message is written, a repeated or map
+    /// field must keep every entry.
+    pub fn message(&mut self, field: u32, value: &Writer) -> &mut Writer {
+        self.key(field, LENGTH_DELIMITED);
+        put_varint(&mut self.0, value.0.len() as u64);
+        self.0.extend_from_slice(&value.0);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    fn key(&mut self, field: u32, wire_type: u64) {
+        put_varint(&mut self.0, u64::from(field) << 3 | wire_type);
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Value {
+    Varint(u64),
+    Fixed(u64),
+    Bytes(Vec<u8>),
+}
+
+/// A message that has been read. Absent fields read as their default.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Fields(Vec<(u32, Value)>);
+
+impl Fields {
+    pub fn read(mut bytes: &[u8]) -> Result<Fields, WireError> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = take_varint(&mut bytes)?;
+            let field = (key >> 3) as u32;
+            let value = match key & 7 {
+                VARINT => Value::Varint(take_varint(&mut bytes)?),
+                FIXED64 => Value::Fixed(take_fixed(&mut bytes, 8)?),
+                FIXED32 => Value::Fixed(take_fixed(&mut bytes, 4)?),
+                LENGTH_DELIMITED => {
+                    let len = take_varint(&mut bytes)?;
+                    if len > bytes.len() as u64 {
+                        return Err(WireError::Truncated);
+                    }
+                    let (value, rest) = bytes.split_at(len as usize);
+                    bytes = rest;
+                    Value::Bytes(value.to_vec())
+                }
+                other => return Err(WireError::UnknownWireType(other)),
+            };
+            fields.push((field, value));
+        }
+        Ok(Fields(fields))
+    }
+
+    /// An integer, bool or enum field. When a scalar is repeated the last value wins.
+    pub fn varint(&self, field: u32) -> u64 {
+        self.0
+            .iter()
+            .rev()
+            .filter(|(number, _)| *number == field)
+            .filter_map(|(_, value)| match value {
+                Value::Varint(value) | Value::Fixed(value) => Some(*value),
+                Value::Bytes(_) => None,
+            })
+            .next()
+            .unwrap_or(0)
+    }
+
+    /// A signed integer field, int32 and int64 alike.
+    pub fn int(&self, field: u32) -> i64 {
+        self.varint(field) as i64
+    }
+
+    pub fn bytes(&self, field: u32) -> &[u8] {
+        self.repeated(field).last().unwrap_or(&[])
+    }
+
+    pub fn string(&self, field: u32) -> Result<&str, WireError> {
+        std::str::from_utf8(self.bytes(field)).map_err(|_| WireError::InvalidUtf8(field))
+    }
+
+    /// Every value of a repeated bytes, string or message field, in order.
+    pub fn repeated<'a>(&'a self, field: u32) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.0
+            .iter()
+            .filter(move |(number, _)| *number == field)
+            .filter_map(|(_, value)| match value {
+                Value::Bytes(bytes) => Some(&bytes[..]),
+                Value::Varint(_) | Value::Fixed(_) => None,
+            })
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn take_varint(bytes: &mut &[u8]) -> Result<u64, WireError> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate() {
+        if i == 10 {
+            return Err(WireError::VarintOverflow);
+        }
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(WireError::Truncated)
+}
+
+/// A little endian integer of len bytes.
+fn take_fixed(bytes: &mut &[u8], len: usize) -> Result<u64, WireError> {
+    if bytes.len() < len {
+        return Err(WireError::Truncated);
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(value
+        .iter()
+        .rev()
+        .fold(0, |acc, byte| acc << 8 | u64::from(*byte)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut entry = Writer::new();
+        entry.varint(1, 5_482_373_484).bytes(2, &[7; 32]);
+        let mut writer = Writer::new();
+        writer
+            .bytes(1, &[2; 33])
+            .varint(2, 1000)
+            .varint(4, 0)
+            .string(5, "lnbc1")
+            .message(11, &entry)
+            .message(11, &Writer::new())
+            .varint(17, 16);
+        let fields = Fields::read(&writer.into_bytes()).unwrap();
+        assert_eq!(fields.bytes(1), &[2; 33][..]);
+        assert_eq!(fields.int(2), 1000);
+        assert_eq!(fields.varint(4), 0);
+        assert_eq!(fields.string(5), Ok("lnbc1"));
+        assert_eq!(fields.varint(17), 16);
+        assert_eq!(fields.repeated(11).count(), 2);
+        let entry = Fields::read(fields.bytes(11)).unwrap();
+        assert_eq!(entry, Fields::default());
+        let entry = Fields::read(fields.repeated(11).next().unwrap()).unwrap();
+        assert_eq!(entry.varint(1), 5_482_373_484);
+        assert_eq!(entry.bytes(2), &[7; 32][..]);
+    }
+
+    #[test]
+    fn known_encoding() {
+        // the example from the protobuf encoding guide, field 1 set to 150
+        assert_eq!(
+            Writer::new().varint(1, 150).clone().into_bytes(),
+            vec![0x08, 0x96, 0x01]
+        );
+        // negative int64s are ten byte varints
+        let mut writer = Writer::new();
+        writer.varint(1, -2i64 as u64);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), 11);
+        assert_eq!(Fields::read(&bytes).unwrap().int(1), -2);
+        // fixed width fields are read as integers
+        let fields = Fields::read(&[0x09, 1, 0, 0, 0, 0, 0, 0, 0, 0x15, 2, 0, 0, 0]).unwrap();
+        assert_eq!(fields.varint(1), 1);
+        assert_eq!(fields.varint(2), 2);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(Fields::read(&[0x08]), Err(WireError::Truncated));
+        assert_eq!(Fields::read(&[0x0a, 2, 0]), Err(WireError::Truncated));
+        assert_eq!(Fields::read(&[0x0b]), Err(WireError::UnknownWireType(3)));
+        assert_eq!(Fields::read(&[0x08; 12]).map(|_| ()), Ok(()));
+        assert_eq!(
+            Fields::read(&[0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            Err(WireError::VarintOverflow)
+        );
+        assert_eq!(
+            Fields::read(&[0x12, 1, 0xff]).unwrap().string(2),
+            Err(WireError::InvalidUtf8(2))
+        );
+    }
+}